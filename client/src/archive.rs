@@ -0,0 +1,268 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Offline export and import of the block DAG. The blocks are written as a
+//! sequence of RLP-encoded blocks in topological order, and are verified
+//! again when they are imported into another node.
+
+use crate::{Client, Configuration, LedgerComponents};
+use cfxcore::{
+    sync::{SynchronizationGraph, SynchronizationProtocolHandler},
+    SharedSynchronizationGraph, TransactionPool,
+};
+use db::SystemDB;
+use primitives::Block;
+use rlp::PayloadInfo;
+use secret_store::SecretStore;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The interval between two progress reports.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The blocks of the local ledger, without the network or RPC services.
+struct Ledger {
+    ledger_db: Arc<SystemDB>,
+    txpool: Arc<TransactionPool>,
+    sync_graph: SharedSynchronizationGraph,
+}
+
+impl Ledger {
+    fn open(conf: &Configuration) -> Result<Ledger, String> {
+        let LedgerComponents {
+            ledger_db,
+            txpool,
+            consensus,
+            ..
+        } = LedgerComponents::open(conf, &SecretStore::new())?;
+        let sync_graph = Arc::new(SynchronizationGraph::new(
+            consensus,
+            conf.verification_config(),
+            conf.pow_config(),
+            conf.fast_recover(),
+        ));
+
+        let ledger = Ledger {
+            ledger_db,
+            txpool,
+            sync_graph,
+        };
+        ledger.wait_for_consensus();
+        Ok(ledger)
+    }
+
+    fn wait_for_consensus(&self) {
+        while self.sync_graph.is_consensus_worker_busy() {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn close(self) {
+        let ledger_db = Arc::downgrade(&self.ledger_db);
+        drop(self);
+
+        // Make sure ledger_db is properly dropped, so rocksdb can be closed
+        // cleanly
+        Client::wait_for_drop(ledger_db);
+    }
+}
+
+/// Write all the blocks of the ledger except the genesis block to `path`.
+pub fn export_blocks(conf: Configuration, path: &str) -> Result<(), String> {
+    let ledger = Ledger::open(&conf)?;
+    let file = File::create(path)
+        .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+
+    let consensus = ledger.sync_graph.consensus.clone();
+    let hashes = consensus.inner.read().all_blocks_with_topo_order();
+    let total = hashes.len().saturating_sub(1);
+    info!("Exporting {} blocks to {}", total, path);
+    let mut last_report = Instant::now();
+    // The first block is the genesis block, which every node computes.
+    for (exported, hash) in hashes.iter().skip(1).enumerate() {
+        let block =
+            consensus
+                .data_man
+                .block_by_hash(hash, false)
+                .ok_or_else(|| {
+                    format!("Block {:?} is not in the database", hash)
+                })?;
+        writer
+            .write_all(&rlp::encode(block.as_ref()))
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            info!("Exported {}/{} blocks", exported, total);
+            last_report = Instant::now();
+        }
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    info!("Exported {} blocks to {}", total, path);
+
+    drop(consensus);
+    ledger.close();
+    Ok(())
+}
+
+/// Insert the blocks in `path` into the ledger with full verification. The
+/// blocks already in the ledger are skipped.
+pub fn import_blocks(conf: Configuration, path: &str) -> Result<(), String> {
+    let ledger = Ledger::open(&conf)?;
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut remaining = file
+        .metadata()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?
+        .len();
+    let mut reader = BufReader::new(file);
+
+    info!("Importing blocks from {}", path);
+    let sync_graph = ledger.sync_graph.clone();
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut last_report = Instant::now();
+    while let Some(item) = read_rlp_item(&mut reader, remaining)
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?
+    {
+        remaining -= item.len() as u64;
+        let mut block: Block = rlp::decode(&item)
+            .map_err(|e| format!("Failed to decode a block: {:?}", e))?;
+        let hash = block.hash();
+        if sync_graph.contains_block(&hash) {
+            skipped += 1;
+            continue;
+        }
+
+        SynchronizationProtocolHandler::recover_public(
+            &mut block,
+            ledger.txpool.clone(),
+            &mut *ledger.txpool.transaction_pubkey_cache.write(),
+            &mut *sync_graph.cache_man.lock(),
+            &*ledger.txpool.worker_pool.lock(),
+        )
+        .map_err(|e| {
+            format!("Invalid transaction in block {:?}: {:?}", hash, e)
+        })?;
+        let (valid, _) = sync_graph.insert_block_header(
+            &mut block.block_header,
+            true,
+            false,
+        );
+        if !valid {
+            return Err(format!("Invalid block header {:?}", hash));
+        }
+        let (success, _) = sync_graph.insert_block(block, true, true, false);
+        if !success {
+            return Err(format!("Invalid block {:?}", hash));
+        }
+        imported += 1;
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            info!(
+                "Imported {} blocks, best epoch {}",
+                imported,
+                sync_graph.consensus.best_epoch_number()
+            );
+            last_report = Instant::now();
+        }
+    }
+
+    ledger.wait_for_consensus();
+    // Wait for the execution of the latest epochs before closing the db.
+    let consensus = &sync_graph.consensus;
+    consensus.wait_for_block_state(&consensus.best_state_block_hash());
+    let best_epoch_number =
+        sync_graph.get_best_info().as_ref().best_epoch_number;
+    info!(
+        "Imported {} blocks and skipped {} known blocks, best epoch {}",
+        imported, skipped, best_epoch_number
+    );
+
+    drop(sync_graph);
+    ledger.close();
+    Ok(())
+}
+
+/// Read the next RLP list from `reader`, or `None` at the end of the input.
+/// A list longer than the `remaining` bytes of the input is rejected before
+/// its payload is allocated.
+fn read_rlp_item<R: Read>(
+    reader: &mut R, remaining: u64,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut first = [0u8; 1];
+    match reader.read_exact(&mut first) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e),
+    }
+    let length_of_length = match first[0] {
+        0xc0..=0xf7 => 0,
+        0xf8..=0xff => (first[0] - 0xf7) as usize,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Expected an RLP list",
+            ))
+        }
+    };
+
+    let mut item = vec![first[0]; 1 + length_of_length];
+    reader.read_exact(&mut item[1..])?;
+    let payload_info = PayloadInfo::from(&item).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+    })?;
+    if payload_info.total() as u64 > remaining {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "RLP list longer than the remaining input",
+        ));
+    }
+    item.resize(payload_info.total(), 0);
+    reader.read_exact(&mut item[payload_info.header_len..])?;
+    Ok(Some(item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_rlp_item;
+    use rlp::RlpStream;
+
+    #[test]
+    fn test_read_rlp_items() {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&1u8).append(&2u8);
+        let short = stream.out().to_vec();
+        let mut stream = RlpStream::new_list(1);
+        stream.append(&vec![7u8; 100]);
+        let long = stream.out().to_vec();
+        let input = [short.clone(), long.clone()].concat();
+
+        let mut reader = &input[..];
+        let remaining = (input.len() - short.len()) as u64;
+        assert_eq!(
+            read_rlp_item(&mut reader, input.len() as u64).unwrap(),
+            Some(short.clone())
+        );
+        assert_eq!(read_rlp_item(&mut reader, remaining).unwrap(), Some(long));
+        assert_eq!(read_rlp_item(&mut reader, 0).unwrap(), None);
+
+        let mut truncated = &input[..input.len() - 1];
+        let remaining = (input.len() - 1 - short.len()) as u64;
+        read_rlp_item(&mut truncated, input.len() as u64 - 1).unwrap();
+        assert!(read_rlp_item(&mut truncated, remaining).is_err());
+
+        // A huge length header is rejected without allocating the payload.
+        let huge = [0xfb, 0xff, 0xff, 0xff, 0xff];
+        assert!(read_rlp_item(&mut &huge[..], huge.len() as u64).is_err());
+    }
+}
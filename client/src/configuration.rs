@@ -9,6 +9,7 @@ use cfxcore::{
         ADAPTIVE_WEIGHT_DEFAULT_ALPHA_DEN, ADAPTIVE_WEIGHT_DEFAULT_ALPHA_NUM,
         ADAPTIVE_WEIGHT_DEFAULT_BETA, HEAVY_BLOCK_DEFAULT_DIFFICULTY_RATIO,
     },
+    machine::{load_builtin_specs, BuiltinSpec},
     storage::{self, state_manager::StorageConfiguration},
     sync::ProtocolConfiguration,
 };
@@ -40,7 +41,10 @@ build_config! {
         (jsonrpc_http_port, (Option<u16>), None)
         (jsonrpc_cors, (Option<String>), None)
         (jsonrpc_http_keep_alive, (bool), false)
+        (health_http_listen_addr, (Option<String>), None)
+        (health_max_epoch_lag, (u64), 10)
         (genesis_accounts, (Option<String>), None)
+        (builtin_spec_file, (Option<String>), None)
         (log_conf, (Option<String>), None)
         (log_file, (Option<String>), None)
         (bootnodes, (Option<String>), None)
@@ -56,8 +60,10 @@ build_config! {
         (node_table_promotion_timeout, (Option<u64>), Some(3 * 24 * 3600))
         (fast_recover, (bool), true)
         (test_mode, (bool), false)
+        (light_node, (bool), false)
         (db_cache_size, (Option<usize>), Some(128))
         (db_compaction_profile, (Option<String>), None)
+        (db_backend, (Option<String>), None)
         (db_dir, (Option<String>), Some("./blockchain_db".to_string()))
         (generate_tx, (bool), false)
         (generate_tx_period_us, (Option<u64>), Some(100_000))
@@ -88,6 +94,15 @@ build_config! {
         (egress_min_throttle, (usize), 10)
         (egress_max_throttle, (usize), 64)
         (p2p_nodes_per_ip, (usize), 1)
+        (max_incoming_peers, (u32), 32)
+        (peer_disconnect_score, (i32), -100)
+        (peer_ban_score, (i32), -300)
+        (peer_ban_timeout, (u64), 24 * 3600)
+        (nat_enabled, (bool), true)
+        (compression_enabled, (bool), true)
+        (peer_upload_rate_limit, (Option<usize>), None)
+        (network_id, (u64), 1)
+        (chain_id, (u64), 1)
         (data_propagate_enabled, (bool), false)
         (data_propagate_interval_ms, (u64), 1000)
         (data_propagate_size, (usize), 1000)
@@ -102,6 +117,7 @@ build_config! {
         (metrics_enabled, (bool), false)
         (metrics_report_interval_ms, (u64), 5000)
         (metrics_output_file, (String), "metrics.log".to_string())
+        (metrics_http_listen_addr, (Option<String>), None)
         (min_peers_propagation, (usize), 8)
         (max_peers_propagation, (usize), 128)
         (txgen_account_count, (usize), 10)
@@ -183,6 +199,18 @@ impl Configuration {
         }
         network_config.test_mode = self.raw_conf.test_mode;
         network_config.nodes_per_ip = self.raw_conf.p2p_nodes_per_ip;
+        network_config.max_incoming_peers = self.raw_conf.max_incoming_peers;
+        network_config.peer_disconnect_score =
+            self.raw_conf.peer_disconnect_score;
+        network_config.peer_ban_score = self.raw_conf.peer_ban_score;
+        network_config.peer_ban_duration =
+            Duration::from_secs(self.raw_conf.peer_ban_timeout);
+        network_config.nat_enabled = self.raw_conf.nat_enabled;
+        network_config.compression_enabled = self.raw_conf.compression_enabled;
+        network_config.peer_upload_rate_limit =
+            self.raw_conf.peer_upload_rate_limit;
+        network_config.network_id = self.raw_conf.network_id;
+        network_config.chain_id = self.raw_conf.chain_id;
         network_config.fast_discovery_refresh_timeout = Duration::from_millis(
             self.raw_conf.discovery_fast_refresh_timeout_ms,
         );
@@ -208,6 +236,13 @@ impl Configuration {
         cache_config
     }
 
+    pub fn db_backend(&self) -> Result<db::DatabaseBackend, String> {
+        match self.raw_conf.db_backend.as_ref() {
+            Some(backend) => db::DatabaseBackend::from_str(backend),
+            None => Ok(db::DatabaseBackend::default()),
+        }
+    }
+
     pub fn db_config(&self) -> DatabaseConfig {
         let db_dir = self.raw_conf.db_dir.as_ref().unwrap();
         if let Err(e) = fs::create_dir_all(&db_dir) {
@@ -227,8 +262,8 @@ impl Configuration {
         )
     }
 
-    pub fn consensus_config(&self) -> ConsensusConfig {
-        ConsensusConfig {
+    pub fn consensus_config(&self) -> Result<ConsensusConfig, String> {
+        Ok(ConsensusConfig {
             debug_dump_dir_invalid_state_root: self
                 .raw_conf
                 .debug_dump_dir_invalid_state_root
@@ -250,6 +285,16 @@ impl Configuration {
                     .enable_optimistic_execution,
             },
             bench_mode: false,
+            builtin_specs: self.builtin_specs()?,
+        })
+    }
+
+    pub fn builtin_specs(&self) -> Result<Vec<BuiltinSpec>, String> {
+        match self.raw_conf.builtin_spec_file {
+            Some(ref file) => load_builtin_specs(file).map_err(|e| {
+                format!("Error loading builtin spec file {}: {}", file, e)
+            }),
+            None => Ok(Vec::new()),
         }
     }
 
@@ -0,0 +1,59 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{open_ledger_db, open_read_only_ledger_db, Client, Configuration};
+use cfxcore::{db_check, storage::StorageManager};
+use std::sync::Arc;
+
+/// Check the integrity of the ledger database, and truncate it back to the
+/// last consistent epoch if `repair` is set. Without `repair`, the database
+/// is only read.
+pub fn check_db(conf: Configuration, repair: bool) -> Result<(), String> {
+    let ledger_db = if repair {
+        open_ledger_db(&conf)?
+    } else {
+        open_read_only_ledger_db(&conf)?
+    };
+    let storage_manager =
+        StorageManager::new(ledger_db.clone(), conf.storage_config());
+
+    info!("Checking the database");
+    let report = db_check::check(&ledger_db, &storage_manager);
+    info!(
+        "Checked {} blocks, {} receipts, {} transaction addresses and {} states with {} trie nodes",
+        report.blocks,
+        report.receipts,
+        report.transaction_addresses,
+        report.states,
+        report.trie_nodes
+    );
+
+    let result = if report.is_consistent() {
+        info!("The database is consistent");
+        Ok(())
+    } else if repair {
+        let deleted = db_check::repair(&ledger_db, &report)?;
+        info!(
+            "Repaired {} problems, deleted {} blocks after epoch {:?}",
+            report.problems.len(),
+            deleted,
+            report.last_consistent_epoch()
+        );
+        Ok(())
+    } else {
+        Err(format!(
+            "Found {} problems in the database, the last consistent epoch is {:?}. Run with --repair to truncate the database back to it.",
+            report.problems.len(),
+            report.last_consistent_epoch()
+        ))
+    };
+
+    let ledger_db_weak = Arc::downgrade(&ledger_db);
+    drop(storage_manager);
+    drop(ledger_db);
+    // Make sure ledger_db is properly dropped, so rocksdb can be closed
+    // cleanly
+    Client::wait_for_drop(ledger_db_weak);
+    result
+}
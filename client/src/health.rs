@@ -0,0 +1,107 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::rpc::RpcSyncStatus;
+use cfxcore::SynchronizationService;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Weak},
+    thread,
+    time::Duration,
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves `GET /health` for load balancers. The response is `200 OK` if the
+/// node is in sync with its peers, and `503 Service Unavailable` otherwise.
+/// In both cases the body is the JSON encoded sync status.
+///
+/// Each connection is served in its own thread, so that a slow client does
+/// not hold up the others. The listening thread exits on the first connection
+/// after the synchronization service is dropped.
+pub fn start_health_server(
+    addr: SocketAddr, sync: &Arc<SynchronizationService>, max_epoch_lag: u64,
+) -> Result<(), String> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| format!("Failed to bind health endpoint {:?}", e))?;
+
+    let sync = Arc::downgrade(sync);
+    thread::Builder::new()
+        .name("health_http".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                if sync.upgrade().is_none() {
+                    return;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        debug!("Failed to accept health request: {:?}", e);
+                        continue;
+                    }
+                };
+                let sync = sync.clone();
+                let result = thread::Builder::new()
+                    .name("health_http_conn".into())
+                    .spawn(move || {
+                        if let Err(e) = serve_once(stream, &sync, max_epoch_lag)
+                        {
+                            debug!("Failed to serve health request: {:?}", e);
+                        }
+                    });
+                if let Err(e) = result {
+                    warn!("Failed to spawn health connection thread {:?}", e);
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to spawn health thread {:?}", e))?;
+
+    Ok(())
+}
+
+fn serve_once(
+    mut stream: TcpStream, sync: &Weak<SynchronizationService>,
+    max_epoch_lag: u64,
+) -> io::Result<()>
+{
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let (status, body) = if method != "GET" {
+        ("405 Method Not Allowed", String::new())
+    } else if path != "/health" {
+        ("404 Not Found", String::new())
+    } else {
+        match sync.upgrade() {
+            None => ("503 Service Unavailable", String::new()),
+            Some(sync) => {
+                let status = sync.sync_status();
+                let healthy = !status.catch_up_mode
+                    && status.peer_count > 0
+                    && status.epoch_lag() <= max_epoch_lag;
+                let body = serde_json::to_string(&RpcSyncStatus::from(status))
+                    .unwrap_or_default();
+                if healthy {
+                    ("200 OK", body)
+                } else {
+                    ("503 Service Unavailable", body)
+                }
+            }
+        }
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
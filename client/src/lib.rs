@@ -14,18 +14,33 @@ extern crate serde_derive;
 
 #[macro_use]
 mod config_macro;
+mod archive;
 mod configuration;
+mod db_check;
+mod health;
+mod light;
 mod rpc;
 #[cfg(test)]
 mod tests;
 
 use self::{http::Server as HttpServer, tcp::Server as TcpServer};
-pub use crate::configuration::Configuration;
+pub use crate::{
+    archive::{export_blocks, import_blocks},
+    configuration::Configuration,
+    db_check::check_db,
+    light::LightClient,
+    rpc::RpcAccountState,
+};
 use blockgen::BlockGenerator;
 use cfxcore::{
-    cache_manager::CacheManager, genesis, pow::WORKER_COMPUTATION_PARALLELISM,
-    statistics::Statistics, storage::StorageManager,
-    transaction_pool::DEFAULT_MAX_BLOCK_GAS_LIMIT, vm_factory::VmFactory,
+    cache_manager::CacheManager,
+    db::{DB_VERSION, NUM_COLUMNS},
+    db_migration, genesis,
+    pow::WORKER_COMPUTATION_PARALLELISM,
+    statistics::Statistics,
+    storage::StorageManager,
+    transaction_pool::DEFAULT_MAX_BLOCK_GAS_LIMIT,
+    vm_factory::VmFactory,
     ConsensusGraph, SynchronizationService, TransactionPool,
 };
 
@@ -34,7 +49,7 @@ use crate::rpc::{
 };
 use cfx_types::{Address, U256};
 use ctrlc::CtrlC;
-use db::SystemDB;
+use db::{DatabaseBackend, SystemDB};
 use keylib::public_to_address;
 use network::NetworkService;
 use parking_lot::{Condvar, Mutex};
@@ -58,7 +73,7 @@ use txgen::{
 
 /// Used in Genesis author to indicate testnet version
 /// Increase by one for every test net reset
-const TESTNET_VERSION: &'static str =
+pub const TESTNET_VERSION: &'static str =
     "0000000000000000000000000000000000000005";
 
 pub struct ClientHandle {
@@ -97,6 +112,143 @@ impl ClientHandle {
     }
 }
 
+/// Open the key-value database of the ledger with the configured backend.
+fn open_backend_db(conf: &Configuration) -> Result<Arc<SystemDB>, String> {
+    Ok(match conf.db_backend()? {
+        DatabaseBackend::RocksDB => db::open_database(
+            conf.raw_conf.db_dir.as_ref().unwrap(),
+            &conf.db_config(),
+        )
+        .map_err(|e| format!("Failed to open database {:?}", e))?,
+        DatabaseBackend::Memory => {
+            db::open_memory_database(NUM_COLUMNS.clone())
+        }
+        DatabaseBackend::Sled => db::open_sled_database(
+            conf.raw_conf.db_dir.as_ref().unwrap(),
+            NUM_COLUMNS.clone(),
+        )
+        .map_err(|e| format!("Failed to open database {:?}", e))?,
+    })
+}
+
+/// Open the key-value database of the ledger with the configured backend,
+/// and upgrade it to the current schema version.
+pub(crate) fn open_ledger_db(
+    conf: &Configuration,
+) -> Result<Arc<SystemDB>, String> {
+    let ledger_db = open_backend_db(conf)?;
+    db_migration::migrate_to_current(&ledger_db)?;
+    Ok(ledger_db)
+}
+
+/// Open the existing key-value database of the ledger, rejecting all writes.
+/// The database must already be at the current schema version.
+pub(crate) fn open_read_only_ledger_db(
+    conf: &Configuration,
+) -> Result<Arc<SystemDB>, String> {
+    let db_dir = conf.raw_conf.db_dir.as_ref().unwrap();
+    if conf.db_backend()? != DatabaseBackend::Memory
+        && !Path::new(db_dir).exists()
+    {
+        return Err(format!("No database in {}", db_dir));
+    }
+    let ledger_db = db::read_only_database(&open_backend_db(conf)?);
+    match db_migration::read_db_version(&ledger_db)? {
+        Some(DB_VERSION) => Ok(ledger_db),
+        version => Err(format!(
+            "The database version is {:?} instead of {}, start the node once to upgrade it",
+            version, DB_VERSION
+        )),
+    }
+}
+
+/// Compute the genesis block and commit its state, which every node does
+/// locally when it starts.
+pub(crate) fn initialize_genesis(
+    conf: &Configuration, storage_manager: &StorageManager,
+    secret_store: &SecretStore,
+) -> Result<Block, String>
+{
+    let genesis_accounts = if conf.raw_conf.test_mode {
+        match conf.raw_conf.genesis_accounts {
+            Some(ref file) => genesis::load_file(file)?,
+            None => genesis::default(secret_store),
+        }
+    } else {
+        genesis::default(secret_store)
+    };
+    let genesis_block = storage_manager.initialize(
+        genesis_accounts,
+        DEFAULT_MAX_BLOCK_GAS_LIMIT.into(),
+        TESTNET_VERSION.into(),
+    );
+    debug!("Initialize genesis_block={:?}", genesis_block);
+    Ok(genesis_block)
+}
+
+/// The ledger database and the components on top of it which keep the
+/// blocks, set up the same way by the full node and the offline tools.
+pub(crate) struct LedgerComponents {
+    pub ledger_db: Arc<SystemDB>,
+    pub storage_manager: Arc<StorageManager>,
+    pub txpool: Arc<TransactionPool>,
+    pub consensus: Arc<ConsensusGraph>,
+}
+
+impl LedgerComponents {
+    pub fn open(
+        conf: &Configuration, secret_store: &SecretStore,
+    ) -> Result<Self, String> {
+        let ledger_db = open_ledger_db(conf)?;
+        let storage_manager = Arc::new(StorageManager::new(
+            ledger_db.clone(),
+            conf.storage_config(),
+        ));
+        let genesis_block =
+            initialize_genesis(conf, &storage_manager, secret_store)?;
+
+        let mb = 1024 * 1024;
+        let max_cache_size = conf.cache_config().ledger_mb() * mb;
+        let pref_cache_size = max_cache_size * 3 / 4;
+        // 400 is the average size of the key. TODO(ming): make sure this again.
+        let cache_man = Arc::new(Mutex::new(CacheManager::new(
+            pref_cache_size,
+            max_cache_size,
+            3 * mb,
+        )));
+
+        let worker_thread_pool = Arc::new(Mutex::new(ThreadPool::with_name(
+            "Tx Recover".into(),
+            WORKER_COMPUTATION_PARALLELISM,
+        )));
+        let txpool = Arc::new(TransactionPool::with_capacity(
+            conf.raw_conf.tx_pool_size,
+            storage_manager.clone(),
+            worker_thread_pool,
+            cache_man.clone(),
+        ));
+
+        let consensus = Arc::new(ConsensusGraph::with_genesis_block(
+            conf.consensus_config()?,
+            genesis_block,
+            storage_manager.clone(),
+            VmFactory::new(1024 * 32),
+            txpool.clone(),
+            Arc::new(Statistics::new()),
+            ledger_db.clone(),
+            cache_man,
+            conf.pow_config(),
+        ));
+
+        Ok(LedgerComponents {
+            ledger_db,
+            storage_manager,
+            txpool,
+            consensus,
+        })
+    }
+}
+
 pub struct Client {}
 
 impl Client {
@@ -112,28 +264,23 @@ impl Client {
                 Duration::from_millis(conf.raw_conf.metrics_report_interval_ms),
                 conf.raw_conf.metrics_output_file.clone(),
             );
+            if let Some(ref addr) = conf.raw_conf.metrics_http_listen_addr {
+                let addr = addr.parse().map_err(|e| {
+                    format!("Invalid metrics_http_listen_addr {:?}", e)
+                })?;
+                metrics::report_http(addr)?;
+            }
         }
 
-        let worker_thread_pool = Arc::new(Mutex::new(ThreadPool::with_name(
-            "Tx Recover".into(),
-            WORKER_COMPUTATION_PARALLELISM,
-        )));
-
         let network_config = conf.net_config();
-        let cache_config = conf.cache_config();
-
-        let db_config = conf.db_config();
-        let ledger_db = db::open_database(
-            conf.raw_conf.db_dir.as_ref().unwrap(),
-            &db_config,
-        )
-        .map_err(|e| format!("Failed to open database {:?}", e))?;
 
         let secret_store = Arc::new(SecretStore::new());
-        let storage_manager = Arc::new(StorageManager::new(
-            ledger_db.clone(),
-            conf.storage_config(),
-        ));
+        let LedgerComponents {
+            ledger_db,
+            storage_manager,
+            txpool,
+            consensus,
+        } = LedgerComponents::open(&conf, &secret_store)?;
         {
             let storage_manager_log_weak_ptr = Arc::downgrade(&storage_manager);
             let exit_clone = exit.clone();
@@ -155,55 +302,7 @@ impl Client {
             });
         }
 
-        let genesis_accounts = if conf.raw_conf.test_mode {
-            match conf.raw_conf.genesis_accounts {
-                Some(ref file) => genesis::load_file(file)?,
-                None => genesis::default(secret_store.as_ref()),
-            }
-        } else {
-            genesis::default(secret_store.as_ref())
-        };
-
-        let genesis_block = storage_manager.initialize(
-            genesis_accounts,
-            DEFAULT_MAX_BLOCK_GAS_LIMIT.into(),
-            TESTNET_VERSION.into(),
-        );
-        debug!("Initialize genesis_block={:?}", genesis_block);
-
-        let mb = 1024 * 1024;
-        let max_cache_size = cache_config.ledger_mb() * mb;
-        let pref_cache_size = max_cache_size * 3 / 4;
-        // 400 is the average size of the key. TODO(ming): make sure this again.
-        let cache_man = Arc::new(Mutex::new(CacheManager::new(
-            pref_cache_size,
-            max_cache_size,
-            3 * mb,
-        )));
-
-        let txpool = Arc::new(TransactionPool::with_capacity(
-            conf.raw_conf.tx_pool_size,
-            storage_manager.clone(),
-            worker_thread_pool.clone(),
-            cache_man.clone(),
-        ));
-
-        let statistics = Arc::new(Statistics::new());
-
-        let vm = VmFactory::new(1024 * 32);
         let pow_config = conf.pow_config();
-        let consensus = Arc::new(ConsensusGraph::with_genesis_block(
-            conf.consensus_config(),
-            genesis_block,
-            storage_manager.clone(),
-            vm.clone(),
-            txpool.clone(),
-            statistics.clone(),
-            ledger_db.clone(),
-            cache_man.clone(),
-            pow_config.clone(),
-        ));
-
         let verification_config = conf.verification_config();
         let protocol_config = conf.protocol_config();
         let mut sync = cfxcore::SynchronizationService::new(
@@ -227,6 +326,17 @@ impl Client {
         let sync = Arc::new(sync);
         let sync_graph = sync.get_synchronization_graph();
 
+        if let Some(ref addr) = conf.raw_conf.health_http_listen_addr {
+            let addr = addr.parse().map_err(|e| {
+                format!("Invalid health_http_listen_addr {:?}", e)
+            })?;
+            health::start_health_server(
+                addr,
+                &sync,
+                conf.raw_conf.health_max_epoch_lag,
+            )?;
+        }
+
         let txgen = Arc::new(TransactionGenerator::new(
             consensus.clone(),
             storage_manager.clone(),
@@ -368,7 +478,7 @@ impl Client {
     }
 
     /// Use a Weak pointer to ensure that other Arc pointers are released
-    fn wait_for_drop<T>(w: Weak<T>) {
+    pub(crate) fn wait_for_drop<T>(w: Weak<T>) {
         let sleep_duration = Duration::from_secs(1);
         let warn_timeout = Duration::from_secs(10);
         let max_timeout = Duration::from_secs(60);
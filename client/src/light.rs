@@ -0,0 +1,119 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{
+    http::Server as HttpServer,
+    initialize_genesis, open_ledger_db,
+    rpc::{self, setup_light_rpc_apis},
+    tcp::Server as TcpServer,
+    Client, Configuration,
+};
+use cfxcore::{light::LightHeaderGraph, storage::StorageManager, LightService};
+use ctrlc::CtrlC;
+use db::SystemDB;
+use network::NetworkService;
+use parking_lot::{Condvar, Mutex};
+use secret_store::SecretStore;
+use std::{
+    any::Any,
+    sync::{Arc, Weak},
+};
+
+pub struct LightClientHandle {
+    pub rpc_tcp_server: Option<TcpServer>,
+    pub rpc_http_server: Option<HttpServer>,
+    pub light: Arc<LightService>,
+    pub ledger_db: Weak<SystemDB>,
+}
+
+impl LightClientHandle {
+    pub fn into_be_dropped(self) -> (Weak<SystemDB>, Box<Any>) {
+        (
+            self.ledger_db,
+            Box::new((self.rpc_tcp_server, self.rpc_http_server, self.light)),
+        )
+    }
+}
+
+/// A node which only syncs block headers, and answers the state and receipt
+/// queries with proofs from full nodes checked against those headers.
+pub struct LightClient {}
+
+impl LightClient {
+    pub fn start(conf: Configuration) -> Result<LightClientHandle, String> {
+        info!("Working directory: {:?}", std::env::current_dir());
+
+        let ledger_db = open_ledger_db(&conf)?;
+
+        // The genesis state has to be computed locally to get the genesis
+        // block, which must be the same as the one of the full nodes.
+        let secret_store = SecretStore::new();
+        let storage_manager =
+            StorageManager::new(ledger_db.clone(), conf.storage_config());
+        let genesis_block =
+            initialize_genesis(&conf, &storage_manager, &secret_store)?;
+
+        let graph = Arc::new(LightHeaderGraph::new(
+            genesis_block.block_header.clone(),
+            conf.verification_config(),
+            conf.raw_conf.heavy_block_difficulty_ratio,
+        ));
+        let mut light =
+            LightService::new(NetworkService::new(conf.net_config()), graph);
+        light.start().map_err(|e| {
+            format!("Failed to start the light service {:?}", e)
+        })?;
+        let light = Arc::new(light);
+
+        let rpc_tcp_server = rpc::new_tcp(
+            rpc::TcpConfiguration::new(None, conf.raw_conf.jsonrpc_tcp_port),
+            setup_light_rpc_apis(light.clone()),
+        )?;
+
+        let rpc_http_server = rpc::new_http(
+            rpc::HttpConfiguration::new(
+                None,
+                conf.raw_conf.jsonrpc_http_port,
+                conf.raw_conf.jsonrpc_cors.clone(),
+                conf.raw_conf.jsonrpc_http_keep_alive,
+            ),
+            setup_light_rpc_apis(light.clone()),
+        )?;
+
+        Ok(LightClientHandle {
+            rpc_tcp_server,
+            rpc_http_server,
+            light,
+            ledger_db: Arc::downgrade(&ledger_db),
+        })
+    }
+
+    pub fn close(handle: LightClientHandle) -> i32 {
+        let (ledger_db, to_drop) = handle.into_be_dropped();
+        drop(to_drop);
+
+        // Make sure ledger_db is properly dropped, so rocksdb can be closed
+        // cleanly
+        Client::wait_for_drop(ledger_db);
+        0
+    }
+
+    pub fn run_until_closed(
+        exit: Arc<(Mutex<bool>, Condvar)>, keep_alive: LightClientHandle,
+    ) -> i32 {
+        CtrlC::set_handler({
+            let e = exit.clone();
+            move || {
+                *e.0.lock() = true;
+                e.1.notify_all();
+            }
+        });
+
+        let mut lock = exit.0.lock();
+        if !*lock {
+            let _ = exit.1.wait(&mut lock);
+        }
+        LightClient::close(keep_alive)
+    }
+}
@@ -9,6 +9,7 @@ use crate::{
     },
     tcp::{Server as TcpServer, ServerBuilder as TcpServerBuilder},
 };
+use cfxcore::SharedLightService;
 use jsonrpc_core::IoHandler;
 use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
@@ -20,11 +21,17 @@ mod traits;
 mod types;
 
 use self::{
-    impls::cfx::{CfxHandler, DebugRpcImpl, RpcImpl, TestRpcImpl},
-    traits::{Cfx, DebugRpc, TestRpc},
+    impls::{
+        cfx::{AdminRpcImpl, CfxHandler, DebugRpcImpl, RpcImpl, TestRpcImpl},
+        light::LightRpcImpl,
+    },
+    traits::{AdminRpc, Cfx, DebugRpc, LightCfx, TestRpc},
 };
 
-pub use self::types::Block as RpcBlock;
+pub use self::types::{
+    AccountState as RpcAccountState, Block as RpcBlock,
+    SyncStatus as RpcSyncStatus,
+};
 
 #[derive(Debug, PartialEq)]
 pub struct TcpConfiguration {
@@ -99,7 +106,16 @@ pub fn setup_debug_rpc_apis(rpc_impl: Arc<RpcImpl>) -> IoHandler {
     // extend_with maps each method in RpcImpl object into a RPC handler
     handler.extend_with(CfxHandler::new(rpc_impl.clone()).to_delegate());
     handler.extend_with(TestRpcImpl::new(rpc_impl.clone()).to_delegate());
-    handler.extend_with(DebugRpcImpl::new(rpc_impl).to_delegate());
+    handler.extend_with(DebugRpcImpl::new(rpc_impl.clone()).to_delegate());
+    handler.extend_with(AdminRpcImpl::new(rpc_impl).to_delegate());
+
+    handler
+}
+
+pub fn setup_light_rpc_apis(light: SharedLightService) -> IoHandler {
+    let mut handler = IoHandler::new();
+
+    handler.extend_with(LightRpcImpl::new(light).to_delegate());
 
     handler
 }
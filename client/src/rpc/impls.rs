@@ -3,3 +3,4 @@
 // See http://www.gnu.org/licenses/
 
 pub mod cfx;
+pub mod light;
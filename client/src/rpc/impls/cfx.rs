@@ -3,30 +3,41 @@
 // See http://www.gnu.org/licenses/
 
 use crate::rpc::{
-    traits::cfx::{Cfx, DebugRpc, TestRpc},
+    traits::cfx::{AdminRpc, Cfx, DebugRpc, TestRpc},
     types::{
-        Block as RpcBlock, Bytes, EpochNumber, Receipt as RpcReceipt, Receipt,
-        Status as RpcStatus, Transaction as RpcTransaction, H160 as RpcH160,
-        H256 as RpcH256, U256 as RpcU256, U64 as RpcU64,
+        encode_trie_proof, Block as RpcBlock, Bytes, EpochNumber,
+        Receipt as RpcReceipt, Receipt, StateDump as RpcStateDump,
+        StateProof as RpcStateProof, Status as RpcStatus,
+        StorageProof as RpcStorageProof, SyncStatus as RpcSyncStatus,
+        Transaction as RpcTransaction, H160 as RpcH160, H256 as RpcH256,
+        U256 as RpcU256, U64 as RpcU64,
     },
 };
 use blockgen::BlockGenerator;
 use cfx_types::{H160, H256};
 use cfxcore::{
-    storage::StorageManager, PeerInfo, SharedConsensusGraph,
-    SharedSynchronizationService, SharedTransactionPool,
+    block_data_manager::note_read_error, consensus::ConfirmationTrait,
+    db::DbError, statedb::StorageKey, storage::StorageManager, PeerInfo,
+    SharedConsensusGraph, SharedSynchronizationService, SharedTransactionPool,
 };
+use ipnetwork::IpNetwork;
 use jsonrpc_core::{Error as RpcError, Result as RpcResult};
 use jsonrpc_macros::Trailing;
-use network::node_table::{NodeEndpoint, NodeEntry, NodeId};
+use network::{
+    node_table::{NodeEndpoint, NodeEntry, NodeId},
+    BannedNodeInfo, Error as NetworkError, NetworkService, NodeInfo,
+};
 use parking_lot::{Condvar, Mutex};
 use primitives::{
     block::MAX_BLOCK_SIZE_IN_BYTES, Action,
     EpochNumber as PrimitiveEpochNumber, SignedTransaction, Transaction,
-    TransactionWithSignature,
+    TransactionWithSignature, TrieProof,
 };
 use rlp::Rlp;
-use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc, time::Duration};
+
+/// The maximum number of accounts returned by `cfx_dumpState` at a time.
+const MAX_DUMP_STATE_ACCOUNTS: usize = 1000;
 
 pub struct RpcImpl {
     pub consensus: SharedConsensusGraph,
@@ -58,12 +69,7 @@ impl RpcImpl {
     fn get_primitive_epoch_number(
         &self, number: EpochNumber,
     ) -> PrimitiveEpochNumber {
-        match number {
-            EpochNumber::Earliest => PrimitiveEpochNumber::Earliest,
-            EpochNumber::LatestMined => PrimitiveEpochNumber::LatestMined,
-            EpochNumber::LatestState => PrimitiveEpochNumber::LatestState,
-            EpochNumber::Num(num) => PrimitiveEpochNumber::Number(num.into()),
-        }
+        number.into_primitive()
     }
 
     fn best_block_hash(&self) -> RpcResult<RpcH256> {
@@ -103,8 +109,14 @@ impl RpcImpl {
                 let block = self
                     .consensus
                     .data_man
-                    .block_by_hash(&hash, false)
-                    .unwrap();
+                    .try_block_by_hash(&hash, false)
+                    .map_err(db_error)?
+                    .ok_or_else(|| {
+                        RpcError::invalid_params(format!(
+                            "Block {:?} is not in the database",
+                            hash
+                        ))
+                    })?;
                 Ok(RpcBlock::new(&*block, inner, include_txs))
             })
     }
@@ -119,7 +131,11 @@ impl RpcImpl {
         );
         let inner = &mut *self.consensus.inner.write();
 
-        if let Some(block) = self.consensus.data_man.block_by_hash(&hash, false)
+        if let Some(block) = self
+            .consensus
+            .data_man
+            .try_block_by_hash(&hash, false)
+            .map_err(db_error)?
         {
             let result_block = Some(RpcBlock::new(&*block, inner, include_txs));
             Ok(result_block)
@@ -145,8 +161,11 @@ impl RpcImpl {
             .check_block_pivot_assumption(&pivot_hash, epoch_number)
             .map_err(|err| RpcError::invalid_params(err))
             .and_then(|_| {
-                if let Some(block) =
-                    self.consensus.data_man.block_by_hash(&block_hash, false)
+                if let Some(block) = self
+                    .consensus
+                    .data_man
+                    .try_block_by_hash(&block_hash, false)
+                    .map_err(db_error)?
                 {
                     debug!("Build RpcBlock {}", block.hash());
                     let result_block = RpcBlock::new(&*block, inner, true);
@@ -162,21 +181,24 @@ impl RpcImpl {
     fn chain(&self) -> RpcResult<Vec<RpcBlock>> {
         info!("RPC Request: cfx_getChain");
         let inner = &mut *self.consensus.inner.write();
-        Ok(inner
+        inner
             .all_blocks_with_topo_order()
             .iter()
             .map(|x| {
-                RpcBlock::new(
-                    self.consensus
-                        .data_man
-                        .block_by_hash(x, false)
-                        .expect("Error to get block by hash")
-                        .as_ref(),
-                    inner,
-                    true,
-                )
+                let block = self
+                    .consensus
+                    .data_man
+                    .try_block_by_hash(x, false)
+                    .map_err(db_error)?
+                    .ok_or_else(|| {
+                        RpcError::invalid_params(format!(
+                            "Block {:?} is not in the database",
+                            x
+                        ))
+                    })?;
+                Ok(RpcBlock::new(block.as_ref(), inner, true))
             })
-            .collect())
+            .collect()
     }
 
     fn transaction_by_hash(
@@ -189,8 +211,10 @@ impl RpcImpl {
             return Ok(Some(RpcTransaction::from_signed(&transaction, None)));
         }
 
-        if let Some((transaction, receipt, tx_address)) =
-            self.consensus.get_transaction_info_by_hash(&hash)
+        if let Some((transaction, receipt, tx_address)) = self
+            .consensus
+            .get_transaction_info_by_hash(&hash)
+            .map_err(db_error)?
         {
             Ok(Some(RpcTransaction::from_signed(
                 &transaction,
@@ -212,6 +236,14 @@ impl RpcImpl {
             .and_then(|vec| Ok(vec.into_iter().map(|x| x.into()).collect()))
     }
 
+    fn confirmation_risk_by_hash(
+        &self, hash: RpcH256,
+    ) -> RpcResult<Option<f64>> {
+        let hash: H256 = hash.into();
+        info!("RPC Request: cfx_getConfirmationRiskByHash({:?})", hash);
+        Ok(self.consensus.confirmation_risk_by_hash(hash))
+    }
+
     fn balance(
         &self, address: RpcH160, num: Trailing<EpochNumber>,
     ) -> RpcResult<RpcU256> {
@@ -228,6 +260,67 @@ impl RpcImpl {
             .map_err(|err| RpcError::invalid_params(err))
     }
 
+    fn proof(
+        &self, address: RpcH160, storage_keys: Vec<RpcH256>,
+        num: Trailing<EpochNumber>,
+    ) -> RpcResult<RpcStateProof>
+    {
+        // The deferred state root of the latest mined epoch commits to the
+        // latest executed state.
+        let num = num.unwrap_or(EpochNumber::LatestMined);
+        let address: H160 = address.into();
+        let storage_keys: Vec<H256> =
+            storage_keys.into_iter().map(|key| key.into()).collect();
+        info!(
+            "RPC Request: cfx_getProof address={:?} storage_keys={:?} epoch_num={:?}",
+            address, storage_keys, num
+        );
+
+        let (deferred_state_root, account_proof, storage_proofs) = self
+            .consensus
+            .get_state_proof(
+                address,
+                &storage_keys,
+                self.get_primitive_epoch_number(num),
+            )
+            .map_err(|err| RpcError::invalid_params(err))?;
+
+        let proven_value = |proof: &TrieProof, key: StorageKey| {
+            proof
+                .verify(&deferred_state_root, key.as_ref())
+                .map_err(|err| {
+                    RpcError::invalid_params(format!(
+                        "Invalid proof generated: {}",
+                        err
+                    ))
+                })
+                .map(|maybe_value| maybe_value.map(Bytes::new))
+        };
+
+        let account = proven_value(
+            &account_proof,
+            StorageKey::new_account_key(&address),
+        )?;
+        let mut rpc_storage_proofs = Vec::with_capacity(storage_keys.len());
+        for (key, proof) in storage_keys.iter().zip(storage_proofs.iter()) {
+            rpc_storage_proofs.push(RpcStorageProof {
+                key: (*key).into(),
+                value: proven_value(
+                    proof,
+                    StorageKey::new_storage_key(&address, key),
+                )?,
+                proof: encode_trie_proof(proof),
+            });
+        }
+
+        Ok(RpcStateProof {
+            deferred_state_root: deferred_state_root.into(),
+            account,
+            account_proof: encode_trie_proof(&account_proof),
+            storage_proofs: rpc_storage_proofs,
+        })
+    }
+
     //    fn account(
     //        &self, address: RpcH160, include_txs: bool, num_txs: RpcU64,
     //        epoch_num: Trailing<EpochNumber>,
@@ -507,6 +600,11 @@ impl RpcImpl {
         }
     }
 
+    fn syncing(&self) -> RpcResult<RpcSyncStatus> {
+        info!("RPC Request: cfx_syncing()");
+        Ok(self.sync.sync_status().into())
+    }
+
     fn add_latency(&self, id: NodeId, latency_ms: f64) -> RpcResult<()> {
         match self.sync.add_latency(id, latency_ms) {
             Ok(_) => Ok(()),
@@ -520,10 +618,13 @@ impl RpcImpl {
     fn get_transaction_receipt(
         &self, tx_hash: H256,
     ) -> RpcResult<Option<RpcReceipt>> {
-        let maybe_receipt =
-            self.consensus.get_transaction_info_by_hash(&tx_hash).map(
-                |(tx, receipt, address)| RpcReceipt::new(tx, receipt, address),
-            );
+        let maybe_receipt = self
+            .consensus
+            .get_transaction_info_by_hash(&tx_hash)
+            .map_err(db_error)?
+            .map(|(tx, receipt, address)| {
+                RpcReceipt::new(tx, receipt, address)
+            });
         Ok(maybe_receipt)
     }
 
@@ -582,6 +683,145 @@ impl RpcImpl {
             .map(|x| x.into())
     }
 
+    fn dump_state(
+        &self, num: EpochNumber, start_key: Option<Bytes>,
+        limit: Trailing<RpcU64>,
+    ) -> RpcResult<RpcStateDump>
+    {
+        let start_key = start_key.map(Bytes::into_vec).unwrap_or_default();
+        let limit: Option<RpcU64> = limit.into();
+        let limit = limit
+            .map_or(MAX_DUMP_STATE_ACCOUNTS, |limit| limit.as_usize())
+            .min(MAX_DUMP_STATE_ACCOUNTS);
+        info!(
+            "RPC Request: cfx_dumpState epoch_num={:?} start_key={:?} limit={}",
+            num, start_key, limit
+        );
+
+        let (accounts, next_key) = self
+            .consensus
+            .dump_accounts(
+                self.get_primitive_epoch_number(num),
+                &start_key,
+                limit,
+            )
+            .map_err(|err| RpcError::invalid_params(err))?;
+        Ok(RpcStateDump {
+            accounts: accounts.into_iter().map(|x| x.into()).collect(),
+            next_key: next_key.map(Bytes::new),
+        })
+    }
+
+    fn reserved_nodes(&self) -> RpcResult<Vec<NodeId>> {
+        info!("RPC Request: admin_reservedNodes()");
+        self.network().reserved_nodes().map_err(network_error)
+    }
+
+    fn add_reserved_node(
+        &self, node_id: NodeId, address: SocketAddr,
+    ) -> RpcResult<()> {
+        let node = NodeEntry {
+            id: node_id,
+            endpoint: NodeEndpoint {
+                address,
+                udp_port: address.port(),
+            },
+        };
+        info!("RPC Request: admin_addReservedNode({:?})", node);
+        self.network()
+            .add_reserved_node(node)
+            .map_err(network_error)
+    }
+
+    fn remove_reserved_node(&self, node_id: NodeId) -> RpcResult<bool> {
+        info!("RPC Request: admin_removeReservedNode({:?})", node_id);
+        self.network()
+            .remove_reserved_node(&node_id)
+            .map_err(network_error)
+    }
+
+    fn banned_nodes(&self) -> RpcResult<Vec<BannedNodeInfo>> {
+        info!("RPC Request: admin_bannedNodes()");
+        self.network().banned_nodes().map_err(network_error)
+    }
+
+    fn ban_node(
+        &self, node_id: NodeId, duration_secs: Trailing<u64>,
+    ) -> RpcResult<()> {
+        let duration_secs: Option<u64> = duration_secs.into();
+        let duration = duration_secs.map(Duration::from_secs);
+        info!("RPC Request: admin_banNode({:?}, {:?})", node_id, duration);
+        self.network()
+            .ban_node(&node_id, duration)
+            .map_err(network_error)
+    }
+
+    fn unban_node(&self, node_id: NodeId) -> RpcResult<bool> {
+        info!("RPC Request: admin_unbanNode({:?})", node_id);
+        self.network().unban_node(&node_id).map_err(network_error)
+    }
+
+    fn blocked_ips(&self) -> RpcResult<Vec<String>> {
+        info!("RPC Request: admin_blockedIps()");
+        let filter = self.network().get_ip_filter().map_err(network_error)?;
+        Ok(filter.custom_block.iter().map(|n| n.to_string()).collect())
+    }
+
+    fn block_ip(&self, network: String) -> RpcResult<()> {
+        info!("RPC Request: admin_blockIp({})", network);
+        let network = parse_ip_network(&network)?;
+        self.network().block_ip(network).map_err(network_error)
+    }
+
+    fn unblock_ip(&self, network: String) -> RpcResult<bool> {
+        info!("RPC Request: admin_unblockIp({})", network);
+        let network = parse_ip_network(&network)?;
+        self.network().unblock_ip(&network).map_err(network_error)
+    }
+
+    fn peer_limits(&self) -> RpcResult<BTreeMap<String, usize>> {
+        info!("RPC Request: admin_peerLimits()");
+        let (incoming, outgoing) =
+            self.network().max_peers().map_err(network_error)?;
+
+        let mut ret: BTreeMap<String, usize> = BTreeMap::new();
+        ret.insert("maxIncomingPeers".into(), incoming);
+        ret.insert("maxOutgoingPeers".into(), outgoing);
+
+        Ok(ret)
+    }
+
+    fn set_peer_limits(
+        &self, incoming: Option<usize>, outgoing: Option<usize>,
+    ) -> RpcResult<()> {
+        info!(
+            "RPC Request: admin_setPeerLimits({:?}, {:?})",
+            incoming, outgoing
+        );
+        self.network()
+            .set_max_peers(incoming, outgoing)
+            .map_err(network_error)
+    }
+
+    fn set_peer_upload_rate_limit(
+        &self, node_id: NodeId, rate: Option<usize>,
+    ) -> RpcResult<bool> {
+        info!(
+            "RPC Request: admin_setPeerUploadRateLimit({:?}, {:?})",
+            node_id, rate
+        );
+        self.network()
+            .set_peer_upload_rate_limit(&node_id, rate)
+            .map_err(network_error)
+    }
+
+    fn node_table(&self) -> RpcResult<Vec<NodeInfo>> {
+        info!("RPC Request: admin_nodeTable()");
+        self.network().get_node_table().map_err(network_error)
+    }
+
+    fn network(&self) -> &NetworkService { self.sync.get_network_service() }
+
     fn txpool_status(&self) -> RpcResult<BTreeMap<String, usize>> {
         let (ready_len, pending_len) = self.tx_pool.stats();
 
@@ -644,6 +884,30 @@ impl RpcImpl {
     }
 }
 
+/// Report a database read error to the RPC client instead of the block or
+/// transaction being missing.
+fn db_error(e: DbError) -> RpcError {
+    note_read_error(&e);
+    let mut error = RpcError::internal_error();
+    error.message = format!("Database error: {}", e);
+    error
+}
+
+fn network_error(e: NetworkError) -> RpcError {
+    let mut error = RpcError::internal_error();
+    error.message = format!("Network error: {}", e);
+    error
+}
+
+fn parse_ip_network(network: &str) -> RpcResult<IpNetwork> {
+    network.parse().map_err(|e| {
+        RpcError::invalid_params(format!(
+            "Invalid IP range {}: {:?}",
+            network, e
+        ))
+    })
+}
+
 fn grouped_txs<T, F>(
     txs: Vec<Arc<SignedTransaction>>, converter: F,
 ) -> BTreeMap<String, BTreeMap<usize, Vec<T>>>
@@ -723,12 +987,28 @@ impl Cfx for CfxHandler {
         self.rpc_impl.blocks_by_epoch(num)
     }
 
+    fn syncing(&self) -> RpcResult<RpcSyncStatus> { self.rpc_impl.syncing() }
+
+    fn confirmation_risk_by_hash(
+        &self, hash: RpcH256,
+    ) -> RpcResult<Option<f64>> {
+        self.rpc_impl.confirmation_risk_by_hash(hash)
+    }
+
     fn balance(
         &self, address: RpcH160, num: Trailing<EpochNumber>,
     ) -> RpcResult<RpcU256> {
         self.rpc_impl.balance(address, num)
     }
 
+    fn proof(
+        &self, address: RpcH160, storage_keys: Vec<RpcH256>,
+        num: Trailing<EpochNumber>,
+    ) -> RpcResult<RpcStateProof>
+    {
+        self.rpc_impl.proof(address, storage_keys, num)
+    }
+
     //    fn account(
     //        &self, address: RpcH160, include_txs: bool, num_txs: RpcU64,
     //        epoch_num: Trailing<EpochNumber>,
@@ -878,6 +1158,14 @@ impl DebugRpcImpl {
 }
 
 impl DebugRpc for DebugRpcImpl {
+    fn dump_state(
+        &self, num: EpochNumber, start_key: Option<Bytes>,
+        limit: Trailing<RpcU64>,
+    ) -> RpcResult<RpcStateDump>
+    {
+        self.rpc_impl.dump_state(num, start_key, limit)
+    }
+
     fn txpool_status(&self) -> RpcResult<BTreeMap<String, usize>> {
         self.rpc_impl.txpool_status()
     }
@@ -901,3 +1189,73 @@ impl DebugRpc for DebugRpcImpl {
         self.rpc_impl.txpool_content()
     }
 }
+
+pub struct AdminRpcImpl {
+    rpc_impl: Arc<RpcImpl>,
+}
+
+impl AdminRpcImpl {
+    pub fn new(rpc_impl: Arc<RpcImpl>) -> Self { AdminRpcImpl { rpc_impl } }
+}
+
+impl AdminRpc for AdminRpcImpl {
+    fn reserved_nodes(&self) -> RpcResult<Vec<NodeId>> {
+        self.rpc_impl.reserved_nodes()
+    }
+
+    fn add_reserved_node(
+        &self, node_id: NodeId, address: SocketAddr,
+    ) -> RpcResult<()> {
+        self.rpc_impl.add_reserved_node(node_id, address)
+    }
+
+    fn remove_reserved_node(&self, node_id: NodeId) -> RpcResult<bool> {
+        self.rpc_impl.remove_reserved_node(node_id)
+    }
+
+    fn banned_nodes(&self) -> RpcResult<Vec<BannedNodeInfo>> {
+        self.rpc_impl.banned_nodes()
+    }
+
+    fn ban_node(
+        &self, node_id: NodeId, duration_secs: Trailing<u64>,
+    ) -> RpcResult<()> {
+        self.rpc_impl.ban_node(node_id, duration_secs)
+    }
+
+    fn unban_node(&self, node_id: NodeId) -> RpcResult<bool> {
+        self.rpc_impl.unban_node(node_id)
+    }
+
+    fn blocked_ips(&self) -> RpcResult<Vec<String>> {
+        self.rpc_impl.blocked_ips()
+    }
+
+    fn block_ip(&self, network: String) -> RpcResult<()> {
+        self.rpc_impl.block_ip(network)
+    }
+
+    fn unblock_ip(&self, network: String) -> RpcResult<bool> {
+        self.rpc_impl.unblock_ip(network)
+    }
+
+    fn peer_limits(&self) -> RpcResult<BTreeMap<String, usize>> {
+        self.rpc_impl.peer_limits()
+    }
+
+    fn set_peer_limits(
+        &self, incoming: Option<usize>, outgoing: Option<usize>,
+    ) -> RpcResult<()> {
+        self.rpc_impl.set_peer_limits(incoming, outgoing)
+    }
+
+    fn set_peer_upload_rate_limit(
+        &self, node_id: NodeId, rate: Option<usize>,
+    ) -> RpcResult<bool> {
+        self.rpc_impl.set_peer_upload_rate_limit(node_id, rate)
+    }
+
+    fn node_table(&self) -> RpcResult<Vec<NodeInfo>> {
+        self.rpc_impl.node_table()
+    }
+}
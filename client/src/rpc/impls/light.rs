@@ -0,0 +1,107 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::rpc::{
+    traits::light::LightCfx,
+    types::{
+        EpochNumber, LightReceipt, H160 as RpcH160, H256 as RpcH256,
+        U256 as RpcU256,
+    },
+};
+use cfx_types::{H160, U256};
+use cfxcore::SharedLightService;
+use jsonrpc_core::{Error as RpcError, Result as RpcResult};
+use jsonrpc_macros::Trailing;
+use primitives::Account;
+
+pub struct LightRpcImpl {
+    light: SharedLightService,
+}
+
+impl LightRpcImpl {
+    pub fn new(light: SharedLightService) -> Self { LightRpcImpl { light } }
+
+    fn account(
+        &self, address: H160, num: EpochNumber,
+    ) -> RpcResult<Option<Account>> {
+        self.light
+            .get_account(&address, num.into_primitive())
+            .map_err(|err| RpcError::invalid_params(err))
+    }
+}
+
+impl LightCfx for LightRpcImpl {
+    fn epoch_number(
+        &self, epoch_num: Trailing<EpochNumber>,
+    ) -> RpcResult<RpcU256> {
+        let epoch_num = epoch_num.unwrap_or(EpochNumber::LatestMined);
+        info!("RPC Request: cfx_epochNumber({:?})", epoch_num);
+        let height = match epoch_num {
+            EpochNumber::LatestMined => self.light.best_epoch_number(),
+            _ => self
+                .light
+                .get_height_from_epoch_number(epoch_num.into_primitive())
+                .map_err(|err| RpcError::invalid_params(err))?,
+        };
+        Ok(U256::from(height).into())
+    }
+
+    fn best_block_hash(&self) -> RpcResult<RpcH256> {
+        info!("RPC Request: cfx_getBestBlockHash()");
+        let graph = self.light.graph();
+        Ok(graph
+            .pivot_hash(graph.best_epoch_number())
+            .expect("pivot chain is never empty")
+            .into())
+    }
+
+    fn balance(
+        &self, address: RpcH160, num: Trailing<EpochNumber>,
+    ) -> RpcResult<RpcU256> {
+        let num = num.unwrap_or(EpochNumber::LatestState);
+        let address: H160 = address.into();
+        info!(
+            "RPC Request: cfx_getBalance address={:?} epoch_num={:?}",
+            address, num
+        );
+
+        self.account(address, num).map(|account| {
+            account
+                .map_or(U256::zero(), |account| account.balance)
+                .into()
+        })
+    }
+
+    fn transaction_count(
+        &self, address: RpcH160, num: Trailing<EpochNumber>,
+    ) -> RpcResult<RpcU256> {
+        let num = num.unwrap_or(EpochNumber::LatestState);
+        let address: H160 = address.into();
+        info!(
+            "RPC Request: cfx_getTransactionCount address={:?} epoch_num={:?}",
+            address, num
+        );
+
+        self.account(address, num).map(|account| {
+            account.map_or(U256::zero(), |account| account.nonce).into()
+        })
+    }
+
+    fn epoch_receipts(
+        &self, num: EpochNumber,
+    ) -> RpcResult<Vec<Vec<LightReceipt>>> {
+        info!("RPC Request: cfx_getEpochReceipts epoch_num={:?}", num);
+        self.light
+            .get_epoch_receipts(num.into_primitive())
+            .map(|receipts| {
+                receipts
+                    .into_iter()
+                    .map(|block_receipts| {
+                        block_receipts.into_iter().map(Into::into).collect()
+                    })
+                    .collect()
+            })
+            .map_err(|err| RpcError::invalid_params(err))
+    }
+}
@@ -5,5 +5,9 @@
 //! Conflux rpc interfaces.
 
 pub mod cfx;
+pub mod light;
 
-pub use self::cfx::{Cfx, DebugRpc, TestRpc};
+pub use self::{
+    cfx::{AdminRpc, Cfx, DebugRpc, TestRpc},
+    light::LightCfx,
+};
@@ -3,15 +3,17 @@
 // See http://www.gnu.org/licenses/
 
 use super::super::types::{
-    Block, Bytes, EpochNumber, Receipt as RpcReceipt, Status as RpcStatus,
-    Transaction, Transaction as RpcTransaction, H160 as RpcH160,
-    H256 as RpcH256, U256 as RpcU256, U64 as RpcU64,
+    Block, Bytes, EpochNumber, Receipt as RpcReceipt,
+    StateDump as RpcStateDump, StateProof as RpcStateProof,
+    Status as RpcStatus, SyncStatus as RpcSyncStatus, Transaction,
+    Transaction as RpcTransaction, H160 as RpcH160, H256 as RpcH256,
+    U256 as RpcU256, U64 as RpcU64,
 };
 use cfx_types::H256;
 use cfxcore::PeerInfo;
 use jsonrpc_core::Result as RpcResult;
 use jsonrpc_macros::{build_rpc_trait, Trailing};
-use network::node_table::NodeId;
+use network::{node_table::NodeId, BannedNodeInfo, NodeInfo};
 use std::{collections::BTreeMap, net::SocketAddr};
 
 build_rpc_trait! {
@@ -49,6 +51,11 @@ build_rpc_trait! {
         #[rpc(name = "cfx_getBalance")]
         fn balance(&self, RpcH160, Trailing<EpochNumber>) -> RpcResult<RpcU256>;
 
+        /// Returns the account and storage entries of the given address with
+        /// their Merkle proofs against the deferred state root of the epoch.
+        #[rpc(name = "cfx_getProof")]
+        fn proof(&self, RpcH160, Vec<RpcH256>, Trailing<EpochNumber>) -> RpcResult<RpcStateProof>;
+
 //        /// Returns content of the storage at given address.
 //        #[rpc(name = "cfx_getStorageAt")]
 //        fn storage_at(&self, RpcH160, RpcU256, Trailing<BlockNumber>) -> BoxFuture<RpcH256>;
@@ -120,6 +127,15 @@ build_rpc_trait! {
         #[rpc(name = "cfx_getBlocksByEpoch")]
         fn blocks_by_epoch(&self, EpochNumber) -> RpcResult<Vec<RpcH256>>;
 
+        /// Returns the synchronization progress of the node.
+        #[rpc(name = "cfx_syncing")]
+        fn syncing(&self) -> RpcResult<RpcSyncStatus>;
+
+        /// Returns the risk that the block is removed from the pivot chain
+        /// or moved to another epoch, `null` if it is not known.
+        #[rpc(name = "cfx_getConfirmationRiskByHash")]
+        fn confirmation_risk_by_hash(&self, RpcH256) -> RpcResult<Option<f64>>;
+
 //        #[rpc(name = "cfx_getAccount")]
 //        fn account(&self, RpcH160, bool, RpcU64, Trailing<EpochNumber>) -> RpcResult<Account>;
 
@@ -202,6 +218,11 @@ build_rpc_trait! {
 
 build_rpc_trait! {
     pub trait DebugRpc {
+        /// Returns a page of accounts with their storage in the state of the
+        /// epoch, starting from the given key, and the key of the next page.
+        #[rpc(name = "cfx_dumpState")]
+        fn dump_state(&self, EpochNumber, Option<Bytes>, Trailing<RpcU64>) -> RpcResult<RpcStateDump>;
+
         #[rpc(name = "txpool_status")]
         fn txpool_status(&self) -> RpcResult<BTreeMap<String, usize>>;
 
@@ -212,3 +233,60 @@ build_rpc_trait! {
         fn txpool_content(&self) -> RpcResult<BTreeMap<String, BTreeMap<String, BTreeMap<usize, Vec<RpcTransaction>>>>>;
     }
 }
+
+build_rpc_trait! {
+    /// Peer management interface.
+    pub trait AdminRpc {
+        #[rpc(name = "admin_reservedNodes")]
+        fn reserved_nodes(&self) -> RpcResult<Vec<NodeId>>;
+
+        #[rpc(name = "admin_addReservedNode")]
+        fn add_reserved_node(&self, NodeId, SocketAddr) -> RpcResult<()>;
+
+        /// Forgets and disconnects a reserved node. Returns false if the node
+        /// was not reserved.
+        #[rpc(name = "admin_removeReservedNode")]
+        fn remove_reserved_node(&self, NodeId) -> RpcResult<bool>;
+
+        #[rpc(name = "admin_bannedNodes")]
+        fn banned_nodes(&self) -> RpcResult<Vec<BannedNodeInfo>>;
+
+        /// Bans a node for the specified seconds, or for the configured ban
+        /// duration of misbehaving peers, and disconnects it.
+        #[rpc(name = "admin_banNode")]
+        fn ban_node(&self, NodeId, Trailing<u64>) -> RpcResult<()>;
+
+        /// Returns false if the node was not banned.
+        #[rpc(name = "admin_unbanNode")]
+        fn unban_node(&self, NodeId) -> RpcResult<bool>;
+
+        #[rpc(name = "admin_blockedIps")]
+        fn blocked_ips(&self) -> RpcResult<Vec<String>>;
+
+        /// Blocks an IP range in CIDR notation, e.g. "10.0.0.0/8", and
+        /// disconnects the peers in it.
+        #[rpc(name = "admin_blockIp")]
+        fn block_ip(&self, String) -> RpcResult<()>;
+
+        /// Returns false if the IP range was not blocked.
+        #[rpc(name = "admin_unblockIp")]
+        fn unblock_ip(&self, String) -> RpcResult<bool>;
+
+        #[rpc(name = "admin_peerLimits")]
+        fn peer_limits(&self) -> RpcResult<BTreeMap<String, usize>>;
+
+        /// Changes the maximum numbers of incoming and/or outgoing peers.
+        #[rpc(name = "admin_setPeerLimits")]
+        fn set_peer_limits(&self, Option<usize>, Option<usize>) -> RpcResult<()>;
+
+        /// Limits the upload rate to a connected peer in bytes per second, or
+        /// lifts the limit if it is null. Returns false if the peer is not
+        /// connected.
+        #[rpc(name = "admin_setPeerUploadRateLimit")]
+        fn set_peer_upload_rate_limit(&self, NodeId, Option<usize>) -> RpcResult<bool>;
+
+        /// Returns all nodes in the node table with their reputation levels.
+        #[rpc(name = "admin_nodeTable")]
+        fn node_table(&self) -> RpcResult<Vec<NodeInfo>>;
+    }
+}
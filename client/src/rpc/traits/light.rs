@@ -0,0 +1,37 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use super::super::types::{
+    EpochNumber, LightReceipt, H160 as RpcH160, H256 as RpcH256,
+    U256 as RpcU256,
+};
+use jsonrpc_core::Result as RpcResult;
+use jsonrpc_macros::{build_rpc_trait, Trailing};
+
+build_rpc_trait! {
+    /// Rpc interface of a light node. The state and the receipts are proven
+    /// against the synced headers, so an epoch can only be queried once the
+    /// pivot chain is `DEFERRED_STATE_EPOCH_COUNT` epochs longer.
+    pub trait LightCfx {
+        /// Returns highest epoch number.
+        #[rpc(name = "cfx_epochNumber")]
+        fn epoch_number(&self, Trailing<EpochNumber>) -> RpcResult<RpcU256>;
+
+        /// Returns best block hash.
+        #[rpc(name = "cfx_getBestBlockHash")]
+        fn best_block_hash(&self) -> RpcResult<RpcH256>;
+
+        /// Returns balance of the given account.
+        #[rpc(name = "cfx_getBalance")]
+        fn balance(&self, RpcH160, Trailing<EpochNumber>) -> RpcResult<RpcU256>;
+
+        /// Returns the number of transactions sent from given address at given time (epoch number).
+        #[rpc(name = "cfx_getTransactionCount")]
+        fn transaction_count(&self, RpcH160, Trailing<EpochNumber>) -> RpcResult<RpcU256>;
+
+        /// Returns the receipts of each block of the given epoch.
+        #[rpc(name = "cfx_getEpochReceipts")]
+        fn epoch_receipts(&self, EpochNumber) -> RpcResult<Vec<Vec<LightReceipt>>>;
+    }
+}
@@ -3,27 +3,33 @@
 // See http://www.gnu.org/licenses/
 
 mod account;
+mod account_state;
 mod block;
 mod bytes;
 mod call_request;
 mod epoch_number;
 mod hash;
 mod index;
+mod proof;
 mod receipt;
 mod status;
+mod sync_status;
 mod transaction;
 mod uint;
 
 pub use self::{
     account::Account,
+    account_state::{AccountState, StateDump},
     block::{Block, BlockTransactions},
     bytes::Bytes,
     call_request::CallRequest,
     epoch_number::EpochNumber,
     hash::{H160, H2048, H256, H512, H64},
     index::Index,
-    receipt::Receipt,
+    proof::{encode_trie_proof, StateProof, StorageProof},
+    receipt::{LightReceipt, Receipt},
     status::Status,
+    sync_status::SyncStatus,
     transaction::Transaction,
     uint::{U128, U256, U64},
 };
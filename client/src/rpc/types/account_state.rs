@@ -0,0 +1,44 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::rpc::types::{Bytes, H160, H256, U256};
+use cfxcore::statedb::AccountDump;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountState {
+    pub address: H160,
+    pub balance: U256,
+    pub nonce: U256,
+    pub code_hash: H256,
+    /// The non-zero storage entries, keyed by position
+    pub storage: BTreeMap<H256, H256>,
+}
+
+/// A page of the accounts of a state.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDump {
+    pub accounts: Vec<AccountState>,
+    /// The start key of the next page, or null if this is the last one.
+    pub next_key: Option<Bytes>,
+}
+
+impl From<AccountDump> for AccountState {
+    fn from(dump: AccountDump) -> Self {
+        AccountState {
+            address: dump.account.address.into(),
+            balance: dump.account.balance.into(),
+            nonce: dump.account.nonce.into(),
+            code_hash: dump.account.code_hash.into(),
+            storage: dump
+                .storage
+                .into_iter()
+                .map(|(position, value)| (position.into(), value.into()))
+                .collect(),
+        }
+    }
+}
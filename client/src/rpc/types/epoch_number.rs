@@ -2,6 +2,7 @@
 // Conflux is free software and distributed under GNU General Public License.
 // See http://www.gnu.org/licenses/
 
+use primitives::EpochNumber as PrimitiveEpochNumber;
 use serde::{
     de::{Error, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
@@ -17,10 +18,29 @@ pub enum EpochNumber {
     LatestMined,
     /// Latest block with state.
     LatestState,
+    /// Latest block with state which is considered confirmed.
+    LatestConfirmed,
+    /// Latest block with state whose confirmation risk is negligible.
+    LatestStable,
     /// Earliest epoch (genesis)
     Earliest,
 }
 
+impl EpochNumber {
+    pub fn into_primitive(self) -> PrimitiveEpochNumber {
+        match self {
+            EpochNumber::Earliest => PrimitiveEpochNumber::Earliest,
+            EpochNumber::LatestMined => PrimitiveEpochNumber::LatestMined,
+            EpochNumber::LatestState => PrimitiveEpochNumber::LatestState,
+            EpochNumber::LatestConfirmed => {
+                PrimitiveEpochNumber::LatestConfirmed
+            }
+            EpochNumber::LatestStable => PrimitiveEpochNumber::LatestStable,
+            EpochNumber::Num(num) => PrimitiveEpochNumber::Number(num.into()),
+        }
+    }
+}
+
 //impl Default for EpochNumber {
 //    fn default() -> Self { EpochNumber::Latest }
 //}
@@ -45,6 +65,12 @@ impl Serialize for EpochNumber {
             EpochNumber::LatestState => {
                 serializer.serialize_str("latest_state")
             }
+            EpochNumber::LatestConfirmed => {
+                serializer.serialize_str("latest_confirmed")
+            }
+            EpochNumber::LatestStable => {
+                serializer.serialize_str("latest_stable")
+            }
             EpochNumber::Earliest => serializer.serialize_str("earliest"),
             /*            EpochNumber::Pending =>
              * serializer.serialize_str("pending"), */
@@ -69,6 +95,8 @@ impl<'a> Visitor<'a> for EpochNumberVisitor {
         match value {
             "latest_mined" => Ok(EpochNumber::LatestMined),
             "latest_state" => Ok(EpochNumber::LatestState),
+            "latest_confirmed" => Ok(EpochNumber::LatestConfirmed),
+            "latest_stable" => Ok(EpochNumber::LatestStable),
             "earliest" => Ok(EpochNumber::Earliest),
             _ if value.starts_with("0x") => {
                 u64::from_str_radix(&value[2..], 16)
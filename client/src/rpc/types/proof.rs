@@ -0,0 +1,39 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::rpc::types::{Bytes, H256};
+use primitives::TrieProof;
+use serde_derive::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateProof {
+    /// The `deferred_state_root` of the pivot block of the requested epoch,
+    /// against which all the proofs are verified
+    pub deferred_state_root: H256,
+    /// The RLP encoded account, or null if the account doesn't exist
+    pub account: Option<Bytes>,
+    /// The RLP encoded trie nodes from the state root to the account
+    pub account_proof: Vec<Bytes>,
+    pub storage_proofs: Vec<StorageProof>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageProof {
+    pub key: H256,
+    /// The RLP encoded storage value, or null if it doesn't exist
+    pub value: Option<Bytes>,
+    /// The RLP encoded trie nodes from the state root to the storage entry
+    pub proof: Vec<Bytes>,
+}
+
+/// Encode each trie node of the proof separately.
+pub fn encode_trie_proof(proof: &TrieProof) -> Vec<Bytes> {
+    proof
+        .nodes
+        .iter()
+        .map(|node| Bytes::new(rlp::encode(node).to_vec()))
+        .collect()
+}
@@ -2,7 +2,7 @@
 // Conflux is free software and distributed under GNU General Public License.
 // See http://www.gnu.org/licenses/
 
-use crate::rpc::types::{H256, U256};
+use crate::rpc::types::{H2048, H256, U256};
 use cfx_types::Address;
 use cfxcore::{executive::contract_address, vm::CreateContractAddress};
 use primitives::{
@@ -51,3 +51,26 @@ impl Receipt {
         }
     }
 }
+
+/// A receipt as committed to by the receipts root of an epoch, without the
+/// transaction it belongs to, which a light node doesn't know.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LightReceipt {
+    /// The total gas used in the block following execution of the transaction.
+    pub gas_used: U256,
+    /// Transaction outcome.
+    pub outcome_status: u8,
+    /// The OR-wide combination of all logs' blooms for this transaction.
+    pub logs_bloom: H2048,
+}
+
+impl From<PrimitiveReceipt> for LightReceipt {
+    fn from(receipt: PrimitiveReceipt) -> Self {
+        LightReceipt {
+            gas_used: receipt.gas_used.into(),
+            outcome_status: receipt.outcome_status,
+            logs_bloom: receipt.log_bloom.into(),
+        }
+    }
+}
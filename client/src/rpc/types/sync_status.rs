@@ -0,0 +1,60 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use cfxcore::sync::SyncStatus as PrimitiveSyncStatus;
+use serde_derive::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    /// Whether the node is still in catch-up mode
+    pub catch_up_mode: bool,
+    /// The highest epoch number announced by connected peers
+    pub best_peer_epoch: Option<u64>,
+    /// The epoch number of the local pivot chain
+    pub local_epoch: u64,
+    /// The number of epochs the local node is behind the best peer
+    pub epoch_lag: u64,
+    /// The number of connected peers that finished handshaking
+    pub peer_count: usize,
+    /// The number of peers that are still handshaking
+    pub handshaking_peer_count: usize,
+    /// The number of block headers requested but not received
+    pub headers_in_flight: usize,
+    /// The number of blocks requested but not received
+    pub blocks_in_flight: usize,
+    /// The number of epochs requested but not received
+    pub epochs_in_flight: usize,
+    /// The number of transactions requested but not received
+    pub transactions_in_flight: usize,
+    /// The number of requests delayed before being resent
+    pub waiting_requests: usize,
+    /// The number of requests given up since the node started
+    pub abandoned_requests: usize,
+    /// The number of epochs waiting to be executed
+    pub execution_queue_size: usize,
+    /// The epoch number most recently executed
+    pub last_executed_epoch: Option<u64>,
+}
+
+impl From<PrimitiveSyncStatus> for SyncStatus {
+    fn from(status: PrimitiveSyncStatus) -> Self {
+        SyncStatus {
+            epoch_lag: status.epoch_lag(),
+            catch_up_mode: status.catch_up_mode,
+            best_peer_epoch: status.best_peer_epoch,
+            local_epoch: status.local_epoch,
+            peer_count: status.peer_count,
+            handshaking_peer_count: status.handshaking_peer_count,
+            headers_in_flight: status.headers_in_flight,
+            blocks_in_flight: status.blocks_in_flight,
+            epochs_in_flight: status.epochs_in_flight,
+            transactions_in_flight: status.transactions_in_flight,
+            waiting_requests: status.waiting_requests,
+            abandoned_requests: status.abandoned_requests,
+            execution_queue_size: status.execution_queue_size,
+            last_executed_epoch: status.last_executed_epoch,
+        }
+    }
+}
@@ -142,6 +142,7 @@ fn initialize_consensus_graph_for_test(
             },
             bench_mode: true, /* Set bench_mode to true so that we skip
                                * execution */
+            builtin_specs: Vec::new(),
         },
         genesis_block,
         storage_manager.clone(),
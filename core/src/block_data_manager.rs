@@ -4,7 +4,7 @@
 
 use crate::{
     cache_manager::{CacheId, CacheManager},
-    db::{COL_BLOCKS, COL_BLOCK_RECEIPTS, COL_TX_ADDRESS},
+    db::{DbError, DbResult, COL_BLOCKS, COL_BLOCK_RECEIPTS, COL_TX_ADDRESS},
     ext_db::SystemDB,
     pow::ProofOfWorkConfig,
     storage::StorageManager,
@@ -13,6 +13,7 @@ use crate::{
 };
 use cfx_types::{Bloom, H256, U256};
 use heapsize::HeapSizeOf;
+use metrics::Counter;
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
 use primitives::{
     receipt::{Receipt, TRANSACTION_OUTCOME_SUCCESS},
@@ -25,7 +26,27 @@ use std::{
     sync::Arc,
 };
 
-const BLOCK_STATUS_SUFFIX_BYTE: u8 = 1;
+lazy_static! {
+    static ref DB_READ_ERROR_COUNTER: Counter =
+        Counter::register("db_read_errors");
+}
+
+pub(crate) const BLOCK_STATUS_SUFFIX_BYTE: u8 = 1;
+
+/// Log and count a failed database read.
+pub fn note_read_error(error: &DbError) {
+    error!("{}", error);
+    DB_READ_ERROR_COUNTER.inc();
+}
+
+/// Treat a value which cannot be read as missing, for the callers which
+/// cannot report the error.
+pub fn missing_on_error<T>(result: DbResult<Option<T>>) -> Option<T> {
+    result.unwrap_or_else(|e| {
+        note_read_error(&e);
+        None
+    })
+}
 
 pub struct BlockDataManager {
     pub block_headers: RwLock<HashMap<H256, Arc<BlockHeader>>>,
@@ -87,13 +108,17 @@ impl BlockDataManager {
         Some(block.transactions[address.index].clone())
     }
 
-    pub fn block_by_hash_from_db(&self, hash: &H256) -> Option<Block> {
+    pub fn block_by_hash_from_db(
+        &self, hash: &H256,
+    ) -> DbResult<Option<Block>> {
         debug!("Loading block {} from db", hash);
-        let block = self.db.key_value().get(COL_BLOCKS, hash)
-            .expect("Low level database error when fetching block. Some issue with disk?")?;
+        let block = match self.db.key_value().get(COL_BLOCKS, hash)? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
         let rlp = Rlp::new(&block);
         let mut block = Block::decode_with_tx_public(&rlp)
-            .expect("Wrong block rlp format!");
+            .map_err(|e| DbError::corrupt(COL_BLOCKS, hash, e))?;
         debug!("Finish constructing block {} from db", hash);
         //let mut block = rlp.as_val::<Block>().expect("Wrong block rlp
         // format!"); SynchronizationProtocolHandler::recover_public(
@@ -104,29 +129,39 @@ impl BlockDataManager {
         //)
         //.expect("Failed to recover public!");
         VerificationConfig::compute_header_pow_quality(&mut block.block_header);
-        Some(block)
+        Ok(Some(block))
     }
 
+    /// Like `try_block_by_hash`, but a block which cannot be read is treated
+    /// as missing.
     pub fn block_by_hash(
         &self, hash: &H256, update_cache: bool,
     ) -> Option<Arc<Block>> {
+        missing_on_error(self.try_block_by_hash(hash, update_cache))
+    }
+
+    pub fn try_block_by_hash(
+        &self, hash: &H256, update_cache: bool,
+    ) -> DbResult<Option<Arc<Block>>> {
         // Check cache first
         {
             let read = self.blocks.read();
             if let Some(v) = read.get(hash) {
-                return Some(v.clone());
+                return Ok(Some(v.clone()));
             }
         }
 
-        let block = self.block_by_hash_from_db(hash)?;
-        let block = Arc::new(block);
+        let block = match self.block_by_hash_from_db(hash)? {
+            Some(block) => Arc::new(block),
+            None => return Ok(None),
+        };
 
         if update_cache {
             let mut write = self.blocks.write();
             write.insert(*hash, block.clone());
             self.cache_man.lock().note_used(CacheId::Block(*hash));
         }
-        Some(block)
+        Ok(Some(block))
     }
 
     pub fn blocks_by_hash_list(
@@ -181,19 +216,17 @@ impl BlockDataManager {
 
     /// Get block status from db. Now the status means if the block is partial
     /// invalid
-    pub fn block_status_from_db(&self, block_hash: &H256) -> Option<bool> {
+    pub fn block_status_from_db(
+        &self, block_hash: &H256,
+    ) -> DbResult<Option<bool>> {
         let mut key = Vec::with_capacity(block_hash.len() + 1);
         key.extend_from_slice(&block_hash);
         key.push(BLOCK_STATUS_SUFFIX_BYTE);
-        self.db
-            .key_value()
-            .get(COL_BLOCKS, &key)
-            .expect("crash for db failure")
-            .map(|encoded| {
-                // TODO May encode more data in the future, and should use an
-                // better structure for encoding and decoding
-                encoded[0] == 1
-            })
+        Ok(self.db.key_value().get(COL_BLOCKS, &key)?.map(|encoded| {
+            // TODO May encode more data in the future, and should use an
+            // better structure for encoding and decoding
+            encoded[0] == 1
+        }))
     }
 
     pub fn remove_block_from_kv(&self, hash: &H256) {
@@ -233,20 +266,36 @@ impl BlockDataManager {
 
     pub fn block_results_by_hash_from_db(
         &self, hash: &H256,
-    ) -> Option<(H256, BlockExecutedResult)> {
+    ) -> DbResult<Option<(H256, BlockExecutedResult)>> {
         trace!("Read receipts from db {}", hash);
-        let block_receipts = self.db.key_value().get(COL_BLOCK_RECEIPTS, hash)
-            .expect("Low level database error when fetching block receipts. Some issue with disk?")?;
+        let block_receipts =
+            match self.db.key_value().get(COL_BLOCK_RECEIPTS, hash)? {
+                Some(block_receipts) => block_receipts,
+                None => return Ok(None),
+            };
         let rlp = Rlp::new(&block_receipts);
-        let epoch: H256 = rlp.val_at(0).expect("encoded");
-        let receipts: Vec<Receipt> = rlp.list_at(1).expect("encoded");
-        let bloom: Bloom = rlp.val_at(2).expect("encoded");
-        Some((
+        let corrupt = |e| DbError::corrupt(COL_BLOCK_RECEIPTS, hash, e);
+        let epoch: H256 = rlp.val_at(0).map_err(corrupt)?;
+        let receipts: Vec<Receipt> = rlp.list_at(1).map_err(corrupt)?;
+        let bloom: Bloom = rlp.val_at(2).map_err(corrupt)?;
+        Ok(Some((
             epoch,
             BlockExecutedResult {
                 receipts: Arc::new(receipts),
                 bloom,
             },
+        )))
+    }
+
+    /// Like `try_block_results_by_hash_with_epoch`, but receipts which cannot
+    /// be read are treated as missing.
+    pub fn block_results_by_hash_with_epoch(
+        &self, hash: &H256, assumed_epoch: &H256, update_cache: bool,
+    ) -> Option<BlockExecutedResult> {
+        missing_on_error(self.try_block_results_by_hash_with_epoch(
+            hash,
+            assumed_epoch,
+            update_cache,
         ))
     }
 
@@ -254,9 +303,9 @@ impl BlockDataManager {
     /// or has been overwritten by another new pivot chain in db
     ///
     /// This function will require lock of block_receipts
-    pub fn block_results_by_hash_with_epoch(
+    pub fn try_block_results_by_hash_with_epoch(
         &self, hash: &H256, assumed_epoch: &H256, update_cache: bool,
-    ) -> Option<BlockExecutedResult> {
+    ) -> DbResult<Option<BlockExecutedResult>> {
         let maybe_receipts =
             self.block_receipts
                 .read()
@@ -265,15 +314,19 @@ impl BlockDataManager {
                     receipt_info.get_receipts_at_epoch(assumed_epoch)
                 });
         if maybe_receipts.is_some() {
-            return maybe_receipts;
+            return Ok(maybe_receipts);
         }
-        let (epoch, receipts) = self.block_results_by_hash_from_db(hash)?;
+        let (epoch, receipts) =
+            match self.block_results_by_hash_from_db(hash)? {
+                Some(results) => results,
+                None => return Ok(None),
+            };
         if epoch != *assumed_epoch {
             debug!(
                 "epoch from db {} does not match assumed {}",
                 epoch, assumed_epoch
             );
-            return None;
+            return Ok(None);
         }
         if update_cache {
             self.block_receipts
@@ -285,7 +338,7 @@ impl BlockDataManager {
                 .lock()
                 .note_used(CacheId::BlockReceipts(*hash));
         }
-        Some(receipts)
+        Ok(Some(receipts))
     }
 
     pub fn insert_block_results_to_kv(
@@ -327,23 +380,39 @@ impl BlockDataManager {
 
     pub fn transaction_address_by_hash_from_db(
         &self, hash: &H256,
-    ) -> Option<TransactionAddress> {
-        let tx_index_encoded = self.db.key_value().get(COL_TX_ADDRESS, hash).expect("Low level database error when fetching transaction index. Some issue with disk?")?;
+    ) -> DbResult<Option<TransactionAddress>> {
+        let tx_index_encoded =
+            match self.db.key_value().get(COL_TX_ADDRESS, hash)? {
+                Some(tx_index_encoded) => tx_index_encoded,
+                None => return Ok(None),
+            };
         let rlp = Rlp::new(&tx_index_encoded);
-        let tx_index: TransactionAddress =
-            rlp.as_val().expect("Wrong tx index rlp format!");
-        Some(tx_index)
+        let tx_index: TransactionAddress = rlp
+            .as_val()
+            .map_err(|e| DbError::corrupt(COL_TX_ADDRESS, hash, e))?;
+        Ok(Some(tx_index))
     }
 
+    /// Like `try_transaction_address_by_hash`, but an address which cannot be
+    /// read is treated as missing.
     pub fn transaction_address_by_hash(
         &self, hash: &H256, update_cache: bool,
     ) -> Option<TransactionAddress> {
+        missing_on_error(
+            self.try_transaction_address_by_hash(hash, update_cache),
+        )
+    }
+
+    pub fn try_transaction_address_by_hash(
+        &self, hash: &H256, update_cache: bool,
+    ) -> DbResult<Option<TransactionAddress>> {
         let transaction_addresses =
             self.transaction_addresses.upgradable_read();
         if let Some(index) = transaction_addresses.get(hash) {
-            return Some(index.clone());
+            return Ok(Some(index.clone()));
         }
-        self.transaction_address_by_hash_from_db(hash)
+        Ok(self
+            .transaction_address_by_hash_from_db(hash)?
             .map(|address| {
                 if update_cache {
                     RwLockUpgradableReadGuard::upgrade(transaction_addresses)
@@ -353,7 +422,7 @@ impl BlockDataManager {
                         .note_used(CacheId::TransactionAddress(*hash));
                 }
                 address
-            })
+            }))
     }
 
     pub fn insert_transaction_address_to_kv(
@@ -94,6 +94,37 @@ impl Pricer for AltBn128PairingPricer {
     }
 }
 
+/// A pricing model for the blake2 compression function, which charges a
+/// fixed amount of gas per round.
+struct Blake2FPricer {
+    gas_per_round: u64,
+}
+
+impl Pricer for Blake2FPricer {
+    fn cost(&self, input: &[u8]) -> U256 {
+        if input.len() != BLAKE2_F_ARG_LEN {
+            // The execution fails on malformed input anyway.
+            return U256::zero();
+        }
+        let rounds = BigEndian::read_u32(&input[..4]);
+        U256::from(self.gas_per_round) * U256::from(rounds)
+    }
+}
+
+/// A bls12-381 pairing pricing model. This computes a price using a base
+/// cost and a cost per (G1, G2) pair.
+struct Bls12PairingPricer {
+    base: usize,
+    pair: usize,
+}
+
+impl Pricer for Bls12PairingPricer {
+    fn cost(&self, input: &[u8]) -> U256 {
+        U256::from(self.base)
+            + U256::from(self.pair) * U256::from(input.len() / BLS12_PAIR_LEN)
+    }
+}
+
 impl Pricer for ModexpPricer {
     fn cost(&self, input: &[u8]) -> U256 {
         let mut reader = input.chain(io::repeat(0));
@@ -185,6 +216,24 @@ pub struct Builtin {
 }
 
 impl Builtin {
+    pub fn new(
+        pricer: Box<Pricer>, native: Box<Impl>, activate_at: u64,
+    ) -> Self {
+        Builtin {
+            pricer,
+            native,
+            activate_at,
+        }
+    }
+
+    /// Create the built-in contract `name` with its default pricing, active
+    /// from the given cardinal number, or `None` if there is no built-in of
+    /// that name.
+    pub fn from_name(name: &str, activate_at: u64) -> Option<Self> {
+        builtin_parts(name)
+            .map(|(pricer, native)| Builtin::new(pricer, native, activate_at))
+    }
+
     /// Simple forwarder for cost.
     pub fn cost(&self, input: &[u8]) -> U256 { self.pricer.cost(input) }
 
@@ -199,20 +248,109 @@ impl Builtin {
     pub fn is_active(&self, at: u64) -> bool { at >= self.activate_at }
 }
 
+/// Default pricing and implementation of the built-in contract `name`, or
+/// `None` if there is no built-in of that name. This is the only list of the
+/// built-ins selectable by name.
+fn builtin_parts(name: &str) -> Option<(Box<Pricer>, Box<Impl>)> {
+    let (pricer, native): (Box<Pricer>, Box<Impl>) = match name {
+        "identity" => {
+            (Box::new(Linear { base: 15, word: 3 }), Box::new(Identity))
+        }
+        "ecrecover" => (
+            Box::new(Linear {
+                base: 3000,
+                word: 0,
+            }),
+            Box::new(EcRecover),
+        ),
+        "sha256" => (Box::new(Linear { base: 60, word: 12 }), Box::new(Sha256)),
+        "ripemd160" => (
+            Box::new(Linear {
+                base: 600,
+                word: 120,
+            }),
+            Box::new(Ripemd160),
+        ),
+        "modexp" => {
+            (Box::new(ModexpPricer { divisor: 20 }), Box::new(ModexpImpl))
+        }
+        "alt_bn128_add" => (
+            Box::new(Linear { base: 500, word: 0 }),
+            Box::new(Bn128AddImpl),
+        ),
+        "alt_bn128_mul" => (
+            Box::new(Linear {
+                base: 40000,
+                word: 0,
+            }),
+            Box::new(Bn128MulImpl),
+        ),
+        "alt_bn128_pairing" => (
+            Box::new(AltBn128PairingPricer {
+                base: 100000,
+                pair: 80000,
+            }),
+            Box::new(Bn128PairingImpl),
+        ),
+        "blake2_f" => (
+            Box::new(Blake2FPricer { gas_per_round: 1 }),
+            Box::new(Blake2F),
+        ),
+        // Priced at 11 times ecrecover, the measured ratio of their running
+        // times.
+        "secp256r1_verify" => (
+            Box::new(Linear {
+                base: 33000,
+                word: 0,
+            }),
+            Box::new(P256Verify),
+        ),
+        "bls12_381_g1_add" => (
+            Box::new(Linear { base: 500, word: 0 }),
+            Box::new(Bls12G1AddImpl),
+        ),
+        "bls12_381_g1_mul" => (
+            Box::new(Linear {
+                base: 12000,
+                word: 0,
+            }),
+            Box::new(Bls12G1MulImpl),
+        ),
+        "bls12_381_g2_add" => (
+            Box::new(Linear { base: 800, word: 0 }),
+            Box::new(Bls12G2AddImpl),
+        ),
+        "bls12_381_g2_mul" => (
+            Box::new(Linear {
+                base: 45000,
+                word: 0,
+            }),
+            Box::new(Bls12G2MulImpl),
+        ),
+        "bls12_381_pairing" => (
+            Box::new(Bls12PairingPricer {
+                base: 65000,
+                pair: 43000,
+            }),
+            Box::new(Bls12PairingImpl),
+        ),
+        _ => return None,
+    };
+    Some((pricer, native))
+}
+
 /// Built-in instruction factory.
 #[allow(dead_code)]
-pub fn builtin_factory(name: &str) -> Box<Impl> {
-    match name {
-        "identity" => Box::new(Identity) as Box<Impl>,
-        "ecrecover" => Box::new(EcRecover) as Box<Impl>,
-        "sha256" => Box::new(Sha256) as Box<Impl>,
-        "ripemd160" => Box::new(Ripemd160) as Box<Impl>,
-        "modexp" => Box::new(ModexpImpl) as Box<Impl>,
-        "alt_bn128_add" => Box::new(Bn128AddImpl) as Box<Impl>,
-        "alt_bn128_mul" => Box::new(Bn128MulImpl) as Box<Impl>,
-        "alt_bn128_pairing" => Box::new(Bn128PairingImpl) as Box<Impl>,
-        _ => panic!("invalid builtin name: {}", name),
-    }
+pub fn builtin_factory(name: &str) -> Option<Box<Impl>> {
+    builtin_parts(name).map(|(_, native)| native)
+}
+
+/// Whether `name` is a built-in contract known to `builtin_factory`.
+pub fn is_builtin_name(name: &str) -> bool { builtin_parts(name).is_some() }
+
+/// Default pricing of the built-in contract `name`.
+pub fn pricer_factory(name: &str) -> Option<Box<Pricer>> {
+    builtin_parts(name).map(|(pricer, _)| pricer)
 }
 
 // Builtins:
@@ -222,6 +360,9 @@ pub fn builtin_factory(name: &str) -> Box<Impl> {
 // - sha256
 // - ripemd160
 // - modexp (EIP198)
+// - blake2 compression function F (EIP152)
+// - secp256r1 signature verification
+// - bls12-381 curve operations (EIP2537)
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -255,6 +396,34 @@ struct Bn128MulImpl;
 #[allow(dead_code)]
 struct Bn128PairingImpl;
 
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Blake2F;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct P256Verify;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Bls12G1AddImpl;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Bls12G1MulImpl;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Bls12G2AddImpl;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Bls12G2MulImpl;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Bls12PairingImpl;
+
 impl Impl for Identity {
     fn execute(
         &self, input: &[u8], output: &mut BytesRef,
@@ -641,10 +810,591 @@ impl Bn128PairingImpl {
     }
 }
 
+const BLAKE2_F_ARG_LEN: usize = 213;
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// The blake2b mixing function G.
+fn blake2b_g(
+    v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64,
+) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// The blake2b compression function F with a configurable number of rounds,
+/// as specified in RFC 7693 and EIP-152.
+fn blake2b_compress(
+    h: &mut [u64; 8], m: &[u64; 16], t: [u64; 2], f: bool, rounds: u32,
+) {
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&BLAKE2B_IV);
+    v[12] ^= t[0];
+    v[13] ^= t[1];
+    if f {
+        v[14] = !v[14];
+    }
+
+    for i in 0..rounds as usize {
+        let s = &BLAKE2B_SIGMA[i % 10];
+        blake2b_g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        blake2b_g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        blake2b_g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        blake2b_g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        blake2b_g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        blake2b_g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        blake2b_g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        blake2b_g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+impl Impl for Blake2F {
+    /// Can fail if:
+    ///     - input length is not exactly 213 bytes
+    ///     - the final block indicator flag is neither 0 nor 1
+    fn execute(
+        &self, input: &[u8], output: &mut BytesRef,
+    ) -> Result<(), Error> {
+        use byteorder::LittleEndian;
+
+        if input.len() != BLAKE2_F_ARG_LEN {
+            return Err("Invalid input length, must be 213 bytes".into());
+        }
+
+        let rounds = BigEndian::read_u32(&input[0..4]);
+
+        let mut h = [0u64; 8];
+        LittleEndian::read_u64_into(&input[4..68], &mut h);
+        let mut m = [0u64; 16];
+        LittleEndian::read_u64_into(&input[68..196], &mut m);
+        let mut t = [0u64; 2];
+        LittleEndian::read_u64_into(&input[196..212], &mut t);
+        let f = match input[212] {
+            0 => false,
+            1 => true,
+            _ => return Err("Invalid final block indicator flag".into()),
+        };
+
+        blake2b_compress(&mut h, &m, t, f, rounds);
+
+        let mut write_buf = [0u8; 64];
+        LittleEndian::write_u64_into(&h, &mut write_buf);
+        output.write(0, &write_buf);
+        Ok(())
+    }
+}
+
+/// ECDSA verification over the NIST P-256 (secp256r1) curve
+/// y^2 = x^3 - 3x + b. Points are kept in Jacobian coordinates so that only
+/// one field inversion is needed per verification, and multiplications modulo
+/// the field prime use its fast reduction. All the data is public, so nothing
+/// needs to run in constant time.
+mod p256 {
+    use cfx_types::{U256, U512};
+    use num::BigUint;
+
+    /// The field prime p = 2^256 - 2^224 + 2^192 + 2^96 - 1.
+    const P: U256 = U256([
+        0xffff_ffff_ffff_ffff,
+        0x0000_0000_ffff_ffff,
+        0x0000_0000_0000_0000,
+        0xffff_ffff_0000_0001,
+    ]);
+    const B: U256 = U256([
+        0x3bce_3c3e_27d2_604b,
+        0x651d_06b0_cc53_b0f6,
+        0xb3eb_bd55_7698_86bc,
+        0x5ac6_35d8_aa3a_93e7,
+    ]);
+    /// The order of the base point.
+    const N: U256 = U256([
+        0xf3b9_cac2_fc63_2551,
+        0xbce6_faad_a717_9e84,
+        0xffff_ffff_ffff_ffff,
+        0xffff_ffff_0000_0000,
+    ]);
+    const GX: U256 = U256([
+        0xf4a1_3945_d898_c296,
+        0x7703_7d81_2deb_33a0,
+        0xf8bc_e6e5_63a4_40f2,
+        0x6b17_d1f2_e12c_4247,
+    ]);
+    const GY: U256 = U256([
+        0xcbb6_4068_37bf_51f5,
+        0x2bce_3357_6b31_5ece,
+        0x8ee7_eb4a_7c0f_9e16,
+        0x4fe3_42e2_fe1a_7f9b,
+    ]);
+
+    /// (X, Y, Z) stands for the affine point (X / Z^2, Y / Z^3), and Z is zero
+    /// for the point at infinity.
+    type JacobianPoint = (U256, U256, U256);
+
+    const INFINITY: JacobianPoint =
+        (U256([1, 0, 0, 0]), U256([1, 0, 0, 0]), U256([0, 0, 0, 0]));
+
+    /// Reduce `carry * 2^256 + x` modulo p.
+    fn reduce(mut x: U256, mut carry: i64) -> U256 {
+        while carry < 0 {
+            let (sum, overflow) = x.overflowing_add(P);
+            x = sum;
+            carry += overflow as i64;
+        }
+        while carry > 0 || x >= P {
+            let (diff, borrow) = x.overflowing_sub(P);
+            x = diff;
+            carry -= borrow as i64;
+        }
+        x
+    }
+
+    fn add(x: U256, y: U256) -> U256 {
+        let (sum, overflow) = x.overflowing_add(y);
+        reduce(sum, overflow as i64)
+    }
+
+    fn sub(x: U256, y: U256) -> U256 {
+        let (diff, borrow) = x.overflowing_sub(y);
+        reduce(diff, -(borrow as i64))
+    }
+
+    /// Multiplication modulo p, with the fast reduction of FIPS 186-4 D.2.3.
+    pub(super) fn mul(x: U256, y: U256) -> U256 {
+        let U512(limbs) = x.full_mul(y);
+        let mut c = [0i64; 16];
+        for i in 0..8 {
+            c[2 * i] = (limbs[i] & 0xffff_ffff) as i64;
+            c[2 * i + 1] = (limbs[i] >> 32) as i64;
+        }
+        // The 32-bit words of s1 + 2 s2 + 2 s3 + s4 + s5 - d1 - d2 - d3 - d4,
+        // least significant first.
+        let words = [
+            c[0] + c[8] + c[9] - c[11] - c[12] - c[13] - c[14],
+            c[1] + c[9] + c[10] - c[12] - c[13] - c[14] - c[15],
+            c[2] + c[10] + c[11] - c[13] - c[14] - c[15],
+            c[3] + 2 * c[11] + 2 * c[12] + c[13] - c[15] - c[8] - c[9],
+            c[4] + 2 * c[12] + 2 * c[13] + c[14] - c[9] - c[10],
+            c[5] + 2 * c[13] + 2 * c[14] + c[15] - c[10] - c[11],
+            c[6] + 3 * c[14] + 2 * c[15] + c[13] - c[8] - c[9],
+            c[7] + 3 * c[15] + c[8] - c[10] - c[11] - c[12] - c[13],
+        ];
+        let mut result = [0u64; 4];
+        let mut carry = 0i64;
+        for (i, word) in words.iter().enumerate() {
+            let v = word + carry;
+            result[i / 2] |= ((v & 0xffff_ffff) as u64) << (32 * (i % 2));
+            carry = v >> 32;
+        }
+        reduce(U256(result), carry)
+    }
+
+    /// The inverse of a nonzero x modulo p, as x^(p - 2).
+    fn inv(x: U256) -> U256 {
+        let e = P - U256::from(2);
+        let mut acc = U256::one();
+        for i in (0..256).rev() {
+            acc = mul(acc, acc);
+            if e.bit(i) {
+                acc = mul(acc, x);
+            }
+        }
+        acc
+    }
+
+    fn is_on_curve(x: U256, y: U256) -> bool {
+        if x >= P || y >= P {
+            return false;
+        }
+        let x3 = mul(mul(x, x), x);
+        mul(y, y) == add(sub(x3, mul(U256::from(3), x)), B)
+    }
+
+    fn double(pt: &JacobianPoint) -> JacobianPoint {
+        let &(x, y, z) = pt;
+        if z.is_zero() || y.is_zero() {
+            return INFINITY;
+        }
+        // dbl-2001-b from the Explicit-Formulas Database, for a = -3.
+        let delta = mul(z, z);
+        let gamma = mul(y, y);
+        let beta = mul(x, gamma);
+        let t = mul(sub(x, delta), add(x, delta));
+        let alpha = add(add(t, t), t);
+        let beta2 = add(beta, beta);
+        let beta4 = add(beta2, beta2);
+        let x3 = sub(mul(alpha, alpha), add(beta4, beta4));
+        let y_plus_z = add(y, z);
+        let z3 = sub(sub(mul(y_plus_z, y_plus_z), gamma), delta);
+        let gamma2 = mul(gamma, gamma);
+        let gamma4 = add(add(gamma2, gamma2), add(gamma2, gamma2));
+        let y3 = sub(mul(alpha, sub(beta4, x3)), add(gamma4, gamma4));
+        (x3, y3, z3)
+    }
+
+    fn add_points(p1: &JacobianPoint, p2: &JacobianPoint) -> JacobianPoint {
+        let &(x1, y1, z1) = p1;
+        let &(x2, y2, z2) = p2;
+        if z1.is_zero() {
+            return *p2;
+        }
+        if z2.is_zero() {
+            return *p1;
+        }
+        // add-2007-bl from the Explicit-Formulas Database.
+        let z1z1 = mul(z1, z1);
+        let z2z2 = mul(z2, z2);
+        let u1 = mul(x1, z2z2);
+        let u2 = mul(x2, z1z1);
+        let s1 = mul(mul(y1, z2), z2z2);
+        let s2 = mul(mul(y2, z1), z1z1);
+        let h = sub(u2, u1);
+        let r = sub(s2, s1);
+        if h.is_zero() {
+            return if r.is_zero() { double(p1) } else { INFINITY };
+        }
+        let hh = mul(h, h);
+        let hhh = mul(h, hh);
+        let v = mul(u1, hh);
+        let x3 = sub(sub(mul(r, r), hhh), add(v, v));
+        let y3 = sub(mul(r, sub(v, x3)), mul(s1, hhh));
+        let z3 = mul(mul(z1, z2), h);
+        (x3, y3, z3)
+    }
+
+    fn to_biguint(x: U256) -> BigUint {
+        let mut buf = [0u8; 32];
+        x.to_big_endian(&mut buf);
+        BigUint::from_bytes_be(&buf)
+    }
+
+    fn from_biguint(x: &BigUint) -> U256 {
+        U256::from_big_endian(&x.to_bytes_be())
+    }
+
+    /// Whether (r, s) is a valid signature of `hash` by the public key (x, y).
+    pub fn verify(hash: U256, r: U256, s: U256, x: U256, y: U256) -> bool {
+        if r.is_zero() || s.is_zero() || r >= N || s >= N {
+            return false;
+        }
+        if !is_on_curve(x, y) {
+            return false;
+        }
+
+        // The few operations modulo the group order are left to BigUint.
+        let n = to_biguint(N);
+        let w = to_biguint(s).modpow(&(&n - BigUint::from(2u32)), &n);
+        let u1 = from_biguint(&(to_biguint(hash) * &w % &n));
+        let u2 = from_biguint(&(to_biguint(r) * &w % &n));
+
+        // u1 G + u2 Q, sharing the doublings between both products.
+        let g = (GX, GY, U256::one());
+        let q = (x, y, U256::one());
+        let g_plus_q = add_points(&g, &q);
+        let mut acc = INFINITY;
+        for i in (0..256).rev() {
+            acc = double(&acc);
+            match (u1.bit(i), u2.bit(i)) {
+                (true, true) => acc = add_points(&acc, &g_plus_q),
+                (true, false) => acc = add_points(&acc, &g),
+                (false, true) => acc = add_points(&acc, &q),
+                (false, false) => {}
+            }
+        }
+        let (x, _, z) = acc;
+        if z.is_zero() {
+            return false;
+        }
+        let z_inv = inv(z);
+        let x = mul(x, mul(z_inv, z_inv));
+        // x < p < 2n, so x mod n is either x or x - n.
+        let x = if x >= N { x - N } else { x };
+        x == r
+    }
+}
+
+impl Impl for P256Verify {
+    /// Returns 32 bytes holding 1 for a valid signature, and an empty output
+    /// for an invalid signature or malformed input, so that callers can tell
+    /// the two apart without the call failing.
+    fn execute(
+        &self, input: &[u8], output: &mut BytesRef,
+    ) -> Result<(), Error> {
+        if input.len() != 160 {
+            return Ok(());
+        }
+
+        if p256::verify(
+            U256::from_big_endian(&input[0..32]),
+            U256::from_big_endian(&input[32..64]),
+            U256::from_big_endian(&input[64..96]),
+            U256::from_big_endian(&input[96..128]),
+            U256::from_big_endian(&input[128..160]),
+        ) {
+            let mut buf = [0u8; 32];
+            U256::one().to_big_endian(&mut buf);
+            output.write(0, &buf);
+        }
+        Ok(())
+    }
+}
+
+const BLS12_FP_LEN: usize = 64;
+const BLS12_G1_LEN: usize = 2 * BLS12_FP_LEN;
+const BLS12_G2_LEN: usize = 4 * BLS12_FP_LEN;
+const BLS12_PAIR_LEN: usize = BLS12_G1_LEN + BLS12_G2_LEN;
+
+/// Strip the 16 bytes of zero padding from a 64-byte field element.
+fn read_bls12_fp(input: &[u8]) -> Result<&[u8], Error> {
+    if input[..16].iter().any(|b| *b != 0) {
+        return Err("Invalid field element padding".into());
+    }
+    Ok(&input[16..BLS12_FP_LEN])
+}
+
+fn read_bls12_g1(
+    input: &[u8], subgroup_check: bool,
+) -> Result<::bls12_381::G1Affine, Error> {
+    use bls12_381::G1Affine;
+
+    if input.iter().all(|b| *b == 0) {
+        return Ok(G1Affine::identity());
+    }
+    let mut buf = [0u8; 96];
+    buf[0..48].copy_from_slice(read_bls12_fp(&input[0..64])?);
+    buf[48..96].copy_from_slice(read_bls12_fp(&input[64..128])?);
+    let p: Option<G1Affine> =
+        G1Affine::from_uncompressed_unchecked(&buf).into();
+    let p = p.ok_or_else(|| Error::from("Invalid field element"))?;
+    if !bool::from(p.is_on_curve()) {
+        return Err("Invalid G1 point - not on curve".into());
+    }
+    if subgroup_check && !bool::from(p.is_torsion_free()) {
+        return Err("Invalid G1 point - not in subgroup".into());
+    }
+    Ok(p)
+}
+
+fn read_bls12_g2(
+    input: &[u8], subgroup_check: bool,
+) -> Result<::bls12_381::G2Affine, Error> {
+    use bls12_381::G2Affine;
+
+    if input.iter().all(|b| *b == 0) {
+        return Ok(G2Affine::identity());
+    }
+    // The input encodes each Fp2 element as (c0, c1) while the serialization
+    // of the bls12_381 crate puts c1 first.
+    let mut buf = [0u8; 192];
+    buf[0..48].copy_from_slice(read_bls12_fp(&input[64..128])?);
+    buf[48..96].copy_from_slice(read_bls12_fp(&input[0..64])?);
+    buf[96..144].copy_from_slice(read_bls12_fp(&input[192..256])?);
+    buf[144..192].copy_from_slice(read_bls12_fp(&input[128..192])?);
+    let p: Option<G2Affine> =
+        G2Affine::from_uncompressed_unchecked(&buf).into();
+    let p = p.ok_or_else(|| Error::from("Invalid field element"))?;
+    if !bool::from(p.is_on_curve()) {
+        return Err("Invalid G2 point - not on curve".into());
+    }
+    if subgroup_check && !bool::from(p.is_torsion_free()) {
+        return Err("Invalid G2 point - not in subgroup".into());
+    }
+    Ok(p)
+}
+
+fn write_bls12_g1(p: &::bls12_381::G1Affine, output: &mut BytesRef) {
+    let mut write_buf = [0u8; BLS12_G1_LEN];
+    if !bool::from(p.is_identity()) {
+        let bytes = p.to_uncompressed();
+        write_buf[16..64].copy_from_slice(&bytes[0..48]);
+        write_buf[80..128].copy_from_slice(&bytes[48..96]);
+    }
+    output.write(0, &write_buf);
+}
+
+fn write_bls12_g2(p: &::bls12_381::G2Affine, output: &mut BytesRef) {
+    let mut write_buf = [0u8; BLS12_G2_LEN];
+    if !bool::from(p.is_identity()) {
+        let bytes = p.to_uncompressed();
+        write_buf[16..64].copy_from_slice(&bytes[48..96]);
+        write_buf[80..128].copy_from_slice(&bytes[0..48]);
+        write_buf[144..192].copy_from_slice(&bytes[144..192]);
+        write_buf[208..256].copy_from_slice(&bytes[96..144]);
+    }
+    output.write(0, &write_buf);
+}
+
+/// Double-and-add multiplication by a 32-byte big-endian scalar. The scalar
+/// is not reduced modulo the group order.
+fn bls12_scalar_mul<T>(base: T, zero: T, scalar: &[u8]) -> T
+where T: Copy + ::std::ops::Add<Output = T> {
+    let mut acc = zero;
+    for byte in scalar {
+        for i in (0..8).rev() {
+            acc = acc + acc;
+            if (byte >> i) & 1 == 1 {
+                acc = acc + base;
+            }
+        }
+    }
+    acc
+}
+
+impl Impl for Bls12G1AddImpl {
+    // Can fail if the input is not 256 bytes or any of the 2 points does not
+    // belong to the curve. No subgroup check is performed.
+    fn execute(
+        &self, input: &[u8], output: &mut BytesRef,
+    ) -> Result<(), Error> {
+        use bls12_381::{G1Affine, G1Projective};
+
+        if input.len() != 2 * BLS12_G1_LEN {
+            return Err("Invalid input length, must be 256 bytes".into());
+        }
+        let p1 = read_bls12_g1(&input[0..128], false)?;
+        let p2 = read_bls12_g1(&input[128..256], false)?;
+        let sum = G1Projective::from(p1) + G1Projective::from(p2);
+        write_bls12_g1(&G1Affine::from(sum), output);
+        Ok(())
+    }
+}
+
+impl Impl for Bls12G1MulImpl {
+    // Can fail if the input is not 160 bytes or the point does not belong to
+    // the G1 subgroup.
+    fn execute(
+        &self, input: &[u8], output: &mut BytesRef,
+    ) -> Result<(), Error> {
+        use bls12_381::{G1Affine, G1Projective};
+
+        if input.len() != BLS12_G1_LEN + 32 {
+            return Err("Invalid input length, must be 160 bytes".into());
+        }
+        let p = read_bls12_g1(&input[0..128], true)?;
+        let product = bls12_scalar_mul(
+            G1Projective::from(p),
+            G1Projective::identity(),
+            &input[128..160],
+        );
+        write_bls12_g1(&G1Affine::from(product), output);
+        Ok(())
+    }
+}
+
+impl Impl for Bls12G2AddImpl {
+    // Can fail if the input is not 512 bytes or any of the 2 points does not
+    // belong to the twisted curve. No subgroup check is performed.
+    fn execute(
+        &self, input: &[u8], output: &mut BytesRef,
+    ) -> Result<(), Error> {
+        use bls12_381::{G2Affine, G2Projective};
+
+        if input.len() != 2 * BLS12_G2_LEN {
+            return Err("Invalid input length, must be 512 bytes".into());
+        }
+        let p1 = read_bls12_g2(&input[0..256], false)?;
+        let p2 = read_bls12_g2(&input[256..512], false)?;
+        let sum = G2Projective::from(p1) + G2Projective::from(p2);
+        write_bls12_g2(&G2Affine::from(sum), output);
+        Ok(())
+    }
+}
+
+impl Impl for Bls12G2MulImpl {
+    // Can fail if the input is not 288 bytes or the point does not belong to
+    // the G2 subgroup.
+    fn execute(
+        &self, input: &[u8], output: &mut BytesRef,
+    ) -> Result<(), Error> {
+        use bls12_381::{G2Affine, G2Projective};
+
+        if input.len() != BLS12_G2_LEN + 32 {
+            return Err("Invalid input length, must be 288 bytes".into());
+        }
+        let p = read_bls12_g2(&input[0..256], true)?;
+        let product = bls12_scalar_mul(
+            G2Projective::from(p),
+            G2Projective::identity(),
+            &input[256..288],
+        );
+        write_bls12_g2(&G2Affine::from(product), output);
+        Ok(())
+    }
+}
+
+impl Impl for Bls12PairingImpl {
+    /// Can fail if:
+    ///     - input is empty or its length is not a multiple of 384
+    ///     - any of the G1 points does not belong to the G1 subgroup
+    ///     - any of the G2 points does not belong to the G2 subgroup
+    fn execute(
+        &self, input: &[u8], output: &mut BytesRef,
+    ) -> Result<(), Error> {
+        use bls12_381::{pairing, Gt};
+
+        if input.is_empty() || input.len() % BLS12_PAIR_LEN != 0 {
+            return Err(
+                "Invalid input length, must be a non-zero multiple of 384"
+                    .into(),
+            );
+        }
+
+        let mut acc = Gt::identity();
+        for chunk in input.chunks(BLS12_PAIR_LEN) {
+            let a = read_bls12_g1(&chunk[0..BLS12_G1_LEN], true)?;
+            let b = read_bls12_g2(&chunk[BLS12_G1_LEN..BLS12_PAIR_LEN], true)?;
+            acc = acc + pairing(&a, &b);
+        }
+
+        let ret_val = if acc == Gt::identity() {
+            U256::one()
+        } else {
+            U256::zero()
+        };
+        let mut buf = [0u8; 32];
+        ret_val.to_big_endian(&mut buf);
+        output.write(0, &buf);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{
-        builtin_factory, modexp as me, Builtin, Linear, ModexpPricer, Pricer,
+        builtin_factory, is_builtin_name, modexp as me, p256, pricer_factory,
+        Builtin, Linear, ModexpPricer, Pricer,
     };
     use crate::bytes::BytesRef;
     use cfx_types::U256;
@@ -689,7 +1439,7 @@ mod tests {
 
     #[test]
     fn identity() {
-        let f = builtin_factory("identity");
+        let f = builtin_factory("identity").unwrap();
 
         let i = [0u8, 1, 2, 3];
 
@@ -712,7 +1462,7 @@ mod tests {
 
     #[test]
     fn sha256() {
-        let f = builtin_factory("sha256");
+        let f = builtin_factory("sha256").unwrap();
 
         let i = [0u8; 0];
 
@@ -742,7 +1492,7 @@ mod tests {
 
     #[test]
     fn ripemd160() {
-        let f = builtin_factory("ripemd160");
+        let f = builtin_factory("ripemd160").unwrap();
 
         let i = [0u8; 0];
 
@@ -767,7 +1517,7 @@ mod tests {
 
     #[test]
     fn ecrecover() {
-        let f = builtin_factory("ecrecover");
+        let f = builtin_factory("ecrecover").unwrap();
 
         let i = FromHex::from_hex("47173285a8d7341e5e972fc677286384f802f8ef42a5ec5f03bbfa254cb01fad000000000000000000000000000000000000000000000000000000000000001b650acf9d3f5f0a2c799776a1254355d5f4061762a237396a99a0e0e3fc2bcd6729514a0dacb2e623ac4abd157cb18163ff942280db4d5caad66ddf941ba12e03").unwrap();
 
@@ -831,7 +1581,7 @@ mod tests {
     fn modexp() {
         let f = Builtin {
             pricer: Box::new(ModexpPricer { divisor: 20 }),
-            native: builtin_factory("modexp"),
+            native: builtin_factory("modexp").unwrap(),
             activate_at: 0,
         };
 
@@ -946,7 +1696,7 @@ mod tests {
     fn bn128_add() {
         let f = Builtin {
             pricer: Box::new(Linear { base: 0, word: 0 }),
-            native: builtin_factory("alt_bn128_add"),
+            native: builtin_factory("alt_bn128_add").unwrap(),
             activate_at: 0,
         };
 
@@ -1007,7 +1757,7 @@ mod tests {
     fn bn128_mul() {
         let f = Builtin {
             pricer: Box::new(Linear { base: 0, word: 0 }),
-            native: builtin_factory("alt_bn128_mul"),
+            native: builtin_factory("alt_bn128_mul").unwrap(),
             activate_at: 0,
         };
 
@@ -1049,7 +1799,7 @@ mod tests {
     fn builtin_pairing() -> Builtin {
         Builtin {
             pricer: Box::new(Linear { base: 0, word: 0 }),
-            native: builtin_factory("alt_bn128_pairing"),
+            native: builtin_factory("alt_bn128_pairing").unwrap(),
             activate_at: 0,
         }
     }
@@ -1123,16 +1873,379 @@ mod tests {
         );
     }
 
+    fn builtin_named(name: &str) -> Builtin {
+        Builtin::from_name(name, 0).unwrap()
+    }
+
+    fn output_test(f: Builtin, input: &[u8], expected: Vec<u8>) {
+        let mut output = vec![0u8; expected.len()];
+        f.execute(input, &mut BytesRef::Fixed(&mut output[..]))
+            .expect("Builtin should not fail");
+        assert_eq!(output, expected);
+    }
+
+    fn blake2_f_input() -> Vec<u8> {
+        // rounds, h, m = "abc", t = 3, f = 1
+        bytes("\
+				0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f\
+				3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e13\
+				19cde05b61626300000000000000000000000000000000000000000000000000\
+				0000000000000000000000000000000000000000000000000000000000000000\
+				0000000000000000000000000000000000000000000000000000000000000000\
+				0000000000000000000000000000000000000000000000000000000000000000\
+				000000000300000000000000000000000000000001"
+        )
+    }
+
+    #[test]
+    fn blake2_f() {
+        // 12 rounds with the final block flag set compress the block "abc".
+        output_test(
+            builtin_named("blake2_f"),
+            &blake2_f_input(),
+            bytes("\
+				ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+				7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+            ),
+        );
+
+        // 0 rounds
+        let mut input = blake2_f_input();
+        input[3] = 0;
+        output_test(
+            builtin_named("blake2_f"),
+            &input,
+            bytes("\
+				08c9bcf367e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5\
+				d282e6ad7f520e511f6c3e2b8c68059b9442be0454267ce079217e1319cde05b"
+            ),
+        );
+
+        // final block flag unset
+        let mut input = blake2_f_input();
+        input[212] = 0;
+        output_test(
+            builtin_named("blake2_f"),
+            &input,
+            bytes("\
+				75ab69d3190a562c51aef8d88f1c2775876944407270c42c9844252c26d28752\
+				98743e7f6d5ea2f2d3e8d226039cd31b4e426ac4f2d3d666a610c2116fde4735"
+            ),
+        );
+    }
+
+    #[test]
+    fn blake2_f_invalid() {
+        let input = blake2_f_input();
+
+        // should fail - input length is invalid
+        error_test(
+            builtin_named("blake2_f"),
+            &input[..212],
+            Some("Invalid input length"),
+        );
+
+        // should fail - final block flag is neither 0 nor 1
+        let mut input = input;
+        input[212] = 2;
+        error_test(
+            builtin_named("blake2_f"),
+            &input,
+            Some("final block indicator"),
+        );
+    }
+
     #[test]
-    #[should_panic]
-    fn from_unknown_linear() { let _ = builtin_factory("foo"); }
+    fn blake2_f_cost() {
+        let b = builtin_named("blake2_f");
+        let mut input = blake2_f_input();
+        assert_eq!(b.cost(&input), U256::from(12));
+        input[0] = 1;
+        assert_eq!(b.cost(&input), U256::from(0x0100000c));
+        assert_eq!(b.cost(&input[..100]), U256::zero());
+    }
+
+    fn secp256r1_verify_input() -> Vec<u8> {
+        // hash, r, s, x, y
+        bytes("\
+				0a5abce35d57736682798e2e39f461f0bce0de4ab0ab4ff4b73d2dc97d61dccd\
+				737a8725a970681eb404426a6816d8cd52bac58b5fcae13b4700e9c19718bd17\
+				882ca61b1d468d8f9f29168fd48f13f1e0e101a4c746381753eed549c1c9233c\
+				471c3e758c4904285bba7e53118ed0f524adeb0757d25bd2f8e7b0d76dfa714c\
+				dd520f7aca8a8b917acc37f51de8f0c9bbe3ad858382e702dc25a12d09f7a858"
+        )
+    }
+
+    fn secp256r1_verify_empty_output(input: &[u8]) {
+        let mut output = Vec::new();
+        builtin_named("secp256r1_verify")
+            .execute(input, &mut BytesRef::Flexible(&mut output))
+            .expect("Builtin should not fail");
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn secp256r1_verify() {
+        output_test(
+            builtin_named("secp256r1_verify"),
+            &secp256r1_verify_input(),
+            bytes("0000000000000000000000000000000000000000000000000000000000000001"),
+        );
+
+        // the signature does not match the modified hash
+        let mut input = secp256r1_verify_input();
+        input[31] ^= 1;
+        secp256r1_verify_empty_output(&input);
+
+        // the public key is not on the curve
+        let mut input = secp256r1_verify_input();
+        input[159] ^= 1;
+        secp256r1_verify_empty_output(&input);
+
+        // the scalars are out of range
+        let mut input = secp256r1_verify_input();
+        for b in &mut input[32..64] {
+            *b = 0;
+        }
+        secp256r1_verify_empty_output(&input);
+        let mut input = secp256r1_verify_input();
+        for b in &mut input[64..96] {
+            *b = 0xff;
+        }
+        secp256r1_verify_empty_output(&input);
+
+        // malformed input
+        secp256r1_verify_empty_output(&input[..128]);
+    }
+
+    #[test]
+    fn secp256r1_field_mul() {
+        let to_biguint = |x: U256| {
+            let mut buf = [0u8; 32];
+            x.to_big_endian(&mut buf);
+            BigUint::from_bytes_be(&buf)
+        };
+        let p = U256::from_big_endian(&bytes(
+            "ffffffff00000001000000000000000000000000ffffffffffffffffffffffff",
+        ));
+        let values = [
+            U256::zero(),
+            U256::one(),
+            p - U256::one(),
+            p - U256::from(2),
+            U256::one() << 224,
+            (U256::one() << 192) + U256::from(5),
+            U256::from_big_endian(&bytes(
+                "6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296",
+            )),
+            U256::from_big_endian(&bytes(
+                "fffffffe00000002fffffffe0000000100000001fffffffe00000001fffffffe",
+            )),
+        ];
+        for x in values.iter() {
+            for y in values.iter() {
+                assert_eq!(
+                    to_biguint(p256::mul(*x, *y)),
+                    to_biguint(*x) * to_biguint(*y) % to_biguint(p)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn secp256r1_verify_cost() {
+        let b = builtin_named("secp256r1_verify");
+        assert_eq!(b.cost(&secp256r1_verify_input()), U256::from(33000));
+    }
+
+    fn bls12_g1() -> Vec<u8> {
+        // the generator of G1
+        bytes("\
+				0000000000000000000000000000000017f1d3a73197d7942695638c4fa9ac0f\
+				c3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb\
+				0000000000000000000000000000000008b3f481e3aaa0f1a09e30ed741d8ae4\
+				fcf5e095d5d00af600db18cb2c04b3edd03cc744a2888ae40caa232946c5e7e1"
+        )
+    }
+
+    fn bls12_g1_double() -> Vec<u8> {
+        // 2 * bls12_g1()
+        bytes("\
+				000000000000000000000000000000000572cbea904d67468808c8eb50a9450c\
+				9721db309128012543902d0ac358a62ae28f75bb8f1c7c42c39a8c5529bf0f4e\
+				00000000000000000000000000000000166a9d8cabc673a322fda673779d8e38\
+				22ba3ecb8670e461f73bb9021d5fd76a4c56d9d4cd16bd1bba86881979749d28"
+        )
+    }
+
+    fn bls12_g1_neg() -> Vec<u8> {
+        // -bls12_g1()
+        bytes("\
+				0000000000000000000000000000000017f1d3a73197d7942695638c4fa9ac0f\
+				c3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb\
+				00000000000000000000000000000000114d1d6855d545a8aa7d76c8cf2e21f2\
+				67816aef1db507c96655b9d5caac42364e6f38ba0ecb751bad54dcd6b939c2ca"
+        )
+    }
+
+    fn bls12_g2() -> Vec<u8> {
+        // the generator of G2
+        bytes("\
+				00000000000000000000000000000000024aa2b2f08f0a91260805272dc51051\
+				c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8\
+				0000000000000000000000000000000013e02b6052719f607dacd3a088274f65\
+				596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e\
+				000000000000000000000000000000000ce5d527727d6e118cc9cdc6da2e351a\
+				adfd9baa8cbdd3a76d429a695160d12c923ac9cc3baca289e193548608b82801\
+				000000000000000000000000000000000606c4a02ea734cc32acd2b02bc28b99\
+				cb3e287e85a763af267492ab572e99ab3f370d275cec1da1aaa9075ff05f79be"
+        )
+    }
+
+    fn bls12_g2_double() -> Vec<u8> {
+        // 2 * bls12_g2()
+        bytes("\
+				000000000000000000000000000000001638533957d540a9d2370f17cc7ed586\
+				3bc0b995b8825e0ee1ea1e1e4d00dbae81f14b0bf3611b78c952aacab827a053\
+				000000000000000000000000000000000a4edef9c1ed7f729f520e47730a124f\
+				d70662a904ba1074728114d1031e1572c6c886f6b57ec72a6178288c47c33577\
+				000000000000000000000000000000000468fb440d82b0630aeb8dca2b525678\
+				9a66da69bf91009cbfe6bd221e47aa8ae88dece9764bf3bd999d95d71e4c9899\
+				000000000000000000000000000000000f6d4552fa65dd2638b361543f887136\
+				a43253d9c66c411697003f7a13c308f5422e1aa0a59c8967acdefd8b6e36ccf3"
+        )
+    }
+
+    #[test]
+    fn bls12_381_g1_add() {
+        let mut input = bls12_g1();
+        input.extend(bls12_g1());
+        output_test(
+            builtin_named("bls12_381_g1_add"),
+            &input,
+            bls12_g1_double(),
+        );
+
+        // adding the negation yields the point at infinity
+        let mut input = bls12_g1();
+        input.extend(bls12_g1_neg());
+        output_test(builtin_named("bls12_381_g1_add"), &input, vec![0u8; 128]);
+    }
+
+    #[test]
+    fn bls12_381_g1_mul() {
+        let mut input = bls12_g1();
+        input.extend(bytes(
+            "0000000000000000000000000000000000000000000000000000000000000002",
+        ));
+        output_test(
+            builtin_named("bls12_381_g1_mul"),
+            &input,
+            bls12_g1_double(),
+        );
+    }
+
+    #[test]
+    fn bls12_381_g1_invalid() {
+        // should fail - point is not on curve
+        let mut input = bls12_g1();
+        input[127] ^= 1;
+        input.extend(bls12_g1());
+        error_test(
+            builtin_named("bls12_381_g1_add"),
+            &input,
+            Some("not on curve"),
+        );
+
+        // should fail - field element padding is not zero
+        let mut input = bls12_g1();
+        input[0] = 1;
+        input.extend(bls12_g1());
+        error_test(
+            builtin_named("bls12_381_g1_add"),
+            &input,
+            Some("padding"),
+        );
+    }
+
+    #[test]
+    fn bls12_381_g2_add() {
+        let mut input = bls12_g2();
+        input.extend(bls12_g2());
+        output_test(
+            builtin_named("bls12_381_g2_add"),
+            &input,
+            bls12_g2_double(),
+        );
+    }
+
+    #[test]
+    fn bls12_381_g2_mul() {
+        let mut input = bls12_g2();
+        input.extend(bytes(
+            "0000000000000000000000000000000000000000000000000000000000000002",
+        ));
+        output_test(
+            builtin_named("bls12_381_g2_mul"),
+            &input,
+            bls12_g2_double(),
+        );
+    }
+
+    #[test]
+    fn bls12_381_pairing() {
+        // e(g1, g2) * e(-g1, g2) == 1
+        let mut input = bls12_g1();
+        input.extend(bls12_g2());
+        input.extend(bls12_g1_neg());
+        input.extend(bls12_g2());
+        output_test(
+            builtin_named("bls12_381_pairing"),
+            &input,
+            bytes("0000000000000000000000000000000000000000000000000000000000000001"),
+        );
+
+        // e(g1, g2) != 1
+        output_test(
+            builtin_named("bls12_381_pairing"),
+            &input[..384],
+            bytes("0000000000000000000000000000000000000000000000000000000000000000"),
+        );
+
+        // should fail - input length is invalid
+        error_test(
+            builtin_named("bls12_381_pairing"),
+            &[],
+            Some("Invalid input length"),
+        );
+        error_test(
+            builtin_named("bls12_381_pairing"),
+            &input[..383],
+            Some("Invalid input length"),
+        );
+    }
+
+    #[test]
+    fn bls12_381_pairing_cost() {
+        let b = builtin_named("bls12_381_pairing");
+        assert_eq!(b.cost(&[0u8; 384]), U256::from(108000));
+        assert_eq!(b.cost(&[0u8; 768]), U256::from(151000));
+    }
+
+    #[test]
+    fn from_unknown_linear() {
+        assert!(builtin_factory("foo").is_none());
+        assert!(pricer_factory("foo").is_none());
+        assert!(Builtin::from_name("foo", 0).is_none());
+        assert!(!is_builtin_name("foo"));
+    }
 
     #[test]
     fn is_active() {
         let pricer = Box::new(Linear { base: 10, word: 20 });
         let b = Builtin {
             pricer: pricer as Box<Pricer>,
-            native: builtin_factory("identity"),
+            native: builtin_factory("identity").unwrap(),
             activate_at: 100_000,
         };
 
@@ -1146,7 +2259,7 @@ mod tests {
         let pricer = Box::new(Linear { base: 10, word: 20 });
         let b = Builtin {
             pricer: pricer as Box<Pricer>,
-            native: builtin_factory("identity"),
+            native: builtin_factory("identity").unwrap(),
             activate_at: 1,
         };
 
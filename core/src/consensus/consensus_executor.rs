@@ -2,11 +2,11 @@ use super::debug::*;
 use crate::{
     block_data_manager::BlockDataManager,
     consensus::{
-        ConsensusGraphInner, ANTICONE_PENALTY_RATIO, BASE_MINING_REWARD,
-        CONFLUX_TOKEN,
+        ConsensusEvent, ConsensusGraphInner, SharedConsensusEventBus,
+        ANTICONE_PENALTY_RATIO, BASE_MINING_REWARD, CONFLUX_TOKEN,
     },
     executive::{ExecutionError, Executive},
-    machine::new_machine,
+    machine::Machine,
     state::{CleanupMode, State},
     statedb::StateDb,
     storage::{state::StateTrait, state_manager::StateManagerTrait},
@@ -14,6 +14,7 @@ use crate::{
     vm_factory::VmFactory,
 };
 use cfx_types::{H256, U256, U512};
+use metrics::{Counter, Gauge, Histogram};
 use parking_lot::{Mutex, RwLock};
 use primitives::{
     receipt::{
@@ -24,6 +25,7 @@ use primitives::{
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{channel, RecvError, Sender, TryRecvError},
         Arc,
     },
@@ -33,6 +35,15 @@ use std::{
 use hash::{KECCAK_EMPTY_LIST_RLP, KECCAK_NULL_RLP};
 use std::fmt::{Debug, Formatter};
 
+lazy_static! {
+    static ref COMPUTE_EPOCH_TIMER: Histogram =
+        Histogram::register("consensus_compute_epoch");
+    static ref EXECUTED_EPOCH_COUNTER: Counter =
+        Counter::register("consensus_executed_epochs");
+    static ref EXECUTION_QUEUE_GAUGE: Gauge =
+        Gauge::register("consensus_execution_queue_size");
+}
+
 // TODO: Parallelize anticone calculation by moving calculation into task.
 /// The struct includes most information to compute rewards for old epochs
 pub struct RewardExecutionInfo {
@@ -119,12 +130,17 @@ pub struct ConsensusExecutor {
 
 impl ConsensusExecutor {
     pub fn start(
-        data_man: Arc<BlockDataManager>, vm: VmFactory,
-        consensus_inner: Arc<RwLock<ConsensusGraphInner>>, bench_mode: bool,
+        data_man: Arc<BlockDataManager>, vm: VmFactory, machine: Arc<Machine>,
+        consensus_inner: Arc<RwLock<ConsensusGraphInner>>,
+        event_bus: SharedConsensusEventBus, bench_mode: bool,
     ) -> Self
     {
-        let handler =
-            Arc::new(ConsensusExecutionHandler::new(data_man.clone(), vm));
+        let handler = Arc::new(ConsensusExecutionHandler::new(
+            data_man.clone(),
+            vm,
+            machine,
+            event_bus,
+        ));
         let (sender, receiver) = channel();
 
         let executor = ConsensusExecutor {
@@ -213,10 +229,20 @@ impl ConsensusExecutor {
     /// holding inner lock.
     pub fn enqueue_epoch(&self, task: EpochExecutionTask) -> bool {
         if !self.bench_mode {
-            self.sender
+            let sent = self
+                .sender
                 .lock()
                 .send(ExecutionTask::ExecuteEpoch(task))
-                .is_ok()
+                .is_ok();
+            if sent {
+                let queue_size = self
+                    .handler
+                    .pending_epoch_tasks
+                    .fetch_add(1, Ordering::SeqCst)
+                    + 1;
+                EXECUTION_QUEUE_GAUGE.update(queue_size as i64);
+            }
+            sent
         } else {
             true
         }
@@ -235,6 +261,17 @@ impl ConsensusExecutor {
         self.handler.call_virtual(tx, epoch_id)
     }
 
+    /// The number of epochs enqueued but not yet picked up by the execution
+    /// thread.
+    pub fn queue_size(&self) -> usize {
+        self.handler.pending_epoch_tasks.load(Ordering::SeqCst)
+    }
+
+    /// The hash of the epoch most recently executed by the handler.
+    pub fn last_executed_epoch(&self) -> Option<H256> {
+        *self.handler.last_executed_epoch.read()
+    }
+
     pub fn stop(&self) {
         self.sender
             .lock()
@@ -249,11 +286,26 @@ impl ConsensusExecutor {
 pub struct ConsensusExecutionHandler {
     data_man: Arc<BlockDataManager>,
     pub vm: VmFactory,
+    machine: Arc<Machine>,
+    pending_epoch_tasks: AtomicUsize,
+    last_executed_epoch: RwLock<Option<H256>>,
+    event_bus: SharedConsensusEventBus,
 }
 
 impl ConsensusExecutionHandler {
-    pub fn new(data_man: Arc<BlockDataManager>, vm: VmFactory) -> Self {
-        ConsensusExecutionHandler { data_man, vm }
+    pub fn new(
+        data_man: Arc<BlockDataManager>, vm: VmFactory, machine: Arc<Machine>,
+        event_bus: SharedConsensusEventBus,
+    ) -> Self
+    {
+        ConsensusExecutionHandler {
+            data_man,
+            vm,
+            machine,
+            pending_epoch_tasks: AtomicUsize::new(0),
+            last_executed_epoch: RwLock::new(None),
+            event_bus,
+        }
     }
 
     /// Return `false` if someting goes wrong, and we will break the working
@@ -280,6 +332,9 @@ impl ConsensusExecutionHandler {
         debug!("Receive execution task: {:?}", task);
         match task {
             ExecutionTask::ExecuteEpoch(task) => {
+                let queue_size =
+                    self.pending_epoch_tasks.fetch_sub(1, Ordering::SeqCst) - 1;
+                EXECUTION_QUEUE_GAUGE.update(queue_size as i64);
                 self.handle_epoch_execution(task)
             }
             ExecutionTask::GetResult(task) => self.handle_get_result_task(task),
@@ -289,13 +344,15 @@ impl ConsensusExecutionHandler {
     }
 
     fn handle_epoch_execution(&self, task: EpochExecutionTask) {
-        self.compute_epoch(
+        if let Err(e) = self.compute_epoch(
             &task.epoch_hash,
             &task.epoch_block_hashes,
             &task.reward_info,
             task.on_local_pivot,
             &mut *task.debug_record.lock(),
-        );
+        ) {
+            warn!("Fail to compute epoch {:?}: {}", task.epoch_hash, e);
+        }
     }
 
     fn handle_get_result_task(&self, task: GetExecutionResultTask) {
@@ -323,13 +380,18 @@ impl ConsensusExecutionHandler {
     /// One different between skipped execution in pivot chain is that the
     /// transactions packed in the skipped epoch will be checked if they can
     /// be recycled.
+    ///
+    /// Returns an error if the outputs to fill in `debug_record` cannot be
+    /// read.
     pub fn compute_epoch(
         &self, epoch_hash: &H256, epoch_block_hashes: &Vec<H256>,
         reward_execution_info: &Option<RewardExecutionInfo>,
         on_local_pivot: bool,
         debug_record: &mut Option<ComputeEpochDebugRecord>,
-    )
+    ) -> Result<(), String>
     {
+        let _timer = COMPUTE_EPOCH_TIMER.start_timer();
+
         // Check if the state has been computed
         if debug_record.is_none()
             && self.data_man.storage_manager.state_exists(*epoch_hash)
@@ -340,7 +402,12 @@ impl ConsensusExecutionHandler {
             )
         {
             debug!("Skip execution in prefix {:?}", epoch_hash);
-            return;
+            // The epoch may have been executed before it was on the local
+            // pivot chain, e.g. to compute the deferred state of a new block.
+            if on_local_pivot {
+                self.publish_epoch_executed(epoch_hash);
+            }
+            return Ok(());
         }
 
         // Get blocks in this epoch after skip checking
@@ -366,12 +433,19 @@ impl ConsensusExecutionHandler {
             0.into(),
             self.vm.clone(),
         );
-        self.process_epoch_transactions(
+        let epoch_receipts = self.process_epoch_transactions(
             &mut state,
             &epoch_blocks,
             &self.data_man.txpool.unexecuted_transaction_addresses,
             on_local_pivot,
         );
+        if debug_record.is_some() {
+            let debug_out = debug_record.as_mut().unwrap();
+            debug_out.receipts = epoch_receipts
+                .iter()
+                .flat_map(|receipts| receipts.iter().cloned())
+                .collect();
+        }
 
         if let Some(reward_execution_info) = reward_execution_info {
             // Calculate the block reward for blocks inside the epoch
@@ -392,6 +466,16 @@ impl ConsensusExecutionHandler {
         } else {
             state.commit(*epoch_hash).unwrap();
         }
+        if debug_record.is_some() {
+            let debug_out = debug_record.as_mut().unwrap();
+            debug_out.state_root_after_applying_rewards =
+                self.epoch_state_root(epoch_hash)?;
+        }
+        EXECUTED_EPOCH_COUNTER.inc();
+        *self.last_executed_epoch.write() = Some(*epoch_hash);
+        if on_local_pivot {
+            self.publish_epoch_executed(epoch_hash);
+        }
         debug!(
             "compute_epoch: on_local_pivot={}, epoch={:?} state_root={:?} receipt_root={:?}",
             on_local_pivot,
@@ -407,6 +491,46 @@ impl ConsensusExecutionHandler {
                 .get_receipts_root(&epoch_hash)
                 .unwrap()
         );
+        Ok(())
+    }
+
+    /// Notify the subscribers of an executed epoch. The event is skipped if
+    /// the roots of the epoch cannot be read.
+    fn publish_epoch_executed(&self, epoch_hash: &H256) {
+        let state_root = match self.epoch_state_root(epoch_hash) {
+            Ok(state_root) => state_root,
+            Err(e) => {
+                warn!("Skip the EpochExecuted event: {}", e);
+                return;
+            }
+        };
+        let receipts_root = match self.data_man.get_receipts_root(epoch_hash) {
+            Some(receipts_root) => receipts_root,
+            None => {
+                warn!(
+                    "Skip the EpochExecuted event: no receipts root for epoch \
+                     {:?}",
+                    epoch_hash
+                );
+                return;
+            }
+        };
+        self.event_bus.publish(ConsensusEvent::EpochExecuted {
+            epoch_hash: *epoch_hash,
+            state_root,
+            receipts_root,
+        });
+    }
+
+    fn epoch_state_root(&self, epoch_hash: &H256) -> Result<H256, String> {
+        self.data_man
+            .storage_manager
+            .get_state_at(*epoch_hash)
+            .and_then(|state| state.get_state_root())
+            .map_err(|e| {
+                format!("Fail to get state of epoch {:?}: {}", epoch_hash, e)
+            })?
+            .ok_or_else(|| format!("No state root for epoch {:?}", epoch_hash))
     }
 
     fn process_epoch_transactions(
@@ -419,7 +543,6 @@ impl ConsensusExecutionHandler {
     {
         let pivot_block = epoch_blocks.last().expect("Epoch not empty");
         let spec = Spec::new_spec();
-        let machine = new_machine();
         let mut epoch_receipts = Vec::with_capacity(epoch_blocks.len());
         let mut to_pending = Vec::new();
         for block in epoch_blocks.iter() {
@@ -438,7 +561,7 @@ impl ConsensusExecutionHandler {
                 gas_limit: U256::from(block.block_header.gas_limit()),
             };
             let mut accumulated_fee: U256 = 0.into();
-            let mut ex = Executive::new(state, &mut env, &machine, &spec);
+            let mut ex = Executive::new(state, &mut env, &self.machine, &spec);
             let mut n_invalid_nonce = 0;
             let mut n_ok = 0;
             let mut n_other = 0;
@@ -824,7 +947,6 @@ impl ConsensusExecutionHandler {
         &self, tx: &SignedTransaction, epoch_id: &H256,
     ) -> Result<(Vec<u8>, U256), String> {
         let spec = Spec::new_spec();
-        let machine = new_machine();
         let mut state = State::new(
             StateDb::new(
                 self.data_man
@@ -843,7 +965,7 @@ impl ConsensusExecutionHandler {
             gas_used: U256::zero(),
             gas_limit: tx.gas.clone(),
         };
-        let mut ex = Executive::new(&mut state, &mut env, &machine, &spec);
+        let mut ex = Executive::new(&mut state, &mut env, &self.machine, &spec);
         let r = ex.transact(tx);
         trace!("Execution result {:?}", r);
         r.map(|r| (r.output, r.gas_used))
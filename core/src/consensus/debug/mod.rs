@@ -24,6 +24,13 @@ pub struct ComputeEpochDebugRecord {
     pub block_txs: Vec<usize>,
     pub transactions: Vec<Arc<SignedTransaction>>,
 
+    // Inputs of the reward computation, in the order of the blocks in the
+    // reward epoch.
+    pub reward_epoch_block_hashes: Vec<H256>,
+    pub reward_epoch_anticone_overlimited: Vec<bool>,
+    pub reward_epoch_anticone_set_sizes: Vec<usize>,
+    pub reward_epoch_anticone_difficulties: Vec<U512>,
+
     // Rewards. Rewards for anticone overlimit blocks may be skipped.
     pub block_authors: Vec<Address>,
     pub anticone_overlimit_blocks: Vec<H256>,
@@ -42,6 +49,74 @@ pub struct ComputeEpochDebugRecord {
     // Storage operations.
     // op name, key, maybe_value
     pub state_ops: Vec<StateOp>,
+
+    // Receipts of all transactions in the epoch, in execution order.
+    pub receipts: Vec<Receipt>,
+}
+
+impl ComputeEpochDebugRecord {
+    /// A new record with only the inputs of the epoch computation copied, to
+    /// be filled by re-executing the epoch.
+    pub fn replay_inputs(&self) -> Self {
+        ComputeEpochDebugRecord {
+            parent_block_hash: self.parent_block_hash,
+            parent_state_root: self.parent_state_root,
+            reward_epoch_hash: self.reward_epoch_hash,
+            anticone_penalty_cutoff_epoch_hash: self
+                .anticone_penalty_cutoff_epoch_hash,
+            block_hashes: self.block_hashes.clone(),
+            block_txs: self.block_txs.clone(),
+            transactions: self.transactions.clone(),
+            block_authors: self.block_authors.clone(),
+            reward_epoch_block_hashes: self.reward_epoch_block_hashes.clone(),
+            reward_epoch_anticone_overlimited: self
+                .reward_epoch_anticone_overlimited
+                .clone(),
+            reward_epoch_anticone_set_sizes: self
+                .reward_epoch_anticone_set_sizes
+                .clone(),
+            reward_epoch_anticone_difficulties: self
+                .reward_epoch_anticone_difficulties
+                .clone(),
+            ..Default::default()
+        }
+    }
+
+    /// The hash of the pivot block of the recorded epoch.
+    pub fn epoch_hash(&self) -> Option<H256> {
+        self.block_hashes.last().cloned()
+    }
+
+    /// Rebuild the reward inputs of the recorded epoch. Returns `None` if the
+    /// epoch does not apply rewards, and `Err` if any block of the reward
+    /// epoch is missing.
+    pub fn reward_execution_info(
+        &self, data_man: &BlockDataManager,
+    ) -> Result<Option<RewardExecutionInfo>, String> {
+        if self.reward_epoch_hash.is_none() {
+            return Ok(None);
+        }
+        let epoch_blocks = data_man
+            .blocks_by_hash_list(&self.reward_epoch_block_hashes, false)
+            .ok_or_else(|| {
+                format!(
+                    "blocks of reward epoch {:?} not found",
+                    self.reward_epoch_hash
+                )
+            })?;
+        Ok(Some(RewardExecutionInfo {
+            epoch_blocks,
+            epoch_block_anticone_overlimited: self
+                .reward_epoch_anticone_overlimited
+                .clone(),
+            epoch_block_anticone_set_sizes: self
+                .reward_epoch_anticone_set_sizes
+                .clone(),
+            epoch_block_anticone_difficulties: self
+                .reward_epoch_anticone_difficulties
+                .clone(),
+        }))
+    }
 }
 
 #[derive(Debug)]
@@ -53,6 +128,22 @@ pub enum StateOp {
     },
 }
 
+impl Decodable for StateOp {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let op_name: String = rlp.val_at(0)?;
+        let maybe_value = rlp.at(2)?;
+        Ok(StateOp::OpNameKeyMaybeValue {
+            op_name: op_name.trim_start_matches("state_op ").to_string(),
+            key: rlp.val_at(1)?,
+            maybe_value: if maybe_value.item_count()? == 0 {
+                None
+            } else {
+                Some(maybe_value.val_at(0)?)
+            },
+        })
+    }
+}
+
 impl Encodable for StateOp {
     fn rlp_append(&self, s: &mut RlpStream) {
         match self {
@@ -102,6 +193,15 @@ impl Encodable for ComputeEpochDebugRecord {
     fn rlp_append(&self, s: &mut RlpStream) {
         s.begin_unbounded_list();
 
+        s.append(&"parent_block_hash")
+            .append(&self.parent_block_hash);
+        s.append(&"parent_state_root")
+            .append(&self.parent_state_root);
+        s.append(&"reward_epoch_hash")
+            .append(&self.reward_epoch_hash);
+        s.append(&"anticone_penalty_cutoff_epoch_hash")
+            .append(&self.anticone_penalty_cutoff_epoch_hash);
+
         s.append(&"block_hashes").append_list(&self.block_hashes);
         s.append(&"block_transactions")
             .append_list::<String, String>(
@@ -116,6 +216,31 @@ impl Encodable for ComputeEpochDebugRecord {
                 &self.transactions,
             );
 
+        s.append(&"reward_epoch_block_hashes")
+            .append_list(&self.reward_epoch_block_hashes);
+        s.append(&"reward_epoch_anticone_overlimited")
+            .append_list(&self.reward_epoch_anticone_overlimited);
+        s.append(&"reward_epoch_anticone_set_sizes")
+            .append_list::<u64, u64>(
+                &self
+                    .reward_epoch_anticone_set_sizes
+                    .iter()
+                    .map(|size| *size as u64)
+                    .collect::<Vec<_>>(),
+            );
+        s.append(&"reward_epoch_anticone_difficulties")
+            .append_list::<Vec<u8>, Vec<u8>>(
+                &self
+                    .reward_epoch_anticone_difficulties
+                    .iter()
+                    .map(|difficulty| {
+                        let mut bytes = vec![0u8; 64];
+                        difficulty.to_big_endian(&mut bytes);
+                        bytes
+                    })
+                    .collect::<Vec<_>>(),
+            );
+
         s.append(&"anticone_overlimit_blocks")
             .append_list(&self.anticone_overlimit_blocks);
         s.append(&"block_authors").append_list(&self.block_authors);
@@ -135,12 +260,193 @@ impl Encodable for ComputeEpochDebugRecord {
         s.append(&"state_root_after_applying_rewards")
             .append(&self.state_root_after_applying_rewards);
         s.append(&"state_ops").append_list(&self.state_ops);
+        s.append(&"receipts").append_list(&self.receipts);
 
         s.complete_unbounded_list();
     }
 }
 
-use cfx_types::{Address, H256, U256};
-use primitives::SignedTransaction;
+/// Only the inputs of the epoch computation and the outputs with a typed
+/// encoding are decoded. The rewards, which are encoded as text for
+/// readability, are left empty.
+impl Decodable for ComputeEpochDebugRecord {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let mut record = ComputeEpochDebugRecord::default();
+        let item_count = rlp.item_count()?;
+        if item_count % 2 != 0 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        for i in (0..item_count).step_by(2) {
+            let label: String = rlp.val_at(i)?;
+            let value = rlp.at(i + 1)?;
+            match label.as_str() {
+                "parent_block_hash" => {
+                    record.parent_block_hash = value.as_val()?
+                }
+                "parent_state_root" => {
+                    record.parent_state_root = value.as_val()?
+                }
+                "reward_epoch_hash" => {
+                    record.reward_epoch_hash = decode_option(&value)?
+                }
+                "anticone_penalty_cutoff_epoch_hash" => {
+                    record.anticone_penalty_cutoff_epoch_hash =
+                        decode_option(&value)?
+                }
+                "block_hashes" => record.block_hashes = value.as_list()?,
+                "block_transactions" => {
+                    record.block_txs = value
+                        .as_list::<String>()?
+                        .iter()
+                        .map(|size| {
+                            size.parse().map_err(|_| {
+                                DecoderError::Custom("invalid block tx count")
+                            })
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "transactions" => {
+                    record.transactions = value
+                        .as_list::<SignedTransaction>()?
+                        .into_iter()
+                        .map(Arc::new)
+                        .collect()
+                }
+                "block_authors" => record.block_authors = value.as_list()?,
+                "reward_epoch_block_hashes" => {
+                    record.reward_epoch_block_hashes = value.as_list()?
+                }
+                "reward_epoch_anticone_overlimited" => {
+                    record.reward_epoch_anticone_overlimited =
+                        value.as_list()?
+                }
+                "reward_epoch_anticone_set_sizes" => {
+                    record.reward_epoch_anticone_set_sizes = value
+                        .as_list::<u64>()?
+                        .into_iter()
+                        .map(|size| size as usize)
+                        .collect()
+                }
+                "reward_epoch_anticone_difficulties" => {
+                    record.reward_epoch_anticone_difficulties = value
+                        .as_list::<Vec<u8>>()?
+                        .iter()
+                        .map(|bytes| U512::from(bytes.as_slice()))
+                        .collect()
+                }
+                "anticone_overlimit_blocks" => {
+                    record.anticone_overlimit_blocks = value.as_list()?
+                }
+                "state_roots_post_tx" => {
+                    record.state_roots_post_tx = value.as_list()?
+                }
+                "state_root_after_applying_rewards" => {
+                    record.state_root_after_applying_rewards = value.as_val()?
+                }
+                "state_ops" => record.state_ops = value.as_list()?,
+                "receipts" => record.receipts = value.as_list()?,
+                _ => {}
+            }
+        }
+        Ok(record)
+    }
+}
+
+fn decode_option<T: Decodable>(rlp: &Rlp) -> Result<Option<T>, DecoderError> {
+    if rlp.item_count()? == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(rlp.val_at(0)?))
+    }
+}
+
+use super::consensus_executor::RewardExecutionInfo;
+use crate::block_data_manager::BlockDataManager;
+use cfx_types::{Address, H256, U256, U512};
+use primitives::{Receipt, SignedTransaction};
 use rlp::*;
 use std::{fmt::Display, sync::Arc, vec::Vec};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keylib::{Generator, Random};
+    use primitives::{transaction::Action, Transaction};
+
+    #[test]
+    fn test_rlp_round_trip() {
+        let keypair = Random.generate().unwrap();
+        let tx = Transaction {
+            nonce: 1.into(),
+            gas_price: 1.into(),
+            gas: 21000.into(),
+            action: Action::Call(Address::from(1u64)),
+            value: 100.into(),
+            data: vec![1, 2, 3],
+        }
+        .sign(keypair.secret());
+        let record = ComputeEpochDebugRecord {
+            parent_block_hash: H256::from(1u64),
+            parent_state_root: H256::from(2u64),
+            reward_epoch_hash: Some(H256::from(3u64)),
+            anticone_penalty_cutoff_epoch_hash: None,
+            block_hashes: vec![H256::from(4u64), H256::from(5u64)],
+            block_txs: vec![0, 1],
+            transactions: vec![Arc::new(tx)],
+            reward_epoch_block_hashes: vec![H256::from(6u64)],
+            reward_epoch_anticone_overlimited: vec![true],
+            reward_epoch_anticone_set_sizes: vec![7],
+            reward_epoch_anticone_difficulties: vec![U512::from(8)],
+            anticone_overlimit_blocks: vec![H256::from(6u64)],
+            state_roots_post_tx: vec![H256::from(9u64)],
+            state_root_after_applying_rewards: H256::from(10u64),
+            state_ops: vec![StateOp::OpNameKeyMaybeValue {
+                op_name: "set".into(),
+                key: vec![11],
+                maybe_value: Some(vec![12]),
+            }],
+            receipts: vec![Receipt::new(0, 21000.into(), vec![])],
+            ..Default::default()
+        };
+
+        let decoded: ComputeEpochDebugRecord =
+            rlp::decode(&rlp::encode(&record)).unwrap();
+        assert_eq!(decoded.parent_block_hash, record.parent_block_hash);
+        assert_eq!(decoded.parent_state_root, record.parent_state_root);
+        assert_eq!(decoded.reward_epoch_hash, record.reward_epoch_hash);
+        assert_eq!(decoded.anticone_penalty_cutoff_epoch_hash, None);
+        assert_eq!(decoded.block_hashes, record.block_hashes);
+        assert_eq!(decoded.block_txs, record.block_txs);
+        assert_eq!(decoded.transactions, record.transactions);
+        assert_eq!(
+            decoded.reward_epoch_block_hashes,
+            record.reward_epoch_block_hashes
+        );
+        assert_eq!(
+            decoded.reward_epoch_anticone_overlimited,
+            record.reward_epoch_anticone_overlimited
+        );
+        assert_eq!(
+            decoded.reward_epoch_anticone_set_sizes,
+            record.reward_epoch_anticone_set_sizes
+        );
+        assert_eq!(
+            decoded.reward_epoch_anticone_difficulties,
+            record.reward_epoch_anticone_difficulties
+        );
+        assert_eq!(
+            decoded.anticone_overlimit_blocks,
+            record.anticone_overlimit_blocks
+        );
+        assert_eq!(decoded.state_roots_post_tx, record.state_roots_post_tx);
+        assert_eq!(
+            decoded.state_root_after_applying_rewards,
+            record.state_root_after_applying_rewards
+        );
+        assert_eq!(
+            rlp::encode_list(&decoded.state_ops),
+            rlp::encode_list(&record.state_ops)
+        );
+        assert_eq!(decoded.receipts, record.receipts);
+    }
+}
@@ -0,0 +1,31 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::event_bus::EventBus;
+use cfx_types::H256;
+use std::sync::Arc;
+
+/// What the consensus graph publishes to its subscribers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConsensusEvent {
+    /// A block has been inserted into the consensus graph.
+    NewBlockInserted { hash: H256 },
+    /// The pivot chain has switched to another branch after `fork_point`.
+    /// `old_epochs` and `new_epochs` are the pivot block hashes of the
+    /// replaced and the new epochs after `fork_point`, in epoch order.
+    PivotChainReorg {
+        old_epochs: Vec<H256>,
+        new_epochs: Vec<H256>,
+        fork_point: H256,
+    },
+    /// An epoch on the local pivot chain has been executed, or found executed
+    /// before it became part of the local pivot chain.
+    EpochExecuted {
+        epoch_hash: H256,
+        state_root: H256,
+        receipts_root: H256,
+    },
+}
+
+pub type SharedConsensusEventBus = Arc<EventBus<ConsensusEvent>>;
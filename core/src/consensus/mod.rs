@@ -5,22 +5,29 @@
 mod confirmation;
 mod consensus_executor;
 mod debug;
+mod events;
+
+pub use self::{
+    confirmation::ConfirmationTrait,
+    consensus_executor::{ConsensusExecutionHandler, RewardExecutionInfo},
+    debug::ComputeEpochDebugRecord,
+    events::{ConsensusEvent, SharedConsensusEventBus},
+};
 
 use self::debug::*;
 use super::consensus::consensus_executor::ConsensusExecutor;
 use crate::{
-    block_data_manager::BlockDataManager,
+    block_data_manager::{missing_on_error, BlockDataManager},
     cache_manager::{CacheId, CacheManager},
-    consensus::{
-        confirmation::ConfirmationTrait,
-        consensus_executor::{EpochExecutionTask, RewardExecutionInfo},
-    },
-    db::COL_MISC,
+    consensus::consensus_executor::{EpochExecutionTask, RewardExecutionInfo},
+    db::{DbResult, COL_MISC},
+    event_bus::EventBus,
     ext_db::SystemDB,
     hash::KECCAK_EMPTY_LIST_RLP,
+    machine::{new_machine_with_builtin, BuiltinSpec},
     pow::ProofOfWorkConfig,
     state::State,
-    statedb::StateDb,
+    statedb::{AccountDump, StateDb, StorageKey},
     statistics::SharedStatistics,
     storage::{state::StateTrait, StorageManager, StorageManagerTrait},
     transaction_pool::SharedTransactionPool,
@@ -29,14 +36,16 @@ use crate::{
 use cfx_types::{Bloom, SignedBigNum, H160, H256, U256, U512};
 use fenwick_tree::FenwickTree;
 use link_cut_tree::MinLinkCutTree;
+use metrics::{Histogram, Meter};
 use parking_lot::{Mutex, RwLock};
 use primitives::{
     filter::{Filter, FilterError},
     log_entry::{LocalizedLogEntry, LogEntry},
     receipt::Receipt,
+    state_proof::MERKLE_NULL_NODE,
     transaction::Action,
     Block, BlockHeaderBuilder, EpochNumber, SignedTransaction,
-    TransactionAddress,
+    TransactionAddress, TrieProof,
 };
 use rayon::prelude::*;
 use rlp::*;
@@ -47,14 +56,26 @@ use std::{
     collections::{HashMap, HashSet, VecDeque},
     io::Write,
     iter::FromIterator,
-    sync::Arc,
+    sync::{mpsc::Receiver, Arc},
     thread::sleep,
     time::Duration,
 };
 
+lazy_static! {
+    static ref CONSENSUS_ON_NEW_BLOCK_TIMER: Histogram =
+        Histogram::register("consensus_on_new_block");
+    static ref CONSENSUS_NEW_BLOCK_METER: Meter =
+        Meter::register("consensus_new_blocks");
+}
+
 const MIN_MAINTAINED_RISK: f64 = 0.000001;
 const MAX_NUM_MAINTAINED_RISK: usize = 10;
 
+/// The maximal confirmation risk of the epoch `latest_confirmed` refers to.
+pub const CONFIRMED_EPOCH_RISK_THRESHOLD: f64 = 0.0001;
+/// The maximal confirmation risk of the epoch `latest_stable` refers to.
+pub const STABLE_EPOCH_RISK_THRESHOLD: f64 = MIN_MAINTAINED_RISK;
+
 pub const DEFERRED_STATE_EPOCH_COUNT: u64 = 5;
 
 /// `REWARD_EPOCH_COUNT` needs to be larger than
@@ -108,6 +129,8 @@ pub struct ConsensusConfig {
     pub bench_mode: bool,
     // The configuration used by inner data
     pub inner_conf: ConsensusInnerConfig,
+    // The built-in contracts enabled for transaction execution
+    pub builtin_specs: Vec<BuiltinSpec>,
 }
 
 #[derive(Debug)]
@@ -246,6 +269,10 @@ pub struct ConsensusGraphInner {
     // execution and the block packaging and verification.
     // optimistic_executed_height is the number of step to go ahead
     optimistic_executed_height: Option<usize>,
+    // The latest pivot epochs whose confirmation risks are below
+    // `CONFIRMED_EPOCH_RISK_THRESHOLD` and `STABLE_EPOCH_RISK_THRESHOLD`.
+    latest_confirmed_epoch_number: usize,
+    latest_stable_epoch_number: usize,
     pub inner_conf: ConsensusInnerConfig,
 }
 
@@ -282,6 +309,8 @@ impl ConsensusGraphInner {
             pivot_chain_metadata: Vec::new(),
             pivot_future_weights: FenwickTree::new(),
             optimistic_executed_height: None,
+            latest_confirmed_epoch_number: 0,
+            latest_stable_epoch_number: 0,
             terminal_hashes: Default::default(),
             genesis_block_index: NULL,
             genesis_block_state_root: data_man
@@ -1068,6 +1097,16 @@ impl ConsensusGraphInner {
             EpochNumber::Earliest => 0,
             EpochNumber::LatestMined => self.best_epoch_number(),
             EpochNumber::LatestState => self.best_state_epoch_number(),
+            // The pivot chain may have been switched to a shorter one since
+            // the risks were computed.
+            EpochNumber::LatestConfirmed => min(
+                self.latest_confirmed_epoch_number,
+                self.best_state_epoch_number(),
+            ),
+            EpochNumber::LatestStable => min(
+                self.latest_stable_epoch_number,
+                self.best_state_epoch_number(),
+            ),
             EpochNumber::Number(num) => {
                 let epoch_num: usize = num.as_usize();
                 if epoch_num > self.best_epoch_number() {
@@ -1142,6 +1181,19 @@ impl ConsensusGraphInner {
         )
     }
 
+    /// Returns the pivot block hash of the given epoch, and the pivot block
+    /// hash of the epoch whose state its `deferred_state_root` commits to.
+    pub fn get_state_proof_epochs(
+        &self, epoch_number: EpochNumber,
+    ) -> Result<(H256, H256), String> {
+        let height = self.get_height_from_epoch_number(epoch_number)?;
+        let pivot_hash = self.arena[self.pivot_chain[height]].hash;
+        let deferred_height =
+            height.saturating_sub(DEFERRED_STATE_EPOCH_COUNT as usize);
+        let deferred_hash = self.arena[self.pivot_chain[deferred_height]].hash;
+        Ok((pivot_hash, deferred_hash))
+    }
+
     pub fn terminal_hashes(&self) -> Vec<H256> {
         self.terminal_hashes
             .iter()
@@ -1197,12 +1249,21 @@ impl ConsensusGraphInner {
     pub fn block_receipts_by_hash(
         &self, hash: &H256, update_cache: bool,
     ) -> Option<Arc<Vec<Receipt>>> {
-        self.get_epoch_hash_for_block(hash).and_then(|epoch| {
-            trace!("Block {} is in epoch {}", hash, epoch);
-            self.data_man
-                .block_results_by_hash_with_epoch(hash, &epoch, update_cache)
-                .map(|r| r.receipts)
-        })
+        missing_on_error(self.try_block_receipts_by_hash(hash, update_cache))
+    }
+
+    pub fn try_block_receipts_by_hash(
+        &self, hash: &H256, update_cache: bool,
+    ) -> DbResult<Option<Arc<Vec<Receipt>>>> {
+        let epoch = match self.get_epoch_hash_for_block(hash) {
+            Some(epoch) => epoch,
+            None => return Ok(None),
+        };
+        trace!("Block {} is in epoch {}", hash, epoch);
+        Ok(self
+            .data_man
+            .try_block_results_by_hash_with_epoch(hash, &epoch, update_cache)?
+            .map(|r| r.receipts))
     }
 
     pub fn is_stable(&self, block_hash: &H256) -> Option<bool> {
@@ -1225,20 +1286,34 @@ impl ConsensusGraphInner {
 
     pub fn get_transaction_receipt_with_address(
         &self, tx_hash: &H256,
-    ) -> Option<(Receipt, TransactionAddress)> {
+    ) -> DbResult<Option<(Receipt, TransactionAddress)>> {
         trace!("Get receipt with tx_hash {}", tx_hash);
-        let address =
-            self.data_man.transaction_address_by_hash(tx_hash, false)?;
+        let address = match self
+            .data_man
+            .try_transaction_address_by_hash(tx_hash, false)?
+        {
+            Some(address) => address,
+            None => return Ok(None),
+        };
         // receipts should never be None if address is not None because
-        let receipts =
-            self.block_receipts_by_hash(&address.block_hash, false)?;
-        Some((
-            receipts
-                .get(address.index)
-                .expect("Error: can't get receipt by tx_address ")
-                .clone(),
-            address,
-        ))
+        let receipts = match self
+            .try_block_receipts_by_hash(&address.block_hash, false)?
+        {
+            Some(receipts) => receipts,
+            None => return Ok(None),
+        };
+        // The receipts of a block don't match its transaction addresses if the
+        // database is inconsistent.
+        match receipts.get(address.index) {
+            Some(receipt) => Ok(Some((receipt.clone(), address))),
+            None => {
+                warn!(
+                    "No receipt at index {} of block {:?} for transaction {:?}",
+                    address.index, address.block_hash, tx_hash
+                );
+                Ok(None)
+            }
+        }
     }
 
     pub fn transaction_count(
@@ -1336,6 +1411,32 @@ pub struct FinalityManager {
     pub risks_less_than: VecDeque<f64>,
 }
 
+impl FinalityManager {
+    /// The latest epoch whose confirmation risk is at most `threshold`. The
+    /// epochs before `lowest_epoch_num` have the minimal maintained risk.
+    /// No risk is maintained if `latest_epoch`, the latest epoch whose risk
+    /// was computed, already has the minimal risk.
+    fn latest_epoch_with_risk_at_most(
+        &self, threshold: f64, latest_epoch: usize,
+    ) -> usize {
+        if self.risks_less_than.is_empty() {
+            return latest_epoch;
+        }
+        let mut latest = self.lowest_epoch_num.saturating_sub(1);
+        let mut max_risk = 0.0;
+        for (i, risk) in self.risks_less_than.iter().enumerate() {
+            if max_risk < *risk {
+                max_risk = *risk;
+            }
+            if max_risk > threshold {
+                break;
+            }
+            latest = self.lowest_epoch_num + i;
+        }
+        latest
+    }
+}
+
 pub struct TotalWeightInPast {
     pub old: U256,
     pub cur: U256,
@@ -1362,6 +1463,7 @@ pub struct ConsensusGraph {
     pub statistics: SharedStatistics,
     finality_manager: RwLock<FinalityManager>,
     pub total_weight_in_past_2d: RwLock<TotalWeightInPast>,
+    event_bus: SharedConsensusEventBus,
 }
 
 pub type SharedConsensusGraph = Arc<ConsensusGraph>;
@@ -1426,10 +1528,16 @@ impl ConsensusGraph {
                 data_man.clone(),
                 conf.inner_conf.clone(),
             )));
+        let event_bus = Arc::new(EventBus::new());
         let executor = Arc::new(ConsensusExecutor::start(
             data_man.clone(),
             vm,
+            Arc::new(
+                new_machine_with_builtin(&conf.builtin_specs)
+                    .expect("builtin names are checked when specs are loaded"),
+            ),
             inner.clone(),
+            event_bus.clone(),
             conf.bench_mode,
         ));
 
@@ -1450,9 +1558,15 @@ impl ConsensusGraph {
                 cur: U256::zero(),
                 delta: U256::zero(),
             }),
+            event_bus,
         }
     }
 
+    /// Subscribe to the `ConsensusEvent`s published from now on.
+    pub fn subscribe_events(&self) -> Receiver<ConsensusEvent> {
+        self.event_bus.subscribe()
+    }
+
     pub fn update_total_weight_in_past(&self) {
         let mut total_weight = self.total_weight_in_past_2d.write();
         total_weight.delta = total_weight.cur - total_weight.old;
@@ -1549,8 +1663,9 @@ impl ConsensusGraph {
             let w_0 =
                 U256::from(inner.weight_tree.get(inner.genesis_block_index));
             let mut risks = VecDeque::new();
-            let mut epoch_num =
+            let latest_epoch =
                 inner.pivot_chain.len() - DEFERRED_STATE_EPOCH_COUNT as usize;
+            let mut epoch_num = latest_epoch;
             let mut count = 0;
             while epoch_num > 0 && count < MAX_NUM_MAINTAINED_RISK {
                 let risk = self.confirmation_risk(inner, w_0, w_4, epoch_num);
@@ -1571,6 +1686,16 @@ impl ConsensusGraph {
             let mut finality = self.finality_manager.write();
             finality.lowest_epoch_num = epoch_num;
             finality.risks_less_than = risks;
+            inner.latest_confirmed_epoch_number = finality
+                .latest_epoch_with_risk_at_most(
+                    CONFIRMED_EPOCH_RISK_THRESHOLD,
+                    latest_epoch,
+                );
+            inner.latest_stable_epoch_number = finality
+                .latest_epoch_with_risk_at_most(
+                    STABLE_EPOCH_RISK_THRESHOLD,
+                    latest_epoch,
+                );
         }
     }
 
@@ -1655,7 +1780,7 @@ impl ConsensusGraph {
             last_epoch_number -= 1;
 
             for hash in hashes {
-                let block = self.data_man.block_by_hash(&hash, false).unwrap();
+                let block = self.data_man.block_by_hash(&hash, false)?;
                 for tx in block.transactions.iter() {
                     if tx_hashes.insert(tx.hash()) {
                         prices.push(tx.gas_price().clone());
@@ -1687,6 +1812,165 @@ impl ConsensusGraph {
             .get_balance_validated(address, epoch_number)
     }
 
+    /// List at most `limit` accounts with their storage in the state of the
+    /// given epoch, starting from `start_key`, and the key of the next
+    /// account. See `StateDb::dump_accounts`.
+    pub fn dump_accounts(
+        &self, epoch_number: EpochNumber, start_key: &[u8], limit: usize,
+    ) -> Result<(Vec<AccountDump>, Option<Vec<u8>>), String> {
+        let hash = {
+            let inner = self.inner.read();
+            inner.validate_stated_epoch(&epoch_number)?;
+            inner.get_hash_from_epoch_number(epoch_number)?
+        };
+        // Don't hold the consensus lock while iterating the state.
+        let state_db = StateDb::new(
+            self.data_man
+                .storage_manager
+                .get_state_at(hash)
+                .map_err(|e| format!("Failed to get state: {:?}", e))?,
+        );
+        state_db
+            .dump_accounts(start_key, limit)
+            .map_err(|e| format!("Failed to dump state: {:?}", e))
+    }
+
+    /// Returns the `deferred_state_root` of the pivot block of the given
+    /// epoch, together with the proofs of the account and of its storage
+    /// entries against it.
+    pub fn get_state_proof(
+        &self, address: H160, storage_keys: &Vec<H256>,
+        epoch_number: EpochNumber,
+    ) -> Result<(H256, TrieProof, Vec<TrieProof>), String>
+    {
+        let (pivot_hash, deferred_hash) =
+            self.inner.read().get_state_proof_epochs(epoch_number)?;
+        // Don't hold the consensus lock while reading the state, since any
+        // peer can ask for proofs.
+        let deferred_state_root = *self
+            .data_man
+            .block_header_by_hash(&pivot_hash)
+            .ok_or_else(|| format!("Block {:?} not found", pivot_hash))?
+            .deferred_state_root();
+
+        let state = self
+            .data_man
+            .storage_manager
+            .get_state_at(deferred_hash)
+            .map_err(|e| format!("Failed to get state: {:?}", e))?;
+        let state_root = state
+            .get_state_root()
+            .map_err(|e| format!("Failed to get state root: {:?}", e))?
+            .unwrap_or(MERKLE_NULL_NODE);
+        if state_root != deferred_state_root {
+            return Err(format!(
+                "State of epoch {:?} has root {:?}, which doesn't match the deferred state root {:?} of block {:?}",
+                deferred_hash, state_root, deferred_state_root, pivot_hash
+            ));
+        }
+
+        let account_proof = state
+            .get_proof(StorageKey::new_account_key(&address).as_ref())
+            .map_err(|e| format!("Failed to get account proof: {:?}", e))?;
+        let mut storage_proofs = Vec::with_capacity(storage_keys.len());
+        for key in storage_keys {
+            storage_proofs.push(
+                state
+                    .get_proof(
+                        StorageKey::new_storage_key(&address, key).as_ref(),
+                    )
+                    .map_err(|e| {
+                        format!("Failed to get storage proof: {:?}", e)
+                    })?,
+            );
+        }
+
+        Ok((deferred_state_root, account_proof, storage_proofs))
+    }
+
+    /// The pivot block hash of the epoch whose state and receipts the
+    /// deferred roots of `block_hash` commit to, i.e. its ancestor
+    /// `DEFERRED_STATE_EPOCH_COUNT` levels up, or genesis.
+    fn get_deferred_epoch_hash(
+        &self, block_hash: &H256,
+    ) -> Result<H256, String> {
+        let mut hash = *block_hash;
+        for _ in 0..DEFERRED_STATE_EPOCH_COUNT {
+            let header = self
+                .data_man
+                .block_header_by_hash(&hash)
+                .ok_or_else(|| format!("Block {:?} not found", hash))?;
+            if header.height() == 0 {
+                break;
+            }
+            hash = *header.parent_hash();
+        }
+        Ok(hash)
+    }
+
+    /// Returns the proofs of `keys` against the `deferred_state_root` of
+    /// `block_hash`, so that light nodes can check them against the header.
+    pub fn get_deferred_state_proofs(
+        &self, block_hash: &H256, keys: &[Vec<u8>],
+    ) -> Result<Vec<TrieProof>, String> {
+        let epoch_hash = self.get_deferred_epoch_hash(block_hash)?;
+        let state = self
+            .data_man
+            .storage_manager
+            .get_state_at(epoch_hash)
+            .map_err(|e| format!("Failed to get state: {:?}", e))?;
+        if !state.does_exist() {
+            return Err(format!("State of epoch {:?} not found", epoch_hash));
+        }
+        keys.iter()
+            .map(|key| {
+                state
+                    .get_proof(key)
+                    .map_err(|e| format!("Failed to get proof: {:?}", e))
+            })
+            .collect()
+    }
+
+    /// Returns the receipts of each block of the epoch that the
+    /// `deferred_receipts_root` of `block_hash` commits to.
+    pub fn get_deferred_epoch_receipts(
+        &self, block_hash: &H256,
+    ) -> Result<Vec<Arc<Vec<Receipt>>>, String> {
+        let epoch_hash = self.get_deferred_epoch_hash(block_hash)?;
+        let epoch_block_hashes = {
+            let inner = self.inner.read();
+            let index = *inner
+                .indices
+                .get(&epoch_hash)
+                .ok_or_else(|| format!("Block {:?} not found", epoch_hash))?;
+            // The receipts root of genesis commits to no receipts.
+            if index == inner.genesis_block_index {
+                return Ok(Vec::new());
+            }
+            if !inner.indices_in_epochs.contains_key(&index) {
+                return Err(format!(
+                    "Block {:?} is not a pivot block",
+                    epoch_hash
+                ));
+            }
+            inner.get_epoch_block_hashes(index)
+        };
+        epoch_block_hashes
+            .iter()
+            .map(|hash| {
+                self.data_man
+                    .block_results_by_hash_with_epoch(hash, &epoch_hash, false)
+                    .map(|result| result.receipts)
+                    .ok_or_else(|| {
+                        format!(
+                            "Receipts of block {:?} in epoch {:?} not found",
+                            hash, epoch_hash
+                        )
+                    })
+            })
+            .collect()
+    }
+
     pub fn get_related_transactions(
         &self, address: H160, num_txs: usize, epoch_number: EpochNumber,
     ) -> Result<Vec<Arc<SignedTransaction>>, String> {
@@ -1971,9 +2255,20 @@ impl ConsensusGraph {
         self.compute_state_for_block(&hash, inner)
     }
 
+    /// Read a block which the synchronization graph has inserted, and so must
+    /// be in the database.
+    fn inserted_block(
+        &self, hash: &H256, update_cache: bool,
+    ) -> Result<Arc<Block>, String> {
+        self.data_man
+            .try_block_by_hash(hash, update_cache)
+            .map_err(|e| format!("Failed to read block {:?}: {}", hash, e))?
+            .ok_or_else(|| format!("Block {:?} is not in the database", hash))
+    }
+
     fn log_debug_epoch_computation(
         &self, epoch_index: usize, inner: &ConsensusGraphInner,
-    ) -> ComputeEpochDebugRecord {
+    ) -> Result<ComputeEpochDebugRecord, String> {
         let epoch_block_hash = inner.arena[epoch_index].hash;
 
         let epoch_block_hashes = {
@@ -1985,6 +2280,10 @@ impl ConsensusGraph {
                 .map(|index| inner.arena[*index].hash)
                 .collect::<Vec<_>>()
         };
+        let blocks = epoch_block_hashes
+            .iter()
+            .map(|hash| self.inserted_block(hash, false))
+            .collect::<Result<Vec<_>, String>>()?;
 
         // Parent state root.
         let parent_index = inner.arena[epoch_index].parent;
@@ -1993,10 +2292,16 @@ impl ConsensusGraph {
             .data_man
             .storage_manager
             .get_state_at(parent_block_hash)
-            .unwrap()
-            .get_state_root()
-            .unwrap()
-            .unwrap();
+            .and_then(|state| state.get_state_root())
+            .map_err(|e| {
+                format!(
+                    "Failed to get the state root of {:?}: {}",
+                    parent_block_hash, e
+                )
+            })?
+            .ok_or_else(|| {
+                format!("No state root for epoch {:?}", parent_block_hash)
+            })?;
 
         // Recompute epoch.
         let anticone_cut_height =
@@ -2024,6 +2329,18 @@ impl ConsensusGraph {
 
         let reward_execution_info = inner
             .get_reward_execution_info_from_index(&self.data_man, reward_index);
+        let reward_epoch_block_hashes = reward_execution_info
+            .as_ref()
+            .map_or(Vec::new(), |info| {
+                info.epoch_blocks.iter().map(|block| block.hash()).collect()
+            });
+        let reward_anticone_info = reward_execution_info.as_ref().map(|info| {
+            (
+                info.epoch_block_anticone_overlimited.clone(),
+                info.epoch_block_anticone_set_sizes.clone(),
+                info.epoch_block_anticone_difficulties.clone(),
+            )
+        });
         let task = EpochExecutionTask::new(
             epoch_block_hash,
             epoch_block_hashes.clone(),
@@ -2050,11 +2367,6 @@ impl ConsensusGraph {
                     None
                 };
 
-            let blocks = epoch_block_hashes
-                .iter()
-                .map(|hash| self.data_man.block_by_hash(hash, false).unwrap())
-                .collect::<Vec<_>>();
-
             debug_record.block_hashes = epoch_block_hashes;
             debug_record.block_txs = blocks
                 .iter()
@@ -2069,14 +2381,23 @@ impl ConsensusGraph {
                 .iter()
                 .map(|block| *block.block_header.author())
                 .collect::<Vec<_>>();
+
+            debug_record.reward_epoch_block_hashes = reward_epoch_block_hashes;
+            if let Some((overlimited, set_sizes, difficulties)) =
+                reward_anticone_info
+            {
+                debug_record.reward_epoch_anticone_overlimited = overlimited;
+                debug_record.reward_epoch_anticone_set_sizes = set_sizes;
+                debug_record.reward_epoch_anticone_difficulties = difficulties;
+            }
         }
         self.executor.enqueue_epoch(task);
         self.executor.wait_for_result(epoch_block_hash);
 
-        Arc::try_unwrap(debug_record_data)
+        Ok(Arc::try_unwrap(debug_record_data)
             .unwrap()
             .into_inner()
-            .unwrap()
+            .unwrap())
     }
 
     fn log_invalid_state_root(
@@ -2084,7 +2405,9 @@ impl ConsensusGraph {
         deferred: usize, inner: &ConsensusGraphInner,
     ) -> std::io::Result<()>
     {
-        let debug_record = self.log_debug_epoch_computation(deferred, inner);
+        let debug_record = self
+            .log_debug_epoch_computation(deferred, inner)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         let debug_record_rlp = debug_record.rlp_bytes();
 
         let deferred_block_hash = inner.arena[deferred].hash;
@@ -2618,8 +2941,8 @@ impl ConsensusGraph {
     /// finish the building from db!ss
     pub fn on_new_block_construction_only(
         &self, hash: &H256, blockset_in_own_epoch: HashSet<usize>,
-    ) {
-        let block = self.data_man.block_by_hash(hash, false).unwrap();
+    ) -> Result<(), String> {
+        let block = self.inserted_block(hash, false)?;
 
         let inner = &mut *self.inner.write();
 
@@ -2631,7 +2954,7 @@ impl ConsensusGraph {
 
         inner.compute_anticone(me);
         let fully_valid = if let Some(partial_invalid) =
-            self.data_man.block_status_from_db(hash)
+            missing_on_error(self.data_man.block_status_from_db(hash))
         {
             !partial_invalid
         } else {
@@ -2660,7 +2983,7 @@ impl ConsensusGraph {
         };
         if !fully_valid {
             inner.arena[me].data.partial_invalid = true;
-            return;
+            return Ok(());
         }
 
         self.update_lcts_initial(inner, me);
@@ -2670,14 +2993,18 @@ impl ConsensusGraph {
         inner.arena[me].adaptive = adaptive;
 
         self.update_lcts_finalize(inner, me, stable);
+        Ok(())
     }
 
     /// This is the main function that SynchronizationGraph calls to deliver a
     /// new block to the consensus graph.
     pub fn on_new_block(
         &self, hash: &H256, blockset_in_own_epoch: HashSet<usize>,
-    ) {
-        let block = self.data_man.block_by_hash(hash, true).unwrap();
+    ) -> Result<(), String> {
+        let _timer = CONSENSUS_ON_NEW_BLOCK_TIMER.start_timer();
+        CONSENSUS_NEW_BLOCK_METER.mark(1);
+
+        let block = self.inserted_block(hash, true)?;
 
         debug!(
             "insert new block into consensus: block_header={:?} tx_count={}, block_size={}",
@@ -2773,6 +3100,7 @@ impl ConsensusGraph {
         inner.arena[me].adaptive = adaptive;
 
         let mut extend_pivot = false;
+        let mut reorg_event = None;
         let mut fork_at = inner.pivot_chain.len() + 1;
         let old_pivot_chain_len = inner.pivot_chain.len();
         if fully_valid {
@@ -2800,6 +3128,10 @@ impl ConsensusGraph {
                     (&prev_weight, &inner.arena[prev].hash),
                 ) {
                     // The new subtree is heavier, update pivot chain
+                    let old_epochs: Vec<H256> = inner.pivot_chain[fork_at..]
+                        .iter()
+                        .map(|index| inner.arena[*index].hash)
+                        .collect();
                     inner.pivot_chain.truncate(fork_at);
                     let mut u = new;
                     loop {
@@ -2826,6 +3158,15 @@ impl ConsensusGraph {
                         }
                         u = heaviest;
                     }
+                    reorg_event = Some(ConsensusEvent::PivotChainReorg {
+                        old_epochs,
+                        new_epochs: inner.pivot_chain[fork_at..]
+                            .iter()
+                            .map(|index| inner.arena[*index].hash)
+                            .collect(),
+                        fork_point: inner.arena[inner.pivot_chain[fork_at - 1]]
+                            .hash,
+                    });
                     fork_at
                 } else {
                     // The previous subtree is still heavier, nothing is updated
@@ -2927,9 +3268,15 @@ impl ConsensusGraph {
                 .insert(me);
         }
 
+        self.event_bus
+            .publish(ConsensusEvent::NewBlockInserted { hash: *hash });
+        if let Some(reorg_event) = reorg_event {
+            self.event_bus.publish(reorg_event);
+        }
+
         // Now we can safely return
         if !fully_valid {
-            return;
+            return Ok(());
         }
 
         let to_state_pos = if inner.pivot_chain.len()
@@ -2980,6 +3327,7 @@ impl ConsensusGraph {
         };
         inner.persist_terminals();
         debug!("Finish processing block in ConsensusGraph: hash={:?}", hash);
+        Ok(())
     }
 
     pub fn best_block_hash(&self) -> H256 {
@@ -2998,19 +3346,35 @@ impl ConsensusGraph {
 
     pub fn get_transaction_info_by_hash(
         &self, hash: &H256,
-    ) -> Option<(SignedTransaction, Receipt, TransactionAddress)> {
+    ) -> DbResult<Option<(SignedTransaction, Receipt, TransactionAddress)>>
+    {
         // We need to hold the inner lock to ensure that tx_address and receipts
         // are consistent
         let inner = self.inner.read();
         if let Some((receipt, address)) =
-            inner.get_transaction_receipt_with_address(hash)
+            inner.get_transaction_receipt_with_address(hash)?
         {
-            let block =
-                self.data_man.block_by_hash(&address.block_hash, false)?;
-            let transaction = (*block.transactions[address.index]).clone();
-            Some((transaction, receipt, address))
+            let block = match self
+                .data_man
+                .try_block_by_hash(&address.block_hash, false)?
+            {
+                Some(block) => block,
+                None => return Ok(None),
+            };
+            match block.transactions.get(address.index) {
+                Some(transaction) => {
+                    Ok(Some(((**transaction).clone(), receipt, address)))
+                }
+                None => {
+                    warn!(
+                        "No transaction at index {} of block {:?} for transaction {:?}",
+                        address.index, address.block_hash, hash
+                    );
+                    Ok(None)
+                }
+            }
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -3027,6 +3391,17 @@ impl ConsensusGraph {
     /// Returns the total number of blocks in consensus graph
     pub fn block_count(&self) -> usize { self.inner.read().indices.len() }
 
+    /// The number of epochs waiting in the queue of the execution thread.
+    pub fn execution_queue_size(&self) -> usize { self.executor.queue_size() }
+
+    /// The epoch number of the epoch most recently executed, or `None` if no
+    /// epoch has been executed since the node started.
+    pub fn last_executed_epoch_number(&self) -> Option<usize> {
+        self.executor
+            .last_executed_epoch()
+            .and_then(|hash| self.get_block_epoch_number(&hash))
+    }
+
     pub fn estimate_gas(&self, tx: &SignedTransaction) -> Result<U256, String> {
         self.call_virtual(tx, EpochNumber::LatestState)
             .map(|(_, gas_used)| gas_used)
@@ -3181,3 +3556,44 @@ impl ConsensusGraph {
 impl Drop for ConsensusGraph {
     fn drop(&mut self) { self.executor.stop(); }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FinalityManager, CONFIRMED_EPOCH_RISK_THRESHOLD,
+        STABLE_EPOCH_RISK_THRESHOLD,
+    };
+    use std::collections::VecDeque;
+
+    fn finality(lowest_epoch_num: usize, risks: &[f64]) -> FinalityManager {
+        FinalityManager {
+            lowest_epoch_num,
+            risks_less_than: risks.iter().cloned().collect::<VecDeque<_>>(),
+        }
+    }
+
+    #[test]
+    fn test_latest_epoch_with_risk_at_most() {
+        // Epochs 10, 11 and 12 have growing risks.
+        let manager = finality(10, &[0.00005, 0.0002, 0.01]);
+        let latest =
+            |threshold| manager.latest_epoch_with_risk_at_most(threshold, 12);
+        assert_eq!(latest(CONFIRMED_EPOCH_RISK_THRESHOLD), 10);
+        assert_eq!(latest(STABLE_EPOCH_RISK_THRESHOLD), 9);
+        assert_eq!(latest(1.0), 12);
+
+        // The risk of an epoch is at least the risks of the epochs before.
+        let manager = finality(10, &[0.0002, 0.00005]);
+        assert_eq!(manager.latest_epoch_with_risk_at_most(0.0001, 11), 9);
+    }
+
+    #[test]
+    fn test_latest_epoch_without_maintained_risks() {
+        // The latest epoch whose risk was computed has the minimal risk.
+        let manager = finality(0, &[]);
+        let latest =
+            |threshold| manager.latest_epoch_with_risk_at_most(threshold, 7);
+        assert_eq!(latest(CONFIRMED_EPOCH_RISK_THRESHOLD), 7);
+        assert_eq!(latest(STABLE_EPOCH_RISK_THRESHOLD), 7);
+    }
+}
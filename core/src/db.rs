@@ -22,9 +22,9 @@
 
 use kvdb::{DBTransaction, KeyValueDB};
 use parking_lot::RwLock;
-use std::{collections::HashMap, hash::Hash, ops::Deref};
+use std::{collections::HashMap, error, fmt, hash::Hash, io, ops::Deref};
 
-use rlp;
+use rlp::{self, DecoderError};
 
 // database columns
 /// Column for miscellaneous items
@@ -40,6 +40,59 @@ pub const COL_TX_ADDRESS: Option<u32> = Some(4);
 /// Number of columns in DB
 pub const NUM_COLUMNS: Option<u32> = Some(5);
 
+/// The key in `COL_MISC` of the schema version of the database.
+pub const DB_VERSION_KEY: &[u8] = b"db_version";
+/// The schema version of the databases written by this node. It has to be
+/// increased, with a migration in `db_migration`, whenever the columns or the
+/// encoding of the stored values change.
+pub const DB_VERSION: u32 = 1;
+
+/// A failure to read a value from the database.
+#[derive(Debug)]
+pub enum DbError {
+    /// The key-value store failed, e.g. because of an I/O error.
+    Io(io::Error),
+    /// The value stored for `key` in `col` cannot be decoded.
+    Corrupt {
+        col: Option<u32>,
+        key: Vec<u8>,
+        error: DecoderError,
+    },
+}
+
+impl DbError {
+    pub fn corrupt(col: Option<u32>, key: &[u8], error: DecoderError) -> Self {
+        DbError::Corrupt {
+            col,
+            key: key.to_vec(),
+            error,
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self { DbError::Io(e) }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Io(e) => write!(f, "Database I/O error: {}", e),
+            DbError::Corrupt { col, key, error } => write!(
+                f,
+                "Corrupt value of key {:?} in column {:?}: {:?}",
+                key, col, error
+            ),
+        }
+    }
+}
+
+impl error::Error for DbError {
+    fn description(&self) -> &str { "Database error" }
+}
+
+pub type DbResult<T> = Result<T, DbError>;
+
 /// Modes for updating caches.
 #[derive(Clone, Copy)]
 pub enum CacheUpdatePolicy {
@@ -179,7 +232,7 @@ pub trait Readable {
     /// Returns value for given key.
     fn read<T, R>(
         &self, col: Option<u32>, key: &Key<T, Target = R>,
-    ) -> Option<T>
+    ) -> DbResult<Option<T>>
     where
         T: rlp::Decodable,
         R: Deref<Target = [u8]>;
@@ -187,7 +240,7 @@ pub trait Readable {
     /// Returns value for given key either in cache or in database.
     fn read_with_cache<K, T, C>(
         &self, col: Option<u32>, cache: &RwLock<C>, key: &K,
-    ) -> Option<T>
+    ) -> DbResult<Option<T>>
     where
         K: Key<T> + Eq + Hash + Clone,
         T: Clone + rlp::Decodable,
@@ -196,25 +249,27 @@ pub trait Readable {
         {
             let read = cache.read();
             if let Some(v) = read.get(key) {
-                return Some(v.clone());
+                return Ok(Some(v.clone()));
             }
         }
 
-        self.read(col, key).map(|value: T| {
+        Ok(self.read(col, key)?.map(|value: T| {
             let mut write = cache.write();
             write.insert(key.clone(), value.clone());
             value
-        })
+        }))
     }
 
     /// Returns true if given value exists.
-    fn exists<T, R>(&self, col: Option<u32>, key: &Key<T, Target = R>) -> bool
+    fn exists<T, R>(
+        &self, col: Option<u32>, key: &Key<T, Target = R>,
+    ) -> DbResult<bool>
     where R: Deref<Target = [u8]>;
 
     /// Returns true if given value exists either in cache or in database.
     fn exists_with_cache<K, T, R, C>(
         &self, col: Option<u32>, cache: &RwLock<C>, key: &K,
-    ) -> bool
+    ) -> DbResult<bool>
     where
         K: Eq + Hash + Key<T, Target = R>,
         R: Deref<Target = [u8]>,
@@ -223,7 +278,7 @@ pub trait Readable {
         {
             let read = cache.read();
             if read.get(key).is_some() {
-                return true;
+                return Ok(true);
             }
         }
 
@@ -253,31 +308,64 @@ impl Writable for DBTransaction {
 impl<KVDB: KeyValueDB + ?Sized> Readable for KVDB {
     fn read<T, R>(
         &self, col: Option<u32>, key: &Key<T, Target = R>,
-    ) -> Option<T>
+    ) -> DbResult<Option<T>>
     where
         T: rlp::Decodable,
         R: Deref<Target = [u8]>,
     {
-        self.get(col, &key.key())
-            .unwrap_or_else(|_| {
-                panic!("db get failed, key: {:?}", &key.key() as &[u8])
-            })
-            .map(|v| rlp::decode(&v).expect("decode db value failed"))
+        let key = key.key();
+        match self.get(col, &key)? {
+            Some(value) => rlp::decode(&value)
+                .map(Some)
+                .map_err(|e| DbError::corrupt(col, &key, e)),
+            None => Ok(None),
+        }
     }
 
-    fn exists<T, R>(&self, col: Option<u32>, key: &Key<T, Target = R>) -> bool
+    fn exists<T, R>(
+        &self, col: Option<u32>, key: &Key<T, Target = R>,
+    ) -> DbResult<bool>
     where R: Deref<Target = [u8]> {
-        let result = self.get(col, &key.key());
-
-        match result {
-            Ok(v) => v.is_some(),
-            Err(err) => {
-                panic!(
-                    "db get failed, key: {:?}, err: {:?}",
-                    &key.key() as &[u8],
-                    err
-                );
+        Ok(self.get(col, &key.key())?.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DbError, Key, Readable, COL_MISC, NUM_COLUMNS};
+    use crate::ext_db::open_memory_database;
+    use kvdb::DBTransaction;
+
+    struct VersionKey;
+
+    impl Key<u32> for VersionKey {
+        type Target = Vec<u8>;
+
+        fn key(&self) -> Vec<u8> { b"version".to_vec() }
+    }
+
+    #[test]
+    fn test_read_corrupt_value() {
+        let db = open_memory_database(NUM_COLUMNS);
+        let kv = db.key_value();
+        assert_eq!(kv.read(COL_MISC, &VersionKey).unwrap(), None);
+        assert!(!kv.exists(COL_MISC, &VersionKey).unwrap());
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_MISC, b"version", &rlp::encode(&7u32));
+        kv.write(transaction).unwrap();
+        assert_eq!(kv.read(COL_MISC, &VersionKey).unwrap(), Some(7));
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_MISC, b"version", &[0xc1]);
+        kv.write(transaction).unwrap();
+        match kv.read(COL_MISC, &VersionKey) {
+            Err(DbError::Corrupt { col, key, .. }) => {
+                assert_eq!(col, COL_MISC);
+                assert_eq!(key, b"version".to_vec());
             }
+            other => panic!("unexpected result {:?}", other),
         }
+        assert!(kv.exists(COL_MISC, &VersionKey).unwrap());
     }
 }
@@ -0,0 +1,731 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Offline integrity check of the ledger database, and repair of a corrupt
+//! database by truncating it back to the last consistent epoch.
+//!
+//! Unlike `BlockDataManager`, nothing here panics on a corrupt value, so the
+//! check can run on a database which the node itself cannot open. The check
+//! only reads the database, and takes the genesis block from it instead of
+//! computing it.
+
+use crate::{
+    block_data_manager::BLOCK_STATUS_SUFFIX_BYTE,
+    consensus::DEFERRED_STATE_EPOCH_COUNT,
+    db::{
+        COL_BLOCKS, COL_BLOCK_RECEIPTS, COL_DELTA_TRIE, COL_MISC,
+        COL_TX_ADDRESS,
+    },
+    ext_db::SystemDB,
+    storage::{
+        state::{StateTrait, VerifiedNodeSet},
+        StorageManager, StorageManagerTrait,
+    },
+};
+use cfx_types::{Bloom, H256};
+use kvdb::DBTransaction;
+use primitives::{
+    receipt::Receipt, state_proof::MERKLE_NULL_NODE, Block, BlockHeader,
+    TransactionAddress,
+};
+use rlp::{Rlp, RlpStream};
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    iter,
+};
+
+const HASH_LEN: usize = 32;
+/// The prefix of the keys of the state roots in `COL_DELTA_TRIE`.
+const STATE_ROOT_KEY_PREFIX: &[u8] = b"state_root_db_key_for_epoch_id_";
+
+#[derive(Default)]
+pub struct DbCheckReport {
+    pub blocks: usize,
+    pub receipts: usize,
+    pub transaction_addresses: usize,
+    pub states: usize,
+    pub trie_nodes: usize,
+    /// One message for every corrupt or inconsistent entry.
+    pub problems: Vec<String>,
+    /// The lowest height of the blocks and epochs affected by a problem.
+    first_inconsistent_height: Option<u64>,
+    /// The only stored block at height 0.
+    genesis_hash: Option<H256>,
+    /// The entries which are corrupt, or which refer to missing data.
+    corrupt_entries: Vec<(Option<u32>, Vec<u8>)>,
+    headers: HashMap<H256, BlockHeader>,
+    transaction_hashes: HashMap<H256, Vec<H256>>,
+    receipt_epochs: HashMap<H256, H256>,
+    transaction_blocks: HashMap<H256, H256>,
+}
+
+impl DbCheckReport {
+    pub fn is_consistent(&self) -> bool { self.problems.is_empty() }
+
+    /// The last epoch which is not affected by any problem, or `None` if no
+    /// epoch is affected.
+    pub fn last_consistent_epoch(&self) -> Option<u64> {
+        self.first_inconsistent_height
+            .map(|height| height.saturating_sub(1))
+    }
+
+    fn problem(&mut self, problem: String, height: Option<u64>) {
+        warn!("{}", problem);
+        self.problems.push(problem);
+        if let Some(height) = height {
+            self.first_inconsistent_height = Some(
+                self.first_inconsistent_height
+                    .map_or(height, |first| min(first, height)),
+            );
+        }
+    }
+
+    fn corrupt_entry(
+        &mut self, col: Option<u32>, key: &[u8], problem: String,
+        height: Option<u64>,
+    )
+    {
+        self.corrupt_entries.push((col, key.to_vec()));
+        self.problem(problem, height);
+    }
+}
+
+/// Check the blocks, receipts, transaction indices and states in `db`. The
+/// merkle hashes of all the trie nodes of the states are recomputed.
+pub fn check(db: &SystemDB, storage_manager: &StorageManager) -> DbCheckReport {
+    let mut report = DbCheckReport::default();
+    check_blocks(db, &mut report);
+    find_genesis(&mut report);
+    check_references(db, &mut report);
+    check_states(db, storage_manager, &mut report);
+    check_receipts(db, &mut report);
+    check_transaction_addresses(db, &mut report);
+    report
+}
+
+fn check_blocks(db: &SystemDB, report: &mut DbCheckReport) {
+    let mut status_keys = Vec::new();
+    for (key, value) in db.key_value().iter(COL_BLOCKS) {
+        if key.len() == HASH_LEN + 1
+            && key[HASH_LEN] == BLOCK_STATUS_SUFFIX_BYTE
+        {
+            status_keys.push(key);
+            continue;
+        }
+        if key.len() != HASH_LEN {
+            report.corrupt_entry(
+                COL_BLOCKS,
+                &key,
+                format!("Unknown key {:?} among the blocks", key),
+                None,
+            );
+            continue;
+        }
+
+        report.blocks += 1;
+        let hash = H256::from_slice(&key);
+        let block = match Block::decode_with_tx_public(&Rlp::new(&value)) {
+            Ok(block) => block,
+            Err(e) => {
+                report.corrupt_entry(
+                    COL_BLOCKS,
+                    &key,
+                    format!("Block {:?} cannot be decoded: {:?}", hash, e),
+                    None,
+                );
+                continue;
+            }
+        };
+        if block.block_header.hash() != hash {
+            report.corrupt_entry(
+                COL_BLOCKS,
+                &key,
+                format!(
+                    "Block {:?} is stored with the key {:?}",
+                    block.block_header.hash(),
+                    hash
+                ),
+                None,
+            );
+            continue;
+        }
+        if Block::compute_transaction_root(&block.transactions)
+            != *block.block_header.transactions_root()
+        {
+            report.corrupt_entry(
+                COL_BLOCKS,
+                &key,
+                format!("The transactions of block {:?} don't match its transactions root", hash),
+                Some(block.block_header.height()),
+            );
+            continue;
+        }
+
+        report.transaction_hashes.insert(
+            hash,
+            block.transactions.iter().map(|tx| tx.hash()).collect(),
+        );
+        report.headers.insert(hash, block.block_header);
+    }
+
+    for key in status_keys {
+        let hash = H256::from_slice(&key[..HASH_LEN]);
+        if !report.headers.contains_key(&hash) {
+            report.corrupt_entry(
+                COL_BLOCKS,
+                &key,
+                format!("Status of the missing block {:?}", hash),
+                None,
+            );
+        }
+    }
+}
+
+fn find_genesis(report: &mut DbCheckReport) {
+    let genesis: Vec<H256> = report
+        .headers
+        .iter()
+        .filter(|(_, header)| header.height() == 0)
+        .map(|(hash, _)| *hash)
+        .collect();
+    match genesis.len() {
+        0 => report.problem("The genesis block is missing".into(), Some(0)),
+        1 => report.genesis_hash = Some(genesis[0]),
+        _ => report
+            .problem(format!("Several genesis blocks {:?}", genesis), Some(0)),
+    }
+}
+
+fn references(header: &BlockHeader) -> impl Iterator<Item = &H256> {
+    iter::once(header.parent_hash()).chain(header.referee_hashes())
+}
+
+fn check_references(db: &SystemDB, report: &mut DbCheckReport) {
+    let genesis_hash = &report.genesis_hash.unwrap_or_default();
+    let mut missing = Vec::new();
+    for (hash, header) in &report.headers {
+        if hash == genesis_hash {
+            continue;
+        }
+        for reference in references(header) {
+            if reference != genesis_hash
+                && !report.headers.contains_key(reference)
+            {
+                // A missing parent is right below its child, but a missing
+                // referee may be at any height.
+                let height = if reference == header.parent_hash() {
+                    header.height().saturating_sub(1)
+                } else {
+                    header.height()
+                };
+                missing.push((*hash, *reference, height));
+            }
+        }
+    }
+    for (hash, reference, height) in missing {
+        report.problem(
+            format!(
+                "Block {:?} refers to the missing block {:?}",
+                hash, reference
+            ),
+            Some(height),
+        );
+    }
+
+    match db.key_value().get(COL_MISC, b"terminals") {
+        Ok(Some(terminals)) => match Rlp::new(&terminals).as_list::<H256>() {
+            Ok(terminals) => {
+                for terminal in terminals {
+                    if !report.headers.contains_key(&terminal) {
+                        report.problem(
+                            format!("Terminal {:?} is missing", terminal),
+                            None,
+                        );
+                    }
+                }
+            }
+            Err(e) => report
+                .problem(format!("Terminals cannot be decoded: {:?}", e), None),
+        },
+        Ok(None) => {}
+        Err(e) => {
+            report.problem(format!("Terminals cannot be read: {}", e), None)
+        }
+    }
+}
+
+/// Verify the trie nodes of the state of `epoch` which are not in
+/// `verified_nodes`, and return the number of verified nodes.
+fn verify_state(
+    storage_manager: &StorageManager, epoch: &H256,
+    verified_nodes: &mut VerifiedNodeSet,
+) -> Result<usize, String>
+{
+    let state = storage_manager
+        .get_state_at(*epoch)
+        .map_err(|e| format!("{:?}", e))?;
+    if !state.does_exist() {
+        return Err("The root node is missing".into());
+    }
+    state
+        .verify_merkles(verified_nodes)
+        .map_err(|e| format!("{}", e))
+}
+
+/// Return the root of the state of `epoch`, or `None` if it hasn't been
+/// computed.
+fn state_root(
+    storage_manager: &StorageManager, epoch: &H256,
+) -> Result<Option<H256>, String> {
+    let state = storage_manager
+        .get_state_at(*epoch)
+        .map_err(|e| format!("{:?}", e))?;
+    if !state.does_exist() {
+        return Ok(None);
+    }
+    state
+        .get_state_root()
+        .map(|root| Some(root.unwrap_or(MERKLE_NULL_NODE)))
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// The epoch whose state root is committed by `header`, if its ancestors are
+/// all in `headers`.
+fn deferred_epoch<'a>(
+    headers: &'a HashMap<H256, BlockHeader>, hash: &'a H256,
+    header: &'a BlockHeader,
+) -> Option<(&'a H256, &'a BlockHeader)>
+{
+    let mut ancestor = (hash, header);
+    for _ in 0..DEFERRED_STATE_EPOCH_COUNT {
+        if ancestor.1.height() == 0 {
+            break;
+        }
+        let parent_hash = ancestor.1.parent_hash();
+        ancestor = (parent_hash, headers.get(parent_hash)?);
+    }
+    Some(ancestor)
+}
+
+fn check_states(
+    db: &SystemDB, storage_manager: &StorageManager, report: &mut DbCheckReport,
+) {
+    let mut verified_nodes = VerifiedNodeSet::new();
+    for (key, _) in db
+        .key_value()
+        .iter_from_prefix(COL_DELTA_TRIE, STATE_ROOT_KEY_PREFIX)
+    {
+        report.states += 1;
+        let epoch = &key[STATE_ROOT_KEY_PREFIX.len()..];
+        let height = if epoch.len() == HASH_LEN {
+            report
+                .headers
+                .get(&H256::from_slice(epoch))
+                .map(|header| header.height())
+        } else {
+            None
+        };
+        let height = match height {
+            Some(height) => height,
+            None => {
+                report.corrupt_entry(
+                    COL_DELTA_TRIE,
+                    &key,
+                    format!("State root of the unknown epoch {:?}", epoch),
+                    None,
+                );
+                continue;
+            }
+        };
+        let epoch = H256::from_slice(epoch);
+        match verify_state(storage_manager, &epoch, &mut verified_nodes) {
+            Ok(trie_nodes) => report.trie_nodes += trie_nodes,
+            Err(e) => report.problem(
+                format!("State of epoch {:?} is corrupt: {}", epoch, e),
+                Some(height),
+            ),
+        }
+    }
+
+    let mut state_roots = HashMap::new();
+    let mut mismatches = Vec::new();
+    for (hash, header) in &report.headers {
+        let (epoch, epoch_header) =
+            match deferred_epoch(&report.headers, hash, header) {
+                Some(deferred) => deferred,
+                None => continue,
+            };
+        let root = state_roots.entry(*epoch).or_insert_with(|| {
+            state_root(storage_manager, epoch).unwrap_or(None)
+        });
+        if let Some(root) = *root {
+            if &root != header.deferred_state_root() {
+                mismatches.push((*hash, *epoch, root, epoch_header.height()));
+            }
+        }
+    }
+    for (hash, epoch, root, height) in mismatches {
+        report.problem(
+            format!(
+                "State of epoch {:?} has root {:?}, which doesn't match the deferred state root of block {:?}",
+                epoch, root, hash
+            ),
+            Some(height),
+        );
+    }
+}
+
+fn decode_receipts(value: &[u8]) -> Result<(H256, Vec<Receipt>), String> {
+    let rlp = Rlp::new(value);
+    let epoch = rlp.val_at(0).map_err(|e| format!("{:?}", e))?;
+    let receipts = rlp.list_at(1).map_err(|e| format!("{:?}", e))?;
+    rlp.val_at::<Bloom>(2).map_err(|e| format!("{:?}", e))?;
+    Ok((epoch, receipts))
+}
+
+fn check_receipts(db: &SystemDB, report: &mut DbCheckReport) {
+    for (key, value) in db.key_value().iter(COL_BLOCK_RECEIPTS) {
+        report.receipts += 1;
+        if key.len() != HASH_LEN {
+            report.corrupt_entry(
+                COL_BLOCK_RECEIPTS,
+                &key,
+                format!("Unknown key {:?} among the receipts", key),
+                None,
+            );
+            continue;
+        }
+        let hash = H256::from_slice(&key);
+        let height = report.headers.get(&hash).map(|header| header.height());
+        let (epoch, receipts) = match decode_receipts(&value) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                report.corrupt_entry(
+                    COL_BLOCK_RECEIPTS,
+                    &key,
+                    format!(
+                        "Receipts of block {:?} cannot be decoded: {}",
+                        hash, e
+                    ),
+                    height,
+                );
+                continue;
+            }
+        };
+        let transaction_count = match report.transaction_hashes.get(&hash) {
+            Some(transaction_hashes) => transaction_hashes.len(),
+            None => {
+                report.corrupt_entry(
+                    COL_BLOCK_RECEIPTS,
+                    &key,
+                    format!("Receipts of the missing block {:?}", hash),
+                    None,
+                );
+                continue;
+            }
+        };
+        let epoch_height = match report.headers.get(&epoch) {
+            Some(epoch_header) => epoch_header.height(),
+            None => {
+                report.corrupt_entry(
+                    COL_BLOCK_RECEIPTS,
+                    &key,
+                    format!(
+                        "Receipts of block {:?} in the missing epoch {:?}",
+                        hash, epoch
+                    ),
+                    None,
+                );
+                continue;
+            }
+        };
+        if receipts.len() != transaction_count {
+            report.corrupt_entry(
+                COL_BLOCK_RECEIPTS,
+                &key,
+                format!(
+                    "Block {:?} has {} transactions but {} receipts",
+                    hash,
+                    transaction_count,
+                    receipts.len()
+                ),
+                Some(epoch_height),
+            );
+            continue;
+        }
+        report.receipt_epochs.insert(hash, epoch);
+    }
+}
+
+fn check_transaction_addresses(db: &SystemDB, report: &mut DbCheckReport) {
+    for (key, value) in db.key_value().iter(COL_TX_ADDRESS) {
+        report.transaction_addresses += 1;
+        let address: TransactionAddress = match rlp::decode(&value) {
+            Ok(address) => address,
+            Err(e) => {
+                report.corrupt_entry(
+                    COL_TX_ADDRESS,
+                    &key,
+                    format!(
+                        "Address of transaction {:?} cannot be decoded: {:?}",
+                        key, e
+                    ),
+                    None,
+                );
+                continue;
+            }
+        };
+        let found = report
+            .transaction_hashes
+            .get(&address.block_hash)
+            .and_then(|transaction_hashes| {
+                transaction_hashes.get(address.index)
+            })
+            .map_or(false, |hash| &hash[..] == &key[..]);
+        if !found {
+            report.corrupt_entry(
+                COL_TX_ADDRESS,
+                &key,
+                format!(
+                    "Transaction {:?} is not at index {} of block {:?}",
+                    key, address.index, address.block_hash
+                ),
+                None,
+            );
+            continue;
+        }
+        report
+            .transaction_blocks
+            .insert(H256::from_slice(&key), address.block_hash);
+    }
+}
+
+/// Delete the corrupt entries found by `check`, and every block at or above
+/// the first inconsistent height or referring to a deleted block, with their
+/// receipts, transaction indices and state roots. The terminals are then
+/// rewritten, so that the node recovers the remaining blocks on restart and
+/// syncs the others again. Returns the number of deleted blocks.
+pub fn repair(db: &SystemDB, report: &DbCheckReport) -> Result<usize, String> {
+    let genesis_hash = &report.genesis_hash.unwrap_or_default();
+    let headers = &report.headers;
+    let mut deleted: HashSet<H256> = match report.first_inconsistent_height {
+        Some(first_height) => headers
+            .iter()
+            .filter(|(hash, header)| {
+                *hash != genesis_hash && header.height() >= first_height
+            })
+            .map(|(hash, _)| *hash)
+            .collect(),
+        None => HashSet::new(),
+    };
+    loop {
+        let referrers: Vec<H256> = headers
+            .iter()
+            .filter(|(hash, header)| {
+                *hash != genesis_hash
+                    && !deleted.contains(*hash)
+                    && references(header).any(|reference| {
+                        deleted.contains(reference)
+                            || (reference != genesis_hash
+                                && !headers.contains_key(reference))
+                    })
+            })
+            .map(|(hash, _)| *hash)
+            .collect();
+        if referrers.is_empty() {
+            break;
+        }
+        deleted.extend(referrers);
+    }
+
+    let mut transaction = DBTransaction::new();
+    for (col, key) in &report.corrupt_entries {
+        transaction.delete(*col, key);
+    }
+    for hash in &deleted {
+        let mut status_key = hash.to_vec();
+        status_key.push(BLOCK_STATUS_SUFFIX_BYTE);
+        transaction.delete(COL_BLOCKS, hash);
+        transaction.delete(COL_BLOCKS, &status_key);
+        transaction.delete(COL_BLOCK_RECEIPTS, hash);
+        transaction.delete(
+            COL_DELTA_TRIE,
+            &[STATE_ROOT_KEY_PREFIX, hash.as_ref()].concat(),
+        );
+    }
+    for (hash, epoch) in &report.receipt_epochs {
+        if deleted.contains(epoch) {
+            transaction.delete(COL_BLOCK_RECEIPTS, hash);
+        }
+    }
+    for (hash, block_hash) in &report.transaction_blocks {
+        if deleted.contains(block_hash) {
+            transaction.delete(COL_TX_ADDRESS, hash);
+        }
+    }
+
+    let mut terminals: HashSet<&H256> = headers
+        .keys()
+        .filter(|hash| !deleted.contains(*hash))
+        .collect();
+    for (hash, header) in headers {
+        if !deleted.contains(hash) {
+            for reference in references(header) {
+                terminals.remove(reference);
+            }
+        }
+    }
+    let mut rlp_stream = RlpStream::new_list(terminals.len());
+    for terminal in terminals {
+        rlp_stream.append(terminal);
+    }
+    transaction.put(COL_MISC, b"terminals", &rlp_stream.drain());
+
+    db.key_value()
+        .write(transaction)
+        .map_err(|e| format!("Failed to repair the database: {}", e))?;
+    Ok(deleted.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, repair};
+    use crate::{
+        db::{COL_BLOCKS, COL_DELTA_TRIE, COL_MISC, NUM_COLUMNS},
+        ext_db::{open_memory_database, SystemDB},
+        storage::{state_manager::StorageConfiguration, StorageManager},
+    };
+    use cfx_types::{Address, H256, U256};
+    use kvdb::DBTransaction;
+    use primitives::{Block, BlockHeaderBuilder};
+    use rlp::Rlp;
+    use std::{collections::HashMap, sync::Arc};
+
+    fn new_storage_manager(db: Arc<SystemDB>) -> StorageManager {
+        StorageManager::new(
+            db,
+            StorageConfiguration {
+                cache_start_size: 1_000_000,
+                cache_size: 20_000_000,
+                idle_size: 200_000,
+                node_map_size: 20_000_000,
+                recent_lfu_factor: 4.0,
+            },
+        )
+    }
+
+    fn put(db: &SystemDB, col: Option<u32>, key: &[u8], value: &[u8]) {
+        let mut transaction = DBTransaction::new();
+        transaction.put(col, key, value);
+        db.key_value().write(transaction).unwrap();
+    }
+
+    /// Store a chain of `length` blocks after the genesis block, and return
+    /// their hashes starting from the genesis block.
+    fn store_chain(db: &SystemDB, genesis: &Block, length: u64) -> Vec<H256> {
+        let mut hashes = vec![genesis.hash()];
+        put(
+            db,
+            COL_BLOCKS,
+            &genesis.hash(),
+            &genesis.encode_with_tx_public(),
+        );
+        for height in 1..=length {
+            let mut block = Block::new(
+                BlockHeaderBuilder::new()
+                    .with_parent_hash(*hashes.last().unwrap())
+                    .with_height(height)
+                    .with_transactions_root(Block::compute_transaction_root(
+                        &Vec::new(),
+                    ))
+                    .with_deferred_state_root(
+                        *genesis.block_header.deferred_state_root(),
+                    )
+                    .build(),
+                Vec::new(),
+            );
+            block.block_header.compute_hash();
+            put(
+                db,
+                COL_BLOCKS,
+                &block.hash(),
+                &block.encode_with_tx_public(),
+            );
+            hashes.push(block.hash());
+        }
+        hashes
+    }
+
+    fn new_ledger() -> (Arc<SystemDB>, StorageManager, Block) {
+        let db = open_memory_database(NUM_COLUMNS);
+        let storage_manager = new_storage_manager(db.clone());
+        let mut accounts = HashMap::new();
+        accounts.insert(Address::from(1), U256::from(100));
+        let genesis =
+            storage_manager.initialize(accounts, 0.into(), Address::zero());
+        (db, storage_manager, genesis)
+    }
+
+    #[test]
+    fn test_consistent_database() {
+        let (db, storage_manager, genesis) = new_ledger();
+        store_chain(&db, &genesis, 3);
+        let report = check(&db, &storage_manager);
+        assert!(report.is_consistent(), "{:?}", report.problems);
+        assert_eq!(report.blocks, 4);
+        assert_eq!(report.states, 1);
+        assert!(report.trie_nodes > 0);
+        assert_eq!(report.last_consistent_epoch(), None);
+    }
+
+    #[test]
+    fn test_corrupt_trie_node_is_detected() {
+        let (db, _, genesis) = new_ledger();
+        store_chain(&db, &genesis, 1);
+        // Change the merkle hash stored in the first committed trie node.
+        let mut node = db
+            .key_value()
+            .get(COL_DELTA_TRIE, b"0")
+            .unwrap()
+            .unwrap()
+            .to_vec();
+        let merkle_offset =
+            Rlp::new(&node).payload_info().unwrap().header_len + 1;
+        node[merkle_offset] ^= 1;
+        put(&db, COL_DELTA_TRIE, b"0", &node);
+
+        // Load the trie nodes from the database again.
+        let storage_manager = new_storage_manager(db.clone());
+        let report = check(&db, &storage_manager);
+        assert!(report
+            .problems
+            .iter()
+            .any(|problem| problem.contains("is corrupt")));
+        assert_eq!(report.last_consistent_epoch(), Some(0));
+    }
+
+    #[test]
+    fn test_corrupt_block_is_truncated() {
+        let (db, storage_manager, genesis) = new_ledger();
+        let hashes = store_chain(&db, &genesis, 4);
+        put(&db, COL_BLOCKS, &hashes[2], b"corrupt");
+
+        let report = check(&db, &storage_manager);
+        // The corrupt block, and the missing parent of its child.
+        assert_eq!(report.problems.len(), 2);
+        assert_eq!(report.last_consistent_epoch(), Some(1));
+
+        assert_eq!(repair(&db, &report), Ok(2));
+        for hash in &hashes[2..] {
+            assert!(db.key_value().get(COL_BLOCKS, hash).unwrap().is_none());
+        }
+        let terminals = db.key_value().get(COL_MISC, b"terminals").unwrap();
+        assert_eq!(
+            Rlp::new(&terminals.unwrap()).as_list::<H256>().unwrap(),
+            vec![hashes[1]]
+        );
+        assert!(check(&db, &storage_manager).is_consistent());
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Checks the schema version of a database when it is opened, and upgrades
+//! an older database one version at a time with `Migration`s.
+
+use crate::{
+    db::{COL_MISC, DB_VERSION, DB_VERSION_KEY, NUM_COLUMNS},
+    ext_db::SystemDB,
+};
+use kvdb::DBTransaction;
+
+/// Upgrades a database from `version()` to `version() + 1`.
+pub trait Migration {
+    fn version(&self) -> u32;
+
+    /// The number of columns after the migration. The runner adds or drops
+    /// columns at the end before calling `migrate`.
+    fn columns(&self) -> u32;
+
+    /// Rewrite the data of `db` for the new version.
+    fn migrate(&self, db: &SystemDB) -> Result<(), String>;
+}
+
+/// The databases written before the version was stored have version 0, and
+/// the same layout as version 1.
+struct StoreVersion;
+
+impl Migration for StoreVersion {
+    fn version(&self) -> u32 { 0 }
+
+    fn columns(&self) -> u32 { 5 }
+
+    fn migrate(&self, _db: &SystemDB) -> Result<(), String> { Ok(()) }
+}
+
+/// All the migrations up to `DB_VERSION`.
+pub fn migrations() -> Vec<Box<Migration>> { vec![Box::new(StoreVersion)] }
+
+pub fn read_db_version(db: &SystemDB) -> Result<Option<u32>, String> {
+    let value = db
+        .key_value()
+        .get(COL_MISC, DB_VERSION_KEY)
+        .map_err(|e| format!("Failed to read the database version: {}", e))?;
+    match value {
+        Some(value) => rlp::decode(&value).map(Some).map_err(|e| {
+            format!("Failed to decode the database version: {:?}", e)
+        }),
+        None => Ok(None),
+    }
+}
+
+fn write_db_version(db: &SystemDB, version: u32) -> Result<(), String> {
+    let mut transaction = DBTransaction::new();
+    transaction.put(COL_MISC, DB_VERSION_KEY, &rlp::encode(&version));
+    db.key_value()
+        .write(transaction)
+        .map_err(|e| format!("Failed to write the database version: {}", e))
+}
+
+fn num_columns(db: &SystemDB) -> u32 {
+    match db.columns() {
+        Some(columns) => columns.num_columns(),
+        None => NUM_COLUMNS.unwrap_or(0),
+    }
+}
+
+fn set_num_columns(db: &SystemDB, target: u32) -> Result<(), String> {
+    let columns = match db.columns() {
+        Some(columns) => columns,
+        None if target == num_columns(db) => return Ok(()),
+        None => return Err("The database columns cannot be changed".into()),
+    };
+    while columns.num_columns() < target {
+        columns
+            .add_column()
+            .map_err(|e| format!("Failed to add a column: {}", e))?;
+    }
+    while columns.num_columns() > target {
+        columns
+            .drop_column()
+            .map_err(|e| format!("Failed to drop a column: {}", e))?;
+    }
+    Ok(())
+}
+
+fn is_empty(db: &SystemDB) -> bool {
+    (0..num_columns(db))
+        .all(|col| db.key_value().iter(Some(col)).next().is_none())
+}
+
+/// Bring `db` to `target_version` with `target_columns` columns, or refuse
+/// it if it cannot be upgraded.
+pub fn migrate(
+    db: &SystemDB, migrations: &[Box<Migration>], target_version: u32,
+    target_columns: u32,
+) -> Result<(), String>
+{
+    let mut version = match read_db_version(db)? {
+        Some(version) => version,
+        None if is_empty(db) => {
+            set_num_columns(db, target_columns)?;
+            return write_db_version(db, target_version);
+        }
+        None => 0,
+    };
+    if version > target_version {
+        return Err(format!(
+            "The database version {} is newer than the version {} supported by this node",
+            version, target_version
+        ));
+    }
+
+    while version < target_version {
+        let migration = migrations
+            .iter()
+            .find(|migration| migration.version() == version)
+            .ok_or_else(|| {
+                format!(
+                    "The database version {} cannot be upgraded to version {}",
+                    version, target_version
+                )
+            })?;
+        info!(
+            "Migrating the database from version {} to {}",
+            version,
+            version + 1
+        );
+        set_num_columns(db, migration.columns())?;
+        migration.migrate(db)?;
+        version += 1;
+        write_db_version(db, version)?;
+    }
+
+    let columns = num_columns(db);
+    if columns != target_columns {
+        return Err(format!(
+            "The database has {} columns instead of {} for version {}",
+            columns, target_columns, version
+        ));
+    }
+    Ok(())
+}
+
+/// Bring `db` to `DB_VERSION`.
+pub fn migrate_to_current(db: &SystemDB) -> Result<(), String> {
+    migrate(
+        db,
+        &migrations(),
+        DB_VERSION,
+        NUM_COLUMNS.expect("columns are numbered"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        migrate, migrate_to_current, migrations, read_db_version, Migration,
+    };
+    use crate::{
+        db::{COL_BLOCKS, COL_MISC, DB_VERSION, NUM_COLUMNS},
+        ext_db::{open_memory_database, SystemDB},
+    };
+    use kvdb::DBTransaction;
+
+    fn put(db: &SystemDB, col: Option<u32>, key: &[u8], value: &[u8]) {
+        let mut transaction = DBTransaction::new();
+        transaction.put(col, key, value);
+        db.key_value().write(transaction).unwrap();
+    }
+
+    #[test]
+    fn test_new_database_gets_current_version() {
+        let db = open_memory_database(NUM_COLUMNS);
+        migrate_to_current(&db).unwrap();
+        assert_eq!(read_db_version(&db), Ok(Some(DB_VERSION)));
+    }
+
+    #[test]
+    fn test_unversioned_database_is_upgraded() {
+        let db = open_memory_database(NUM_COLUMNS);
+        put(&db, COL_MISC, b"terminals", b"");
+        migrate_to_current(&db).unwrap();
+        assert_eq!(read_db_version(&db), Ok(Some(DB_VERSION)));
+    }
+
+    #[test]
+    fn test_newer_database_is_refused() {
+        let db = open_memory_database(NUM_COLUMNS);
+        migrate(&db, &migrations(), DB_VERSION + 1, 5).unwrap();
+        assert!(migrate_to_current(&db).is_err());
+    }
+
+    /// Moves the blocks to a new last column.
+    struct MoveBlocks;
+
+    impl Migration for MoveBlocks {
+        fn version(&self) -> u32 { 1 }
+
+        fn columns(&self) -> u32 { 6 }
+
+        fn migrate(&self, db: &SystemDB) -> Result<(), String> {
+            let mut transaction = DBTransaction::new();
+            for (key, value) in db.key_value().iter(COL_BLOCKS) {
+                transaction.put(Some(5), &key, &value);
+                transaction.delete(COL_BLOCKS, &key);
+            }
+            db.key_value().write(transaction).map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn test_migration_adds_column_and_rewrites_data() {
+        let db = open_memory_database(NUM_COLUMNS);
+        migrate_to_current(&db).unwrap();
+        put(&db, COL_BLOCKS, b"block", b"data");
+
+        let mut migrations = migrations();
+        migrations.push(Box::new(MoveBlocks));
+        migrate(&db, &migrations, 2, 6).unwrap();
+        assert_eq!(read_db_version(&db), Ok(Some(2)));
+        assert_eq!(db.columns().unwrap().num_columns(), 6);
+        assert!(db.key_value().get(COL_BLOCKS, b"block").unwrap().is_none());
+        assert_eq!(
+            db.key_value()
+                .get(Some(5), b"block")
+                .unwrap()
+                .unwrap()
+                .to_vec(),
+            b"data"
+        );
+
+        // A version without migration cannot be reached.
+        assert!(migrate(&db, &migrations, 3, 6).is_err());
+    }
+}
@@ -2,6 +2,7 @@
 // Conflux is free software and distributed under GNU General Public License.
 // See http://www.gnu.org/licenses/
 
+use crate::db::DbError;
 use cfx_types::{Address, H256, U256};
 use primitives::{filter::FilterError, transaction::TransactionError};
 use std::{error, fmt, time::SystemTime};
@@ -108,6 +109,7 @@ error_chain! {
         Block(BlockError) #[doc = "Error concerning block processing."];
         Transaction(TransactionError) #[doc = "Error concerning transaction processing."];
         Filter(FilterError) #[doc = "Error concerning log filtering."];
+        Db(DbError) #[doc = "Error reading the database."];
     }
 
     errors {
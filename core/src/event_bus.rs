@@ -0,0 +1,102 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! A minimal publish/subscribe channel to notify the components of a node,
+//! e.g. RPC subscriptions or indexers, of what happens in another one.
+
+use parking_lot::Mutex;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+
+/// The number of events a subscriber may leave unread by default.
+pub const DEFAULT_SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Every subscriber gets its own copy of each published event, in the order
+/// the events are published. Publishing never blocks, and a subscriber is
+/// forgotten once its `Receiver` is dropped.
+///
+/// A subscriber which falls `capacity` events behind is dropped as well, so
+/// that a slow or abandoned subscriber doesn't hold events without limit. It
+/// still receives the events queued before, and then sees the channel
+/// disconnected, after which it may subscribe again.
+pub struct EventBus<T> {
+    subscribers: Mutex<Vec<SyncSender<T>>>,
+    capacity: usize,
+}
+
+impl<T: Clone> EventBus<T> {
+    pub fn new() -> Self { Self::with_capacity(DEFAULT_SUBSCRIBER_CAPACITY) }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        EventBus {
+            subscribers: Mutex::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<T> {
+        let (sender, receiver) = sync_channel(self.capacity);
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: T) {
+        self.subscribers.lock().retain(|subscriber| {
+            match subscriber.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Drop an event subscriber which falls behind");
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        })
+    }
+
+    pub fn subscriber_count(&self) -> usize { self.subscribers.lock().len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventBus;
+    use std::sync::mpsc::TryRecvError;
+
+    #[test]
+    fn test_publish_to_all_subscribers() {
+        let bus = EventBus::new();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+        bus.publish(1);
+        bus.publish(2);
+        assert_eq!(first.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(second.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_dropped_subscriber_is_removed() {
+        let bus = EventBus::new();
+        let kept = bus.subscribe();
+        drop(bus.subscribe());
+        assert_eq!(bus.subscriber_count(), 2);
+        bus.publish(1);
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(kept.try_recv(), Ok(1));
+    }
+
+    #[test]
+    fn test_slow_subscriber_is_dropped() {
+        let bus = EventBus::with_capacity(2);
+        let slow = bus.subscribe();
+        let fast = bus.subscribe();
+        for event in 0..3 {
+            bus.publish(event);
+            assert_eq!(fast.try_recv(), Ok(event));
+        }
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(slow.try_iter().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Disconnected));
+
+        bus.publish(3);
+        assert_eq!(fast.try_recv(), Ok(3));
+    }
+}
@@ -129,11 +129,20 @@ impl<Gas: evm::CostType> Gasometer<Gas> {
         let cost = match instruction {
             instructions::JUMPDEST => Request::Gas(Gas::from(1)),
             instructions::SSTORE => {
+                if spec.eip1706
+                    && self.current_gas <= Gas::from(spec.call_stipend)
+                {
+                    return Err(vm::Error::OutOfGas);
+                }
                 let address = H256::from(stack.peek(0));
                 let newval = stack.peek(1);
                 let val = U256::from(&*context.storage_at(&address)?);
 
-                let gas = if val.is_zero() && !newval.is_zero() {
+                let gas = if spec.eip1283 {
+                    let orig =
+                        U256::from(&*context.initial_storage_at(&address)?);
+                    calculate_eip1283_sstore_gas(spec, &orig, &val, &newval)
+                } else if val.is_zero() && !newval.is_zero() {
                     spec.sstore_set_gas
                 } else {
                     // Refund for below case is added when actually
@@ -442,3 +451,70 @@ fn test_calculate_mem_cost() {
     assert_eq!(new_mem_gas, 3);
     assert_eq!(mem_size, 32);
 }
+
+/// Gas cost of `SSTORE` under net gas metering, determined by the value of
+/// the slot at the beginning of the transaction (`original`), its value now
+/// (`current`) and the value to be written (`new`).
+pub fn calculate_eip1283_sstore_gas(
+    spec: &Spec, original: &U256, current: &U256, new: &U256,
+) -> usize {
+    let dirty_gas = spec.sstore_dirty_gas.unwrap_or(spec.sload_gas);
+    if current == new {
+        // No-op.
+        dirty_gas
+    } else if original == current {
+        // The slot has not been changed by the current transaction.
+        if original.is_zero() {
+            spec.sstore_set_gas
+        } else {
+            spec.sstore_reset_gas
+        }
+    } else {
+        // The slot is dirty.
+        dirty_gas
+    }
+}
+
+/// Adjust the refund counter for `SSTORE` under net gas metering. The refund
+/// may go down when a slot cleared earlier in the transaction is set again.
+pub fn handle_eip1283_sstore_clears_refund(
+    context: &mut vm::Context, original: &U256, current: &U256, new: &U256,
+) {
+    let (refund_gas, set_gas, reset_gas, dirty_gas) = {
+        let spec = context.spec();
+        (
+            spec.sstore_refund_gas,
+            spec.sstore_set_gas,
+            spec.sstore_reset_gas,
+            spec.sstore_dirty_gas.unwrap_or(spec.sload_gas),
+        )
+    };
+
+    if current == new {
+        return;
+    }
+    if original == current {
+        if new.is_zero() {
+            context.add_sstore_refund(refund_gas);
+        }
+        return;
+    }
+
+    // The slot is dirty.
+    if !original.is_zero() {
+        if current.is_zero() {
+            // Recreate the slot cleared earlier.
+            context.sub_sstore_refund(refund_gas);
+        } else if new.is_zero() {
+            context.add_sstore_refund(refund_gas);
+        }
+    }
+    if original == new {
+        // Reset to the original value.
+        if original.is_zero() {
+            context.add_sstore_refund(set_gas - dirty_gas);
+        } else {
+            context.add_sstore_refund(reset_gas - dirty_gas);
+        }
+    }
+}
@@ -979,7 +979,16 @@ impl<Cost: CostType> Interpreter<Cost> {
 
                 let current_val = U256::from(&*context.storage_at(&address)?);
 
-                if !current_val.is_zero() && val.is_zero() {
+                if context.spec().eip1283 {
+                    let original_val =
+                        U256::from(&*context.initial_storage_at(&address)?);
+                    gasometer::handle_eip1283_sstore_clears_refund(
+                        context,
+                        &original_val,
+                        &current_val,
+                        &val,
+                    );
+                } else if !current_val.is_zero() && val.is_zero() {
                     let sstore_clears_schedule =
                         context.spec().sstore_refund_gas;
                     context.add_sstore_refund(sstore_clears_schedule);
@@ -1078,6 +1078,140 @@ fn test_create_in_staticcall(factory: super::Factory) {
     assert_eq!(context.calls.len(), 0);
 }
 
+/// Run `code` against a slot 0 holding `original` at the beginning of the
+/// transaction, and return the gas used and the refund counter.
+fn run_sstore(
+    factory: &super::Factory, spec: vm::Spec, code: &str, original: u64,
+    gas: u64,
+) -> vm::Result<(U256, i128)>
+{
+    let mut params = ActionParams::default();
+    params.gas = U256::from(gas);
+    params.code = Some(Arc::new(code.from_hex().unwrap()));
+    let mut context = MockContext::new();
+    context.spec = spec;
+    context.store.insert(H256::zero(), H256::from(original));
+    context
+        .initial_store
+        .insert(H256::zero(), H256::from(original));
+
+    let gas_left = {
+        let vm = factory.create(params, context.spec(), context.depth());
+        test_finalize(vm.exec(&mut context).ok().unwrap())?
+    };
+    Ok((U256::from(gas) - gas_left, context.sstore_clears))
+}
+
+// (code, original value, gas used, refund)
+const EIP1283_CASES: [(&str, u64, u64, i128); 17] = [
+    ("60006000556000600055", 0, 412, 0),
+    ("60006000556001600055", 0, 20212, 0),
+    ("60016000556000600055", 0, 20212, 19800),
+    ("60016000556002600055", 0, 20212, 0),
+    ("60016000556001600055", 0, 20212, 0),
+    ("60006000556000600055", 1, 5212, 15000),
+    ("60006000556001600055", 1, 5212, 4800),
+    ("60006000556002600055", 1, 5212, 0),
+    ("60026000556000600055", 1, 5212, 15000),
+    ("60026000556003600055", 1, 5212, 0),
+    ("60026000556001600055", 1, 5212, 4800),
+    ("60026000556002600055", 1, 5212, 0),
+    ("60016000556000600055", 1, 5212, 15000),
+    ("60016000556002600055", 1, 5212, 0),
+    ("60016000556001600055", 1, 412, 0),
+    ("600160005560006000556001600055", 0, 40218, 19800),
+    ("600060005560016000556000600055", 1, 10218, 19800),
+];
+
+const EIP2200_CASES: [(&str, u64, u64, i128); 17] = [
+    ("60006000556000600055", 0, 1612, 0),
+    ("60006000556001600055", 0, 20812, 0),
+    ("60016000556000600055", 0, 20812, 19200),
+    ("60016000556002600055", 0, 20812, 0),
+    ("60016000556001600055", 0, 20812, 0),
+    ("60006000556000600055", 1, 5812, 15000),
+    ("60006000556001600055", 1, 5812, 4200),
+    ("60006000556002600055", 1, 5812, 0),
+    ("60026000556000600055", 1, 5812, 15000),
+    ("60026000556003600055", 1, 5812, 0),
+    ("60026000556001600055", 1, 5812, 4200),
+    ("60026000556002600055", 1, 5812, 0),
+    ("60016000556000600055", 1, 5812, 15000),
+    ("60016000556002600055", 1, 5812, 0),
+    ("60016000556001600055", 1, 1612, 0),
+    ("600160005560006000556001600055", 0, 40818, 19200),
+    ("600060005560016000556000600055", 1, 10818, 19200),
+];
+
+fn eip1283_spec() -> vm::Spec {
+    let mut spec = vm::Spec::new_spec();
+    spec.eip1283 = true;
+    spec.sstore_dirty_gas = Some(200);
+    spec
+}
+
+fn eip2200_spec() -> vm::Spec {
+    let mut spec = vm::Spec::new_spec();
+    spec.eip1283 = true;
+    spec.eip1706 = true;
+    spec.sload_gas = 800;
+    spec
+}
+
+evm_test! {test_sstore_eip1283: test_sstore_eip1283_int}
+fn test_sstore_eip1283(factory: super::Factory) {
+    for &(code, original, used, refund) in EIP1283_CASES.iter() {
+        assert_eq!(
+            run_sstore(&factory, eip1283_spec(), code, original, 1_000_000)
+                .unwrap(),
+            (U256::from(used), refund),
+            "code: {}, original: {}",
+            code,
+            original
+        );
+    }
+}
+
+evm_test! {test_sstore_eip2200: test_sstore_eip2200_int}
+fn test_sstore_eip2200(factory: super::Factory) {
+    for &(code, original, used, refund) in EIP2200_CASES.iter() {
+        assert_eq!(
+            run_sstore(&factory, eip2200_spec(), code, original, 1_000_000)
+                .unwrap(),
+            (U256::from(used), refund),
+            "code: {}, original: {}",
+            code,
+            original
+        );
+    }
+
+    // SSTORE fails if the gas left is not above the call stipend.
+    assert_eq!(
+        run_sstore(&factory, eip2200_spec(), "6001600055", 1, 2306),
+        Err(vm::Error::OutOfGas)
+    );
+    assert_eq!(
+        run_sstore(&factory, eip2200_spec(), "6001600055", 1, 2307).unwrap(),
+        (U256::from(806), 0)
+    );
+}
+
+evm_test! {test_sstore_without_net_metering: test_sstore_without_net_metering_int}
+fn test_sstore_without_net_metering(factory: super::Factory) {
+    // Writing back the original value is charged and refunded in full.
+    assert_eq!(
+        run_sstore(
+            &factory,
+            vm::Spec::new_spec(),
+            "60016000556000600055",
+            0,
+            1_000_000
+        )
+        .unwrap(),
+        (U256::from(25012), 15000)
+    );
+}
+
 fn assert_set_contains<T: Debug + Eq + PartialEq + Hash>(
     set: &HashSet<T>, val: &T,
 ) {
@@ -26,6 +26,7 @@ extern crate slab;
 #[macro_use]
 extern crate lazy_static;
 extern crate bit_set;
+extern crate bls12_381;
 extern crate bn;
 extern crate byteorder;
 extern crate heapsize;
@@ -43,10 +44,14 @@ pub mod cache_config;
 pub mod cache_manager;
 pub mod consensus;
 pub mod db;
+pub mod db_check;
+pub mod db_migration;
 pub mod error;
+pub mod event_bus;
 mod evm;
 pub mod executive;
 pub mod genesis;
+pub mod light;
 pub mod machine;
 pub mod pow;
 pub(crate) mod snapshot;
@@ -62,6 +67,7 @@ pub mod vm_factory;
 
 pub use crate::{
     consensus::{ConsensusGraph, SharedConsensusGraph},
+    light::{LightService, SharedLightService},
     sync::{
         BestInformation, SharedSynchronizationGraph,
         SharedSynchronizationService, SynchronizationService,
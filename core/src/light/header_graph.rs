@@ -0,0 +1,499 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{
+    consensus::ConsensusGraphInner, error::Error,
+    verification::VerificationConfig,
+};
+use cfx_types::{SignedBigNum, H256, U256, U512};
+use link_cut_tree::MinLinkCutTree;
+use network::PeerId;
+use parking_lot::RwLock;
+use primitives::BlockHeader;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+/// The maximum number of headers waiting for their parent, in total and
+/// from a single peer. The oldest ones are dropped first.
+const MAX_ORPHANS: usize = 10_000;
+const MAX_ORPHANS_PER_PEER: usize = 2_000;
+
+struct HeaderNode {
+    header: Arc<BlockHeader>,
+    /// The index of the header in `weight_tree`.
+    index: usize,
+    children: Vec<H256>,
+}
+
+struct Orphan {
+    header: BlockHeader,
+    peer: PeerId,
+    sequence: u64,
+}
+
+/// Headers whose parent is not known yet.
+#[derive(Default)]
+struct OrphanPool {
+    orphans: HashMap<H256, Orphan>,
+    /// The hashes of the orphans waiting for each parent.
+    by_parent: HashMap<H256, Vec<H256>>,
+    /// The orphans in the order of arrival, in total and per peer.
+    by_sequence: BTreeMap<u64, H256>,
+    by_peer: HashMap<PeerId, BTreeMap<u64, H256>>,
+    next_sequence: u64,
+}
+
+impl OrphanPool {
+    fn insert(&mut self, header: BlockHeader, peer: PeerId) {
+        let hash = header.hash();
+        if self.orphans.contains_key(&hash) {
+            return;
+        }
+        let oldest_of_peer = self.by_peer.get(&peer).and_then(|orphans| {
+            if orphans.len() >= MAX_ORPHANS_PER_PEER {
+                orphans.values().next().cloned()
+            } else {
+                None
+            }
+        });
+        if let Some(oldest) = oldest_of_peer {
+            self.remove(&oldest);
+        }
+        if self.orphans.len() >= MAX_ORPHANS {
+            let oldest = *self.by_sequence.values().next().expect("not empty");
+            self.remove(&oldest);
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.by_parent
+            .entry(*header.parent_hash())
+            .or_insert_with(Vec::new)
+            .push(hash);
+        self.by_sequence.insert(sequence, hash);
+        self.by_peer
+            .entry(peer)
+            .or_insert_with(BTreeMap::new)
+            .insert(sequence, hash);
+        self.orphans.insert(
+            hash,
+            Orphan {
+                header,
+                peer,
+                sequence,
+            },
+        );
+    }
+
+    fn remove(&mut self, hash: &H256) -> Option<BlockHeader> {
+        let orphan = self.orphans.remove(hash)?;
+        self.by_sequence.remove(&orphan.sequence);
+        let peer_is_empty = match self.by_peer.get_mut(&orphan.peer) {
+            Some(orphans) => {
+                orphans.remove(&orphan.sequence);
+                orphans.is_empty()
+            }
+            None => false,
+        };
+        if peer_is_empty {
+            self.by_peer.remove(&orphan.peer);
+        }
+        let parent_hash = *orphan.header.parent_hash();
+        let parent_is_empty = match self.by_parent.get_mut(&parent_hash) {
+            Some(children) => {
+                children.retain(|child| child != hash);
+                children.is_empty()
+            }
+            None => false,
+        };
+        if parent_is_empty {
+            self.by_parent.remove(&parent_hash);
+        }
+        Some(orphan.header)
+    }
+
+    /// Remove and return the orphans waiting for `parent_hash`.
+    fn take_children(&mut self, parent_hash: &H256) -> Vec<BlockHeader> {
+        let children = self.by_parent.remove(parent_hash).unwrap_or_default();
+        children
+            .iter()
+            .filter_map(|child| self.remove(child))
+            .collect()
+    }
+}
+
+struct LightHeaderGraphInner {
+    nodes: HashMap<H256, HeaderNode>,
+    /// The hash of each header by its index in `weight_tree`.
+    hashes: Vec<H256>,
+    /// Maintains the subtree weight of each header in the parent tree.
+    weight_tree: MinLinkCutTree,
+    heavy_block_difficulty_ratio: u64,
+    orphans: OrphanPool,
+    /// The pivot block hash of each epoch, starting from genesis.
+    pivot_chain: Vec<H256>,
+}
+
+/// The tree of block headers known by a light node, and its pivot chain.
+///
+/// Like the consensus graph, the pivot chain starts from genesis and descends
+/// into the child with the heaviest subtree, ties broken by the larger hash,
+/// so that the epochs of a light node are the ones of the full nodes. A light
+/// node doesn't have the block bodies to find partially invalid blocks, which
+/// are assumed to be valid.
+pub struct LightHeaderGraph {
+    inner: RwLock<LightHeaderGraphInner>,
+    genesis_hash: H256,
+    verification_config: VerificationConfig,
+}
+
+impl LightHeaderGraph {
+    pub fn new(
+        genesis: BlockHeader, verification_config: VerificationConfig,
+        heavy_block_difficulty_ratio: u64,
+    ) -> Self
+    {
+        let genesis_hash = genesis.hash();
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            genesis_hash,
+            HeaderNode {
+                header: Arc::new(genesis),
+                index: 0,
+                children: Vec::new(),
+            },
+        );
+        let mut weight_tree = MinLinkCutTree::new();
+        weight_tree.make_tree(0);
+        LightHeaderGraph {
+            inner: RwLock::new(LightHeaderGraphInner {
+                nodes,
+                hashes: vec![genesis_hash],
+                weight_tree,
+                heavy_block_difficulty_ratio,
+                orphans: OrphanPool::default(),
+                pivot_chain: vec![genesis_hash],
+            }),
+            genesis_hash,
+            verification_config,
+        }
+    }
+
+    pub fn genesis_hash(&self) -> H256 { self.genesis_hash }
+
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.inner.read().nodes.contains_key(hash)
+    }
+
+    pub fn header_by_hash(&self, hash: &H256) -> Option<Arc<BlockHeader>> {
+        self.inner
+            .read()
+            .nodes
+            .get(hash)
+            .map(|node| node.header.clone())
+    }
+
+    pub fn best_epoch_number(&self) -> u64 {
+        self.inner.read().pivot_chain.len() as u64 - 1
+    }
+
+    pub fn pivot_hash(&self, epoch_number: u64) -> Option<H256> {
+        self.inner
+            .read()
+            .pivot_chain
+            .get(epoch_number as usize)
+            .cloned()
+    }
+
+    /// The hashes of the missing parents of the orphan headers.
+    pub fn missing_parent_hashes(&self) -> Vec<H256> {
+        self.inner
+            .read()
+            .orphans
+            .by_parent
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Verify and insert a header received from `peer`. Returns `false` if
+    /// the header has to wait for its parent.
+    pub fn insert_header(
+        &self, mut header: BlockHeader, peer: PeerId,
+    ) -> Result<bool, Error> {
+        if self.contains(&header.hash()) {
+            return Ok(true);
+        }
+        self.verification_config.verify_header_params(&mut header)?;
+        Ok(self.insert_verified_header(header, peer))
+    }
+
+    fn insert_verified_header(
+        &self, header: BlockHeader, peer: PeerId,
+    ) -> bool {
+        let mut inner = self.inner.write();
+        if !inner.nodes.contains_key(header.parent_hash()) {
+            inner.orphans.insert(header, peer);
+            return false;
+        }
+
+        // Attach the header, then the orphans that were waiting for it, and
+        // so on. They were verified before they were put aside.
+        let mut ready = vec![header];
+        while let Some(header) = ready.pop() {
+            let hash = header.hash();
+            let children = inner.orphans.take_children(&hash);
+            if inner.nodes.contains_key(&hash) {
+                continue;
+            }
+            let parent_height =
+                inner.nodes[header.parent_hash()].header.height();
+            if header.height() != parent_height + 1 {
+                warn!(
+                    "Header {:?} has height {} but its parent has height {}",
+                    hash,
+                    header.height(),
+                    parent_height
+                );
+                continue;
+            }
+            inner.attach(header);
+            ready.extend(children);
+        }
+        true
+    }
+}
+
+impl LightHeaderGraphInner {
+    /// The weight of a header following the GHAST algorithm, as
+    /// `ConsensusGraphInner::block_weight` computes it.
+    fn block_weight(&self, header: &BlockHeader) -> U256 {
+        if !header.adaptive() {
+            return *header.difficulty();
+        }
+        let is_heavy = U512::from(header.pow_quality)
+            >= U512::from(self.heavy_block_difficulty_ratio)
+                * U512::from(header.difficulty());
+        if is_heavy {
+            U256::from(self.heavy_block_difficulty_ratio) * *header.difficulty()
+        } else {
+            U256::zero()
+        }
+    }
+
+    fn is_heavier(&mut self, a: usize, b: usize) -> bool {
+        let weight_a = self.weight_tree.get(a);
+        let weight_b = self.weight_tree.get(b);
+        ConsensusGraphInner::is_heavier(
+            (&weight_a, &self.hashes[a]),
+            (&weight_b, &self.hashes[b]),
+        )
+    }
+
+    /// Add a header whose parent is known, and switch the pivot chain to its
+    /// subtree if it becomes the heaviest one at the fork.
+    fn attach(&mut self, header: BlockHeader) {
+        let hash = header.hash();
+        let parent_hash = *header.parent_hash();
+        let weight = self.block_weight(&header);
+        let index = self.hashes.len();
+        self.hashes.push(hash);
+        let parent = self.nodes.get_mut(&parent_hash).expect("parent exists");
+        parent.children.push(hash);
+        let parent_index = parent.index;
+        self.nodes.insert(
+            hash,
+            HeaderNode {
+                header: Arc::new(header),
+                index,
+                children: Vec::new(),
+            },
+        );
+        self.weight_tree.make_tree(index);
+        self.weight_tree.link(parent_index, index);
+        self.weight_tree
+            .path_apply(index, &SignedBigNum::pos(weight));
+
+        let tip = *self.pivot_chain.last().expect("genesis exists");
+        if parent_hash == tip {
+            self.pivot_chain.push(hash);
+            return;
+        }
+        // Only the subtrees at the fork from the pivot chain can change order.
+        let tip_index = self.nodes[&tip].index;
+        let lca = self.weight_tree.lca(tip_index, index);
+        let fork_at =
+            self.nodes[&self.hashes[lca]].header.height() as usize + 1;
+        let prev = self.nodes[&self.pivot_chain[fork_at]].index;
+        let new = self.weight_tree.ancestor_at(index, fork_at);
+        if !self.is_heavier(new, prev) {
+            return;
+        }
+        self.pivot_chain.truncate(fork_at);
+        let mut u = new;
+        loop {
+            self.pivot_chain.push(self.hashes[u]);
+            let children: Vec<usize> = self.nodes[&self.hashes[u]]
+                .children
+                .iter()
+                .map(|child| self.nodes[child].index)
+                .collect();
+            let mut heaviest = match children.first() {
+                Some(child) => *child,
+                None => break,
+            };
+            for child in children {
+                if self.is_heavier(child, heaviest) {
+                    heaviest = child;
+                }
+            }
+            u = heaviest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LightHeaderGraph, MAX_ORPHANS, MAX_ORPHANS_PER_PEER};
+    use crate::{
+        consensus::HEAVY_BLOCK_DEFAULT_DIFFICULTY_RATIO,
+        verification::VerificationConfig,
+    };
+    use cfx_types::{H256, U256};
+    use primitives::{BlockHeader, BlockHeaderBuilder};
+
+    fn header(
+        parent: &BlockHeader, difficulty: u64, nonce: u64,
+    ) -> BlockHeader {
+        let mut header = BlockHeaderBuilder::new()
+            .with_parent_hash(parent.hash())
+            .with_height(parent.height() + 1)
+            .with_difficulty(U256::from(difficulty))
+            .with_nonce(nonce)
+            .build();
+        header.compute_hash();
+        header
+    }
+
+    fn new_graph() -> (LightHeaderGraph, BlockHeader) {
+        let mut genesis = BlockHeaderBuilder::new().build();
+        genesis.compute_hash();
+        (
+            LightHeaderGraph::new(
+                genesis.clone(),
+                VerificationConfig::new(true),
+                HEAVY_BLOCK_DEFAULT_DIFFICULTY_RATIO,
+            ),
+            genesis,
+        )
+    }
+
+    #[test]
+    fn test_pivot_chain_follows_heaviest_subtree() {
+        let (graph, genesis) = new_graph();
+        let a1 = header(&genesis, 10, 1);
+        let a2 = header(&a1, 10, 2);
+        let b1 = header(&genesis, 15, 3);
+        assert!(graph.insert_verified_header(a1.clone(), 0));
+        assert!(graph.insert_verified_header(b1.clone(), 0));
+        assert_eq!(graph.pivot_hash(1), Some(b1.hash()));
+        assert_eq!(graph.best_epoch_number(), 1);
+
+        assert!(graph.insert_verified_header(a2.clone(), 0));
+        assert_eq!(graph.pivot_hash(1), Some(a1.hash()));
+        assert_eq!(graph.pivot_hash(2), Some(a2.hash()));
+        assert_eq!(graph.best_epoch_number(), 2);
+
+        // The subtree of b1 weighs 23, more than the 20 of a1, though each
+        // chain in it weighs only 19.
+        let b2 = header(&b1, 4, 4);
+        let c2 = header(&b1, 4, 5);
+        assert!(graph.insert_verified_header(b2.clone(), 0));
+        assert_eq!(graph.pivot_hash(1), Some(a1.hash()));
+        assert!(graph.insert_verified_header(c2.clone(), 0));
+        assert_eq!(graph.pivot_hash(1), Some(b1.hash()));
+        let heavier = if b2.hash() > c2.hash() { b2 } else { c2 };
+        assert_eq!(graph.pivot_hash(2), Some(heavier.hash()));
+        assert_eq!(graph.best_epoch_number(), 2);
+    }
+
+    #[test]
+    fn test_orphans_are_attached_with_parent() {
+        let (graph, genesis) = new_graph();
+        let a1 = header(&genesis, 10, 1);
+        let a2 = header(&a1, 10, 2);
+        let a3 = header(&a2, 10, 3);
+        assert!(!graph.insert_verified_header(a3.clone(), 0));
+        assert!(!graph.insert_verified_header(a2.clone(), 0));
+        let mut missing = graph.missing_parent_hashes();
+        missing.sort();
+        let mut expected = vec![a1.hash(), a2.hash()];
+        expected.sort();
+        assert_eq!(missing, expected);
+
+        assert!(graph.insert_verified_header(a1.clone(), 0));
+        assert!(graph.missing_parent_hashes().is_empty());
+        assert_eq!(graph.pivot_hash(3), Some(a3.hash()));
+        assert_eq!(graph.pivot_hash(4), None::<H256>);
+    }
+
+    #[test]
+    fn test_invalid_height_is_rejected() {
+        let (graph, genesis) = new_graph();
+        let mut bad = BlockHeaderBuilder::new()
+            .with_parent_hash(genesis.hash())
+            .with_height(5)
+            .with_difficulty(U256::from(10))
+            .build();
+        bad.compute_hash();
+        graph.insert_verified_header(bad.clone(), 0);
+        assert!(!graph.contains(&bad.hash()));
+        assert_eq!(graph.best_epoch_number(), 0);
+    }
+
+    #[test]
+    fn test_orphans_are_bounded() {
+        let (graph, genesis) = new_graph();
+        let unknown = header(&genesis, 10, 0);
+        let orphans: Vec<BlockHeader> = (0..MAX_ORPHANS as u64 + 1)
+            .map(|nonce| header(&unknown, 10, nonce))
+            .collect();
+
+        // A single peer can only fill its own share, dropping its oldest.
+        for orphan in &orphans[..MAX_ORPHANS_PER_PEER + 1] {
+            graph.insert_verified_header(orphan.clone(), 1);
+        }
+        assert_eq!(
+            graph.inner.read().orphans.orphans.len(),
+            MAX_ORPHANS_PER_PEER
+        );
+        assert!(!graph
+            .inner
+            .read()
+            .orphans
+            .orphans
+            .contains_key(&orphans[0].hash()));
+
+        // The oldest orphan is dropped when the pool is full.
+        for (i, orphan) in orphans.iter().enumerate() {
+            graph.insert_verified_header(orphan.clone(), 2 + i % 100);
+        }
+        let inner = graph.inner.read();
+        assert_eq!(inner.orphans.orphans.len(), MAX_ORPHANS);
+        assert_eq!(inner.orphans.by_sequence.len(), MAX_ORPHANS);
+        assert!(!inner.orphans.orphans.contains_key(&orphans[1].hash()));
+        assert!(inner
+            .orphans
+            .orphans
+            .contains_key(&orphans[MAX_ORPHANS].hash()));
+        drop(inner);
+
+        assert!(graph.insert_verified_header(unknown, 3));
+        assert!(graph.inner.read().orphans.orphans.is_empty());
+        assert!(graph.inner.read().orphans.by_peer.is_empty());
+        assert_eq!(graph.best_epoch_number(), 2);
+    }
+}
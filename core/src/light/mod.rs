@@ -0,0 +1,17 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Light node mode: sync only the block headers, and answer queries with the
+//! Merkle proofs of full peers, checked against the `deferred_state_root` and
+//! `deferred_receipts_root` of the headers on the pivot chain.
+
+mod header_graph;
+mod protocol_handler;
+mod service;
+
+pub use self::{
+    header_graph::LightHeaderGraph,
+    protocol_handler::LightProtocolHandler,
+    service::{LightService, SharedLightService},
+};
@@ -0,0 +1,480 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use super::LightHeaderGraph;
+use crate::sync::{
+    check_status, msg_sender::send_message, peer_protocol_version, Error,
+    ErrorKind, SynchronizationPeerState, SynchronizationState,
+    INVALID_STATUS_PENALTY,
+};
+use cfx_types::H256;
+use io::TimerToken;
+use message::{
+    GetBlockHeaders, GetBlockHeadersResponse, GetEpochReceipts,
+    GetEpochReceiptsResponse, GetStateProof, GetStateProofResponse, Message,
+    MsgId, NewBlock, NewBlockHashes, RequestId, Status,
+};
+use network::{
+    Error as NetworkError, NetworkContext, NetworkProtocolHandler, PeerId,
+};
+use parking_lot::Mutex;
+use primitives::{receipt::Receipt, BlockHeader, TrieProof};
+use priority_send_queue::SendQueuePriority;
+use rlp::Rlp;
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+    ops::DerefMut,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const CHECK_REQUEST_TIMER: TimerToken = 0;
+const CHECK_REQUEST_PERIOD: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The number of ancestors requested with a header, as many as a full node
+/// sends in one response.
+const HEADERS_BATCH_SIZE: u64 = 512;
+
+enum LightRequest {
+    Headers(H256),
+    StateProof(Sender<Vec<TrieProof>>),
+    EpochReceipts(Sender<Vec<Vec<Receipt>>>),
+}
+
+struct InFlightRequest {
+    peer: PeerId,
+    request: LightRequest,
+    sent_at: Instant,
+}
+
+#[derive(Default)]
+struct RequestTable {
+    next_request_id: u64,
+    in_flight: HashMap<u64, InFlightRequest>,
+    headers_in_flight: HashSet<H256>,
+}
+
+impl RequestTable {
+    fn remove(&mut self, request_id: u64) -> Option<InFlightRequest> {
+        let req = self.in_flight.remove(&request_id)?;
+        if let LightRequest::Headers(ref hash) = req.request {
+            self.headers_in_flight.remove(hash);
+        }
+        Some(req)
+    }
+}
+
+/// The handler of the light node for the `cfx` protocol. It only syncs the
+/// block headers, and asks full peers for proofs of the state and of the
+/// receipts on behalf of the queries.
+pub struct LightProtocolHandler {
+    graph: Arc<LightHeaderGraph>,
+    syn: SynchronizationState,
+    requests: Mutex<RequestTable>,
+}
+
+impl LightProtocolHandler {
+    pub fn new(graph: Arc<LightHeaderGraph>) -> Self {
+        LightProtocolHandler {
+            graph,
+            syn: SynchronizationState::new(false),
+            requests: Default::default(),
+        }
+    }
+
+    pub fn graph(&self) -> &Arc<LightHeaderGraph> { &self.graph }
+
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.syn.peers.read().keys().cloned().collect()
+    }
+
+    /// The highest epoch announced by any connected peer.
+    pub fn best_peer_epoch(&self) -> Option<u64> {
+        self.syn
+            .peers
+            .read()
+            .values()
+            .map(|state| state.read().best_epoch)
+            .max()
+    }
+
+    fn send_request<M>(
+        &self, io: &NetworkContext, peer: PeerId, request: LightRequest,
+        mut msg: M,
+    ) -> Result<(), Error>
+    where
+        M: Message + DerefMut<Target = RequestId>,
+    {
+        let mut requests = self.requests.lock();
+        let request_id = requests.next_request_id;
+        requests.next_request_id += 1;
+        msg.set_request_id(request_id);
+        send_message(io, peer, &msg, SendQueuePriority::High)?;
+
+        if let LightRequest::Headers(hash) = request {
+            requests.headers_in_flight.insert(hash);
+        }
+        requests.in_flight.insert(
+            request_id,
+            InFlightRequest {
+                peer,
+                request,
+                sent_at: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+
+    fn match_request(
+        &self, peer: PeerId, request_id: u64,
+    ) -> Result<LightRequest, Error> {
+        let mut requests = self.requests.lock();
+        match requests.in_flight.get(&request_id) {
+            Some(req) if req.peer == peer => {}
+            _ => return Err(ErrorKind::UnexpectedResponse.into()),
+        }
+        Ok(requests.remove(request_id).expect("checked above").request)
+    }
+
+    fn request_headers(&self, io: &NetworkContext, peer: PeerId, hash: H256) {
+        if self.graph.contains(&hash)
+            || self.requests.lock().headers_in_flight.contains(&hash)
+        {
+            return;
+        }
+        let msg = GetBlockHeaders {
+            request_id: RequestId::default(),
+            hash,
+            max_blocks: HEADERS_BATCH_SIZE,
+        };
+        if let Err(e) =
+            self.send_request(io, peer, LightRequest::Headers(hash), msg)
+        {
+            debug!("Failed to request header {:?}: {:?}", hash, e);
+        }
+    }
+
+    /// Request the headers that orphan headers are waiting for, e.g. after
+    /// a request timed out or its peer disconnected.
+    fn request_missing_parents(&self, io: &NetworkContext) {
+        let peers = self.peers();
+        if peers.is_empty() {
+            return;
+        }
+        for (i, hash) in
+            self.graph.missing_parent_hashes().into_iter().enumerate()
+        {
+            self.request_headers(io, peers[i % peers.len()], hash);
+        }
+    }
+
+    /// Ask `peer` for the proofs of `keys` against the `deferred_state_root`
+    /// of the block `block_hash`.
+    pub fn request_state_proof(
+        &self, io: &NetworkContext, peer: PeerId, block_hash: H256,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Receiver<Vec<TrieProof>>, Error> {
+        let (sender, receiver) = channel();
+        let msg = GetStateProof {
+            request_id: RequestId::default(),
+            block_hash,
+            keys,
+        };
+        self.send_request(io, peer, LightRequest::StateProof(sender), msg)?;
+        Ok(receiver)
+    }
+
+    /// Ask `peer` for the receipts committed to by the
+    /// `deferred_receipts_root` of the block `block_hash`.
+    pub fn request_epoch_receipts(
+        &self, io: &NetworkContext, peer: PeerId, block_hash: H256,
+    ) -> Result<Receiver<Vec<Vec<Receipt>>>, Error> {
+        let (sender, receiver) = channel();
+        let msg = GetEpochReceipts {
+            request_id: RequestId::default(),
+            block_hash,
+        };
+        self.send_request(io, peer, LightRequest::EpochReceipts(sender), msg)?;
+        Ok(receiver)
+    }
+
+    fn dispatch_message(
+        &self, io: &NetworkContext, peer: PeerId, msg_id: MsgId, rlp: Rlp,
+    ) {
+        trace!("Dispatching message: peer={:?}, msg_id={:?}", peer, msg_id);
+        if !self.syn.contains_peer(&peer) && msg_id != MsgId::STATUS {
+            debug!("Message from unknown peer {:?}", msg_id);
+            return;
+        }
+        let version = peer_protocol_version(io, peer);
+        if !msg_id.is_valid_in(version) {
+            warn!(
+                "Message not valid in protocol version {}: peer={:?} msgid={:?}",
+                version, peer, msg_id
+            );
+            return;
+        }
+        match msg_id {
+            MsgId::STATUS => self.on_status(io, peer, &rlp),
+            MsgId::GET_BLOCK_HEADERS => {
+                self.on_get_block_headers(io, peer, &rlp)
+            }
+            MsgId::GET_BLOCK_HEADERS_RESPONSE => {
+                self.on_block_headers_response(io, peer, &rlp)
+            }
+            MsgId::NEW_BLOCK => self.on_new_block(io, peer, &rlp),
+            MsgId::NEW_BLOCK_HASHES => self.on_new_block_hashes(io, peer, &rlp),
+            MsgId::GET_STATE_PROOF_RESPONSE => {
+                self.on_state_proof_response(peer, &rlp)
+            }
+            MsgId::GET_EPOCH_RECEIPTS_RESPONSE => {
+                self.on_epoch_receipts_response(peer, &rlp)
+            }
+            // Full peers also relay transactions and may ask for blocks,
+            // which a light node doesn't have.
+            _ => {
+                trace!("Ignored message: peer={:?} msgid={:?}", peer, msg_id);
+                Ok(())
+            }
+        }
+        .unwrap_or_else(|e| {
+            warn!(
+                "Error while handling message msgid={:?}, error={:?}",
+                msg_id, e
+            );
+        });
+    }
+
+    fn on_status(
+        &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let status = rlp.as_val::<Status>()?;
+        if !self.syn.on_status(peer) {
+            warn!("Unexpected Status message from peer={}", peer);
+            return Err(ErrorKind::UnknownPeer.into());
+        }
+        debug!("on_status, msg=:{:?}", status);
+        if let Err(e) =
+            check_status(io, peer, &status, &self.graph.genesis_hash())
+        {
+            debug!("Peer {:?} sent invalid status: {}", peer, e);
+            io.decrease_peer_score(peer, INVALID_STATUS_PENALTY);
+            io.disconnect_peer(peer);
+            return Err(e);
+        }
+
+        self.syn.peer_connected(
+            peer,
+            SynchronizationPeerState {
+                id: peer,
+                protocol_version: peer_protocol_version(io, peer),
+                genesis_hash: status.genesis_hash,
+                best_epoch: status.best_epoch,
+                latest_block_hashes: HashSet::new(),
+                received_transaction_count: 0,
+                need_prop_trans: false,
+                notified_mode: None,
+            },
+        );
+        for hash in status.terminal_block_hashes {
+            self.request_headers(io, peer, hash);
+        }
+        Ok(())
+    }
+
+    fn on_get_block_headers(
+        &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let req = rlp.as_val::<GetBlockHeaders>()?;
+        debug!("on_get_block_headers, msg=:{:?}", req);
+
+        let mut hash = req.hash;
+        let mut resp = GetBlockHeadersResponse::default();
+        resp.set_request_id(req.request_id());
+        for _ in 0..cmp::min(HEADERS_BATCH_SIZE, req.max_blocks) {
+            let header = match self.graph.header_by_hash(&hash) {
+                Some(header) => header,
+                None => break,
+            };
+            resp.headers.push(header.as_ref().clone());
+            if hash == self.graph.genesis_hash() {
+                break;
+            }
+            hash = *header.parent_hash();
+        }
+
+        send_message(io, peer, &resp, SendQueuePriority::High)?;
+        Ok(())
+    }
+
+    fn on_block_headers_response(
+        &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let resp = rlp.as_val::<GetBlockHeadersResponse>()?;
+        debug!("on_block_headers_response, {} headers", resp.headers.len());
+        match self.match_request(peer, resp.request_id())? {
+            LightRequest::Headers(_) => {}
+            _ => return Err(ErrorKind::UnexpectedResponse.into()),
+        }
+        self.on_headers(io, peer, resp.headers)
+    }
+
+    fn on_headers(
+        &self, io: &NetworkContext, peer: PeerId, headers: Vec<BlockHeader>,
+    ) -> Result<(), Error> {
+        let mut dependent_hashes = Vec::new();
+        for header in headers {
+            let hash = header.hash();
+            let parent_hash = *header.parent_hash();
+            let referee_hashes = header.referee_hashes().clone();
+            if self.graph.insert_header(header, peer).is_err() {
+                warn!("Invalid header {:?} from peer {:?}", hash, peer);
+                return Err(ErrorKind::Invalid.into());
+            }
+            dependent_hashes.push(parent_hash);
+            // Referees may only be reachable from the terminals through
+            // referee edges, so request them as well.
+            dependent_hashes.extend(referee_hashes);
+        }
+        for hash in dependent_hashes {
+            self.request_headers(io, peer, hash);
+        }
+        Ok(())
+    }
+
+    fn on_new_block(
+        &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let new_block = rlp.as_val::<NewBlock>()?;
+        debug!("on_new_block, hash={:?}", new_block.block.hash());
+        self.on_headers(io, peer, vec![new_block.block.block_header])
+    }
+
+    fn on_new_block_hashes(
+        &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let new_block_hashes = rlp.as_val::<NewBlockHashes>()?;
+        debug!("on_new_block_hashes, msg={:?}", new_block_hashes);
+        for hash in new_block_hashes.block_hashes {
+            self.request_headers(io, peer, hash);
+        }
+        Ok(())
+    }
+
+    fn on_state_proof_response(
+        &self, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let resp = rlp.as_val::<GetStateProofResponse>()?;
+        match self.match_request(peer, resp.request_id())? {
+            // The query may have given up waiting already.
+            LightRequest::StateProof(sender) => {
+                sender.send(resp.proofs).ok();
+                Ok(())
+            }
+            _ => Err(ErrorKind::UnexpectedResponse.into()),
+        }
+    }
+
+    fn on_epoch_receipts_response(
+        &self, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let resp = rlp.as_val::<GetEpochReceiptsResponse>()?;
+        match self.match_request(peer, resp.request_id())? {
+            LightRequest::EpochReceipts(sender) => {
+                sender.send(resp.receipts).ok();
+                Ok(())
+            }
+            _ => Err(ErrorKind::UnexpectedResponse.into()),
+        }
+    }
+
+    fn send_status(
+        &self, io: &NetworkContext, peer: PeerId,
+    ) -> Result<(), NetworkError> {
+        debug!("Sending status message to {:?}", peer);
+        // A light node has no blocks to offer, so full peers must not pick it
+        // to sync epochs or terminals from.
+        let msg = Status {
+            protocol_version: peer_protocol_version(io, peer),
+            network_id: io.network_id(),
+            genesis_hash: self.graph.genesis_hash(),
+            best_epoch: 0,
+            terminal_block_hashes: Vec::new(),
+        };
+        send_message(io, peer, &msg, SendQueuePriority::High)
+    }
+
+    fn remove_expired_requests(&self) {
+        let mut requests = self.requests.lock();
+        let expired: Vec<u64> = requests
+            .in_flight
+            .iter()
+            .filter(|(_, req)| req.sent_at.elapsed() > REQUEST_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        for request_id in expired {
+            requests.remove(request_id);
+        }
+    }
+}
+
+impl NetworkProtocolHandler for LightProtocolHandler {
+    fn initialize(&self, io: &NetworkContext) {
+        io.register_timer(CHECK_REQUEST_TIMER, CHECK_REQUEST_PERIOD)
+            .expect("Error registering check request timer");
+    }
+
+    fn on_message(&self, io: &NetworkContext, peer: PeerId, raw: &[u8]) {
+        let msg_id = raw[0];
+        let rlp = Rlp::new(&raw[1..]);
+        debug!("on_message: peer={:?}, msgid={:?}", peer, msg_id);
+        self.dispatch_message(io, peer, msg_id.into(), rlp);
+    }
+
+    fn on_peer_connected(&self, io: &NetworkContext, peer: PeerId) {
+        info!("Peer connected: peer={:?}", peer);
+        if let Err(e) = self.send_status(io, peer) {
+            debug!("Error sending status message: {:?}", e);
+            io.disconnect_peer(peer);
+        } else {
+            self.syn
+                .handshaking_peers
+                .write()
+                .insert(peer, Instant::now());
+        }
+    }
+
+    fn on_peer_disconnected(&self, _io: &NetworkContext, peer: PeerId) {
+        info!("Peer disconnected: peer={:?}", peer);
+        self.syn.peers.write().remove(&peer);
+        self.syn.handshaking_peers.write().remove(&peer);
+        // Dropping the senders wakes up the queries waiting for this peer.
+        let mut requests = self.requests.lock();
+        let request_ids: Vec<u64> = requests
+            .in_flight
+            .iter()
+            .filter(|(_, req)| req.peer == peer)
+            .map(|(id, _)| *id)
+            .collect();
+        for request_id in request_ids {
+            requests.remove(request_id);
+        }
+    }
+
+    fn on_timeout(&self, io: &NetworkContext, timer: TimerToken) {
+        trace!("Timeout: timer={:?}", timer);
+        match timer {
+            CHECK_REQUEST_TIMER => {
+                self.remove_expired_requests();
+                self.request_missing_parents(io);
+            }
+            _ => warn!("Unknown timer {} triggered.", timer),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use super::{LightHeaderGraph, LightProtocolHandler};
+use crate::{
+    consensus::DEFERRED_STATE_EPOCH_COUNT,
+    statedb::StorageKey,
+    sync::{
+        Error, MIN_SYNCHRONIZATION_PROTOCOL_VERSION,
+        SYNCHRONIZATION_PROTOCOL_VERSION,
+    },
+};
+use cfx_types::Address;
+use network::{NetworkContext, NetworkService, PeerId, ProtocolId};
+use primitives::{
+    receipt::Receipt, Account, BlockHeader, BlockHeaderBuilder, EpochNumber,
+};
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    time::Duration,
+};
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// The number of different peers asked before a query fails.
+const MAX_QUERY_ATTEMPTS: usize = 3;
+
+/// The network service of a light node, and the queries it answers by
+/// checking the proofs of full peers against the synced headers.
+pub struct LightService {
+    network: NetworkService,
+    protocol_handler: Arc<LightProtocolHandler>,
+    protocol: ProtocolId,
+}
+
+impl LightService {
+    pub fn new(network: NetworkService, graph: Arc<LightHeaderGraph>) -> Self {
+        LightService {
+            network,
+            protocol_handler: Arc::new(LightProtocolHandler::new(graph)),
+            protocol: *b"cfx",
+        }
+    }
+
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.network.start()?;
+        self.network.register_protocol(
+            self.protocol_handler.clone(),
+            self.protocol,
+            &(MIN_SYNCHRONIZATION_PROTOCOL_VERSION
+                ..=SYNCHRONIZATION_PROTOCOL_VERSION)
+                .collect::<Vec<_>>(),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_network_service(&self) -> &NetworkService { &self.network }
+
+    pub fn graph(&self) -> &Arc<LightHeaderGraph> {
+        self.protocol_handler.graph()
+    }
+
+    pub fn best_epoch_number(&self) -> u64 { self.graph().best_epoch_number() }
+
+    /// The highest epoch whose state and receipts can be proven, i.e. which
+    /// has a pivot block `DEFERRED_STATE_EPOCH_COUNT` epochs later.
+    pub fn best_proven_epoch_number(&self) -> u64 {
+        self.best_epoch_number()
+            .saturating_sub(DEFERRED_STATE_EPOCH_COUNT)
+    }
+
+    pub fn get_height_from_epoch_number(
+        &self, epoch_number: EpochNumber,
+    ) -> Result<u64, String> {
+        let best = self.best_proven_epoch_number();
+        let height = match epoch_number {
+            EpochNumber::Earliest => 0,
+            EpochNumber::LatestMined | EpochNumber::LatestState => best,
+            EpochNumber::Number(num) => num.as_u64(),
+            EpochNumber::LatestConfirmed | EpochNumber::LatestStable => {
+                return Err(
+                    "Confirmation risks are not known by light nodes".into()
+                );
+            }
+        };
+        if height > best {
+            return Err(format!(
+                "Epoch {} cannot be proven yet, the highest provable epoch is {}",
+                height, best
+            ));
+        }
+        Ok(height)
+    }
+
+    /// The header whose deferred roots commit to the state and the receipts
+    /// of the given epoch.
+    fn witness_header(
+        &self, epoch_number: EpochNumber,
+    ) -> Result<Arc<BlockHeader>, String> {
+        let height = self.get_height_from_epoch_number(epoch_number)?;
+        let graph = self.graph();
+        graph
+            .pivot_hash(height + DEFERRED_STATE_EPOCH_COUNT)
+            .and_then(|hash| graph.header_by_hash(&hash))
+            .ok_or_else(|| format!("Epoch {} cannot be proven yet", height))
+    }
+
+    /// Send a request to up to `MAX_QUERY_ATTEMPTS` peers in turn, until one
+    /// of them answers with a response that `verify` accepts.
+    fn query<T, R, S, V>(&self, send: S, verify: V) -> Result<T, String>
+    where
+        S: Fn(
+            &LightProtocolHandler,
+            &NetworkContext,
+            PeerId,
+        ) -> Result<Receiver<R>, Error>,
+        V: Fn(R) -> Result<T, String>,
+    {
+        let mut tried = HashSet::new();
+        let mut last_error = "No peer to query".to_string();
+        for _ in 0..MAX_QUERY_ATTEMPTS {
+            let peer = match self
+                .protocol_handler
+                .peers()
+                .into_iter()
+                .find(|peer| !tried.contains(peer))
+            {
+                Some(peer) => peer,
+                None => break,
+            };
+            tried.insert(peer);
+
+            let mut receiver = None;
+            self.network.with_context(self.protocol, |io| {
+                receiver = Some(send(&self.protocol_handler, io, peer));
+            });
+            let response = match receiver {
+                Some(Ok(receiver)) => receiver.recv_timeout(QUERY_TIMEOUT),
+                Some(Err(e)) => {
+                    last_error = format!("Failed to send request: {:?}", e);
+                    continue;
+                }
+                None => return Err("Network is not started".into()),
+            };
+            match response {
+                Ok(response) => match verify(response) {
+                    Ok(result) => return Ok(result),
+                    Err(e) => {
+                        warn!("Invalid response from peer {:?}: {}", peer, e);
+                        last_error = e;
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    last_error = format!("Peer {:?} timed out", peer);
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    last_error = format!("Peer {:?} disconnected", peer);
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Get the account at `address` in the state of the given epoch, proven
+    /// against the `deferred_state_root` of the synced headers.
+    pub fn get_account(
+        &self, address: &Address, epoch_number: EpochNumber,
+    ) -> Result<Option<Account>, String> {
+        let witness = self.witness_header(epoch_number)?;
+        let key = StorageKey::new_account_key(address).as_ref().to_vec();
+        self.query(
+            |handler, io, peer| {
+                handler.request_state_proof(
+                    io,
+                    peer,
+                    witness.hash(),
+                    vec![key.clone()],
+                )
+            },
+            |proofs| {
+                if proofs.len() != 1 {
+                    return Err(format!(
+                        "Expected 1 proof, got {}",
+                        proofs.len()
+                    ));
+                }
+                match proofs[0].verify(witness.deferred_state_root(), &key)? {
+                    Some(value) => rlp::decode::<Account>(&value)
+                        .map(Some)
+                        .map_err(|e| format!("Invalid account: {:?}", e)),
+                    None => Ok(None),
+                }
+            },
+        )
+    }
+
+    /// Get the receipts of each block of the given epoch, proven against the
+    /// `deferred_receipts_root` of the synced headers.
+    pub fn get_epoch_receipts(
+        &self, epoch_number: EpochNumber,
+    ) -> Result<Vec<Vec<Receipt>>, String> {
+        let witness = self.witness_header(epoch_number)?;
+        self.query(
+            |handler, io, peer| {
+                handler.request_epoch_receipts(io, peer, witness.hash())
+            },
+            |receipts| {
+                let receipts: Vec<Arc<Vec<Receipt>>> =
+                    receipts.into_iter().map(Arc::new).collect();
+                let root =
+                    BlockHeaderBuilder::compute_block_receipts_root(&receipts);
+                if root != *witness.deferred_receipts_root() {
+                    return Err(format!(
+                        "Receipts root {:?} doesn't match {:?}",
+                        root,
+                        witness.deferred_receipts_root()
+                    ));
+                }
+                Ok(receipts
+                    .into_iter()
+                    .map(|r| Arc::try_unwrap(r).expect("not shared"))
+                    .collect())
+            },
+        )
+    }
+}
+
+pub type SharedLightService = Arc<LightService>;
@@ -2,11 +2,12 @@
 // Conflux is free software and distributed under GNU General Public License.
 // See http://www.gnu.org/licenses/
 
-use super::builtin::Builtin;
+use super::builtin::{is_builtin_name, Builtin};
 use crate::vm::Spec;
 use cfx_types::{Address, U256};
 use primitives::CardinalNumber;
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, fs::File, io::Read, sync::Arc};
+use toml::Value;
 
 #[derive(Debug, PartialEq, Default)]
 pub struct CommonParams {
@@ -103,3 +104,93 @@ pub fn new_machine() -> Machine {
         spec_rules: None,
     }
 }
+
+/// A built-in contract enabled in the chain spec: the contract `name` (as
+/// accepted by `builtin_factory`) is installed at `address` and becomes active
+/// from the cardinal number `activate_at`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuiltinSpec {
+    pub name: String,
+    pub address: Address,
+    pub activate_at: CardinalNumber,
+}
+
+pub fn new_machine_with_builtin(
+    specs: &[BuiltinSpec],
+) -> Result<Machine, String> {
+    let mut builtins = BTreeMap::new();
+    for spec in specs {
+        let builtin = Builtin::from_name(&spec.name, spec.activate_at)
+            .ok_or_else(|| format!("unknown builtin name: {}", spec.name))?;
+        builtins.insert(spec.address, builtin);
+    }
+    Ok(Machine {
+        params: CommonParams::common_params(),
+        builtins: Arc::new(builtins),
+        spec_rules: None,
+    })
+}
+
+/// Load the built-in contract specs from a toml file, in which each entry maps
+/// an address to the contract name and its activation cardinal number, e.g.
+///
+/// ```toml
+/// [0000000000000000000000000000000000000009]
+/// name = "blake2_f"
+/// activate_at = 0
+/// ```
+pub fn load_builtin_specs(path: &str) -> Result<Vec<BuiltinSpec>, String> {
+    let mut content = String::new();
+    let mut file = File::open(path)
+        .map_err(|e| format!("failed to open file: {:?}", e))?;
+    file.read_to_string(&mut content)
+        .map_err(|e| format!("failed to read file content: {:?}", e))?;
+    let builtin_values = content
+        .parse::<Value>()
+        .map_err(|e| format!("failed to parse toml file: {:?}", e))?;
+
+    let table = match builtin_values {
+        Value::Table(table) => table,
+        _ => {
+            return Err(format!(
+                "invalid root value type {:?} in toml file",
+                builtin_values.type_str()
+            ));
+        }
+    };
+
+    let mut specs = Vec::new();
+    for (key, value) in table {
+        let address = key.parse::<Address>().map_err(|e| {
+            format!("failed to parse address: value = {}, error = {:?}", key, e)
+        })?;
+        let name = match value.get("name") {
+            Some(Value::String(name)) if is_builtin_name(name) => name.clone(),
+            Some(Value::String(name)) => {
+                return Err(format!("unknown builtin name: {}", name));
+            }
+            _ => {
+                return Err(format!(
+                    "builtin {} requires a name of String type",
+                    key
+                ));
+            }
+        };
+        let activate_at = match value.get("activate_at") {
+            None => 0,
+            Some(Value::Integer(n)) if *n >= 0 => *n as CardinalNumber,
+            _ => {
+                return Err(format!(
+                    "builtin {} requires a non-negative Integer activate_at",
+                    key
+                ));
+            }
+        };
+        specs.push(BuiltinSpec {
+            name,
+            address,
+            activate_at,
+        });
+    }
+    Ok(specs)
+}
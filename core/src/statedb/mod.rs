@@ -9,7 +9,7 @@ use crate::{
         Storage, StorageTrait,
     },
 };
-use cfx_types::Address;
+use cfx_types::{Address, H256};
 use primitives::{Account, EpochId};
 
 mod error;
@@ -20,10 +20,20 @@ pub use self::{
     storage_key::StorageKey,
 };
 
+/// The number of key value pairs read from the state at a time when dumping
+/// accounts.
+const DUMP_BATCH_SIZE: usize = 1024;
+
 pub struct StateDb<'a> {
     storage: Storage<'a>,
 }
 
+/// An account with all its storage entries.
+pub struct AccountDump {
+    pub account: Account,
+    pub storage: Vec<(H256, H256)>,
+}
+
 impl<'a> StateDb<'a> {
     pub fn new(storage: Storage<'a>) -> Self { StateDb { storage } }
 
@@ -108,6 +118,58 @@ impl<'a> StateDb<'a> {
         Ok(self.storage.delete_all(key_prefix.as_ref())?)
     }
 
+    /// List at most `limit` accounts with their storage entries, in the
+    /// order of their keys, starting from the account whose key is at least
+    /// `start_key`. Also returns the key of the next account, if any, to
+    /// continue from. The state is read in batches, so that only the
+    /// returned accounts are loaded.
+    pub fn dump_accounts(
+        &self, start_key: &[u8], limit: usize,
+    ) -> Result<(Vec<AccountDump>, Option<Vec<u8>>)> {
+        let mut accounts: Vec<AccountDump> = Vec::new();
+        let mut last_account_key = Vec::new();
+        let mut lower_bound = start_key.to_vec();
+        loop {
+            let entries = self.storage.read_range(
+                &lower_bound,
+                None,
+                Some(DUMP_BATCH_SIZE),
+            )?;
+            let exhausted = entries.len() < DUMP_BATCH_SIZE;
+            for (key, value) in entries {
+                if StorageKey::is_account_key(&key) {
+                    if accounts.len() >= limit {
+                        return Ok((accounts, Some(key)));
+                    }
+                    accounts.push(AccountDump {
+                        account: ::rlp::decode::<Account>(value.as_ref())?,
+                        storage: Vec::new(),
+                    });
+                    last_account_key = key.clone();
+                } else if let Some((account_key, position)) =
+                    StorageKey::parse_storage_key(&key)
+                {
+                    // Storage entries directly follow their account in key
+                    // order.
+                    if account_key == last_account_key.as_slice() {
+                        accounts.last_mut().unwrap().storage.push((
+                            position,
+                            ::rlp::decode::<H256>(value.as_ref())?,
+                        ));
+                    } else {
+                        warn!("Storage entry {:?} without account", key);
+                    }
+                }
+                lower_bound = key;
+            }
+            if exhausted {
+                return Ok((accounts, None));
+            }
+            // The smallest key after the last one read.
+            lower_bound.push(0);
+        }
+    }
+
     pub fn commit(&mut self, epoch_id: EpochId) -> Result<MerkleHash> {
         let merkle_hash = self.storage.compute_state_root()?;
         self.storage.commit(epoch_id)?;
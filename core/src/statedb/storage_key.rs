@@ -120,6 +120,31 @@ impl StorageKey {
     }
 }
 
+impl StorageKey {
+    /// Whether a raw key read from the state is the key of an account.
+    pub fn is_account_key(raw_key: &[u8]) -> bool {
+        raw_key.len() == Self::ACCOUNT_HASH_BYTES
+    }
+
+    /// Split a raw key read from the state into the account key and the
+    /// storage position, if it's the key of a storage entry.
+    pub fn parse_storage_key(raw_key: &[u8]) -> Option<(&[u8], H256)> {
+        let position_offset =
+            Self::ACCOUNT_HASH_BYTES + Self::STORAGE_PREFIX.len();
+        if raw_key.len() == position_offset + Self::KEY_BYTES
+            && &raw_key[Self::ACCOUNT_HASH_BYTES..position_offset]
+                == Self::STORAGE_PREFIX
+        {
+            Some((
+                &raw_key[0..Self::ACCOUNT_HASH_BYTES],
+                H256::from_slice(&raw_key[position_offset..]),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
 impl AsRef<[u8]> for StorageKey {
     fn as_ref(&self) -> &[u8] {
         match self {
@@ -2,6 +2,7 @@
 // Conflux is free software and distributed under GNU General Public License.
 // See http://www.gnu.org/licenses/
 
+use cfx_types::H256;
 use std::{io, num};
 
 error_chain! {
@@ -47,6 +48,11 @@ error_chain! {
             display("Invalid value."),
         }
 
+        MPTInvalidMerkle(merkle: H256) {
+            description("Invalid merkle hash of a trie node."),
+            display("Trie node {:?} doesn't match its children and value.", merkle),
+        }
+
         MPTTooManyNodes {
             description("Too many nodes."),
             display("Too many nodes."),
@@ -127,31 +127,9 @@ impl<'trie> SubTrieVisitor<'trie> {
                     Ok(Some(trie_node.merkle_hash))
                 } else {
                     let maybe_value = trie_node.value_clone().into_option();
-                    let merkles = match trie_node
-                        .children_table
-                        .get_children_count()
-                    {
-                        0 => None,
-                        _ => {
-                            let mut merkles = ChildrenMerkleTable::default();
-                            let children_table =
-                                trie_node.children_table.clone();
-                            drop(trie_node);
-                            for (i, maybe_node_ref) in
-                                children_table.iter_non_skip()
-                            {
-                                merkles[i as usize] = match maybe_node_ref {
-                                    None => super::merkle::MERKLE_NULL_NODE,
-                                    Some(node_ref) => self
-                                        .trie_ref
-                                        .get_merkle(Some((*node_ref).into()))?
-                                        .unwrap(),
-                                };
-                            }
-
-                            Some(merkles)
-                        }
-                    };
+                    let children_table = trie_node.children_table.clone();
+                    drop(trie_node);
+                    let merkles = self.get_children_merkles(&children_table)?;
 
                     Ok(Some(compute_node_merkle(
                         merkles.as_ref(),
@@ -162,6 +140,139 @@ impl<'trie> SubTrieVisitor<'trie> {
         }
     }
 
+    fn get_children_merkles(
+        &self, children_table: &ChildrenTableDeltaMpt,
+    ) -> Result<MaybeMerkleTable> {
+        match children_table.get_children_count() {
+            0 => Ok(None),
+            _ => {
+                let mut merkles = ChildrenMerkleTable::default();
+                for (i, maybe_node_ref) in children_table.iter_non_skip() {
+                    merkles[i as usize] = match maybe_node_ref {
+                        None => super::merkle::MERKLE_NULL_NODE,
+                        Some(node_ref) => self
+                            .trie_ref
+                            .get_merkle(Some((*node_ref).into()))?
+                            .unwrap(),
+                    };
+                }
+
+                Ok(Some(merkles))
+            }
+        }
+    }
+
+    /// Collect the trie nodes from the root of the sub-trie to the node where
+    /// the walk for key stops, which proves either the value of key or its
+    /// absence.
+    pub fn get_proof(&self, key: KeyPart) -> Result<TrieProof> {
+        let node_memory_manager = self.node_memory_manager();
+        let allocator = node_memory_manager.get_allocator();
+        let cache_manager = node_memory_manager.get_cache_manager();
+        let mut node_ref = self.root.node_ref.clone();
+        let mut key = key;
+
+        let mut nodes = Vec::new();
+        loop {
+            let trie_node = node_memory_manager
+                .node_as_ref_with_cache_manager(
+                    &allocator,
+                    node_ref,
+                    cache_manager,
+                    &mut false,
+                )?;
+            let compressed_path = trie_node.compressed_path_ref();
+            let path_end_mask = compressed_path.end_mask();
+            let compressed_path = compressed_path.path_slice().to_vec();
+            let value = trie_node
+                .value_clone()
+                .into_option()
+                .map(|value| value.into_vec());
+            let children_table = trie_node.children_table.clone();
+            let next = match trie_node.walk::<Read>(key) {
+                WalkStop::Descent {
+                    key_remaining,
+                    child_index: _,
+                    child_node,
+                } => Some((child_node, key_remaining)),
+                _ => None,
+            };
+            drop(trie_node);
+
+            nodes.push(TrieProofNode {
+                path_end_mask,
+                compressed_path,
+                children_merkles: self
+                    .get_children_merkles(&children_table)?
+                    .map(|merkles| merkles.to_vec()),
+                value,
+            });
+
+            match next {
+                Some((child_node, key_remaining)) => {
+                    node_ref = child_node;
+                    key = key_remaining;
+                }
+                None => return Ok(TrieProof::new(nodes)),
+            }
+        }
+    }
+
+    /// Recompute the merkle hash of every node in the sub-trie from its value
+    /// and the merkle hashes of its children, and compare it with the stored
+    /// one. The nodes in verified_nodes are skipped and the others are added
+    /// to it. Returns the number of verified nodes.
+    pub fn verify_merkles(
+        &self, verified_nodes: &mut BTreeSet<NodeRefDeltaMpt>,
+    ) -> Result<usize> {
+        let node_memory_manager = self.node_memory_manager();
+        let allocator = node_memory_manager.get_allocator();
+        let cache_manager = node_memory_manager.get_cache_manager();
+        let mut node_refs = vec![self.root.node_ref.clone()];
+
+        let mut verified = 0;
+        while let Some(node_ref) = node_refs.pop() {
+            if !verified_nodes.insert(node_ref.clone()) {
+                continue;
+            }
+            let trie_node = node_memory_manager
+                .node_as_ref_with_cache_manager(
+                    &allocator,
+                    node_ref,
+                    cache_manager,
+                    &mut false,
+                )?;
+            let merkle_hash = trie_node.merkle_hash;
+            let compressed_path = trie_node.compressed_path_ref();
+            let path_end_mask = compressed_path.end_mask();
+            let path = compressed_path.path_slice().to_vec();
+            let value = trie_node.value_clone().into_option();
+            let children_table = trie_node.children_table.clone();
+            drop(trie_node);
+
+            let children_merkles =
+                self.get_children_merkles(&children_table)?;
+            let computed_merkle_hash = compute_merkle(
+                CompressedPathRef {
+                    path_slice: &path,
+                    end_mask: path_end_mask,
+                },
+                children_merkles.as_ref(),
+                value.as_ref().map(|value| value.as_ref()),
+            );
+            if computed_merkle_hash != merkle_hash {
+                return Err(ErrorKind::MPTInvalidMerkle(merkle_hash).into());
+            }
+            for (_, maybe_node_ref) in children_table.iter_non_skip() {
+                if let Some(node_ref) = maybe_node_ref {
+                    node_refs.push((*node_ref).into());
+                }
+            }
+            verified += 1;
+        }
+        Ok(verified)
+    }
+
     /// The visitor can only be used once to modify.
     /// Returns (deleted value, is root node replaced, the current root node for
     /// the subtree).
@@ -330,6 +441,193 @@ impl<'trie> SubTrieVisitor<'trie> {
         unimplemented!();
     }
 
+    /// Find the node of the subtree which contains all keys prefixed by
+    /// key_prefix, and the full path of the node.
+    fn find_subtree(
+        &self, key_prefix: KeyPart,
+    ) -> Result<Option<(NodeRefDeltaMpt, CompressedPathRaw)>> {
+        let node_memory_manager = self.node_memory_manager();
+        let allocator = node_memory_manager.get_allocator();
+        let owned_node_set = self.owned_node_set.get_ref();
+        let mut node_ref = self.root.node_ref.clone();
+        let mut key_remaining = key_prefix;
+
+        loop {
+            let mut node_cow = CowNodeRef::new(node_ref, owned_node_set);
+            let trie_node =
+                node_cow.get_trie_node(node_memory_manager, &allocator)?;
+            let subtree_key_prefix: CompressedPathRaw;
+            match trie_node.walk::<Write>(key_remaining) {
+                WalkStop::ChildNotFound { .. } => return Ok(None),
+                WalkStop::Arrived => {
+                    subtree_key_prefix = key_prefix.into();
+                }
+                WalkStop::PathDiverted {
+                    key_child_index,
+                    unmatched_child_index,
+                    unmatched_path_remaining,
+                    ..
+                } => {
+                    if key_child_index.is_some() {
+                        return Ok(None);
+                    }
+                    subtree_key_prefix = CompressedPathRaw::concat(
+                        &key_prefix,
+                        unmatched_child_index,
+                        &unmatched_path_remaining,
+                    );
+                }
+                WalkStop::Descent {
+                    key_remaining: child_key_remaining,
+                    child_node,
+                    ..
+                } => {
+                    node_ref = child_node;
+                    key_remaining = child_key_remaining;
+                    continue;
+                }
+            }
+            drop(trie_node);
+
+            return Ok(Some((node_cow.node_ref.clone(), subtree_key_prefix)));
+        }
+    }
+
+    /// Collect all the key value pairs whose key is prefixed by key_prefix.
+    /// The pairs are in the order of the trie, which visits the first
+    /// nibble of each byte before the second nibble.
+    pub fn read_all(
+        &self, key_prefix: KeyPart,
+    ) -> Result<Vec<(Vec<u8>, Box<[u8]>)>> {
+        let mut values = vec![];
+        let (node_ref, subtree_key_prefix) =
+            match self.find_subtree(key_prefix)? {
+                None => return Ok(values),
+                Some(subtree) => subtree,
+            };
+
+        let node_memory_manager = self.node_memory_manager();
+        let allocator = node_memory_manager.get_allocator();
+        let owned_node_set = self.owned_node_set.get_ref();
+        let mut node_cow = CowNodeRef::new(node_ref, owned_node_set);
+        let trie_node = GuardedValue::take(
+            node_cow.get_trie_node(node_memory_manager, &allocator)?,
+        );
+        node_cow.iterate_internal(
+            owned_node_set,
+            self.get_trie_ref(),
+            trie_node,
+            subtree_key_prefix,
+            &mut values,
+        )?;
+        Ok(values)
+    }
+
+    /// Collect the key value pairs prefixed by key_prefix whose key is in
+    /// [lower_bound, upper_bound), in the order of keys, and stop after
+    /// `limit` pairs. Deleted values are skipped.
+    ///
+    /// Since the trie visits the first nibble of each byte before the second
+    /// nibble, its order differs from the order of keys. Instead, the
+    /// subtrees are visited in the order of the smallest key they may
+    /// contain, so that only the nodes up to the last collected key and their
+    /// children are loaded.
+    pub fn read_range(
+        &self, key_prefix: KeyPart, lower_bound: &[u8],
+        upper_bound: Option<&[u8]>, limit: Option<usize>,
+    ) -> Result<Vec<(Vec<u8>, Box<[u8]>)>>
+    {
+        let mut values = vec![];
+        let (root, root_path) = match self.find_subtree(key_prefix)? {
+            None => return Ok(values),
+            Some(subtree) => subtree,
+        };
+
+        let node_memory_manager = self.node_memory_manager();
+        let allocator = node_memory_manager.get_allocator();
+        let owned_node_set = self.owned_node_set.get_ref();
+
+        // Keyed by the smallest key that the item may contain, where a value
+        // goes before the subtrees with the same bound. The sequence number
+        // separates subtrees with the same bound.
+        let mut pending: BTreeMap<(Vec<u8>, bool, usize), RangeItem> =
+            BTreeMap::new();
+        pending.insert(
+            (subtree_key_bound(&root_path), true, 0),
+            RangeItem::Subtree(root, root_path),
+        );
+        let mut sequence = 1;
+
+        while limit.map_or(true, |limit| values.len() < limit) {
+            let item_key = match pending.keys().next() {
+                None => break,
+                Some(item_key) => item_key.clone(),
+            };
+            if let Some(upper_bound) = upper_bound {
+                if item_key.0.as_slice() >= upper_bound {
+                    break;
+                }
+            }
+            match pending.remove(&item_key).expect("key exists") {
+                RangeItem::Value(value) => {
+                    if item_key.0.as_slice() >= lower_bound {
+                        values.push((item_key.0, value));
+                    }
+                }
+                RangeItem::Subtree(node_ref, path) => {
+                    let mut node_cow =
+                        CowNodeRef::new(node_ref, owned_node_set);
+                    let trie_node = node_cow
+                        .get_trie_node(node_memory_manager, &allocator)?;
+                    let value = if trie_node.has_value() {
+                        Some(trie_node.value_clone().unwrap())
+                    } else {
+                        None
+                    };
+                    let children_table = trie_node.children_table.clone();
+                    // Free the lock for trie_node before loading children.
+                    drop(trie_node);
+
+                    if let Some(value) = value {
+                        if value.len() > 0 {
+                            pending.insert(
+                                (path.path_slice().to_vec(), false, 0),
+                                RangeItem::Value(value),
+                            );
+                        }
+                    }
+                    for (i, child_ref) in children_table.iter() {
+                        let child_ref: NodeRefDeltaMpt = (*child_ref).into();
+                        let mut child_cow =
+                            CowNodeRef::new(child_ref.clone(), owned_node_set);
+                        let child_node = child_cow
+                            .get_trie_node(node_memory_manager, &allocator)?;
+                        let child_path = CompressedPathRaw::concat(
+                            &path,
+                            i,
+                            &child_node.compressed_path_ref(),
+                        );
+                        drop(child_node);
+
+                        if !is_subtree_before(&child_path, lower_bound) {
+                            pending.insert(
+                                (
+                                    subtree_key_bound(&child_path),
+                                    true,
+                                    sequence,
+                                ),
+                                RangeItem::Subtree(child_ref, child_path),
+                            );
+                            sequence += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
     /// The visitor can only be used once to modify.
     /// Returns (deleted value, is root node replaced, the current root node for
     /// the subtree).
@@ -678,6 +976,34 @@ impl<'trie> SubTrieVisitor<'trie> {
     }
 }
 
+enum RangeItem {
+    Value(Box<[u8]>),
+    Subtree(NodeRefDeltaMpt, CompressedPathRaw),
+}
+
+/// The smallest key in the subtree at `path`, where the unknown second
+/// nibble of the last byte is zero.
+fn subtree_key_bound(path: &CompressedPathRaw) -> Vec<u8> {
+    let mut bound = path.path_slice().to_vec();
+    if path.end_mask() != 0 {
+        if let Some(last) = bound.last_mut() {
+            *last &= path.end_mask();
+        }
+    }
+    bound
+}
+
+/// Whether all keys in the subtree at `path` are smaller than `key`.
+fn is_subtree_before(path: &CompressedPathRaw, key: &[u8]) -> bool {
+    let path_slice = path.path_slice();
+    let full_bytes = if path.end_mask() != 0 {
+        &path_slice[0..path_slice.len() - 1]
+    } else {
+        path_slice
+    };
+    full_bytes < key && !key.starts_with(full_bytes)
+}
+
 use super::{
     super::{
         super::{errors::*, state::OwnedNodeSet},
@@ -691,4 +1017,8 @@ use super::{
     *,
 };
 use parking_lot::MutexGuard;
-use std::hint::unreachable_unchecked;
+use primitives::{TrieProof, TrieProofNode};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    hint::unreachable_unchecked,
+};
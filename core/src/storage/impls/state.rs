@@ -3,6 +3,9 @@
 // See http://www.gnu.org/licenses/
 
 pub type OwnedNodeSet = BTreeSet<NodeRefDeltaMpt>;
+/// The trie nodes whose merkle hashes are verified, which may be shared by
+/// several states.
+pub type VerifiedNodeSet = BTreeSet<NodeRefDeltaMpt>;
 
 pub struct State<'a> {
     manager: &'a StateManager,
@@ -70,6 +73,64 @@ impl<'a> StateTrait for State<'a> {
         }
     }
 
+    fn read_all(
+        &self, access_key_prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Box<[u8]>)>> {
+        // Read won't create any new nodes so it's fine to pass an empty
+        // owned_node_set.
+        let mut empty_owned_node_set: Option<OwnedNodeSet> =
+            Some(Default::default());
+        let mut values = match self.get_root_node() {
+            None => vec![],
+            Some(root_node) => SubTrieVisitor::new(
+                self.delta_trie,
+                root_node,
+                &mut empty_owned_node_set,
+            )
+            .read_all(access_key_prefix)?,
+        };
+        // Skip deleted keys.
+        values.retain(|(_, value)| value.len() > 0);
+        // The trie visits the lower nibble of a byte first.
+        values.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+
+        Ok(values)
+    }
+
+    fn read_range(
+        &self, lower_bound: &[u8], upper_bound: Option<&[u8]>,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Vec<u8>, Box<[u8]>)>>
+    {
+        // All keys in the range share the common prefix of the bounds.
+        let common_prefix_len = match upper_bound {
+            None => 0,
+            Some(upper_bound) => lower_bound
+                .iter()
+                .zip(upper_bound.iter())
+                .take_while(|(a, b)| a == b)
+                .count(),
+        };
+        // Read won't create any new nodes so it's fine to pass an empty
+        // owned_node_set.
+        let mut empty_owned_node_set: Option<OwnedNodeSet> =
+            Some(Default::default());
+        match self.get_root_node() {
+            None => Ok(vec![]),
+            Some(root_node) => SubTrieVisitor::new(
+                self.delta_trie,
+                root_node,
+                &mut empty_owned_node_set,
+            )
+            .read_range(
+                &lower_bound[0..common_prefix_len],
+                lower_bound,
+                upper_bound,
+                limit,
+            ),
+        }
+    }
+
     fn set(&mut self, access_key: &[u8], value: &[u8]) -> Result<()> {
         self.pre_modification();
 
@@ -143,6 +204,38 @@ impl<'a> StateTrait for State<'a> {
         commit_result
     }
 
+    fn get_proof(&self, access_key: &[u8]) -> Result<TrieProof> {
+        let mut empty_owned_node_set: Option<OwnedNodeSet> =
+            Some(Default::default());
+        match self.get_root_node() {
+            None => Ok(TrieProof::default()),
+            Some(root_node) => SubTrieVisitor::new(
+                self.delta_trie,
+                root_node,
+                &mut empty_owned_node_set,
+            )
+            .get_proof(access_key),
+        }
+    }
+
+    fn verify_merkles(
+        &self, verified_nodes: &mut VerifiedNodeSet,
+    ) -> Result<usize> {
+        // Verification won't create any new nodes so it's fine to pass an
+        // empty owned_node_set.
+        let mut empty_owned_node_set: Option<OwnedNodeSet> =
+            Some(Default::default());
+        match self.get_root_node() {
+            None => Ok(0),
+            Some(root_node) => SubTrieVisitor::new(
+                self.delta_trie,
+                root_node,
+                &mut empty_owned_node_set,
+            )
+            .verify_merkles(verified_nodes),
+        }
+    }
+
     fn revert(&mut self) {
         self.dirty = false;
 
@@ -343,7 +436,7 @@ use super::{
         MultiVersionMerklePatriciaTrie,
     },
 };
-use primitives::EpochId;
+use primitives::{EpochId, TrieProof};
 use std::{
     collections::BTreeSet, hint::unreachable_unchecked, sync::atomic::Ordering,
 };
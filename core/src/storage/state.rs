@@ -9,7 +9,7 @@
 ///
 /// A writable state is copy-on-write reference to the base state in the
 /// state union. State is supposed to be owned by single user.
-pub use super::impls::state::State;
+pub use super::impls::state::{State, VerifiedNodeSet};
 
 // The trait is created to separate the implementation to another file, and the
 // concrete struct is put into inner mod, because the implementation is
@@ -27,6 +27,18 @@ pub trait StateTrait {
     fn get(&self, access_key: &[u8]) -> Result<Option<Box<[u8]>>>;
     fn set(&mut self, access_key: &[u8], value: &[u8]) -> Result<()>;
     fn delete(&mut self, access_key: &[u8]) -> Result<Option<Box<[u8]>>>;
+    /// All the key value pairs with key prefixed by access_key_prefix, in
+    /// ascending order of key.
+    fn read_all(
+        &self, access_key_prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Box<[u8]>)>>;
+    /// The key value pairs with lower_bound <= key < upper_bound, in
+    /// ascending order of key. No more than limit pairs are returned, and the
+    /// trie is only visited up to the last returned key.
+    fn read_range(
+        &self, lower_bound: &[u8], upper_bound: Option<&[u8]>,
+        limit: Option<usize>,
+    ) -> Result<Vec<(Vec<u8>, Box<[u8]>)>>;
     // Delete everything prefixed by access_key and return deleted key value
     // pairs.
     fn delete_all(
@@ -41,11 +53,24 @@ pub trait StateTrait {
     fn commit(&mut self, epoch: EpochId) -> Result<()>;
     fn revert(&mut self);
 
-    // TODO(yz): verifiable proof related methods.
+    // Verifiable proof related methods.
+    /// The trie nodes from the state root to access_key, proving either its
+    /// value or its absence against the state root. The state root must have
+    /// been computed.
+    fn get_proof(&self, access_key: &[u8]) -> Result<TrieProof>;
+
+    // Integrity check.
+    /// Recompute the merkle hash of every trie node reachable from the state
+    /// root, and check it against the stored one. The nodes already in
+    /// verified_nodes are skipped, so that the nodes shared by several states
+    /// are verified once. Returns the number of verified nodes.
+    fn verify_merkles(
+        &self, verified_nodes: &mut VerifiedNodeSet,
+    ) -> Result<usize>;
 }
 
 use super::impls::{
     errors::*,
     multi_version_merkle_patricia_trie::merkle_patricia_trie::MerkleHash,
 };
-use primitives::EpochId;
+use primitives::{EpochId, TrieProof};
@@ -275,6 +275,158 @@ fn test_set_delete_all() {
     assert_eq!(state_root, empty_state_root);
 }
 
+#[test]
+fn test_read_all_read_range() {
+    let mut rng = get_rng_for_test();
+    let state_manager = new_state_manager_for_testing();
+    let mut state = state_manager.get_state_at(H256::default()).unwrap();
+    let mut keys: Vec<[u8; 4]> = generate_keys(10000);
+
+    for key in &keys {
+        state.set(key, key).expect("Failed to insert key.");
+    }
+    assert_eq!(state.read_all(&[]).unwrap().len(), keys.len());
+
+    let mut epoch_id = H256::default();
+    epoch_id[0] = 1;
+    state.compute_state_root().unwrap();
+    state.commit(epoch_id).unwrap();
+
+    let state = state_manager.get_state_at(epoch_id).unwrap();
+    keys.sort();
+    let all_keys: Vec<Vec<u8>> = state
+        .read_all(&[])
+        .unwrap()
+        .into_iter()
+        .map(|(key, value)| {
+            assert_eq!(key.as_slice(), value.as_ref());
+            key
+        })
+        .collect();
+    assert_eq!(
+        all_keys,
+        keys.iter().map(|key| key.to_vec()).collect::<Vec<_>>()
+    );
+
+    for _ in 0..100 {
+        let key = keys[rng.gen::<usize>() % keys.len()];
+        let key_prefix = &key[0..(1 + rng.gen::<usize>() % 3)];
+        let expected: Vec<Vec<u8>> = keys
+            .iter()
+            .filter(|key| key.starts_with(key_prefix))
+            .map(|key| key.to_vec())
+            .collect();
+        let read: Vec<Vec<u8>> = state
+            .read_all(key_prefix)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(read, expected);
+    }
+
+    for _ in 0..100 {
+        let mut bounds = [
+            keys[rng.gen::<usize>() % keys.len()],
+            keys[rng.gen::<usize>() % keys.len()],
+        ];
+        bounds.sort();
+        let limit = rng.gen::<usize>() % 100;
+        let expected: Vec<Vec<u8>> = keys
+            .iter()
+            .filter(|key| **key >= bounds[0] && **key < bounds[1])
+            .take(limit)
+            .map(|key| key.to_vec())
+            .collect();
+        let read: Vec<Vec<u8>> = state
+            .read_range(&bounds[0], Some(&bounds[1][..]), Some(limit))
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(read, expected);
+    }
+    assert_eq!(
+        state.read_range(&keys[1], None, None).unwrap().len(),
+        keys.len() - 1
+    );
+}
+
+#[test]
+fn test_read_range_limit_in_key_order() {
+    let state_manager = new_state_manager_for_testing();
+    let mut state = state_manager.get_state_at(H256::default()).unwrap();
+    // The trie visits the first nibble of a byte first, e.g. 0x21 before
+    // 0x12.
+    let keys: Vec<Vec<u8>> = vec![
+        vec![0x12],
+        vec![0x12, 0x01],
+        vec![0x13],
+        vec![0x21],
+        vec![0x21, 0x05],
+        vec![0x22],
+    ];
+    for key in &keys {
+        state.set(key, key).expect("Failed to insert key.");
+    }
+    state.delete(&[0x13]).unwrap();
+
+    let read = |lower_bound: &[u8], upper_bound: Option<&[u8]>, limit| {
+        state
+            .read_range(lower_bound, upper_bound, limit)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        read(&[], None, Some(3)),
+        vec![vec![0x12], vec![0x12, 0x01], vec![0x21]]
+    );
+    assert_eq!(
+        read(&[0x12, 0x00], Some(&[0x21, 0x05]), None),
+        vec![vec![0x12, 0x01], vec![0x21]]
+    );
+    assert_eq!(
+        read(&[0x21], None, Some(2)),
+        vec![vec![0x21], vec![0x21, 0x05]]
+    );
+    assert_eq!(read(&[0x20], None, Some(0)), Vec::<Vec<u8>>::new());
+}
+
+#[test]
+fn test_get_proof() {
+    let state_manager = new_state_manager_for_testing();
+    let mut state = state_manager.get_state_at(H256::default()).unwrap();
+    let keys: Vec<[u8; 4]> = generate_keys(10000);
+    let (keys_set, keys_absent) = keys.split_at(keys.len() / 2);
+
+    let empty_proof = state.get_proof(&keys[0]).unwrap();
+    assert_eq!(
+        empty_proof.verify(&MERKLE_NULL_NODE, &keys[0]).unwrap(),
+        None
+    );
+
+    for key in keys_set {
+        state.set(key, key).expect("Failed to insert key.");
+    }
+    let merkle = state.compute_state_root().unwrap();
+    let mut epoch_id = H256::default();
+    epoch_id[0] = 1;
+    state.commit(epoch_id).unwrap();
+
+    let state = state_manager.get_state_at(epoch_id).unwrap();
+    for key in keys_set {
+        let proof = state.get_proof(key).expect("Failed to get proof.");
+        assert!(proof.is_valid(&merkle, key, Some(&key[..])));
+        assert!(!proof.is_valid(&merkle, key, None));
+    }
+    for key in keys_absent {
+        let proof = state.get_proof(key).expect("Failed to get proof.");
+        assert_eq!(proof.verify(&merkle, key).unwrap(), None);
+    }
+}
+
 fn print(key: &[u8]) {
     print!("key = (");
     for char in key {
@@ -432,8 +584,11 @@ fn test_set_order_concurrent() {
 
 use super::{
     super::{
-        impls::multi_version_merkle_patricia_trie::merkle_patricia_trie::CompressedPathRaw,
-        state::*, state_manager::*,
+        impls::multi_version_merkle_patricia_trie::merkle_patricia_trie::{
+            CompressedPathRaw, MERKLE_NULL_NODE,
+        },
+        state::*,
+        state_manager::*,
     },
     new_state_manager_for_testing,
 };
@@ -20,6 +20,11 @@ error_chain! {
             display("Invalid block"),
         }
 
+        InvalidStatus(reason: String) {
+            description("Invalid status message"),
+            display("Invalid status message: {}", reason),
+        }
+
         UnknownPeer {
             description("Unknown peer"),
             display("Unknown peer"),
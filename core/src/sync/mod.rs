@@ -8,6 +8,8 @@ mod synchronization_graph;
 mod synchronization_protocol_handler;
 mod synchronization_service;
 mod synchronization_state;
+#[cfg(test)]
+mod tests;
 
 pub use self::{
     error::{Error, ErrorKind},
@@ -17,8 +19,9 @@ pub use self::{
         SynchronizationGraphNode,
     },
     synchronization_protocol_handler::{
-        ProtocolConfiguration, SynchronizationProtocolHandler,
-        SYNCHRONIZATION_PROTOCOL_VERSION,
+        check_status, peer_protocol_version, ProtocolConfiguration, SyncStatus,
+        SynchronizationProtocolHandler, INVALID_STATUS_PENALTY,
+        MIN_SYNCHRONIZATION_PROTOCOL_VERSION, SYNCHRONIZATION_PROTOCOL_VERSION,
     },
     synchronization_service::{
         SharedSynchronizationService, SynchronizationService,
@@ -35,8 +38,8 @@ pub mod msg_sender {
     use cfx_bytes::Bytes;
     use message::Message;
     use network::{
-        throttling::THROTTLING_SERVICE, Error as NetworkError, NetworkContext,
-        PeerId,
+        throttling::THROTTLING_SERVICE, Error as NetworkError,
+        ErrorKind as NetworkErrorKind, NetworkContext, PeerId,
     };
     use priority_send_queue::SendQueuePriority;
 
@@ -53,6 +56,16 @@ pub mod msg_sender {
         priority: SendQueuePriority, throttling_disabled: bool,
     ) -> Result<(), NetworkError>
     {
+        if let Some(version) = io.get_protocol_version(peer) {
+            if !msg.msg_id().is_valid_in(version) {
+                debug!(
+                    "Message({}) is not supported by protocol version {}",
+                    msg.msg_id(),
+                    version
+                );
+                return Err(NetworkErrorKind::BadProtocol.into());
+            }
+        }
         if !throttling_disabled && msg.is_size_sensitive() {
             if let Err(e) = THROTTLING_SERVICE.read().check_throttling() {
                 debug!("Throttling failure: {:?}", e);
@@ -0,0 +1,373 @@
+use crate::sync::synchronization_protocol_handler::{
+    EPOCH_SYNC_MAX_INFLIGHT, EPOCH_SYNC_STRIDE,
+};
+use network::PeerId;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+/// The number of epochs requested from a new peer at a time.
+const INITIAL_EPOCH_BATCH_SIZE: usize = 2;
+const MAX_EPOCH_BATCH_SIZE: usize = 16;
+/// Peers answering within this time get larger batches.
+const TARGET_EPOCH_LATENCY: Duration = Duration::from_secs(2);
+/// A request is re-assigned to another peer if it is not answered within
+/// `SLOW_REQUEST_FACTOR` times the average latency of the peer, and at least
+/// `MIN_SLOW_REQUEST_TIME`.
+const SLOW_REQUEST_FACTOR: u32 = 4;
+const MIN_SLOW_REQUEST_TIME: Duration = Duration::from_secs(3);
+
+/// The download performance of a peer in catch-up mode.
+struct PeerSyncStats {
+    /// The number of epochs which may be in flight to the peer.
+    batch_size: usize,
+    in_flight: usize,
+    /// Moving average of the response time.
+    latency: Option<Duration>,
+}
+
+impl PeerSyncStats {
+    fn new() -> Self {
+        PeerSyncStats {
+            batch_size: INITIAL_EPOCH_BATCH_SIZE,
+            in_flight: 0,
+            latency: None,
+        }
+    }
+
+    /// Estimated epochs per second, where a peer without measurements is
+    /// assumed to answer within `TARGET_EPOCH_LATENCY`.
+    fn throughput(&self) -> f64 {
+        let latency = self.latency.unwrap_or(TARGET_EPOCH_LATENCY);
+        let secs =
+            latency.as_secs() as f64 + latency.subsec_nanos() as f64 / 1e9;
+        self.batch_size as f64 / secs.max(1e-3)
+    }
+
+    fn slow_request_time(&self) -> Duration {
+        self.latency
+            .map_or(MIN_SLOW_REQUEST_TIME, |latency| {
+                latency * SLOW_REQUEST_FACTOR
+            })
+            .max(MIN_SLOW_REQUEST_TIME)
+    }
+
+    fn on_response(&mut self, elapsed: Duration) {
+        self.latency = Some(match self.latency {
+            None => elapsed,
+            Some(latency) => (latency * 7 + elapsed) / 8,
+        });
+        if elapsed <= TARGET_EPOCH_LATENCY {
+            self.batch_size = (self.batch_size + 1).min(MAX_EPOCH_BATCH_SIZE);
+        } else {
+            self.on_failure();
+        }
+    }
+
+    fn on_failure(&mut self) { self.batch_size = (self.batch_size / 2).max(1); }
+}
+
+/// Splits the epochs to download in catch-up mode across all peers which
+/// have them. Every peer may have `batch_size` epoch requests in flight,
+/// which grows while the peer answers fast and halves when it is slow or
+/// fails, and at most `EPOCH_SYNC_MAX_INFLIGHT` requests are in flight in
+/// total. Epochs of failed or slow requests are assigned again, preferably
+/// to other peers, before new epochs.
+///
+/// Only every `EPOCH_SYNC_STRIDE`-th epoch is requested far from the best
+/// epoch of peers, since the parents of the received headers are requested
+/// recursively.
+pub struct EpochSyncScheduler {
+    peers: HashMap<PeerId, PeerSyncStats>,
+    /// The time when an epoch was requested from a peer.
+    in_flight: HashMap<(u64, PeerId), Instant>,
+    /// Epochs to assign again, with the peer which failed them.
+    retry: BTreeMap<u64, PeerId>,
+    latest_requested: u64,
+}
+
+impl EpochSyncScheduler {
+    pub fn new() -> Self {
+        EpochSyncScheduler {
+            peers: HashMap::new(),
+            in_flight: HashMap::new(),
+            retry: BTreeMap::new(),
+            latest_requested: 0,
+        }
+    }
+
+    pub fn num_in_flight(&self) -> usize { self.in_flight.len() }
+
+    /// Assign epochs to `peers`, given with their best epochs. Returns the
+    /// requests to send.
+    pub fn schedule(
+        &mut self, my_best_epoch: u64, peers: &[(PeerId, u64)], now: Instant,
+    ) -> Vec<(PeerId, u64)> {
+        self.retry.retain(|epoch, _| *epoch > my_best_epoch);
+        self.reassign_slow_requests(now);
+
+        let mut peers: Vec<(PeerId, u64)> = peers.to_vec();
+        for (peer, _) in &peers {
+            self.peers.entry(*peer).or_insert_with(PeerSyncStats::new);
+        }
+        // The fastest peers get the most urgent epochs.
+        peers.sort_by(|(a, _), (b, _)| {
+            self.peers[b]
+                .throughput()
+                .partial_cmp(&self.peers[a].throughput())
+                .unwrap_or(Ordering::Equal)
+        });
+        let best_peer_epoch =
+            peers.iter().map(|(_, epoch)| *epoch).max().unwrap_or(0);
+
+        let mut requests = Vec::new();
+        for (peer, peer_best_epoch) in peers {
+            loop {
+                if self.in_flight.len() as u64 >= EPOCH_SYNC_MAX_INFLIGHT {
+                    return requests;
+                }
+                let stats = &self.peers[&peer];
+                if stats.in_flight >= stats.batch_size {
+                    break;
+                }
+                let epoch = match self.next_epoch_for(
+                    peer,
+                    peer_best_epoch,
+                    my_best_epoch,
+                    best_peer_epoch,
+                ) {
+                    Some(epoch) => epoch,
+                    None => break,
+                };
+                self.in_flight.insert((epoch, peer), now);
+                self.peers.get_mut(&peer).expect("inserted above").in_flight +=
+                    1;
+                requests.push((peer, epoch));
+            }
+        }
+        requests
+    }
+
+    /// Take the next epoch for `peer`, preferring the epochs to retry which
+    /// were not failed by the same peer.
+    fn next_epoch_for(
+        &mut self, peer: PeerId, peer_best_epoch: u64, my_best_epoch: u64,
+        best_peer_epoch: u64,
+    ) -> Option<u64>
+    {
+        let retry = self
+            .retry
+            .iter()
+            .find(|(epoch, failed_peer)| {
+                **epoch <= peer_best_epoch && **failed_peer != peer
+            })
+            .map(|(epoch, _)| *epoch);
+        if let Some(epoch) = retry {
+            self.retry.remove(&epoch);
+            return Some(epoch);
+        }
+
+        let last = self.latest_requested.max(my_best_epoch);
+        if last < best_peer_epoch {
+            // Request one-by-one near the end to avoid getting stuck.
+            let stride = if last + EPOCH_SYNC_STRIDE > best_peer_epoch {
+                1
+            } else {
+                EPOCH_SYNC_STRIDE
+            };
+            let next = (last + stride).min(best_peer_epoch);
+            if next <= peer_best_epoch {
+                self.latest_requested = next;
+                return Some(next);
+            }
+        }
+
+        // Nothing else to do for the peer, so give it another try.
+        let epoch = self
+            .retry
+            .keys()
+            .find(|epoch| **epoch <= peer_best_epoch)
+            .cloned()?;
+        self.retry.remove(&epoch);
+        Some(epoch)
+    }
+
+    fn reassign_slow_requests(&mut self, now: Instant) {
+        let peers = &self.peers;
+        let slow: Vec<(u64, PeerId)> = self
+            .in_flight
+            .iter()
+            .filter(|((_, peer), sent_at)| {
+                peers.get(peer).map_or(true, |stats| {
+                    now.duration_since(**sent_at) > stats.slow_request_time()
+                })
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for (epoch, peer) in slow {
+            debug!("Reassign slow epoch request epoch={} peer={}", epoch, peer);
+            self.on_failed(peer, epoch);
+        }
+    }
+
+    pub fn on_received(&mut self, peer: PeerId, epoch: u64, now: Instant) {
+        self.retry.remove(&epoch);
+        if let Some(sent_at) = self.in_flight.remove(&(epoch, peer)) {
+            if let Some(stats) = self.peers.get_mut(&peer) {
+                stats.in_flight -= 1;
+                stats.on_response(now.duration_since(sent_at));
+            }
+        }
+        // The epoch may have been assigned to other peers as well.
+        let others: Vec<PeerId> = self
+            .in_flight
+            .keys()
+            .filter(|(e, _)| *e == epoch)
+            .map(|(_, p)| *p)
+            .collect();
+        for other in others {
+            self.in_flight.remove(&(epoch, other));
+            if let Some(stats) = self.peers.get_mut(&other) {
+                stats.in_flight -= 1;
+            }
+        }
+    }
+
+    /// The request of `epoch` to `peer` failed or timed out.
+    pub fn on_failed(&mut self, peer: PeerId, epoch: u64) {
+        if self.in_flight.remove(&(epoch, peer)).is_none() {
+            return;
+        }
+        if let Some(stats) = self.peers.get_mut(&peer) {
+            stats.in_flight -= 1;
+            stats.on_failure();
+        }
+        if !self.in_flight.keys().any(|(e, _)| *e == epoch) {
+            self.retry.insert(epoch, peer);
+        }
+    }
+
+    pub fn on_peer_disconnected(&mut self, peer: PeerId) {
+        let epochs: Vec<u64> = self
+            .in_flight
+            .keys()
+            .filter(|(_, p)| *p == peer)
+            .map(|(epoch, _)| *epoch)
+            .collect();
+        for epoch in epochs {
+            self.on_failed(peer, epoch);
+        }
+        self.peers.remove(&peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_epochs_across_peers() {
+        let mut scheduler = EpochSyncScheduler::new();
+        let now = Instant::now();
+        let requests =
+            scheduler.schedule(0, &[(1, 10 * EPOCH_SYNC_STRIDE), (2, 5)], now);
+        // Peer 2 does not have the strided epochs.
+        assert_eq!(
+            requests,
+            vec![(1, EPOCH_SYNC_STRIDE), (1, 2 * EPOCH_SYNC_STRIDE)]
+        );
+
+        let requests = scheduler.schedule(
+            0,
+            &[(1, 10 * EPOCH_SYNC_STRIDE), (2, 10 * EPOCH_SYNC_STRIDE)],
+            now,
+        );
+        assert_eq!(requests.len(), INITIAL_EPOCH_BATCH_SIZE);
+        assert!(requests.iter().all(|(peer, _)| *peer == 2));
+        assert_eq!(scheduler.num_in_flight(), 2 * INITIAL_EPOCH_BATCH_SIZE);
+    }
+
+    #[test]
+    fn test_adapt_batch_size() {
+        let mut scheduler = EpochSyncScheduler::new();
+        let now = Instant::now();
+        let peers = [(1, 1000), (2, 1000)];
+        let requests = scheduler.schedule(0, &peers, now);
+        for (peer, epoch) in requests {
+            let latency = if peer == 1 {
+                Duration::from_millis(100)
+            } else {
+                Duration::from_secs(5)
+            };
+            scheduler.on_received(peer, epoch, now + latency);
+        }
+        assert_eq!(
+            scheduler.peers[&1].batch_size,
+            INITIAL_EPOCH_BATCH_SIZE + 2
+        );
+        assert_eq!(scheduler.peers[&2].batch_size, 1);
+
+        // The fast peer gets more and earlier epochs.
+        let requests = scheduler.schedule(0, &peers, now);
+        assert_eq!(requests.len(), INITIAL_EPOCH_BATCH_SIZE + 3);
+        assert!(requests[..4].iter().all(|(peer, _)| *peer == 1));
+    }
+
+    #[test]
+    fn test_max_inflight() {
+        let mut scheduler = EpochSyncScheduler::new();
+        let now = Instant::now();
+        let peers: Vec<(PeerId, u64)> = (0..10)
+            .map(|peer| (peer, 100 * EPOCH_SYNC_STRIDE))
+            .collect();
+        let requests = scheduler.schedule(0, &peers, now);
+        assert_eq!(requests.len() as u64, EPOCH_SYNC_MAX_INFLIGHT);
+
+        // Only the received epochs are replaced.
+        let (peer, epoch) = requests[0];
+        scheduler.on_received(peer, epoch, now);
+        let requests = scheduler.schedule(0, &peers, now);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(scheduler.num_in_flight() as u64, EPOCH_SYNC_MAX_INFLIGHT);
+    }
+
+    #[test]
+    fn test_reassign_failed_and_slow_epochs() {
+        let mut scheduler = EpochSyncScheduler::new();
+        let now = Instant::now();
+        let peers = [(1, 1000), (2, 1000)];
+        let requests = scheduler.schedule(0, &[(1, 1000)], now);
+        let (_, failed) = requests[0];
+        let (_, slow) = requests[1];
+
+        scheduler.on_failed(1, failed);
+        let requests = scheduler.schedule(0, &peers, now);
+        assert!(requests.contains(&(2, failed)));
+        assert!(!requests.contains(&(1, failed)));
+
+        let later = now + MIN_SLOW_REQUEST_TIME + Duration::from_secs(1);
+        let requests = scheduler.schedule(0, &peers, later);
+        assert!(requests.contains(&(2, slow)));
+
+        // A late response completes the epoch for all peers.
+        scheduler.on_received(1, slow, later);
+        assert!(!scheduler.in_flight.contains_key(&(slow, 2)));
+    }
+
+    #[test]
+    fn test_disconnected_peer() {
+        let mut scheduler = EpochSyncScheduler::new();
+        let now = Instant::now();
+        let requests = scheduler.schedule(0, &[(1, 1000)], now);
+        scheduler.on_peer_disconnected(1);
+        assert_eq!(scheduler.num_in_flight(), 0);
+
+        let retried = scheduler.schedule(0, &[(2, 1000)], now);
+        assert_eq!(
+            retried,
+            requests.iter().map(|(_, e)| (2, *e)).collect::<Vec<_>>()
+        );
+    }
+}
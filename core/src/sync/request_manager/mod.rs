@@ -1,17 +1,18 @@
 use super::{
     synchronization_protocol_handler::{
-        ProtocolConfiguration, EPOCH_RETRY_TIME_SECONDS,
-        REQUEST_START_WAITING_TIME,
+        ProtocolConfiguration, REQUEST_START_WAITING_TIME,
+        REQUEST_TIMEOUT_PENALTY,
     },
     synchronization_state::SynchronizationState,
 };
 use crate::sync::Error;
 use cfx_types::H256;
+use epoch_scheduler::EpochSyncScheduler;
 use message::{
     GetBlockHashesByEpoch, GetBlockHeaders, GetBlockTxn, GetBlocks,
     GetCompactBlocks, GetTransactions, TransIndex,
 };
-use metrics::Gauge;
+use metrics::{CounterVec, Gauge};
 use network::{NetworkContext, PeerId};
 use parking_lot::{Mutex, RwLock};
 use primitives::{SignedTransaction, TransactionWithSignature, TxPropagateId};
@@ -19,24 +20,39 @@ use priority_send_queue::SendQueuePriority;
 pub use request_handler::{
     RequestHandler, RequestMessage, SynchronizationPeerRequest,
 };
+use request_history::{RequestDecision, RequestHistory};
 use std::{
-    collections::{binary_heap::BinaryHeap, hash_map::Entry, HashMap, HashSet},
-    sync::Arc,
-    time::{Duration, Instant},
+    collections::{binary_heap::BinaryHeap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc,
+    },
+    time::Instant,
 };
 use tx_handler::{ReceivedTransactionContainer, SentTransactionContainer};
 
+mod epoch_scheduler;
 mod request_handler;
+mod request_history;
 mod tx_handler;
 
 lazy_static! {
     static ref TX_REQUEST_GAUGE: Gauge = Gauge::register("tx_diff_set_size");
+    static ref ABANDONED_REQUESTS: CounterVec =
+        CounterVec::register("sync_abandoned_requests", &["type"]);
 }
+
+/// The maximum number of header or block hashes remembered in
+/// `RequestHistory`. Beyond it, the oldest hashes which are expired or not in
+/// flight are forgotten.
+const MAX_TRACKED_REQUESTS: usize = 200_000;
+/// The maximum number of transactions in flight.
+const MAX_INFLIGHT_TRANSACTIONS: usize = 100_000;
+
 #[derive(Debug, Eq, PartialEq, PartialOrd, Ord)]
 enum WaitingRequest {
     Header(H256),
     Block(H256),
-    Epoch(u64),
 }
 
 /// When a header or block is requested by the `RequestManager`, it is ensured
@@ -48,19 +64,24 @@ enum WaitingRequest {
 ///
 /// No lock is held when we call another function in this struct, and all locks
 /// are acquired in the same order, so there should exist no deadlocks.
-// TODO A non-existing block request will remain in the struct forever, and we
-// need garbage collect
+///
+/// A header or block is given up if it's requested from several peers or for
+/// a long time without being received, so that requests of non-existing
+/// blocks do not remain in the struct forever. See `RequestHistory`.
 pub struct RequestManager {
     inflight_requested_transactions: Mutex<HashSet<TxPropagateId>>,
     headers_in_flight: Mutex<HashSet<H256>>,
-    header_request_waittime: Mutex<HashMap<H256, Duration>>,
+    header_request_history: Mutex<RequestHistory>,
     blocks_in_flight: Mutex<HashSet<H256>>,
-    block_request_waittime: Mutex<HashMap<H256, Duration>>,
-    epochs_in_flight: Mutex<HashSet<u64>>,
+    block_request_history: Mutex<RequestHistory>,
+    /// Assigns the epochs to request in catch-up mode to peers.
+    epoch_scheduler: Mutex<EpochSyncScheduler>,
 
     /// Each element is (timeout_time, request, chosen_peer)
     waiting_requests:
         Mutex<BinaryHeap<(Instant, WaitingRequest, Option<PeerId>)>>,
+    /// The number of header, block and transaction requests given up so far.
+    num_abandoned_requests: AtomicUsize,
 
     /// The following fields are used to control how to
     /// propagate transactions in normal case.
@@ -96,22 +117,48 @@ impl RequestManager {
                 sent_transaction_window_size as usize,
             )),
             headers_in_flight: Default::default(),
-            header_request_waittime: Default::default(),
+            header_request_history: Mutex::new(RequestHistory::new(
+                MAX_TRACKED_REQUESTS,
+            )),
             blocks_in_flight: Default::default(),
-            block_request_waittime: Default::default(),
-            epochs_in_flight: Default::default(),
+            block_request_history: Mutex::new(RequestHistory::new(
+                MAX_TRACKED_REQUESTS,
+            )),
+            epoch_scheduler: Mutex::new(EpochSyncScheduler::new()),
             waiting_requests: Default::default(),
+            num_abandoned_requests: AtomicUsize::new(0),
             request_handler: Arc::new(RequestHandler::new(protocol_config)),
             syn,
         }
     }
 
     pub fn num_epochs_in_flight(&self) -> u64 {
-        self.epochs_in_flight.lock().len() as u64
+        self.epoch_scheduler.lock().num_in_flight() as u64
+    }
+
+    pub fn num_headers_in_flight(&self) -> usize {
+        self.headers_in_flight.lock().len()
+    }
+
+    pub fn num_blocks_in_flight(&self) -> usize {
+        self.blocks_in_flight.lock().len()
+    }
+
+    pub fn num_transactions_in_flight(&self) -> usize {
+        self.inflight_requested_transactions.lock().len()
+    }
+
+    pub fn num_waiting_requests(&self) -> usize {
+        self.waiting_requests.lock().len()
+    }
+
+    pub fn num_abandoned_requests(&self) -> usize {
+        self.num_abandoned_requests.load(AtomicOrdering::SeqCst)
     }
 
     /// Request a header if it's not already in_flight. The request is delayed
-    /// if the header is requested before.
+    /// if the header is requested before, and given up if it's requested too
+    /// many times.
     pub fn request_block_headers(
         &self, io: &NetworkContext, peer_id: Option<PeerId>, hash: &H256,
         max_blocks: u64,
@@ -121,31 +168,42 @@ impl RequestManager {
             // Already inflight, return directly
             return;
         }
-        let mut header_request_waittime = self.header_request_waittime.lock();
-        match header_request_waittime.entry(*hash) {
-            Entry::Occupied(mut e) => {
-                // Requested before, so wait for the stored time and increase it
-                let t = e.get_mut();
+        let decision = {
+            let headers_in_flight = self.headers_in_flight.lock();
+            self.header_request_history.lock().on_request(
+                hash,
+                io.now(),
+                &headers_in_flight,
+            )
+        };
+        match decision {
+            RequestDecision::GiveUp => {
+                self.headers_in_flight.lock().remove(hash);
+                ABANDONED_REQUESTS.inc_by(&["header"], 1);
+                self.num_abandoned_requests
+                    .fetch_add(1, AtomicOrdering::SeqCst);
+                debug!("Block header request is given up hash={:?}", hash);
+                return;
+            }
+            RequestDecision::Delay(t) => {
+                // Requested before, so wait for the stored time
                 self.waiting_requests.lock().push((
-                    Instant::now() + *t,
+                    io.now() + t,
                     WaitingRequest::Header(*hash),
                     peer_id,
                 ));
-                *t += *REQUEST_START_WAITING_TIME;
                 debug!(
                     "Block header request is delayed peer={:?} hash={:?}",
                     peer_id, hash
                 );
                 return;
             }
-            Entry::Vacant(e) => {
-                // Not requested before, so store the initial wait time
-                e.insert(*REQUEST_START_WAITING_TIME);
+            RequestDecision::Send => {
                 if peer_id.is_none() {
                     // No available peer, so add to the waiting queue directly
                     // to be sent later
                     self.waiting_requests.lock().push((
-                        Instant::now() + *REQUEST_START_WAITING_TIME,
+                        io.now() + *REQUEST_START_WAITING_TIME,
                         WaitingRequest::Header(*hash),
                         peer_id,
                     ));
@@ -177,11 +235,14 @@ impl RequestManager {
             // if the request is not waited before, so we just wait for
             // the initial value.
             self.waiting_requests.lock().push((
-                Instant::now() + *REQUEST_START_WAITING_TIME,
+                io.now() + *REQUEST_START_WAITING_TIME,
                 WaitingRequest::Header(*hash),
                 None,
             ));
         } else {
+            self.header_request_history
+                .lock()
+                .on_sent(hash, peer_id.unwrap());
             debug!(
                 "Requesting block header peer={:?} hash={} max_blocks={}",
                 peer_id, hash, max_blocks
@@ -189,22 +250,25 @@ impl RequestManager {
         }
     }
 
-    /// Remove in-flight blocks, and blocks requested before will be delayed.
-    /// If `peer_id` is `None`, all blocks will be delayed and `hashes` will
-    /// always become empty.
+    /// Remove in-flight blocks, and blocks requested before will be delayed
+    /// or given up. If `peer_id` is `None`, all blocks will be delayed and
+    /// `hashes` will always become empty.
     fn preprocess_block_request(
-        &self, hashes: &mut Vec<H256>, peer_id: &Option<PeerId>,
+        &self, hashes: &mut Vec<H256>, peer_id: &Option<PeerId>, now: Instant,
     ) {
         let mut blocks_in_flight = self.blocks_in_flight.lock();
-        let mut block_request_waittime = self.block_request_waittime.lock();
+        let mut block_request_history = self.block_request_history.lock();
         hashes.retain(|hash| {
             if blocks_in_flight.insert(*hash) {
-                match block_request_waittime.entry(*hash) {
-                    Entry::Vacant(entry) => {
-                        entry.insert(*REQUEST_START_WAITING_TIME);
+                match block_request_history.on_request(
+                    hash,
+                    now,
+                    &blocks_in_flight,
+                ) {
+                    RequestDecision::Send => {
                         if peer_id.is_none() {
                             self.waiting_requests.lock().push((
-                                Instant::now() + *REQUEST_START_WAITING_TIME,
+                                now + *REQUEST_START_WAITING_TIME,
                                 WaitingRequest::Block(*hash),
                                 *peer_id,
                             ));
@@ -217,21 +281,27 @@ impl RequestManager {
                             true
                         }
                     }
-                    Entry::Occupied(mut entry) => {
+                    RequestDecision::Delay(t) => {
                         // It is requested before. To prevent possible attacks,
                         // we wait for more time to start
                         // the next request.
-                        let t = entry.get_mut();
                         debug!(
                             "Block {:?} is requested again, delay for {:?}",
                             hash, t
                         );
                         self.waiting_requests.lock().push((
-                            Instant::now() + *t,
+                            now + t,
                             WaitingRequest::Block(*hash),
                             *peer_id,
                         ));
-                        *t += *REQUEST_START_WAITING_TIME;
+                        false
+                    }
+                    RequestDecision::GiveUp => {
+                        blocks_in_flight.remove(hash);
+                        ABANDONED_REQUESTS.inc_by(&["block"], 1);
+                        self.num_abandoned_requests
+                            .fetch_add(1, AtomicOrdering::SeqCst);
+                        debug!("Block {:?} request is given up", hash);
                         false
                     }
                 }
@@ -250,7 +320,7 @@ impl RequestManager {
         mut hashes: Vec<H256>, with_public: bool,
     )
     {
-        self.preprocess_block_request(&mut hashes, &peer_id);
+        self.preprocess_block_request(&mut hashes, &peer_id, io.now());
         if hashes.is_empty() {
             debug!("All blocks in_flight, skip requesting");
             return;
@@ -279,67 +349,57 @@ impl RequestManager {
             );
             for hash in hashes {
                 self.waiting_requests.lock().push((
-                    Instant::now() + *REQUEST_START_WAITING_TIME,
+                    io.now() + *REQUEST_START_WAITING_TIME,
                     WaitingRequest::Block(hash),
                     None,
                 ));
             }
         } else {
+            let mut block_request_history = self.block_request_history.lock();
+            for hash in &hashes {
+                block_request_history.on_sent(hash, peer_id);
+            }
             debug!("Requesting blocks peer={:?} hashes={:?}", peer_id, hashes);
         }
     }
 
-    pub fn request_epoch_hashes(
-        &self, io: &NetworkContext, peer_id: Option<PeerId>, epoch_number: u64,
-    ) {
-        if !self.epochs_in_flight.lock().insert(epoch_number) {
-            // Already inflight, return directly
-            return;
-        }
-
-        if peer_id.is_none() {
-            self.waiting_requests.lock().push((
-                Instant::now() + Duration::new(EPOCH_RETRY_TIME_SECONDS, 0),
-                WaitingRequest::Epoch(epoch_number),
-                peer_id,
-            ));
-            debug!(
-                "Epoch request is delayed peer={:?} epoch_number={:?}",
-                peer_id, epoch_number
-            );
-            return;
-        }
-
-        if let Err(e) = self.request_handler.send_request(
-            io,
-            peer_id.unwrap(),
-            Box::new(RequestMessage::Epochs(GetBlockHashesByEpoch {
-                request_id: 0.into(),
-                epoch_number,
-            })),
-            SendQueuePriority::High,
-        ) {
-            warn!(
-                "Error requesting epoch peer={:?} epoch_number={} err={:?}",
-                peer_id, epoch_number, e
-            );
+    /// Request the hashes of the next epochs from all peers which have them,
+    /// as many as the `EpochSyncScheduler` allows.
+    pub fn request_epochs(&self, io: &NetworkContext, my_best_epoch: u64) {
+        let peers: Vec<(PeerId, u64)> = self
+            .syn
+            .peers
+            .read()
+            .iter()
+            .map(|(peer, state)| (*peer, state.read().best_epoch))
+            .collect();
+        let requests = self.epoch_scheduler.lock().schedule(
+            my_best_epoch,
+            &peers,
+            io.now(),
+        );
 
-            // TODO handle different errors
-            // Currently we just queue the request and send it later with the
-            // same logic as delayed requests, so we do not remove
-            // it from `epochs_in_flight`. We can reach here only
-            // if the request is not waited before, so we just wait for
-            // the initial value.
-            self.waiting_requests.lock().push((
-                Instant::now() + Duration::new(EPOCH_RETRY_TIME_SECONDS, 0),
-                WaitingRequest::Epoch(epoch_number),
-                None,
-            ));
-        } else {
-            debug!(
-                "Requesting epoch peer={:?} epoch_number={}",
-                peer_id, epoch_number
-            );
+        for (peer, epoch_number) in requests {
+            if let Err(e) = self.request_handler.send_request(
+                io,
+                peer,
+                Box::new(RequestMessage::Epochs(GetBlockHashesByEpoch {
+                    request_id: 0.into(),
+                    epoch_number,
+                })),
+                SendQueuePriority::High,
+            ) {
+                warn!(
+                    "Error requesting epoch peer={:?} epoch_number={} err={:?}",
+                    peer, epoch_number, e
+                );
+                self.epoch_scheduler.lock().on_failed(peer, epoch_number);
+            } else {
+                debug!(
+                    "Requesting epoch peer={:?} epoch_number={}",
+                    peer, epoch_number
+                );
+            }
         }
     }
 
@@ -358,23 +418,40 @@ impl RequestManager {
         let (indices, tx_ids) = {
             let mut tx_ids = HashSet::new();
             let mut indices = Vec::new();
+            let mut abandoned = 0;
 
             for (idx, tx_id) in received_tx_ids.iter().enumerate() {
-                if !inflight_transactions.insert(*tx_id) {
+                if received_transactions.contains(tx_id) {
+                    // Already received
+                    continue;
+                }
+
+                if inflight_transactions.contains(tx_id) {
                     // Already being requested
                     continue;
                 }
 
-                if received_transactions.contains(tx_id) {
-                    // Already received
+                if inflight_transactions.len() >= MAX_INFLIGHT_TRANSACTIONS {
+                    abandoned += 1;
                     continue;
                 }
 
+                inflight_transactions.insert(*tx_id);
                 let index = TransIndex::new((window_index, idx));
                 indices.push(index);
                 tx_ids.insert(*tx_id);
             }
 
+            if abandoned > 0 {
+                ABANDONED_REQUESTS.inc_by(&["transaction"], abandoned);
+                self.num_abandoned_requests
+                    .fetch_add(abandoned as usize, AtomicOrdering::SeqCst);
+                debug!(
+                    "Too many transactions in flight, {} not requested",
+                    abandoned
+                );
+            }
+
             (indices, tx_ids)
         };
         TX_REQUEST_GAUGE.update(tx_ids.len() as i64);
@@ -406,7 +483,7 @@ impl RequestManager {
         mut hashes: Vec<H256>,
     )
     {
-        self.preprocess_block_request(&mut hashes, &peer_id);
+        self.preprocess_block_request(&mut hashes, &peer_id, io.now());
         if hashes.is_empty() {
             debug!("All blocks in_flight, skip requesting");
             return;
@@ -432,12 +509,16 @@ impl RequestManager {
             );
             for hash in hashes {
                 self.waiting_requests.lock().push((
-                    Instant::now() + *REQUEST_START_WAITING_TIME,
+                    io.now() + *REQUEST_START_WAITING_TIME,
                     WaitingRequest::Block(hash),
                     None,
                 ));
             }
         } else {
+            let mut block_request_history = self.block_request_history.lock();
+            for hash in &hashes {
+                block_request_history.on_sent(hash, peer_id.unwrap());
+            }
             debug!(
                 "Requesting compact blocks peer={:?} hashes={:?}",
                 peer_id, hashes
@@ -506,19 +587,13 @@ impl RequestManager {
                 hashes.push(blocktxn.block_hash);
                 self.request_blocks(io, chosen_peer, hashes, true);
             }
-            RequestMessage::Epochs(get_epoch_hashes) => {
-                self.request_epoch_hashes(
-                    io,
-                    chosen_peer,
-                    get_epoch_hashes.epoch_number,
-                );
-            }
+            // Failed epochs are assigned again by `request_epochs`.
             _ => {}
         }
     }
 
     pub fn remove_mismatch_request(
-        &self, io: &NetworkContext, req: &RequestMessage,
+        &self, io: &NetworkContext, peer: PeerId, req: &RequestMessage,
     ) {
         match req {
             RequestMessage::Headers(ref get_headers) => {
@@ -547,9 +622,9 @@ impl RequestManager {
                 }
             }
             RequestMessage::Epochs(ref get_epoch_hashes) => {
-                self.epochs_in_flight
+                self.epoch_scheduler
                     .lock()
-                    .remove(&get_epoch_hashes.epoch_number);
+                    .on_failed(peer, get_epoch_hashes.epoch_number);
             }
         }
         self.send_request_again(io, req);
@@ -573,7 +648,7 @@ impl RequestManager {
         let missing = {
             let mut missing = false;
             let mut headers_in_flight = self.headers_in_flight.lock();
-            let mut header_waittime = self.header_request_waittime.lock();
+            let mut header_request_history = self.header_request_history.lock();
             if !received_headers.remove(req_hash) {
                 // If `req_hash` is not in `headers_in_flight`, it may has been
                 // received or requested again by another
@@ -584,11 +659,11 @@ impl RequestManager {
             } else {
                 // `req_hash` is indeed returned, so we can remove all records
                 headers_in_flight.remove(req_hash);
-                header_waittime.remove(req_hash);
+                header_request_history.remove(req_hash);
             }
             for h in &received_headers {
                 headers_in_flight.remove(h);
-                header_waittime.remove(h);
+                header_request_history.remove(h);
             }
             missing
         };
@@ -601,9 +676,14 @@ impl RequestManager {
         }
     }
 
-    /// Remove from `epochs_in_flight` when an epoch is received.
-    pub fn epoch_received(&self, _io: &NetworkContext, epoch_number: u64) {
-        self.epochs_in_flight.lock().remove(&epoch_number);
+    /// Notify the `EpochSyncScheduler` when an epoch is received from `peer`,
+    /// so that it adapts the number of epochs requested from the peer.
+    pub fn epoch_received(
+        &self, io: &NetworkContext, peer: PeerId, epoch_number: u64,
+    ) {
+        self.epoch_scheduler
+            .lock()
+            .on_received(peer, epoch_number, io.now());
     }
 
     /// Remove from `blocks_in_flight` when a block is received.
@@ -624,7 +704,7 @@ impl RequestManager {
         );
         let missing_blocks = {
             let mut blocks_in_flight = self.blocks_in_flight.lock();
-            let mut block_waittime = self.block_request_waittime.lock();
+            let mut block_request_history = self.block_request_history.lock();
             let mut missing_blocks = Vec::new();
             for req_hash in &req_hashes {
                 if !received_blocks.remove(req_hash) {
@@ -637,12 +717,12 @@ impl RequestManager {
                     }
                 } else {
                     blocks_in_flight.remove(req_hash);
-                    block_waittime.remove(req_hash);
+                    block_request_history.remove(req_hash);
                 }
             }
             for h in &received_blocks {
                 blocks_in_flight.remove(h);
-                block_waittime.remove(h);
+                block_request_history.remove(h);
             }
             missing_blocks
         };
@@ -708,9 +788,16 @@ impl RequestManager {
     pub fn resend_timeout_requests(&self, io: &NetworkContext) {
         debug!("resend_timeout_requests: start");
         let timeout_requests = self.request_handler.get_timeout_requests(io);
-        for req in timeout_requests {
+        // A peer is penalized once per sweep however many of its requests
+        // timed out, so that a single stall doesn't get it banned.
+        let mut timeout_peers = HashSet::new();
+        for (peer, req) in timeout_requests {
             debug!("Timeout requests: {:?}", req);
-            self.remove_mismatch_request(io, &req);
+            self.remove_mismatch_request(io, peer, &req);
+            timeout_peers.insert(peer);
+        }
+        for peer in timeout_peers {
+            io.decrease_peer_score(peer, REQUEST_TIMEOUT_PENALTY);
         }
     }
 
@@ -719,10 +806,11 @@ impl RequestManager {
         &self, io: &NetworkContext, with_public: bool,
     ) {
         debug!("resend_waiting_requests: start");
-        let mut headers_waittime = self.header_request_waittime.lock();
-        let mut blocks_waittime = self.block_request_waittime.lock();
+        let now = io.now();
+        self.expire_request_history(now);
+        let mut header_request_history = self.header_request_history.lock();
+        let mut block_request_history = self.block_request_history.lock();
         let mut waiting_requests = self.waiting_requests.lock();
-        let now = Instant::now();
         loop {
             if waiting_requests.is_empty() {
                 break;
@@ -737,6 +825,8 @@ impl RequestManager {
                 let chosen_peer = match maybe_peer {
                     Some(p) => p,
                     None => {
+                        // Keep the request, which is still in flight.
+                        waiting_requests.push(req);
                         break;
                     }
                 };
@@ -759,45 +849,13 @@ impl RequestManager {
                             SendQueuePriority::High,
                         ) {
                             warn!("Error requesting waiting block header peer={:?} hash={} max_blocks={} err={:?}", chosen_peer, h, 1, e);
-                            // TODO `h` is got from `waiting_requests`, so it
-                            // should
-                            // be in `headers_waittime`, and thus we can remove
-                            // `or_insert`
                             waiting_requests.push((
-                                Instant::now()
-                                    + *headers_waittime
-                                        .entry(*h)
-                                        .and_modify(|t| {
-                                            *t += *REQUEST_START_WAITING_TIME
-                                        })
-                                        .or_insert(*REQUEST_START_WAITING_TIME),
+                                now + header_request_history.next_delay(h),
                                 WaitingRequest::Header(*h),
                                 None,
                             ));
-                        }
-                    }
-                    WaitingRequest::Epoch(n) => {
-                        if let Err(e) = self.request_handler.send_request(
-                            io,
-                            chosen_peer,
-                            Box::new(RequestMessage::Epochs(
-                                GetBlockHashesByEpoch {
-                                    request_id: 0.into(),
-                                    epoch_number: *n,
-                                },
-                            )),
-                            SendQueuePriority::High,
-                        ) {
-                            warn!("Error requesting waiting epoch peer={:?} epoch_number={} err={:?}", chosen_peer, n, e);
-                            waiting_requests.push((
-                                Instant::now()
-                                    + Duration::new(
-                                        EPOCH_RETRY_TIME_SECONDS,
-                                        0,
-                                    ),
-                                WaitingRequest::Epoch(*n),
-                                None,
-                            ));
+                        } else {
+                            header_request_history.on_sent(h, chosen_peer);
                         }
                     }
                     WaitingRequest::Block(h) => {
@@ -813,26 +871,16 @@ impl RequestManager {
                             SendQueuePriority::High,
                         ) {
                             warn!("Error requesting waiting blocks peer={:?} hashes={:?} err={:?}", chosen_peer, blocks, e);
-                            // TODO `blocks` is got from `waiting_requests`, so
-                            // it should
-                            // be in `blocks_waittime`, and thus we can remove
-                            // `or_insert`
                             for hash in blocks {
                                 waiting_requests.push((
-                                    Instant::now()
-                                        + *blocks_waittime
-                                            .entry(*h)
-                                            .and_modify(|t| {
-                                                *t +=
-                                                    *REQUEST_START_WAITING_TIME
-                                            })
-                                            .or_insert(
-                                                *REQUEST_START_WAITING_TIME,
-                                            ),
+                                    now + block_request_history
+                                        .next_delay(&hash),
                                     WaitingRequest::Block(hash),
                                     None,
                                 ));
                             }
+                        } else {
+                            block_request_history.on_sent(h, chosen_peer);
                         }
                     }
                 }
@@ -840,53 +888,78 @@ impl RequestManager {
         }
     }
 
+    /// Forget the requests which are expired and not in flight, so that
+    /// `RequestHistory` does not keep them forever.
+    fn expire_request_history(&self, now: Instant) {
+        let expired_headers = {
+            let headers_in_flight = self.headers_in_flight.lock();
+            self.header_request_history
+                .lock()
+                .expire(now, &headers_in_flight)
+        };
+        let expired_blocks = {
+            let blocks_in_flight = self.blocks_in_flight.lock();
+            self.block_request_history
+                .lock()
+                .expire(now, &blocks_in_flight)
+        };
+        if expired_headers + expired_blocks > 0 {
+            debug!(
+                "Expired request history headers={} blocks={}",
+                expired_headers, expired_blocks
+            );
+        }
+    }
+
     pub fn on_peer_connected(&self, peer: PeerId) {
         self.request_handler.add_peer(peer);
     }
 
     pub fn on_peer_disconnected(&self, io: &NetworkContext, peer: PeerId) {
+        self.epoch_scheduler.lock().on_peer_disconnected(peer);
         if let Some(unfinished_requests) =
             self.request_handler.remove_peer(peer)
         {
             {
                 let mut headers_in_flight = self.headers_in_flight.lock();
-                let mut header_waittime = self.header_request_waittime.lock();
+                let mut header_request_history =
+                    self.header_request_history.lock();
                 let mut blocks_in_flight = self.blocks_in_flight.lock();
-                let mut block_waittime = self.block_request_waittime.lock();
-                let mut epochs_in_flight = self.epochs_in_flight.lock();
+                let mut block_request_history =
+                    self.block_request_history.lock();
                 let mut inflight_transactions =
                     self.inflight_requested_transactions.lock();
                 for request in &unfinished_requests {
                     match &**request {
                         RequestMessage::Headers(get_headers) => {
                             headers_in_flight.remove(&get_headers.hash);
-                            header_waittime.remove(&get_headers.hash);
+                            header_request_history
+                                .reset_delay(&get_headers.hash);
                         }
                         RequestMessage::Blocks(get_blocks) => {
                             for hash in &get_blocks.hashes {
                                 blocks_in_flight.remove(hash);
-                                block_waittime.remove(hash);
+                                block_request_history.reset_delay(hash);
                             }
                         }
                         RequestMessage::Compact(get_compact) => {
                             for hash in &get_compact.hashes {
                                 blocks_in_flight.remove(hash);
-                                block_waittime.remove(hash);
+                                block_request_history.reset_delay(hash);
                             }
                         }
                         RequestMessage::BlockTxn(blocktxn) => {
                             blocks_in_flight.remove(&blocktxn.block_hash);
-                            block_waittime.remove(&blocktxn.block_hash);
+                            block_request_history
+                                .reset_delay(&blocktxn.block_hash);
                         }
                         RequestMessage::Transactions(get_transactions) => {
                             for tx_id in &get_transactions.tx_ids {
                                 inflight_transactions.remove(tx_id);
                             }
                         }
-                        RequestMessage::Epochs(get_epoch_hashes) => {
-                            epochs_in_flight
-                                .remove(&get_epoch_hashes.epoch_number);
-                        }
+                        // Handled by `epoch_scheduler` above.
+                        RequestMessage::Epochs(_) => {}
                     }
                 }
             }
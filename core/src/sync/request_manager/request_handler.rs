@@ -88,6 +88,7 @@ impl RequestHandler {
                     request_id,
                     &msg,
                     &self.protocol_config,
+                    io.now(),
                 ));
                 peer_info.append_inflight_request(
                     request_id,
@@ -105,10 +106,11 @@ impl RequestHandler {
         }
     }
 
-    fn get_timeout_sync_requests(&self) -> Vec<Arc<TimedSyncRequests>> {
+    fn get_timeout_sync_requests(
+        &self, now: Instant,
+    ) -> Vec<Arc<TimedSyncRequests>> {
         let mut requests = self.requests_queue.lock();
         let mut timeout_requests = Vec::new();
-        let now = Instant::now();
         loop {
             if requests.is_empty() {
                 break;
@@ -122,23 +124,23 @@ impl RequestHandler {
                 break;
             } else {
                 debug!("Timeout request {:?}", sync_req);
-                // TODO And should handle timeout peers.
                 timeout_requests.push(sync_req);
             }
         }
         timeout_requests
     }
 
+    /// Return the timed out requests with the peers they were sent to.
     pub fn get_timeout_requests(
         &self, io: &NetworkContext,
-    ) -> Vec<RequestMessage> {
+    ) -> Vec<(PeerId, RequestMessage)> {
         // Check if in-flight requests timeout
         let mut timeout_requests = Vec::new();
-        for sync_req in self.get_timeout_sync_requests() {
+        for sync_req in self.get_timeout_sync_requests(io.now()) {
             if let Ok(req) =
                 self.match_request(io, sync_req.peer_id, sync_req.request_id)
             {
-                timeout_requests.push(req);
+                timeout_requests.push((sync_req.peer_id, req));
             } else {
                 debug!("Timeout a removed request {:?}", sync_req);
             }
@@ -280,6 +282,7 @@ impl RequestContainer {
                         new_request_id,
                         &pending_msg,
                         protocol_config,
+                        io.now(),
                     ));
                     self.append_inflight_request(
                         new_request_id,
@@ -375,11 +378,11 @@ pub struct TimedSyncRequests {
 
 impl TimedSyncRequests {
     pub fn new(
-        peer_id: PeerId, timeout: Duration, request_id: u64,
+        peer_id: PeerId, timeout: Duration, request_id: u64, now: Instant,
     ) -> TimedSyncRequests {
         TimedSyncRequests {
             peer_id,
-            timeout_time: Instant::now() + timeout,
+            timeout_time: now + timeout,
             request_id,
             removed: AtomicBool::new(false),
         }
@@ -387,7 +390,7 @@ impl TimedSyncRequests {
 
     pub fn from_request(
         peer_id: PeerId, request_id: u64, msg: &RequestMessage,
-        conf: &ProtocolConfiguration,
+        conf: &ProtocolConfiguration, now: Instant,
     ) -> TimedSyncRequests
     {
         let timeout = match *msg {
@@ -398,7 +401,7 @@ impl TimedSyncRequests {
             | RequestMessage::BlockTxn(_) => conf.blocks_request_timeout,
             RequestMessage::Transactions(_) => conf.transaction_request_timeout,
         };
-        TimedSyncRequests::new(peer_id, timeout, request_id)
+        TimedSyncRequests::new(peer_id, timeout, request_id, now)
     }
 }
 
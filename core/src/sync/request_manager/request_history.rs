@@ -0,0 +1,282 @@
+use crate::sync::synchronization_protocol_handler::REQUEST_START_WAITING_TIME;
+use cfx_types::H256;
+use network::PeerId;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+/// A request is given up after it has been sent to this number of distinct
+/// peers without being answered.
+pub const MAX_REQUEST_PEERS: usize = 5;
+/// A request is given up if it is still not answered after this time.
+pub const REQUEST_EXPIRE_TIME: Duration = Duration::from_secs(600);
+
+#[derive(Debug, PartialEq)]
+pub enum RequestDecision {
+    /// Send the request now.
+    Send,
+    /// The hash has been requested before, so wait for the given time.
+    Delay(Duration),
+    /// Do not request the hash, since peers do not seem to have it or there
+    /// are too many requests already.
+    GiveUp,
+}
+
+/// The retry state of a requested hash.
+struct RequestRecord {
+    first_requested: Instant,
+    /// The delay before the next request, where zero means no delay.
+    wait_time: Duration,
+    /// The peers which the request has been sent to.
+    peers: HashSet<PeerId>,
+}
+
+/// Remembers the requests of hashes until they are received, to delay the
+/// requests sent again and to give them up eventually. At most `capacity`
+/// hashes are remembered.
+pub struct RequestHistory {
+    records: HashMap<H256, RequestRecord>,
+    capacity: usize,
+}
+
+impl RequestHistory {
+    pub fn new(capacity: usize) -> Self {
+        RequestHistory {
+            records: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Decide how to request `hash`. The first request is sent immediately,
+    /// and the following ones are delayed for a growing time. If the history
+    /// is full, the oldest request which is expired or not in flight is
+    /// forgotten to make room for `hash`.
+    pub fn on_request(
+        &mut self, hash: &H256, now: Instant, in_flight: &HashSet<H256>,
+    ) -> RequestDecision {
+        let give_up = match self.records.get_mut(hash) {
+            None => {
+                if self.records.len() >= self.capacity
+                    && !self.evict_oldest(now, in_flight)
+                {
+                    return RequestDecision::GiveUp;
+                }
+                self.records.insert(
+                    *hash,
+                    RequestRecord {
+                        first_requested: now,
+                        wait_time: *REQUEST_START_WAITING_TIME,
+                        peers: HashSet::new(),
+                    },
+                );
+                return RequestDecision::Send;
+            }
+            Some(record) => {
+                record.peers.len() >= MAX_REQUEST_PEERS
+                    || now.duration_since(record.first_requested)
+                        >= REQUEST_EXPIRE_TIME
+            }
+        };
+        if give_up {
+            self.records.remove(hash);
+            return RequestDecision::GiveUp;
+        }
+
+        let record = self.records.get_mut(hash).expect("checked above");
+        let wait_time = record.wait_time;
+        record.wait_time += *REQUEST_START_WAITING_TIME;
+        if wait_time == Duration::from_secs(0) {
+            RequestDecision::Send
+        } else {
+            RequestDecision::Delay(wait_time)
+        }
+    }
+
+    /// Forget the oldest request which is expired or not in flight. Returns
+    /// false if all requests are still in flight and not expired.
+    fn evict_oldest(
+        &mut self, now: Instant, in_flight: &HashSet<H256>,
+    ) -> bool {
+        let oldest = self
+            .records
+            .iter()
+            .filter(|(hash, record)| {
+                !in_flight.contains(*hash)
+                    || now.duration_since(record.first_requested)
+                        >= REQUEST_EXPIRE_TIME
+            })
+            .min_by_key(|(_, record)| record.first_requested)
+            .map(|(hash, _)| *hash);
+        match oldest {
+            Some(hash) => {
+                self.records.remove(&hash);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The delay before sending a request again which could not be sent.
+    pub fn next_delay(&mut self, hash: &H256) -> Duration {
+        match self.records.get_mut(hash) {
+            Some(record) => {
+                let wait_time =
+                    record.wait_time.max(*REQUEST_START_WAITING_TIME);
+                record.wait_time = wait_time + *REQUEST_START_WAITING_TIME;
+                wait_time
+            }
+            None => *REQUEST_START_WAITING_TIME,
+        }
+    }
+
+    pub fn on_sent(&mut self, hash: &H256, peer: PeerId) {
+        if let Some(record) = self.records.get_mut(hash) {
+            record.peers.insert(peer);
+        }
+    }
+
+    /// Send the next request of `hash` without delay, e.g. when the peer
+    /// which the request was sent to is disconnected.
+    pub fn reset_delay(&mut self, hash: &H256) {
+        if let Some(record) = self.records.get_mut(hash) {
+            record.wait_time = Duration::from_secs(0);
+        }
+    }
+
+    pub fn remove(&mut self, hash: &H256) { self.records.remove(hash); }
+
+    /// Forget the expired requests which are no longer in flight, i.e. will
+    /// not be requested again. Returns the number of removed requests.
+    pub fn expire(&mut self, now: Instant, in_flight: &HashSet<H256>) -> usize {
+        let len = self.records.len();
+        self.records.retain(|hash, record| {
+            in_flight.contains(hash)
+                || now.duration_since(record.first_requested)
+                    < REQUEST_EXPIRE_TIME
+        });
+        len - self.records.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_requests_sent_again() {
+        let mut history = RequestHistory::new(10);
+        let hash = H256::from(1u64);
+        let now = Instant::now();
+        let in_flight = HashSet::new();
+        assert_eq!(
+            history.on_request(&hash, now, &in_flight),
+            RequestDecision::Send
+        );
+        assert_eq!(
+            history.on_request(&hash, now, &in_flight),
+            RequestDecision::Delay(*REQUEST_START_WAITING_TIME)
+        );
+        assert_eq!(
+            history.on_request(&hash, now, &in_flight),
+            RequestDecision::Delay(*REQUEST_START_WAITING_TIME * 2)
+        );
+
+        history.reset_delay(&hash);
+        assert_eq!(
+            history.on_request(&hash, now, &in_flight),
+            RequestDecision::Send
+        );
+
+        history.remove(&hash);
+        assert!(history.records.is_empty());
+    }
+
+    #[test]
+    fn test_give_up_unanswered_requests() {
+        let mut history = RequestHistory::new(10);
+        let hash = H256::from(1u64);
+        let now = Instant::now();
+        let in_flight = HashSet::new();
+
+        // No peer answers.
+        for peer in 0..MAX_REQUEST_PEERS {
+            assert_ne!(
+                history.on_request(&hash, now, &in_flight),
+                RequestDecision::GiveUp
+            );
+            history.on_sent(&hash, peer);
+        }
+        assert_eq!(
+            history.on_request(&hash, now, &in_flight),
+            RequestDecision::GiveUp
+        );
+        assert!(history.records.is_empty());
+
+        // The only peer never answers.
+        assert_eq!(
+            history.on_request(&hash, now, &in_flight),
+            RequestDecision::Send
+        );
+        for _ in 0..2 * MAX_REQUEST_PEERS {
+            history.on_sent(&hash, 0);
+            assert_ne!(
+                history.on_request(&hash, now, &in_flight),
+                RequestDecision::GiveUp
+            );
+        }
+        assert_eq!(
+            history.on_request(&hash, now + REQUEST_EXPIRE_TIME, &in_flight),
+            RequestDecision::GiveUp
+        );
+    }
+
+    #[test]
+    fn test_bounded_history() {
+        let mut history = RequestHistory::new(2);
+        let hashes: Vec<H256> = (0..4u64).map(H256::from).collect();
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+        let mut in_flight: HashSet<H256> =
+            hashes[..3].iter().cloned().collect();
+        assert_eq!(
+            history.on_request(&hashes[0], now, &in_flight),
+            RequestDecision::Send
+        );
+        assert_eq!(
+            history.on_request(&hashes[1], later, &in_flight),
+            RequestDecision::Send
+        );
+
+        // All requests are in flight and not expired.
+        assert_eq!(
+            history.on_request(&hashes[2], later, &in_flight),
+            RequestDecision::GiveUp
+        );
+
+        // The request not in flight is evicted.
+        in_flight.remove(&hashes[1]);
+        assert_eq!(
+            history.on_request(&hashes[2], later, &in_flight),
+            RequestDecision::Send
+        );
+        assert!(!history.records.contains_key(&hashes[1]));
+
+        // The oldest expired request is evicted.
+        assert_eq!(
+            history.on_request(
+                &hashes[3],
+                later + REQUEST_EXPIRE_TIME,
+                &in_flight
+            ),
+            RequestDecision::Send
+        );
+        assert!(!history.records.contains_key(&hashes[0]));
+        assert!(history.records.contains_key(&hashes[2]));
+
+        let expire_time = later + REQUEST_EXPIRE_TIME;
+        assert_eq!(history.expire(expire_time, &in_flight), 0);
+        in_flight.remove(&hashes[2]);
+        assert_eq!(history.expire(expire_time, &in_flight), 1);
+    }
+}
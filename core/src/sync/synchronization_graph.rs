@@ -3,7 +3,7 @@
 // See http://www.gnu.org/licenses/
 
 use crate::{
-    block_data_manager::BlockDataManager,
+    block_data_manager::{missing_on_error, BlockDataManager},
     cache_manager::{CacheId, CacheManager, CacheSize},
     consensus::{ConsensusGraphInner, SharedConsensusGraph},
     db::COL_MISC,
@@ -16,7 +16,7 @@ use crate::{
 };
 use cfx_types::{H256, U256};
 use heapsize::HeapSizeOf;
-use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
+use parking_lot::{Condvar, Mutex, RwLock, RwLockUpgradableReadGuard};
 use primitives::{block::CompactBlock, Block, BlockHeader, EpochNumber};
 use rlp::Rlp;
 use slab::Slab;
@@ -25,6 +25,7 @@ use std::{
     collections::{HashMap, HashSet, VecDeque},
     ops::DerefMut,
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{self, Sender},
         Arc,
     },
@@ -473,6 +474,10 @@ pub struct SynchronizationGraph {
 
     /// Channel used to send work to `ConsensusGraph`
     consensus_sender: Mutex<Sender<H256>>,
+    /// The number of blocks sent to `ConsensusGraph` and not handled yet
+    consensus_unprocessed_count: Arc<AtomicUsize>,
+    /// Notified when `consensus_unprocessed_count` drops to zero
+    consensus_idle: Arc<(Mutex<()>, Condvar)>,
 }
 
 pub type SharedSynchronizationGraph = Arc<SynchronizationGraph>;
@@ -486,6 +491,8 @@ impl SynchronizationGraph {
     {
         let data_man = consensus.data_man.clone();
         let (consensus_sender, consensus_receiver) = mpsc::channel();
+        let consensus_unprocessed_count = Arc::new(AtomicUsize::new(0));
+        let consensus_idle = Arc::new((Mutex::new(()), Condvar::new()));
         let inner = Arc::new(RwLock::new(
             SynchronizationGraphInner::with_genesis_block(
                 Arc::new(data_man.genesis_block().block_header.clone()),
@@ -503,6 +510,8 @@ impl SynchronizationGraph {
             consensus: consensus.clone(),
             statistics: consensus.statistics.clone(),
             consensus_sender: Mutex::new(consensus_sender),
+            consensus_unprocessed_count: consensus_unprocessed_count.clone(),
+            consensus_idle: consensus_idle.clone(),
         };
 
         // It receives `BLOCK_GRAPH_READY` blocks in order and handles them in
@@ -517,7 +526,21 @@ impl SynchronizationGraph {
                                 &hash,
                                 consensus.clone(),
                             );
-                        consensus.on_new_block(&hash, translated_blockset)
+                        if let Err(e) =
+                            consensus.on_new_block(&hash, translated_blockset)
+                        {
+                            error!(
+                                "Failed to insert block {:?} into consensus: {}",
+                                hash, e
+                            );
+                        }
+                        if consensus_unprocessed_count
+                            .fetch_sub(1, Ordering::SeqCst)
+                            == 1
+                        {
+                            let _guard = consensus_idle.0.lock();
+                            consensus_idle.1.notify_all();
+                        }
                     }
                     Err(_) => break,
                 }
@@ -707,7 +730,7 @@ impl SynchronizationGraph {
     }
 
     pub fn block_by_hash_from_db(&self, hash: &H256) -> Option<Block> {
-        self.data_man.block_by_hash_from_db(hash)
+        missing_on_error(self.data_man.block_by_hash_from_db(hash))
     }
 
     pub fn compact_block_by_hash(&self, hash: &H256) -> Option<CompactBlock> {
@@ -1081,6 +1104,8 @@ impl SynchronizationGraph {
                 if !sync_graph_only {
                     // Make Consensus Worker handle the block in order
                     // asynchronously
+                    self.consensus_unprocessed_count
+                        .fetch_add(1, Ordering::SeqCst);
                     self.consensus_sender.lock().send(h).expect("Cannot fail");
                 } else {
                     let translated_blockset = inner
@@ -1088,10 +1113,15 @@ impl SynchronizationGraph {
                             &h,
                             self.consensus.clone(),
                         );
-                    self.consensus.on_new_block_construction_only(
-                        &h,
-                        translated_blockset,
-                    );
+                    if let Err(e) = self
+                        .consensus
+                        .on_new_block_construction_only(&h, translated_blockset)
+                    {
+                        error!(
+                            "Failed to insert block {:?} into consensus: {}",
+                            h, e
+                        );
+                    }
                 }
 
                 for child in &inner.arena[index].children {
@@ -1268,6 +1298,20 @@ impl SynchronizationGraph {
     /// Get the current number of blocks in the synchronization graph
     pub fn block_count(&self) -> usize { self.data_man.blocks.read().len() }
 
+    /// Whether some graph-ready blocks have not been inserted into
+    /// `ConsensusGraph` yet.
+    pub fn is_consensus_worker_busy(&self) -> bool {
+        self.consensus_unprocessed_count.load(Ordering::SeqCst) != 0
+    }
+
+    /// Block until `ConsensusGraph` has handled all the blocks sent to it.
+    pub fn wait_for_consensus_worker(&self) {
+        let mut guard = self.consensus_idle.0.lock();
+        while self.is_consensus_worker_busy() {
+            self.consensus_idle.1.wait(&mut guard);
+        }
+    }
+
     // Manage statistics
     pub fn stat_inc_inserted_count(&self) {
         let mut inner = self.statistics.inner.write();
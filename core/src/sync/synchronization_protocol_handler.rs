@@ -14,7 +14,8 @@ use message::{
     GetBlockHashesByEpoch, GetBlockHashesResponse, GetBlockHeaders,
     GetBlockHeadersResponse, GetBlockTxn, GetBlockTxnResponse, GetBlocks,
     GetBlocksResponse, GetBlocksWithPublicResponse, GetCompactBlocks,
-    GetCompactBlocksResponse, GetTerminalBlockHashes,
+    GetCompactBlocksResponse, GetEpochReceipts, GetEpochReceiptsResponse,
+    GetStateProof, GetStateProofResponse, GetTerminalBlockHashes,
     GetTerminalBlockHashesResponse, GetTransactions, GetTransactionsResponse,
     Message, MsgId, NewBlock, NewBlockHashes, Status, TransactionDigests,
     TransactionPropagationControl, Transactions,
@@ -47,7 +48,7 @@ use std::{
     collections::{HashMap, HashSet, VecDeque},
     iter::FromIterator,
     sync::{atomic::Ordering as AtomicOrdering, mpsc::channel, Arc},
-    time::{Duration, Instant},
+    time::Duration,
 };
 use threadpool::ThreadPool;
 lazy_static! {
@@ -57,10 +58,56 @@ lazy_static! {
 
 const CATCH_UP_EPOCH_LAG_THRESHOLD: u64 = 3;
 
-pub const SYNCHRONIZATION_PROTOCOL_VERSION: u8 = 0x01;
+/// Version 2 no longer accepts `GET_BLOCK_HASHES`, which was never answered
+/// and is superseded by `GET_BLOCK_HASHES_BY_EPOCH`.
+pub const SYNCHRONIZATION_PROTOCOL_VERSION: u8 = 0x02;
+/// The oldest protocol version still spoken with peers. Each connection uses
+/// the highest version both sides support, so that upgrades can be rolled out
+/// gradually.
+pub const MIN_SYNCHRONIZATION_PROTOCOL_VERSION: u8 = 0x01;
+
+/// The protocol version negotiated with `peer`. Handlers should decode and
+/// answer messages according to it.
+pub fn peer_protocol_version(io: &NetworkContext, peer: PeerId) -> u8 {
+    io.get_protocol_version(peer)
+        .unwrap_or(MIN_SYNCHRONIZATION_PROTOCOL_VERSION)
+}
+
+/// Check that the `Status` of `peer` is from the same network and chain, and
+/// claims the protocol version negotiated with it. Peers failing the check
+/// should be penalized and disconnected.
+pub fn check_status(
+    io: &NetworkContext, peer: PeerId, status: &Status, genesis_hash: &H256,
+) -> Result<(), Error> {
+    if status.genesis_hash != *genesis_hash {
+        return Err(ErrorKind::InvalidStatus(format!(
+            "genesis hash mismatches (ours: {:?}, theirs: {:?})",
+            genesis_hash, status.genesis_hash
+        ))
+        .into());
+    }
+    if status.network_id != io.network_id() {
+        return Err(ErrorKind::InvalidStatus(format!(
+            "network id mismatches (ours: {}, theirs: {})",
+            io.network_id(),
+            status.network_id
+        ))
+        .into());
+    }
+    let protocol_version = peer_protocol_version(io, peer);
+    if status.protocol_version != protocol_version {
+        return Err(ErrorKind::InvalidStatus(format!(
+            "protocol version {} is not the negotiated version {}",
+            status.protocol_version, protocol_version
+        ))
+        .into());
+    }
+    Ok(())
+}
 
 pub const MAX_HEADERS_TO_SEND: u64 = 512;
 pub const MAX_BLOCKS_TO_SEND: u64 = 256;
+pub const MAX_STATE_PROOF_KEYS_TO_SEND: u64 = 256;
 const MAX_PACKET_SIZE: usize = 15 * 1024 * 1024 + 512 * 1024; // 15.5 MB
 const DEFAULT_GET_HEADERS_NUM: u64 = 1;
 const DEFAULT_GET_PARENT_HEADERS_NUM: u64 = 30;
@@ -79,11 +126,20 @@ const TOTAL_WEIGHT_IN_PAST_TIMER: TimerToken = 5;
 
 const MAX_TXS_BYTES_TO_PROPAGATE: usize = 1024 * 1024; // 1MB
 
-pub const EPOCH_RETRY_TIME_SECONDS: u64 = 1;
-const EPOCH_SYNC_MAX_INFLIGHT: u64 = 10;
+// The maximum number of epoch requests in flight to all peers
+pub const EPOCH_SYNC_MAX_INFLIGHT: u64 = 10;
 
 // make sure we do not request overlapping regions of the DAG
-const EPOCH_SYNC_STRIDE: u64 = DEFAULT_GET_PARENT_HEADERS_NUM;
+pub const EPOCH_SYNC_STRIDE: u64 = DEFAULT_GET_PARENT_HEADERS_NUM;
+
+// The scores decreased for misbehaving peers, see
+// `NetworkConfiguration::peer_disconnect_score` and `peer_ban_score` for the
+// thresholds.
+const INVALID_BLOCK_PENALTY: i32 = 100;
+const MALFORMED_MESSAGE_PENALTY: i32 = 50;
+const UNEXPECTED_RESPONSE_PENALTY: i32 = 20;
+pub const INVALID_STATUS_PENALTY: i32 = 100;
+pub const REQUEST_TIMEOUT_PENALTY: i32 = 10;
 
 #[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 enum SyncHandlerWorkType {
@@ -102,12 +158,39 @@ pub struct SynchronizationProtocolHandler {
     graph: SharedSynchronizationGraph,
     syn: Arc<SynchronizationState>,
     request_manager: Arc<RequestManager>,
-    latest_epoch_requested: Mutex<u64>,
 
     // Worker task queue for recover public
     recover_public_queue: Mutex<VecDeque<RecoverPublicTask>>,
 }
 
+/// A snapshot of the synchronization progress of the local node.
+#[derive(Debug, Clone)]
+pub struct SyncStatus {
+    pub catch_up_mode: bool,
+    /// The highest epoch announced by any connected peer.
+    pub best_peer_epoch: Option<u64>,
+    pub local_epoch: u64,
+    pub peer_count: usize,
+    pub handshaking_peer_count: usize,
+    pub headers_in_flight: usize,
+    pub blocks_in_flight: usize,
+    pub epochs_in_flight: usize,
+    pub transactions_in_flight: usize,
+    pub waiting_requests: usize,
+    /// The number of requests given up since the node started.
+    pub abandoned_requests: usize,
+    pub execution_queue_size: usize,
+    pub last_executed_epoch: Option<u64>,
+}
+
+impl SyncStatus {
+    /// The number of epochs the local pivot chain is behind the best peer.
+    pub fn epoch_lag(&self) -> u64 {
+        self.best_peer_epoch
+            .map_or(0, |best| best.saturating_sub(self.local_epoch))
+    }
+}
+
 #[derive(Clone)]
 pub struct ProtocolConfiguration {
     pub send_tx_period: Duration,
@@ -150,7 +233,6 @@ impl SynchronizationProtocolHandler {
             )),
             syn,
             request_manager,
-            latest_epoch_requested: Mutex::new(0),
             recover_public_queue: Mutex::new(VecDeque::new()),
         }
     }
@@ -167,6 +249,30 @@ impl SynchronizationProtocolHandler {
         self.graph.block_by_hash(hash)
     }
 
+    pub fn sync_status(&self) -> SyncStatus {
+        let consensus = &self.graph.consensus;
+        SyncStatus {
+            catch_up_mode: self.catch_up_mode(),
+            best_peer_epoch: self.best_peer_epoch(),
+            local_epoch: consensus.best_epoch_number() as u64,
+            peer_count: self.syn.peers.read().len(),
+            handshaking_peer_count: self.syn.handshaking_peers.read().len(),
+            headers_in_flight: self.request_manager.num_headers_in_flight(),
+            blocks_in_flight: self.request_manager.num_blocks_in_flight(),
+            epochs_in_flight: self.request_manager.num_epochs_in_flight()
+                as usize,
+            transactions_in_flight: self
+                .request_manager
+                .num_transactions_in_flight(),
+            waiting_requests: self.request_manager.num_waiting_requests(),
+            abandoned_requests: self.request_manager.num_abandoned_requests(),
+            execution_queue_size: consensus.execution_queue_size(),
+            last_executed_epoch: consensus
+                .last_executed_epoch_number()
+                .map(|epoch| epoch as u64),
+        }
+    }
+
     fn best_peer_epoch(&self) -> Option<u64> {
         self.syn
             .peers
@@ -198,6 +304,15 @@ impl SynchronizationProtocolHandler {
                 return;
             }
         }
+        let version = peer_protocol_version(io, peer);
+        if !msg_id.is_valid_in(version) {
+            warn!(
+                "Message not valid in protocol version {}: peer={:?} msgid={:?}",
+                version, peer, msg_id
+            );
+            io.decrease_peer_score(peer, MALFORMED_MESSAGE_PENALTY);
+            return;
+        }
         match msg_id {
             MsgId::STATUS => self.on_status(io, peer, &rlp),
             MsgId::GET_BLOCK_HEADERS_RESPONSE => {
@@ -246,6 +361,10 @@ impl SynchronizationProtocolHandler {
             MsgId::GET_BLOCK_HASHES_RESPONSE => {
                 self.on_block_hashes_response(io, peer, &rlp)
             }
+            MsgId::GET_STATE_PROOF => self.on_get_state_proof(io, peer, &rlp),
+            MsgId::GET_EPOCH_RECEIPTS => {
+                self.on_get_epoch_receipts(io, peer, &rlp)
+            }
             _ => {
                 warn!("Unknown message: peer={:?} msgid={:?}", peer, msg_id);
                 Ok(())
@@ -256,9 +375,22 @@ impl SynchronizationProtocolHandler {
                 "Error while handling message msgid={:?}, error={:?}",
                 msg_id, e
             );
+            if let Some(penalty) = Self::penalty_for(&e) {
+                io.decrease_peer_score(peer, penalty);
+            }
         });
     }
 
+    /// The score decreased for the peer whose message caused `error`.
+    fn penalty_for(error: &Error) -> Option<i32> {
+        match error.kind() {
+            ErrorKind::Invalid => Some(INVALID_BLOCK_PENALTY),
+            ErrorKind::Decoder(_) => Some(MALFORMED_MESSAGE_PENALTY),
+            ErrorKind::UnexpectedResponse => Some(UNEXPECTED_RESPONSE_PENALTY),
+            _ => None,
+        }
+    }
+
     fn on_get_compact_blocks(
         &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
     ) -> Result<(), Error> {
@@ -586,7 +718,7 @@ impl SynchronizationProtocolHandler {
             RequestMessage::BlockTxn(request) => request,
             _ => {
                 warn!("Get response not matching the request! req={:?}, resp={:?}", req, resp);
-                self.request_manager.remove_mismatch_request(io, &req);
+                self.request_manager.remove_mismatch_request(io, peer, &req);
                 return Err(ErrorKind::UnexpectedResponse.into());
             }
         };
@@ -947,6 +1079,63 @@ impl SynchronizationProtocolHandler {
         Ok(())
     }
 
+    /// Serve the state proofs requested by a light node. An empty response
+    /// tells the requester that we cannot prove the keys.
+    fn on_get_state_proof(
+        &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let req = rlp.as_val::<GetStateProof>()?;
+        debug!("on_get_state_proof, msg=:{:?}", req);
+
+        // Only the proofs of the first keys are sent.
+        let num_keys =
+            cmp::min(req.keys.len(), MAX_STATE_PROOF_KEYS_TO_SEND as usize);
+        let proofs = self
+            .graph
+            .consensus
+            .get_deferred_state_proofs(&req.block_hash, &req.keys[..num_keys])
+            .unwrap_or_else(|e| {
+                debug!("Failed to get state proofs: {}", e);
+                Vec::new()
+            });
+
+        let msg: Box<dyn Message> = Box::new(GetStateProofResponse {
+            request_id: req.request_id().into(),
+            proofs,
+        });
+        send_message(io, peer, msg.as_ref(), SendQueuePriority::High)?;
+        Ok(())
+    }
+
+    /// Serve the epoch receipts requested by a light node. An empty response
+    /// tells the requester that we don't have them, unless the epoch is
+    /// genesis.
+    fn on_get_epoch_receipts(
+        &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
+    ) -> Result<(), Error> {
+        let req = rlp.as_val::<GetEpochReceipts>()?;
+        debug!("on_get_epoch_receipts, msg=:{:?}", req);
+
+        let receipts = self
+            .graph
+            .consensus
+            .get_deferred_epoch_receipts(&req.block_hash)
+            .unwrap_or_else(|e| {
+                debug!("Failed to get epoch receipts: {}", e);
+                Vec::new()
+            })
+            .iter()
+            .map(|block_receipts| block_receipts.as_ref().clone())
+            .collect();
+
+        let msg: Box<dyn Message> = Box::new(GetEpochReceiptsResponse {
+            request_id: req.request_id().into(),
+            receipts,
+        });
+        send_message(io, peer, msg.as_ref(), SendQueuePriority::High)?;
+        Ok(())
+    }
+
     fn on_block_hashes_response(
         &self, io: &NetworkContext, peer: PeerId, rlp: &Rlp,
     ) -> Result<(), Error> {
@@ -959,12 +1148,15 @@ impl SynchronizationProtocolHandler {
 
         match req {
             RequestMessage::Epochs(epoch_req) => {
-                self.request_manager
-                    .epoch_received(io, epoch_req.epoch_number);
+                self.request_manager.epoch_received(
+                    io,
+                    peer,
+                    epoch_req.epoch_number,
+                );
             }
             _ => {
                 warn!("Get response not matching the request! req={:?}, resp={:?}", req, resp);
-                self.request_manager.remove_mismatch_request(io, &req);
+                self.request_manager.remove_mismatch_request(io, peer, &req);
                 return Err(ErrorKind::UnexpectedResponse.into());
             }
         };
@@ -1013,22 +1205,23 @@ impl SynchronizationProtocolHandler {
             return Err(ErrorKind::UnknownPeer.into());
         }
         debug!("on_status, msg=:{:?}", status);
-        let genesis_hash = self.graph.genesis_hash();
-        if genesis_hash != status.genesis_hash {
-            debug!(
-                "Peer {:?} genesis hash mismatches (ours: {:?}, theirs: {:?})",
-                peer, genesis_hash, status.genesis_hash
-            );
-            return Err(ErrorKind::Invalid.into());
+        if let Err(e) =
+            check_status(io, peer, &status, &self.graph.genesis_hash())
+        {
+            debug!("Peer {:?} sent invalid status: {}", peer, e);
+            io.decrease_peer_score(peer, INVALID_STATUS_PENALTY);
+            io.disconnect_peer(peer);
+            return Err(e);
         }
 
         let mut latest: HashSet<H256> =
             status.terminal_block_hashes.into_iter().collect();
         latest.extend(self.graph.initial_missed_block_hashes.lock().drain());
 
+        let protocol_version = peer_protocol_version(io, peer);
         let peer_state = SynchronizationPeerState {
             id: peer,
-            protocol_version: status.protocol_version,
+            protocol_version,
             genesis_hash: status.genesis_hash,
             best_epoch: status.best_epoch,
             latest_block_hashes: latest,
@@ -1093,50 +1286,11 @@ impl SynchronizationProtocolHandler {
         }
     }
 
+    /// Split the epochs which are not received yet across the peers, see
+    /// `EpochSyncScheduler`.
     fn request_epochs(&self, io: &NetworkContext) {
-        // make sure only one thread can request new epochs at a time
-        let mut latest_requested = self.latest_epoch_requested.lock();
-        let best_peer_epoch = self.best_peer_epoch().unwrap_or(0);
-
-        while self.request_manager.num_epochs_in_flight()
-            < EPOCH_SYNC_MAX_INFLIGHT
-            && *latest_requested < best_peer_epoch
-        {
-            let next = {
-                let my_best_epoch = self.graph.best_epoch_number();
-                let last = (*latest_requested).max(my_best_epoch);
-
-                // request one-by-one near the end to avoid getting stuck
-                let stride = if last + EPOCH_SYNC_STRIDE > best_peer_epoch {
-                    1
-                } else {
-                    EPOCH_SYNC_STRIDE
-                };
-
-                (last + stride).min(best_peer_epoch)
-            };
-
-            let peer = self.syn.get_random_peer_satisfying(|peer| {
-                match self.syn.get_peer_info(&peer) {
-                    Err(_) => false,
-                    Ok(info) => info.read().best_epoch >= next,
-                }
-            });
-
-            debug!(
-                "requesting epoch {:?}/{:?} from peer {:?}",
-                next, best_peer_epoch, peer
-            );
-
-            self.request_manager.request_epoch_hashes(io, peer, next);
-
-            *latest_requested = next;
-        }
-
-        debug_assert!(
-            self.request_manager.num_epochs_in_flight()
-                <= EPOCH_SYNC_MAX_INFLIGHT
-        );
+        self.request_manager
+            .request_epochs(io, self.graph.best_epoch_number());
     }
 
     fn on_block_headers_response(
@@ -1155,7 +1309,7 @@ impl SynchronizationProtocolHandler {
             }
             _ => {
                 warn!("Get response not matching the request! req={:?}, resp={:?}", req, block_headers);
-                self.request_manager.remove_mismatch_request(io, &req);
+                self.request_manager.remove_mismatch_request(io, peer, &req);
                 return Err(ErrorKind::UnexpectedResponse.into());
             }
         };
@@ -1603,8 +1757,8 @@ impl SynchronizationProtocolHandler {
         let (_guard, best_info) = self.graph.get_best_info().into();
 
         let msg: Box<dyn Message> = Box::new(Status {
-            protocol_version: SYNCHRONIZATION_PROTOCOL_VERSION,
-            network_id: 0x0,
+            protocol_version: peer_protocol_version(io, peer),
+            network_id: io.network_id(),
             genesis_hash: self.graph.genesis_hash(),
             best_epoch: best_info.best_epoch_number as u64,
             terminal_block_hashes: best_info.terminal_block_hashes,
@@ -2153,10 +2307,7 @@ impl NetworkProtocolHandler for SynchronizationProtocolHandler {
             debug!("Error sending status message: {:?}", e);
             io.disconnect_peer(peer);
         } else {
-            self.syn
-                .handshaking_peers
-                .write()
-                .insert(peer, Instant::now());
+            self.syn.handshaking_peers.write().insert(peer, io.now());
         }
     }
 
@@ -2175,6 +2326,11 @@ impl NetworkProtocolHandler for SynchronizationProtocolHandler {
             }
             CHECK_REQUEST_TIMER => {
                 self.remove_expired_flying_request(io);
+                // Assign the epochs of slow or failed requests to other peers
+                // without waiting for the next response.
+                if self.catch_up_mode() {
+                    self.request_epochs(io);
+                }
             }
             BLOCK_CACHE_GC_TIMER => {
                 self.block_cache_gc();
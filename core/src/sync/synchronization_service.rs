@@ -3,7 +3,8 @@
 // See http://www.gnu.org/licenses/
 
 use super::{
-    Error, SharedSynchronizationGraph, SynchronizationProtocolHandler,
+    Error, SharedSynchronizationGraph, SyncStatus,
+    SynchronizationProtocolHandler, MIN_SYNCHRONIZATION_PROTOCOL_VERSION,
     SYNCHRONIZATION_PROTOCOL_VERSION,
 };
 use crate::{
@@ -55,6 +56,10 @@ impl SynchronizationService {
         self.protocol_handler.catch_up_mode()
     }
 
+    pub fn sync_status(&self) -> SyncStatus {
+        self.protocol_handler.sync_status()
+    }
+
     pub fn get_synchronization_graph(&self) -> SharedSynchronizationGraph {
         self.protocol_handler.get_synchronization_graph()
     }
@@ -64,7 +69,9 @@ impl SynchronizationService {
         self.network.register_protocol(
             self.protocol_handler.clone(),
             self.protocol,
-            &[SYNCHRONIZATION_PROTOCOL_VERSION],
+            &(MIN_SYNCHRONIZATION_PROTOCOL_VERSION
+                ..=SYNCHRONIZATION_PROTOCOL_VERSION)
+                .collect::<Vec<_>>(),
         )?;
         Ok(())
     }
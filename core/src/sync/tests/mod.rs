@@ -0,0 +1,512 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Synchronization between in-process nodes connected by a simulated network.
+
+use super::{
+    msg_sender::send_message,
+    synchronization_protocol_handler::REQUEST_TIMEOUT_PENALTY,
+    ProtocolConfiguration, SharedSynchronizationGraph,
+    SynchronizationProtocolHandler, MIN_SYNCHRONIZATION_PROTOCOL_VERSION,
+    SYNCHRONIZATION_PROTOCOL_VERSION,
+};
+use crate::{
+    cache_manager::CacheManager,
+    consensus::{
+        ConsensusConfig, ConsensusEvent, ConsensusInnerConfig,
+        ADAPTIVE_WEIGHT_DEFAULT_ALPHA_DEN, ADAPTIVE_WEIGHT_DEFAULT_ALPHA_NUM,
+        ADAPTIVE_WEIGHT_DEFAULT_BETA, DEFERRED_STATE_EPOCH_COUNT,
+        HEAVY_BLOCK_DEFAULT_DIFFICULTY_RATIO,
+    },
+    db::NUM_COLUMNS,
+    ext_db::open_memory_database,
+    pow::{
+        difficulty_to_boundary, validate, ProofOfWorkConfig,
+        ProofOfWorkProblem, ProofOfWorkSolution,
+        WORKER_COMPUTATION_PARALLELISM,
+    },
+    statistics::Statistics,
+    storage::{state_manager::StorageConfiguration, StorageManager},
+    transaction_pool::DEFAULT_MAX_BLOCK_GAS_LIMIT,
+    verification::VerificationConfig,
+    vm_factory::VmFactory,
+    ConsensusGraph, TransactionPool,
+};
+use cfx_types::{Address, H256};
+use message::{GetBlockHashes, Message};
+use network::{
+    simulation::{LinkConfig, SimulatedNetwork},
+    ErrorKind as NetworkErrorKind,
+};
+use parking_lot::Mutex;
+use primitives::{Block, BlockHeaderBuilder};
+use priority_send_queue::SendQueuePriority;
+use rlp::RlpStream;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use threadpool::ThreadPool;
+
+/// The virtual time allowed for the nodes to synchronize.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn new_handler() -> Arc<SynchronizationProtocolHandler> {
+    let db = open_memory_database(NUM_COLUMNS);
+    let storage_manager = Arc::new(StorageManager::new(
+        db.clone(),
+        StorageConfiguration {
+            cache_start_size: 1_000_000,
+            cache_size: 20_000_000,
+            idle_size: 200_000,
+            node_map_size: 20_000_000,
+            recent_lfu_factor: 4.0,
+        },
+    ));
+    let genesis_block = storage_manager.initialize(
+        HashMap::new(),
+        DEFAULT_MAX_BLOCK_GAS_LIMIT.into(),
+        Address::zero(),
+    );
+
+    let mb = 1024 * 1024;
+    let cache_man =
+        Arc::new(Mutex::new(CacheManager::new(48 * mb, 64 * mb, 3 * mb)));
+    let worker_thread_pool = Arc::new(Mutex::new(ThreadPool::with_name(
+        "Tx Recover".into(),
+        WORKER_COMPUTATION_PARALLELISM,
+    )));
+    let txpool = Arc::new(TransactionPool::with_capacity(
+        10_000,
+        storage_manager.clone(),
+        worker_thread_pool,
+        cache_man.clone(),
+    ));
+
+    let pow_config = ProofOfWorkConfig::new(true, None);
+    let consensus = Arc::new(ConsensusGraph::with_genesis_block(
+        ConsensusConfig {
+            debug_dump_dir_invalid_state_root: "./invalid_state_root/".into(),
+            record_tx_address: true,
+            inner_conf: ConsensusInnerConfig {
+                adaptive_weight_alpha_num: ADAPTIVE_WEIGHT_DEFAULT_ALPHA_NUM,
+                adaptive_weight_alpha_den: ADAPTIVE_WEIGHT_DEFAULT_ALPHA_DEN,
+                adaptive_weight_beta: ADAPTIVE_WEIGHT_DEFAULT_BETA,
+                heavy_block_difficulty_ratio:
+                    HEAVY_BLOCK_DEFAULT_DIFFICULTY_RATIO,
+                enable_optimistic_execution: false,
+            },
+            bench_mode: false,
+            builtin_specs: Vec::new(),
+        },
+        genesis_block,
+        storage_manager,
+        VmFactory::new(1024 * 32),
+        txpool,
+        Arc::new(Statistics::new()),
+        db,
+        cache_man,
+        pow_config.clone(),
+    ));
+
+    Arc::new(SynchronizationProtocolHandler::new(
+        ProtocolConfiguration {
+            send_tx_period: Duration::from_millis(1300),
+            check_request_period: Duration::from_millis(1000),
+            block_cache_gc_period: Duration::from_millis(5000),
+            persist_terminal_period: Duration::from_millis(60_000),
+            headers_request_timeout: Duration::from_millis(10_000),
+            blocks_request_timeout: Duration::from_millis(10_000),
+            transaction_request_timeout: Duration::from_millis(30_000),
+            tx_maintained_for_peer_timeout: Duration::from_millis(600_000),
+            max_inflight_request_count: 64,
+            start_as_catch_up_mode: false,
+            received_tx_index_maintain_timeout: Duration::from_millis(600_000),
+            request_block_with_public: false,
+            max_trans_count_received_in_catch_up: 60_000,
+            min_peers_propagation: 8,
+            max_peers_propagation: 128,
+        },
+        consensus,
+        VerificationConfig::new(true),
+        pow_config,
+        false,
+    ))
+}
+
+/// Sync nodes in a simulated network, where `handlers[i]` is node `i`.
+struct SyncNetwork {
+    network: SimulatedNetwork,
+    handlers: Vec<Arc<SynchronizationProtocolHandler>>,
+    /// The graphs of the nodes, whose consensus workers are waited for after
+    /// every event, so that a run does not depend on their timing.
+    graphs: Arc<Mutex<Vec<SharedSynchronizationGraph>>>,
+}
+
+impl SyncNetwork {
+    fn new(count: usize, seed: u64) -> Self {
+        Self::with_version(count, seed, SYNCHRONIZATION_PROTOCOL_VERSION)
+    }
+
+    /// Nodes which negotiated protocol `version` with each other.
+    fn with_version(count: usize, seed: u64, version: u8) -> Self {
+        let graphs: Arc<Mutex<Vec<SharedSynchronizationGraph>>> =
+            Default::default();
+        let mut network = SimulatedNetwork::new(*b"cfx", version, seed);
+        let waited_graphs = graphs.clone();
+        network.set_after_event(move || {
+            for graph in waited_graphs.lock().iter() {
+                graph.wait_for_consensus_worker();
+            }
+        });
+        let mut sync_network = SyncNetwork {
+            network,
+            handlers: Vec::new(),
+            graphs,
+        };
+        for _ in 0..count {
+            sync_network.add_node();
+        }
+        sync_network
+    }
+
+    fn add_node(&mut self) -> usize {
+        let handler = new_handler();
+        self.handlers.push(handler.clone());
+        self.graphs.lock().push(handler.get_synchronization_graph());
+        self.network.add_node(handler)
+    }
+
+    /// Mine an empty block on the best block of `node` and relay it to the
+    /// peers of the node, like the block generator does.
+    fn mine_block(&self, node: usize) -> H256 {
+        let handler = &self.handlers[node];
+        let graph = handler.get_synchronization_graph();
+        // The blocks are in the consensus graph after waiting for its worker,
+        // so `wait_for_generation` only waits for their execution.
+        graph.wait_for_consensus_worker();
+        let parent_hash = graph.consensus.best_block_hash();
+        graph.consensus.wait_for_generation(&parent_hash);
+
+        let (deferred_state_root, deferred_receipts_root) =
+            graph.consensus.compute_deferred_state_for_block(
+                &parent_hash,
+                DEFERRED_STATE_EPOCH_COUNT as usize - 1,
+            );
+        let difficulty = graph.inner.read().expected_difficulty(&parent_hash);
+        let adaptive = graph.check_mining_adaptive_block(
+            &mut *graph.consensus.inner.write(),
+            &parent_hash,
+            &difficulty,
+        );
+        let parent_height = graph.block_height_by_hash(&parent_hash).unwrap();
+        let mut block = Block::new(
+            BlockHeaderBuilder::new()
+                .with_transactions_root(Block::compute_transaction_root(
+                    &Vec::new(),
+                ))
+                .with_parent_hash(parent_hash)
+                .with_height(parent_height + 1)
+                .with_timestamp(self.network.now().as_secs())
+                .with_author(Address::from(node as u64 + 1))
+                .with_deferred_state_root(deferred_state_root)
+                .with_deferred_receipts_root(deferred_receipts_root)
+                .with_difficulty(difficulty)
+                .with_adaptive(adaptive)
+                .with_gas_limit(DEFAULT_MAX_BLOCK_GAS_LIMIT.into())
+                .build(),
+            Vec::new(),
+        );
+
+        let problem = ProofOfWorkProblem {
+            block_hash: block.block_header.problem_hash(),
+            difficulty,
+            boundary: difficulty_to_boundary(&difficulty),
+        };
+        let mut nonce = 0;
+        while !validate(&problem, &ProofOfWorkSolution { nonce }) {
+            nonce += 1;
+        }
+        block.block_header.set_nonce(nonce);
+        let hash = block.block_header.compute_hash();
+
+        handler.on_mined_block(block);
+        self.network
+            .with_context(node, |io| handler.relay_blocks(io, vec![hash]))
+            .expect("relay mined block");
+        graph.wait_for_consensus_worker();
+        graph.consensus.wait_for_generation(&hash);
+        hash
+    }
+
+    fn has_blocks(&self, node: usize, hashes: &[H256]) -> bool {
+        let graph = self.handlers[node].get_synchronization_graph();
+        hashes.iter().all(|hash| graph.contains_block(hash))
+    }
+
+    /// The best block of `node` after its consensus graph has processed all
+    /// received blocks.
+    fn best_block_hash(&self, node: usize) -> H256 {
+        let graph = self.handlers[node].get_synchronization_graph();
+        graph.wait_for_consensus_worker();
+        graph.consensus.best_block_hash()
+    }
+}
+
+#[test]
+fn test_relay_mined_blocks() {
+    let nodes = SyncNetwork::new(3, 1);
+    nodes.network.set_default_link(LinkConfig {
+        latency: Duration::from_millis(100),
+        jitter: Duration::from_millis(50),
+        loss_rate: 0.0,
+    });
+    nodes.network.connect(0, 1);
+    nodes.network.connect(1, 2);
+    nodes.network.run_for(Duration::from_secs(1));
+
+    let hashes: Vec<H256> = (0..3).map(|_| nodes.mine_block(0)).collect();
+    assert!(nodes.network.run_until(SYNC_TIMEOUT, |_| {
+        (1..3).all(|node| nodes.has_blocks(node, &hashes))
+    }));
+    for node in 0..3 {
+        assert_eq!(nodes.best_block_hash(node), hashes[2]);
+    }
+}
+
+#[test]
+fn test_sync_over_lossy_links() {
+    let nodes = SyncNetwork::new(3, 7);
+    nodes.network.connect_all();
+    nodes.network.run_for(Duration::from_secs(1));
+
+    // Lost requests and responses are sent again after they time out.
+    nodes.network.set_default_link(LinkConfig {
+        latency: Duration::from_millis(100),
+        jitter: Duration::from_millis(50),
+        loss_rate: 0.3,
+    });
+    let mut hashes = Vec::new();
+    for _ in 0..5 {
+        hashes.push(nodes.mine_block(0));
+        nodes.network.run_for(Duration::from_secs(1));
+    }
+    nodes.network.run_for(SYNC_TIMEOUT);
+    let (_, lost) = nodes.network.message_stats();
+    assert!(lost > 0);
+
+    // Restore the links for the last block, whose announcement may not be
+    // relayed again. The requests lost before still have to be retried.
+    nodes.network.set_default_link(LinkConfig::default());
+    hashes.push(nodes.mine_block(0));
+    assert!(nodes.network.run_until(SYNC_TIMEOUT, |_| {
+        (1..3).all(|node| nodes.has_blocks(node, &hashes))
+    }));
+    for node in 1..3 {
+        assert_eq!(nodes.best_block_hash(node), hashes[5]);
+    }
+}
+
+#[test]
+fn test_give_up_dropped_requests() {
+    let nodes = SyncNetwork::new(2, 8);
+    nodes.network.connect(0, 1);
+    nodes.network.run_for(Duration::from_secs(1));
+    let abandoned = nodes.handlers[1].sync_status().abandoned_requests;
+
+    // Node 1 is told about the block, but none of its requests are answered.
+    let hash = nodes.mine_block(0);
+    nodes.network.set_default_link(LinkConfig {
+        loss_rate: 1.0,
+        ..LinkConfig::default()
+    });
+    // The request is given up after it is not answered for 10 minutes.
+    assert!(nodes.network.run_until(Duration::from_secs(1200), |_| {
+        nodes.handlers[1].sync_status().abandoned_requests > abandoned
+    }));
+    assert!(!nodes.has_blocks(1, &[hash]));
+    assert_eq!(nodes.handlers[1].sync_status().headers_in_flight, 0);
+}
+
+#[test]
+fn test_penalize_timeout_peer_once() {
+    let nodes = SyncNetwork::new(2, 9);
+    nodes.network.connect(0, 1);
+    nodes.network.run_for(Duration::from_secs(1));
+    let score = nodes.network.peer_score(1, 0);
+
+    // Node 1 requests each of the blocks from node 0 at the same time, and
+    // all the requests time out together.
+    for _ in 0..3 {
+        nodes.mine_block(0);
+    }
+    nodes.network.set_default_link(LinkConfig {
+        loss_rate: 1.0,
+        ..LinkConfig::default()
+    });
+    assert!(nodes.network.run_until(SYNC_TIMEOUT, |network| {
+        network.peer_score(1, 0) != score
+    }));
+    assert_eq!(
+        nodes.network.peer_score(1, 0),
+        score - REQUEST_TIMEOUT_PENALTY
+    );
+}
+
+#[test]
+fn test_sync_late_joining_node() {
+    let mut nodes = SyncNetwork::new(2, 2);
+    nodes.network.connect(0, 1);
+    let hashes: Vec<H256> = (0..4).map(|i| nodes.mine_block(i % 2)).collect();
+    assert!(nodes
+        .network
+        .run_until(SYNC_TIMEOUT, |_| nodes.has_blocks(1, &hashes)));
+
+    let late = nodes.add_node();
+    nodes.network.connect(late, 0);
+    assert!(nodes
+        .network
+        .run_until(SYNC_TIMEOUT, |_| nodes.has_blocks(late, &hashes)));
+    assert_eq!(nodes.best_block_hash(late), nodes.best_block_hash(0));
+}
+
+#[test]
+fn test_converge_after_partition() {
+    let nodes = SyncNetwork::new(4, 3);
+    nodes.network.connect_all();
+    nodes.network.run_for(Duration::from_secs(1));
+
+    nodes.network.partition(&[&[0, 1], &[2, 3]]);
+    let left: Vec<H256> = (0..3).map(|_| nodes.mine_block(0)).collect();
+    let right: Vec<H256> = (0..2).map(|_| nodes.mine_block(2)).collect();
+    nodes.network.run_for(SYNC_TIMEOUT);
+    assert!(nodes.has_blocks(1, &left));
+    assert!(!nodes.has_blocks(1, &right));
+    assert!(nodes.has_blocks(3, &right));
+    assert!(!nodes.has_blocks(3, &left));
+    assert_ne!(nodes.best_block_hash(1), nodes.best_block_hash(3));
+
+    nodes.network.heal();
+    let all: Vec<H256> = left.iter().chain(right.iter()).cloned().collect();
+    assert!(nodes.network.run_until(SYNC_TIMEOUT, |_| {
+        (0..4).all(|node| nodes.has_blocks(node, &all))
+    }));
+    let best = nodes.best_block_hash(0);
+    for node in 1..4 {
+        assert_eq!(nodes.best_block_hash(node), best);
+    }
+}
+
+#[test]
+fn test_consensus_events() {
+    let nodes = SyncNetwork::new(2, 4);
+    let subscribe = |node: usize| {
+        nodes.handlers[node]
+            .get_synchronization_graph()
+            .consensus
+            .subscribe_events()
+    };
+
+    let events = subscribe(0);
+    let mine_count = DEFERRED_STATE_EPOCH_COUNT as usize + 2;
+    let mined: Vec<H256> =
+        (0..mine_count).map(|_| nodes.mine_block(0)).collect();
+    let events: Vec<ConsensusEvent> = events.try_iter().collect();
+    let inserted: Vec<H256> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConsensusEvent::NewBlockInserted { hash } => Some(*hash),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(inserted, mined);
+    // The first epoch is executed once it is deferred enough.
+    assert!(events.iter().any(|event| match event {
+        ConsensusEvent::EpochExecuted { epoch_hash, .. } => {
+            *epoch_hash == mined[0]
+        }
+        _ => false,
+    }));
+
+    // Node 1 mines a longer chain on its own, which node 0 switches to.
+    let fork: Vec<H256> =
+        (0..mine_count + 1).map(|_| nodes.mine_block(1)).collect();
+    let events = subscribe(0);
+    nodes.network.connect(0, 1);
+    assert!(nodes
+        .network
+        .run_until(SYNC_TIMEOUT, |_| nodes.has_blocks(0, &fork)));
+    assert_eq!(nodes.best_block_hash(0), fork[mine_count]);
+
+    let reorg = events.try_iter().find_map(|event| match event {
+        ConsensusEvent::PivotChainReorg {
+            old_epochs,
+            new_epochs,
+            fork_point,
+        } => Some((old_epochs, new_epochs, fork_point)),
+        _ => None,
+    });
+    let (old_epochs, new_epochs, fork_point) =
+        reorg.expect("node 0 switches to the longer chain");
+    assert_eq!(
+        fork_point,
+        nodes.handlers[0].get_synchronization_graph().genesis_hash()
+    );
+    assert_eq!(old_epochs, mined);
+    assert_eq!(new_epochs[0], fork[0]);
+}
+
+/// A request for block hashes, which is deprecated since protocol version 2.
+fn get_block_hashes() -> GetBlockHashes {
+    let mut stream = RlpStream::new_list(3);
+    stream.append(&0u64).append(&H256::zero()).append(&1usize);
+    rlp::decode(&stream.out()).unwrap()
+}
+
+#[test]
+fn test_deprecated_message_is_not_sent() {
+    for &(version, valid) in &[
+        (MIN_SYNCHRONIZATION_PROTOCOL_VERSION, true),
+        (SYNCHRONIZATION_PROTOCOL_VERSION, false),
+    ] {
+        let nodes = SyncNetwork::with_version(2, 5, version);
+        nodes.network.connect(0, 1);
+        nodes.network.run_for(Duration::from_secs(1));
+
+        let result = nodes.network.with_context(0, |io| {
+            send_message(io, 1, &get_block_hashes(), SendQueuePriority::High)
+        });
+        match result {
+            Ok(()) => assert!(valid),
+            Err(e) => {
+                assert!(!valid);
+                match e.kind() {
+                    NetworkErrorKind::BadProtocol => {}
+                    kind => panic!("unexpected error {:?}", kind),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_deprecated_message_is_rejected() {
+    for &(version, valid) in &[
+        (MIN_SYNCHRONIZATION_PROTOCOL_VERSION, true),
+        (SYNCHRONIZATION_PROTOCOL_VERSION, false),
+    ] {
+        let nodes = SyncNetwork::with_version(2, 6, version);
+        nodes.network.connect(0, 1);
+        nodes.network.run_for(Duration::from_secs(1));
+        let score = nodes.network.peer_score(1, 0);
+
+        // Bypass the check in `send_message`, like a misbehaving peer.
+        let msg = get_block_hashes();
+        let mut raw = vec![msg.msg_id().into()];
+        raw.extend(rlp::encode(&msg).iter());
+        nodes
+            .network
+            .with_context(0, |io| io.send(1, raw, SendQueuePriority::High))
+            .unwrap();
+        nodes.network.run_for(Duration::from_secs(1));
+        assert_eq!(nodes.network.peer_score(1, 0) == score, valid);
+    }
+}
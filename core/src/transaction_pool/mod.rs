@@ -22,7 +22,7 @@ use crate::{
     vm,
 };
 use cfx_types::{Address, H256, H512, U256, U512};
-use metrics::Gauge;
+use metrics::{Gauge, Histogram, Meter};
 use parking_lot::{Mutex, RwLock};
 use primitives::{
     Account, Action, EpochId, SignedTransaction, TransactionAddress,
@@ -41,6 +41,10 @@ lazy_static! {
     static ref TX_POOL_GAUGE: Gauge = Gauge::register("tx_pool_size");
     static ref TX_POOL_READY_GAUGE: Gauge =
         Gauge::register("tx_pool_ready_size");
+    static ref TX_POOL_INSERT_TIMER: Histogram =
+        Histogram::register("tx_pool_insert_new_transactions");
+    static ref TX_POOL_INSERT_METER: Meter =
+        Meter::register("tx_pool_inserted_transactions");
 }
 
 pub const DEFAULT_MIN_TRANSACTION_GAS_PRICE: u64 = 1;
@@ -366,6 +370,9 @@ impl TransactionPool {
         transactions: &Vec<TransactionWithSignature>,
     ) -> Vec<Result<H256, String>>
     {
+        let _timer = TX_POOL_INSERT_TIMER.start_timer();
+        TX_POOL_INSERT_METER.mark(transactions.len());
+
         // FIXME: do not unwrap.
         let mut failures = HashMap::new();
 
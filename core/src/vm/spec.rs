@@ -54,6 +54,13 @@ pub struct Spec {
     pub sstore_reset_gas: usize,
     /// Gas refund for `SSTORE` clearing (when `storage!=0`, `new==0`)
     pub sstore_refund_gas: usize,
+    /// Gas price for `SSTORE` on a dirty slot or a no-op write under net gas
+    /// metering. If `None`, `sload_gas` is charged (EIP-2200).
+    pub sstore_dirty_gas: Option<usize>,
+    /// Enable net gas metering for `SSTORE` (EIP-1283)
+    pub eip1283: bool,
+    /// Fail `SSTORE` if the gas left is not above the call stipend (EIP-1706)
+    pub eip1706: bool,
     /// Gas price for `JUMPDEST` opcode
     pub jumpdest_gas: usize,
     /// Gas price for `LOG*`
@@ -225,6 +232,9 @@ impl Spec {
             sstore_set_gas: 20000,
             sstore_reset_gas: 5000,
             sstore_refund_gas: 15000,
+            sstore_dirty_gas: None,
+            eip1283: false,
+            eip1706: false,
             jumpdest_gas: 1,
             log_gas: 375,
             log_data_gas: 8,
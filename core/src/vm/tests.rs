@@ -60,6 +60,7 @@ pub struct MockCall {
 #[derive(Default)]
 pub struct MockContext {
     pub store: HashMap<H256, H256>,
+    pub initial_store: HashMap<H256, H256>,
     pub suicides: HashSet<Address>,
     pub calls: HashSet<MockCall>,
     pub sstore_clears: i128,
@@ -107,8 +108,8 @@ impl MockContext {
 }
 
 impl Context for MockContext {
-    fn initial_storage_at(&self, _key: &H256) -> Result<H256> {
-        Ok(H256::new())
+    fn initial_storage_at(&self, key: &H256) -> Result<H256> {
+        Ok(self.initial_store.get(key).unwrap_or(&H256::new()).clone())
     }
 
     fn storage_at(&self, key: &H256) -> Result<H256> {
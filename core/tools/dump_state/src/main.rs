@@ -0,0 +1,136 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Dump every account of a state, with balance, nonce, code hash and storage,
+//! as JSON in the format of the `cfx_dumpState` RPC.
+//!
+//! The state is read from the database given by `--db-dir`. Without
+//! `--epoch-hash`, the genesis state is dumped; it's computed from the genesis
+//! accounts and committed into the database, so point `--db-dir` to a copy
+//! of the node database or to a new directory.
+
+use cfx_types::H256;
+use cfxcore::{
+    db::NUM_COLUMNS,
+    genesis,
+    statedb::StateDb,
+    storage::{
+        state_manager::StorageConfiguration, StorageManager,
+        StorageManagerTrait, StorageTrait,
+    },
+    transaction_pool::DEFAULT_MAX_BLOCK_GAS_LIMIT,
+};
+use clap::{App, Arg, ArgMatches};
+use client::RpcAccountState;
+use secret_store::SecretStore;
+use std::{fs::File, io, path::Path, process, str::FromStr, sync::Arc};
+
+/// The number of accounts read from the state at a time.
+const DUMP_PAGE_SIZE: usize = 1000;
+
+fn main() {
+    let matches = App::new("dump_state")
+        .about("Dump all accounts of a state as JSON")
+        .arg(
+            Arg::with_name("db-dir")
+                .long("db-dir")
+                .value_name("DIR")
+                .help("The database of the node.")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("epoch-hash")
+                .long("epoch-hash")
+                .value_name("HASH")
+                .help("The pivot block hash of the epoch to dump, genesis if absent.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("genesis-accounts")
+                .long("genesis-accounts")
+                .value_name("FILE")
+                .help("The genesis accounts file used by the node, if any.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("FILE")
+                .help("The file to write the JSON to, stdout if absent.")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    if let Err(e) = run(&matches) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<(), String> {
+    let db_dir = matches.value_of("db-dir").unwrap();
+    let ledger_db = db::open_database(
+        db_dir,
+        &db::db_config(
+            Path::new(db_dir),
+            Some(128),
+            db::DatabaseCompactionProfile::default(),
+            NUM_COLUMNS.clone(),
+        ),
+    )
+    .map_err(|e| format!("Failed to open database {:?}", e))?;
+    let storage_manager = Arc::new(StorageManager::new(
+        ledger_db,
+        StorageConfiguration::default(),
+    ));
+
+    let epoch_hash = match matches.value_of("epoch-hash") {
+        Some(hash) => H256::from_str(hash.trim_start_matches("0x"))
+            .map_err(|e| format!("Invalid epoch hash {}: {:?}", hash, e))?,
+        None => {
+            let genesis_accounts = match matches.value_of("genesis-accounts") {
+                Some(file) => genesis::load_file(&file.to_string())?,
+                None => genesis::default(&SecretStore::new()),
+            };
+            storage_manager
+                .initialize(
+                    genesis_accounts,
+                    DEFAULT_MAX_BLOCK_GAS_LIMIT.into(),
+                    client::TESTNET_VERSION.into(),
+                )
+                .hash()
+        }
+    };
+
+    let state = storage_manager
+        .get_state_at(epoch_hash)
+        .map_err(|e| format!("Failed to get state: {:?}", e))?;
+    if !state.does_exist() {
+        return Err(format!("No state for epoch {:?}", epoch_hash));
+    }
+    let statedb = StateDb::new(state);
+    let mut accounts: Vec<RpcAccountState> = Vec::new();
+    let mut start_key = Vec::new();
+    loop {
+        let (page, next_key) = statedb
+            .dump_accounts(&start_key, DUMP_PAGE_SIZE)
+            .map_err(|e| format!("Failed to dump state: {:?}", e))?;
+        accounts.extend(page.into_iter().map(|account| account.into()));
+        match next_key {
+            Some(key) => start_key = key,
+            None => break,
+        }
+    }
+
+    let result = match matches.value_of("output") {
+        Some(file) => serde_json::to_writer_pretty(
+            File::create(file)
+                .map_err(|e| format!("Failed to create {}: {:?}", file, e))?,
+            &accounts,
+        ),
+        None => serde_json::to_writer_pretty(io::stdout(), &accounts),
+    };
+    result.map_err(|e| format!("Failed to write JSON: {:?}", e))
+}
@@ -0,0 +1,309 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Replay an epoch dumped into `debug_dump_dir_invalid_state_root` when the
+//! node hits an invalid state root, and print the differences between the
+//! recorded and the recomputed execution.
+//!
+//! Re-execution commits the recomputed state into the database given by
+//! `--db-dir`, so it should be run against a copy of the node database.
+//! Without `--db-dir`, the dump is only printed.
+
+use cfx_types::U256;
+use cfxcore::{
+    block_data_manager::BlockDataManager,
+    cache_manager::CacheManager,
+    consensus::{ComputeEpochDebugRecord, ConsensusExecutionHandler},
+    db::NUM_COLUMNS,
+    event_bus::EventBus,
+    genesis,
+    machine::{load_builtin_specs, new_machine_with_builtin},
+    pow::WORKER_COMPUTATION_PARALLELISM,
+    storage::{state_manager::StorageConfiguration, StorageManager},
+    transaction_pool::DEFAULT_MAX_BLOCK_GAS_LIMIT,
+    vm_factory::VmFactory,
+    TransactionPool,
+};
+use clap::{App, Arg, ArgMatches};
+use parking_lot::Mutex;
+use rlp::{Encodable, Rlp};
+use secret_store::SecretStore;
+use std::{fs, path::Path, process, sync::Arc};
+use threadpool::ThreadPool;
+
+fn main() {
+    let matches = App::new("replay_epoch")
+        .about("Replay an epoch dumped on invalid state root")
+        .arg(
+            Arg::with_name("dump")
+                .value_name("DUMP_FILE")
+                .help("The dumped ComputeEpochDebugRecord.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("db-dir")
+                .long("db-dir")
+                .value_name("DIR")
+                .help("A copy of the database of the node to replay against.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("genesis-accounts")
+                .long("genesis-accounts")
+                .value_name("FILE")
+                .help("The genesis accounts file used by the node, if any.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("builtin-spec-file")
+                .long("builtin-spec-file")
+                .value_name("FILE")
+                .help("The built-in contract specs used by the node, if any.")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    match run(&matches) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Returns whether the replay matches the dump.
+fn run(matches: &ArgMatches) -> Result<bool, String> {
+    let dump_path = matches.value_of("dump").unwrap();
+    let recorded_rlp = fs::read(dump_path)
+        .map_err(|e| format!("failed to read {}: {:?}", dump_path, e))?;
+    let recorded = Rlp::new(&recorded_rlp)
+        .as_val::<ComputeEpochDebugRecord>()
+        .map_err(|e| format!("failed to decode {}: {:?}", dump_path, e))?;
+
+    let db_dir = match matches.value_of("db-dir") {
+        Some(db_dir) => db_dir,
+        None => {
+            print_record(&Rlp::new(&recorded_rlp));
+            return Ok(true);
+        }
+    };
+
+    let replayed = replay(matches, db_dir, &recorded)?;
+    let replayed_rlp = replayed.rlp_bytes();
+    let diff_count =
+        print_diff(&Rlp::new(&recorded_rlp), &Rlp::new(&replayed_rlp));
+    if diff_count == 0 {
+        println!(
+            "Replay of epoch {:?} matches the dump.",
+            recorded.epoch_hash()
+        );
+    } else {
+        println!(
+            "Replay of epoch {:?} differs from the dump in {} items.",
+            recorded.epoch_hash(),
+            diff_count
+        );
+    }
+    Ok(diff_count == 0)
+}
+
+fn replay(
+    matches: &ArgMatches, db_dir: &str, recorded: &ComputeEpochDebugRecord,
+) -> Result<ComputeEpochDebugRecord, String> {
+    let epoch_hash = recorded
+        .epoch_hash()
+        .ok_or_else(|| "the dump contains no block".to_string())?;
+
+    let ledger_db = db::open_database(
+        db_dir,
+        &db::db_config(
+            Path::new(db_dir),
+            Some(128),
+            db::DatabaseCompactionProfile::default(),
+            NUM_COLUMNS.clone(),
+        ),
+    )
+    .map_err(|e| format!("Failed to open database {:?}", e))?;
+
+    let storage_manager = Arc::new(StorageManager::new(
+        ledger_db.clone(),
+        StorageConfiguration::default(),
+    ));
+
+    let genesis_accounts = match matches.value_of("genesis-accounts") {
+        Some(file) => genesis::load_file(&file.to_string())?,
+        None => genesis::default(&SecretStore::new()),
+    };
+    let genesis_block = storage_manager.initialize(
+        genesis_accounts,
+        DEFAULT_MAX_BLOCK_GAS_LIMIT.into(),
+        client::TESTNET_VERSION.into(),
+    );
+
+    let mb = 1024 * 1024;
+    let max_cache_size = 2048 * mb;
+    let pref_cache_size = max_cache_size * 3 / 4;
+    let cache_man = Arc::new(Mutex::new(CacheManager::new(
+        pref_cache_size,
+        max_cache_size,
+        3 * mb,
+    )));
+
+    let worker_thread_pool = Arc::new(Mutex::new(ThreadPool::with_name(
+        "Tx Recover".into(),
+        WORKER_COMPUTATION_PARALLELISM,
+    )));
+    let txpool = Arc::new(TransactionPool::with_capacity(
+        500_000,
+        storage_manager.clone(),
+        worker_thread_pool,
+        cache_man.clone(),
+    ));
+
+    let data_man = Arc::new(BlockDataManager::new(
+        Arc::new(genesis_block),
+        txpool,
+        ledger_db,
+        storage_manager,
+        cache_man,
+        false,
+    ));
+    if data_man
+        .blocks_by_hash_list(&recorded.block_hashes, false)
+        .is_none()
+    {
+        return Err(format!(
+            "blocks of epoch {:?} not found in {}",
+            epoch_hash, db_dir
+        ));
+    }
+    let reward_execution_info = recorded.reward_execution_info(&data_man)?;
+
+    let builtin_specs = match matches.value_of("builtin-spec-file") {
+        Some(file) => load_builtin_specs(file)?,
+        None => Vec::new(),
+    };
+    let handler = ConsensusExecutionHandler::new(
+        data_man,
+        VmFactory::new(1024 * 32),
+        Arc::new(new_machine_with_builtin(&builtin_specs)?),
+        Arc::new(EventBus::new()),
+    );
+
+    let mut replayed = Some(recorded.replay_inputs());
+    handler.compute_epoch(
+        &epoch_hash,
+        &recorded.block_hashes,
+        &reward_execution_info,
+        false,
+        &mut replayed,
+    )?;
+    Ok(replayed.unwrap())
+}
+
+/// Iterate the (label, value) pairs of an encoded `ComputeEpochDebugRecord`.
+fn labeled_items<'a>(rlp: &'a Rlp<'a>) -> Vec<(String, Rlp<'a>)> {
+    let item_count = rlp.item_count().unwrap_or(0);
+    (0..item_count / 2)
+        .filter_map(|i| {
+            let label = rlp.val_at::<String>(2 * i).ok()?;
+            let value = rlp.at(2 * i + 1).ok()?;
+            Some((label, value))
+        })
+        .collect()
+}
+
+fn print_record(record: &Rlp) {
+    for (label, value) in labeled_items(record) {
+        if value.is_list() {
+            println!("{}: {} items", label, value.item_count().unwrap_or(0));
+            for item in value.iter() {
+                println!("    {}", describe(&item));
+            }
+        } else {
+            println!("{}: {}", label, describe(&value));
+        }
+    }
+}
+
+/// Print the differences between the recorded and the replayed values for
+/// every label, and return the number of differing items.
+fn print_diff(recorded: &Rlp, replayed: &Rlp) -> usize {
+    let recorded_items = labeled_items(recorded);
+    let replayed_items = labeled_items(replayed);
+
+    let mut diff_count = 0;
+    for (label, recorded_value) in &recorded_items {
+        let replayed_value =
+            match replayed_items.iter().find(|(l, _)| l == label) {
+                Some((_, value)) => value,
+                None => {
+                    println!("{}: not replayed", label);
+                    continue;
+                }
+            };
+        if recorded_value.as_raw() == replayed_value.as_raw() {
+            continue;
+        }
+
+        println!("{}:", label);
+        if recorded_value.is_list() && replayed_value.is_list() {
+            let recorded_list = recorded_value.iter().collect::<Vec<_>>();
+            let replayed_list = replayed_value.iter().collect::<Vec<_>>();
+            for i in 0..recorded_list.len().max(replayed_list.len()) {
+                let recorded_item = recorded_list.get(i);
+                let replayed_item = replayed_list.get(i);
+                if recorded_item.map(|item| item.as_raw())
+                    == replayed_item.map(|item| item.as_raw())
+                {
+                    continue;
+                }
+                diff_count += 1;
+                println!("  #{}", i);
+                if let Some(item) = recorded_item {
+                    println!("  - {}", describe(item));
+                }
+                if let Some(item) = replayed_item {
+                    println!("  + {}", describe(item));
+                }
+            }
+        } else {
+            diff_count += 1;
+            println!("  - {}", describe(recorded_value));
+            println!("  + {}", describe(replayed_value));
+        }
+    }
+    diff_count
+}
+
+/// Render an RLP item, showing printable data as text and other data as hex.
+fn describe(rlp: &Rlp) -> String {
+    if rlp.is_list() {
+        let items = rlp.iter().map(|item| describe(&item)).collect::<Vec<_>>();
+        return format!("[{}]", items.join(", "));
+    }
+    let data = rlp.data().unwrap_or(&[]);
+    match std::str::from_utf8(data) {
+        Ok(text)
+            if !text.is_empty() && text.chars().all(|c| !c.is_control()) =>
+        {
+            text.to_string()
+        }
+        _ if data.len() <= 8 => {
+            let mut value = U256::zero();
+            for byte in data {
+                value = (value << 8) | U256::from(*byte);
+            }
+            value.to_string()
+        }
+        _ => {
+            let hex: String =
+                data.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("0x{}", hex)
+        }
+    }
+}
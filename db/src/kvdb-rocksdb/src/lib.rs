@@ -365,17 +365,37 @@ impl Database {
 						Ok(db)
 					}
 					Err(_) => {
-						// retry and create CFs
-						match DB::open_cf(&opts, path, &[]) {
-							Ok(mut db) => {
-								cfs = cfnames.iter()
-									.enumerate()
-									.map(|(i, n)| db.create_cf(n, &cf_options[i]))
-									.collect::<::std::result::Result<_, _>>()
-									.map_err(other_io_err)?;
-								Ok(db)
-							},
-							err => err,
+						// The database may have been created with another number of columns, in
+						// which case the existing ones are opened, and it's up to the caller to
+						// migrate it with `add_column` and `drop_column`.
+						let existing_columns = DB::list_cf(&opts, path)
+							.map(|names| names.iter().filter(|n| n.starts_with("col")).count())
+							.unwrap_or(0);
+						if existing_columns > 0 {
+							warn!("DB has {} columns instead of {}", existing_columns, columns);
+							let existing_names: Vec<_> = (0..existing_columns).map(|c| format!("col{}", c)).collect();
+							let existing_names: Vec<&str> = existing_names.iter().map(|n| n as &str).collect();
+							match DB::open_cf(&opts, path, &existing_names) {
+								Ok(db) => {
+									cfs = existing_names.iter().map(|n| db.cf_handle(n)
+										.expect("rocksdb opens a cf_handle for each cfname; qed")).collect();
+									Ok(db)
+								}
+								err => err,
+							}
+						} else {
+							// retry and create CFs
+							match DB::open_cf(&opts, path, &[]) {
+								Ok(mut db) => {
+									cfs = cfnames.iter()
+										.enumerate()
+										.map(|(i, n)| db.create_cf(n, &cf_options[i]))
+										.collect::<::std::result::Result<_, _>>()
+										.map_err(other_io_err)?;
+									Ok(db)
+								},
+								err => err,
+							}
 						}
 					}
 				}
@@ -676,6 +696,8 @@ impl Database {
 					let name = format!("col{}", cfs.len());
 					drop(col);
 					db.drop_cf(&name).map_err(other_io_err)?;
+					self.overlay.write().pop();
+					self.flushing.write().pop();
 				}
 				Ok(())
 			},
@@ -690,6 +712,8 @@ impl Database {
 				let col = cfs.len() as u32;
 				let name = format!("col{}", col);
 				cfs.push(db.create_cf(&name, &col_config(&self.config, &self.block_opts)?).map_err(other_io_err)?.into());
+				self.overlay.write().push(HashMap::new());
+				self.flushing.write().push(HashMap::new());
 				Ok(())
 			},
 			None => Ok(()),
@@ -25,7 +25,59 @@ extern crate log;
 
 #[path = "rocksdb/mod.rs"]
 mod impls;
+mod memory;
+mod read_only;
+#[path = "sled/mod.rs"]
+mod sled_db;
 
-pub use self::impls::{
-    db_config, open_database, DatabaseCompactionProfile, SystemDB,
+pub use self::{
+    impls::{db_config, open_database, DatabaseCompactionProfile, SystemDB},
+    memory::{open_memory_database, InMemoryDB},
+    read_only::{read_only_database, ReadOnlyDB},
+    sled_db::{open_sled_database, SledDB},
 };
+use std::{io, str::FromStr};
+
+/// Access to the numbered columns of a key-value database, to change them
+/// when migrating the database schema. Columns are added and dropped at the
+/// end, so that the ids of the others are unchanged.
+pub trait DatabaseColumns: Send + Sync {
+    fn num_columns(&self) -> u32;
+
+    fn add_column(&self) -> io::Result<()>;
+
+    fn drop_column(&self) -> io::Result<()>;
+}
+
+/// The storage behind the key-value database of a node. All of them have the
+/// same column layout.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DatabaseBackend {
+    /// RocksDB in the database directory.
+    RocksDB,
+    /// In memory, lost when the node stops.
+    Memory,
+    /// Sled in the database directory. It doesn't need a C++ toolchain, but
+    /// writes to several columns are not atomic together.
+    Sled,
+}
+
+impl Default for DatabaseBackend {
+    fn default() -> Self { DatabaseBackend::RocksDB }
+}
+
+impl FromStr for DatabaseBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rocksdb" => Ok(DatabaseBackend::RocksDB),
+            "memory" => Ok(DatabaseBackend::Memory),
+            "sled" => Ok(DatabaseBackend::Sled),
+            _ => Err(
+                "Invalid database backend given. Expected rocksdb/memory/sled."
+                    .into(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! A `KeyValueDB` kept in memory, for tests and devnets which don't need to
+//! persist the ledger across restarts.
+
+use super::{DatabaseColumns, SystemDB};
+use kvdb::{DBOp, DBTransaction, DBValue, KeyValueDB};
+use parking_lot::RwLock;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::Arc,
+};
+
+type Column = BTreeMap<Vec<u8>, DBValue>;
+
+pub struct InMemoryDB {
+    columns: RwLock<HashMap<Option<u32>, Column>>,
+}
+
+impl InMemoryDB {
+    /// Create a database with the default column and `columns` numbered
+    /// columns, like the RocksDB backend does.
+    pub fn new(columns: Option<u32>) -> Self {
+        let mut map = HashMap::new();
+        map.insert(None, Column::new());
+        for col in 0..columns.unwrap_or(0) {
+            map.insert(Some(col), Column::new());
+        }
+        InMemoryDB {
+            columns: RwLock::new(map),
+        }
+    }
+
+    fn no_such_column(col: Option<u32>) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("No such column family: {:?}", col),
+        )
+    }
+
+    fn column_items<'a>(
+        &self, col: Option<u32>, prefix: &'a [u8],
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        // Iterate over a snapshot, so that the lock isn't held by the
+        // iterator.
+        let items: Vec<(Box<[u8]>, Box<[u8]>)> =
+            match self.columns.read().get(&col) {
+                Some(column) => column
+                    .range(prefix.to_vec()..)
+                    .take_while(|(key, _)| key.starts_with(prefix))
+                    .map(|(key, value)| {
+                        (
+                            key.clone().into_boxed_slice(),
+                            value.to_vec().into_boxed_slice(),
+                        )
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+        Box::new(items.into_iter())
+    }
+}
+
+impl KeyValueDB for InMemoryDB {
+    fn get(&self, col: Option<u32>, key: &[u8]) -> io::Result<Option<DBValue>> {
+        match self.columns.read().get(&col) {
+            Some(column) => Ok(column.get(key).cloned()),
+            None => Err(Self::no_such_column(col)),
+        }
+    }
+
+    fn get_by_prefix(
+        &self, col: Option<u32>, prefix: &[u8],
+    ) -> Option<Box<[u8]>> {
+        self.column_items(col, prefix)
+            .next()
+            .map(|(_, value)| value)
+    }
+
+    fn write_buffered(&self, transaction: DBTransaction) {
+        let mut columns = self.columns.write();
+        for op in transaction.ops {
+            match op {
+                DBOp::Insert { col, key, value } => {
+                    if let Some(column) = columns.get_mut(&col) {
+                        column.insert(key.into_vec(), value);
+                    }
+                }
+                DBOp::Delete { col, key } => {
+                    if let Some(column) = columns.get_mut(&col) {
+                        column.remove(&*key);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write(&self, transaction: DBTransaction) -> io::Result<()> {
+        {
+            let columns = self.columns.read();
+            for op in &transaction.ops {
+                let col = match op {
+                    DBOp::Insert { col, .. } | DBOp::Delete { col, .. } => col,
+                };
+                if !columns.contains_key(col) {
+                    return Err(Self::no_such_column(*col));
+                }
+            }
+        }
+        self.write_buffered(transaction);
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> { Ok(()) }
+
+    fn iter<'a>(
+        &'a self, col: Option<u32>,
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.column_items(col, &[])
+    }
+
+    fn iter_from_prefix<'a>(
+        &'a self, col: Option<u32>, prefix: &'a [u8],
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.column_items(col, prefix)
+    }
+
+    fn restore(&self, _new_db: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "An in-memory database cannot be restored",
+        ))
+    }
+}
+
+impl DatabaseColumns for InMemoryDB {
+    fn num_columns(&self) -> u32 { self.columns.read().len() as u32 - 1 }
+
+    fn add_column(&self) -> io::Result<()> {
+        let mut columns = self.columns.write();
+        let col = columns.len() as u32 - 1;
+        columns.insert(Some(col), Column::new());
+        Ok(())
+    }
+
+    fn drop_column(&self) -> io::Result<()> {
+        let mut columns = self.columns.write();
+        let num_columns = columns.len() as u32 - 1;
+        if num_columns > 0 {
+            columns.remove(&Some(num_columns - 1));
+        }
+        Ok(())
+    }
+}
+
+pub fn open_memory_database(columns: Option<u32>) -> Arc<SystemDB> {
+    info!("Open in-memory db with {:?} columns", columns);
+    let db = Arc::new(InMemoryDB::new(columns));
+    Arc::new(SystemDB::with_columns(db.clone(), db))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemoryDB;
+    use kvdb::{DBTransaction, KeyValueDB};
+
+    #[test]
+    fn test_write_and_read() {
+        let db = InMemoryDB::new(Some(2));
+        let mut transaction = DBTransaction::new();
+        transaction.put(Some(0), b"key", b"value");
+        transaction.put(Some(1), b"key", b"other");
+        db.write(transaction).unwrap();
+        assert_eq!(
+            db.get(Some(0), b"key").unwrap().unwrap().to_vec(),
+            b"value"
+        );
+        assert_eq!(
+            db.get(Some(1), b"key").unwrap().unwrap().to_vec(),
+            b"other"
+        );
+        assert!(db.get(None, b"key").unwrap().is_none());
+        assert!(db.get(Some(2), b"key").is_err());
+
+        let mut transaction = DBTransaction::new();
+        transaction.delete(Some(0), b"key");
+        db.write(transaction).unwrap();
+        assert!(db.get(Some(0), b"key").unwrap().is_none());
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(Some(2), b"key", b"value");
+        assert!(db.write(transaction).is_err());
+    }
+
+    #[test]
+    fn test_iter_from_prefix() {
+        let db = InMemoryDB::new(Some(1));
+        let mut transaction = DBTransaction::new();
+        for key in &[&b"aa"[..], b"ab", b"b", b"a"] {
+            transaction.put(Some(0), key, key);
+        }
+        db.write(transaction).unwrap();
+
+        let keys: Vec<Vec<u8>> = db
+            .iter_from_prefix(Some(0), b"a")
+            .map(|(key, _)| key.to_vec())
+            .collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"aa".to_vec(), b"ab".to_vec()]);
+        assert_eq!(db.iter(Some(0)).count(), 4);
+        assert_eq!(
+            db.get_by_prefix(Some(0), b"b").map(|value| value.to_vec()),
+            Some(b"b".to_vec())
+        );
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! A view of a `KeyValueDB` which rejects all the writes, for the tools
+//! which only inspect a database.
+
+use super::SystemDB;
+use kvdb::{DBTransaction, DBValue, KeyValueDB};
+use std::{io, sync::Arc};
+
+pub struct ReadOnlyDB {
+    db: Arc<KeyValueDB>,
+}
+
+impl ReadOnlyDB {
+    fn write_rejected() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "The database is opened read-only",
+        )
+    }
+}
+
+impl KeyValueDB for ReadOnlyDB {
+    fn get(&self, col: Option<u32>, key: &[u8]) -> io::Result<Option<DBValue>> {
+        self.db.get(col, key)
+    }
+
+    fn get_by_prefix(
+        &self, col: Option<u32>, prefix: &[u8],
+    ) -> Option<Box<[u8]>> {
+        self.db.get_by_prefix(col, prefix)
+    }
+
+    fn write_buffered(&self, _transaction: DBTransaction) {
+        warn!("Write to a read-only database is dropped");
+    }
+
+    fn write(&self, _transaction: DBTransaction) -> io::Result<()> {
+        Err(Self::write_rejected())
+    }
+
+    fn flush(&self) -> io::Result<()> { Ok(()) }
+
+    fn iter<'a>(
+        &'a self, col: Option<u32>,
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.db.iter(col)
+    }
+
+    fn iter_from_prefix<'a>(
+        &'a self, col: Option<u32>, prefix: &'a [u8],
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.db.iter_from_prefix(col, prefix)
+    }
+
+    fn restore(&self, _new_db: &str) -> io::Result<()> {
+        Err(Self::write_rejected())
+    }
+}
+
+/// Wrap `db` so that all writes to it are rejected. Its columns cannot be
+/// changed either.
+pub fn read_only_database(db: &SystemDB) -> Arc<SystemDB> {
+    Arc::new(SystemDB::new(Arc::new(ReadOnlyDB {
+        db: db.key_value().clone(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_only_database;
+    use crate::open_memory_database;
+    use kvdb::DBTransaction;
+
+    #[test]
+    fn test_writes_are_rejected() {
+        let db = open_memory_database(Some(1));
+        let mut transaction = DBTransaction::new();
+        transaction.put(Some(0), b"key", b"value");
+        db.key_value().write(transaction).unwrap();
+
+        let read_only = read_only_database(&db);
+        assert!(read_only.columns().is_none());
+        assert_eq!(
+            &*read_only.key_value().get(Some(0), b"key").unwrap().unwrap(),
+            b"value"
+        );
+        let mut transaction = DBTransaction::new();
+        transaction.delete(Some(0), b"key");
+        assert!(read_only.key_value().write(transaction).is_err());
+        assert!(db.key_value().get(Some(0), b"key").unwrap().is_some());
+    }
+}
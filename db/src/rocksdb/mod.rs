@@ -26,18 +26,47 @@ use self::{
     kvdb::KeyValueDB,
     kvdb_rocksdb::{CompactionProfile, Database, DatabaseConfig},
 };
+use crate::DatabaseColumns;
 use std::{io, path::Path, str::FromStr, sync::Arc};
 
 pub struct SystemDB {
     // This is the general db that will be shared and used by
     // all the special db at upper layer.
     key_value: Arc<KeyValueDB>,
+    // The columns of `key_value`, if they can be changed.
+    columns: Option<Arc<DatabaseColumns>>,
 }
 
 impl SystemDB {
     pub fn key_value(&self) -> &Arc<KeyValueDB> { &self.key_value }
 
-    pub fn new(kvdb: Arc<KeyValueDB>) -> Self { Self { key_value: kvdb } }
+    pub fn columns(&self) -> Option<&Arc<DatabaseColumns>> {
+        self.columns.as_ref()
+    }
+
+    pub fn new(kvdb: Arc<KeyValueDB>) -> Self {
+        Self {
+            key_value: kvdb,
+            columns: None,
+        }
+    }
+
+    pub fn with_columns(
+        kvdb: Arc<KeyValueDB>, columns: Arc<DatabaseColumns>,
+    ) -> Self {
+        Self {
+            key_value: kvdb,
+            columns: Some(columns),
+        }
+    }
+}
+
+impl DatabaseColumns for Database {
+    fn num_columns(&self) -> u32 { Database::num_columns(self) }
+
+    fn add_column(&self) -> io::Result<()> { Database::add_column(self) }
+
+    fn drop_column(&self) -> io::Result<()> { Database::drop_column(self) }
 }
 
 /// db compaction profile
@@ -108,9 +137,6 @@ pub fn open_database(
         }
     };
 
-    let sys_db = SystemDB {
-        key_value: Arc::new(db),
-    };
-
-    Ok(Arc::new(sys_db))
+    let db = Arc::new(db);
+    Ok(Arc::new(SystemDB::with_columns(db.clone(), db)))
 }
@@ -0,0 +1,254 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! A `KeyValueDB` stored with sled, an embedded database written in Rust,
+//! as an alternative to RocksDB which doesn't need a C++ toolchain.
+//!
+//! The default column is the default tree of sled, and numbered column `i`
+//! is the tree named `col_<i>`.
+
+use super::{DatabaseColumns, SystemDB};
+use kvdb::{DBOp, DBTransaction, DBValue, KeyValueDB};
+use parking_lot::RwLock;
+use sled::{Batch, Db, Tree};
+use std::{io, sync::Arc};
+
+fn other_io_err<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+fn column_name(col: u32) -> String { format!("col_{}", col) }
+
+pub struct SledDB {
+    db: Db,
+    /// The trees of the numbered columns.
+    columns: RwLock<Vec<Tree>>,
+}
+
+impl SledDB {
+    /// Open the database at `path` with at least `columns` numbered columns.
+    /// The columns added since the database was created, e.g. by a schema
+    /// migration, are kept.
+    pub fn open(path: &str, columns: Option<u32>) -> io::Result<Self> {
+        let db = sled::open(path).map_err(other_io_err)?;
+        let mut num_columns = columns.unwrap_or(0);
+        while db
+            .tree_names()
+            .iter()
+            .any(|name| name.as_ref() == column_name(num_columns).as_bytes())
+        {
+            num_columns += 1;
+        }
+        let columns = (0..num_columns)
+            .map(|col| db.open_tree(column_name(col)).map_err(other_io_err))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(SledDB {
+            db,
+            columns: RwLock::new(columns),
+        })
+    }
+
+    fn column(&self, col: Option<u32>) -> io::Result<Tree> {
+        match col {
+            None => Ok((*self.db).clone()),
+            Some(col) => self
+                .columns
+                .read()
+                .get(col as usize)
+                .cloned()
+                .ok_or_else(|| {
+                    other_io_err(format!("No such column family: {:?}", col))
+                }),
+        }
+    }
+
+    fn column_items<'a>(
+        &self, col: Option<u32>, prefix: &'a [u8],
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        let tree = match self.column(col) {
+            Ok(tree) => tree,
+            Err(_) => return Box::new(std::iter::empty()),
+        };
+        Box::new(tree.scan_prefix(prefix).filter_map(|item| match item {
+            Ok((key, value)) => Some((
+                key.to_vec().into_boxed_slice(),
+                value.to_vec().into_boxed_slice(),
+            )),
+            Err(e) => {
+                warn!("Failed to read sled db: {}", e);
+                None
+            }
+        }))
+    }
+
+    /// Apply the operations of `transaction` to each column in one batch.
+    /// Unlike RocksDB, the batches of different columns are not applied
+    /// atomically together.
+    fn apply(&self, transaction: DBTransaction) -> io::Result<()> {
+        let mut batches: Vec<(Option<u32>, Batch)> = Vec::new();
+        for op in transaction.ops {
+            let col = match op {
+                DBOp::Insert { col, .. } | DBOp::Delete { col, .. } => col,
+            };
+            let index = match batches.iter().position(|(c, _)| *c == col) {
+                Some(index) => index,
+                None => {
+                    batches.push((col, Batch::default()));
+                    batches.len() - 1
+                }
+            };
+            let batch = &mut batches[index].1;
+            match op {
+                DBOp::Insert { key, value, .. } => {
+                    batch.insert(&*key, value.to_vec())
+                }
+                DBOp::Delete { key, .. } => batch.remove(&*key),
+            }
+        }
+        let trees = batches
+            .iter()
+            .map(|(col, _)| self.column(*col))
+            .collect::<io::Result<Vec<_>>>()?;
+        for (tree, (_, batch)) in trees.iter().zip(batches.into_iter()) {
+            tree.apply_batch(batch).map_err(other_io_err)?;
+        }
+        Ok(())
+    }
+}
+
+impl KeyValueDB for SledDB {
+    fn get(&self, col: Option<u32>, key: &[u8]) -> io::Result<Option<DBValue>> {
+        let value = self.column(col)?.get(key).map_err(other_io_err)?;
+        Ok(value.map(|value| DBValue::from_slice(&value)))
+    }
+
+    fn get_by_prefix(
+        &self, col: Option<u32>, prefix: &[u8],
+    ) -> Option<Box<[u8]>> {
+        self.column_items(col, prefix)
+            .next()
+            .map(|(_, value)| value)
+    }
+
+    fn write_buffered(&self, transaction: DBTransaction) {
+        if let Err(e) = self.apply(transaction) {
+            warn!("Failed to write sled db: {}", e);
+        }
+    }
+
+    fn write(&self, transaction: DBTransaction) -> io::Result<()> {
+        self.apply(transaction)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.db.flush().map(|_| ()).map_err(other_io_err)
+    }
+
+    fn iter<'a>(
+        &'a self, col: Option<u32>,
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.column_items(col, &[])
+    }
+
+    fn iter_from_prefix<'a>(
+        &'a self, col: Option<u32>, prefix: &'a [u8],
+    ) -> Box<Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.column_items(col, prefix)
+    }
+
+    fn restore(&self, _new_db: &str) -> io::Result<()> {
+        Err(other_io_err("A sled database cannot be restored"))
+    }
+}
+
+impl DatabaseColumns for SledDB {
+    fn num_columns(&self) -> u32 { self.columns.read().len() as u32 }
+
+    fn add_column(&self) -> io::Result<()> {
+        let mut columns = self.columns.write();
+        let tree = self
+            .db
+            .open_tree(column_name(columns.len() as u32))
+            .map_err(other_io_err)?;
+        columns.push(tree);
+        Ok(())
+    }
+
+    fn drop_column(&self) -> io::Result<()> {
+        let mut columns = self.columns.write();
+        if columns.pop().is_some() {
+            self.db
+                .drop_tree(column_name(columns.len() as u32).as_bytes())
+                .map_err(other_io_err)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn open_sled_database(
+    path: &str, columns: Option<u32>,
+) -> io::Result<Arc<SystemDB>> {
+    let db = match SledDB::open(path, columns) {
+        Ok(db) => {
+            info!("Open sled db successfully ({:?})", path);
+            db
+        }
+        Err(e) => {
+            warn!("Failed to open sled db ({:?})", path);
+            return Err(e);
+        }
+    };
+    let db = Arc::new(db);
+    Ok(Arc::new(SystemDB::with_columns(db.clone(), db)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SledDB;
+    use crate::DatabaseColumns;
+    use kvdb::{DBTransaction, KeyValueDB};
+    use std::{env, fs, process};
+
+    #[test]
+    fn test_write_read_and_reopen() {
+        let dir =
+            env::temp_dir().join(format!("sled_db_test_{}", process::id()));
+        let path = dir.to_str().unwrap();
+        {
+            let db = SledDB::open(path, Some(2)).unwrap();
+            let mut transaction = DBTransaction::new();
+            transaction.put(Some(0), b"aa", b"value");
+            transaction.put(Some(0), b"ab", b"value");
+            transaction.put(Some(1), b"aa", b"other");
+            transaction.put(None, b"aa", b"default");
+            db.write(transaction).unwrap();
+            assert!(db.get(Some(2), b"aa").is_err());
+
+            let mut transaction = DBTransaction::new();
+            transaction.delete(Some(0), b"ab");
+            transaction.put(Some(2), b"aa", b"value");
+            assert!(db.write(transaction).is_err());
+
+            db.add_column().unwrap();
+            let mut transaction = DBTransaction::new();
+            transaction.put(Some(2), b"aa", b"new");
+            db.write(transaction).unwrap();
+            db.flush().unwrap();
+        }
+
+        let db = SledDB::open(path, Some(2)).unwrap();
+        assert_eq!(db.num_columns(), 3);
+        assert_eq!(&*db.get(Some(0), b"aa").unwrap().unwrap(), b"value");
+        assert_eq!(&*db.get(Some(1), b"aa").unwrap().unwrap(), b"other");
+        assert_eq!(&*db.get(Some(2), b"aa").unwrap().unwrap(), b"new");
+        assert_eq!(&*db.get(None, b"aa").unwrap().unwrap(), b"default");
+        assert_eq!(db.iter_from_prefix(Some(0), b"a").count(), 2);
+
+        db.drop_column().unwrap();
+        assert_eq!(db.num_columns(), 2);
+        assert!(db.get(Some(2), b"aa").is_err());
+        drop(db);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
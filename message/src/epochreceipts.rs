@@ -0,0 +1,57 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{Message, MsgId, RequestId};
+use primitives::receipt::Receipt;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::ops::{Deref, DerefMut};
+
+/// The receipts of each block of the epoch, in execution order.
+#[derive(Debug, PartialEq, Default)]
+pub struct GetEpochReceiptsResponse {
+    pub request_id: RequestId,
+    pub receipts: Vec<Vec<Receipt>>,
+}
+
+impl Message for GetEpochReceiptsResponse {
+    fn msg_id(&self) -> MsgId { MsgId::GET_EPOCH_RECEIPTS_RESPONSE }
+
+    fn is_size_sensitive(&self) -> bool { self.receipts.len() > 0 }
+}
+
+impl Deref for GetEpochReceiptsResponse {
+    type Target = RequestId;
+
+    fn deref(&self) -> &Self::Target { &self.request_id }
+}
+
+impl DerefMut for GetEpochReceiptsResponse {
+    fn deref_mut(&mut self) -> &mut RequestId { &mut self.request_id }
+}
+
+impl Encodable for GetEpochReceiptsResponse {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream
+            .begin_list(2)
+            .append(&self.request_id)
+            .begin_list(self.receipts.len());
+        for block_receipts in &self.receipts {
+            stream.append_list(block_receipts);
+        }
+    }
+}
+
+impl Decodable for GetEpochReceiptsResponse {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let receipts = rlp
+            .at(1)?
+            .iter()
+            .map(|block_receipts| block_receipts.as_list())
+            .collect::<Result<_, _>>()?;
+        Ok(GetEpochReceiptsResponse {
+            request_id: rlp.val_at(0)?,
+            receipts,
+        })
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{Message, MsgId, RequestId};
+use cfx_types::H256;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::ops::{Deref, DerefMut};
+
+/// Request the receipts of the epoch committed to by the
+/// `deferred_receipts_root` of the block `block_hash`.
+#[derive(Debug, PartialEq)]
+pub struct GetEpochReceipts {
+    pub request_id: RequestId,
+    pub block_hash: H256,
+}
+
+impl Message for GetEpochReceipts {
+    fn msg_id(&self) -> MsgId { MsgId::GET_EPOCH_RECEIPTS }
+}
+
+impl Deref for GetEpochReceipts {
+    type Target = RequestId;
+
+    fn deref(&self) -> &Self::Target { &self.request_id }
+}
+
+impl DerefMut for GetEpochReceipts {
+    fn deref_mut(&mut self) -> &mut RequestId { &mut self.request_id }
+}
+
+impl Encodable for GetEpochReceipts {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream
+            .begin_list(2)
+            .append(&self.request_id)
+            .append(&self.block_hash);
+    }
+}
+
+impl Decodable for GetEpochReceipts {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 2 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        Ok(GetEpochReceipts {
+            request_id: rlp.val_at(0)?,
+            block_hash: rlp.val_at(1)?,
+        })
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{Message, MsgId, RequestId};
+use cfx_types::H256;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::ops::{Deref, DerefMut};
+
+/// Request the proofs of `keys` against the `deferred_state_root` of the
+/// block `block_hash`.
+#[derive(Debug, PartialEq)]
+pub struct GetStateProof {
+    pub request_id: RequestId,
+    pub block_hash: H256,
+    pub keys: Vec<Vec<u8>>,
+}
+
+impl Message for GetStateProof {
+    fn msg_id(&self) -> MsgId { MsgId::GET_STATE_PROOF }
+}
+
+impl Deref for GetStateProof {
+    type Target = RequestId;
+
+    fn deref(&self) -> &Self::Target { &self.request_id }
+}
+
+impl DerefMut for GetStateProof {
+    fn deref_mut(&mut self) -> &mut RequestId { &mut self.request_id }
+}
+
+impl Encodable for GetStateProof {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream
+            .begin_list(3)
+            .append(&self.request_id)
+            .append(&self.block_hash)
+            .begin_list(self.keys.len());
+        for key in &self.keys {
+            stream.append(key);
+        }
+    }
+}
+
+impl Decodable for GetStateProof {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 3 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        Ok(GetStateProof {
+            request_id: rlp.val_at(0)?,
+            block_hash: rlp.val_at(1)?,
+            keys: rlp.list_at(2)?,
+        })
+    }
+}
@@ -13,6 +13,7 @@ mod blockheaders;
 mod blocks;
 mod blocktxn;
 mod cmpctblocks;
+mod epochreceipts;
 mod getblockbodies;
 mod getblockhashes;
 mod getblockhashesbyepoch;
@@ -20,10 +21,13 @@ mod getblockheaders;
 mod getblocks;
 mod getblocktxn;
 mod getcmpctblocks;
+mod getepochreceipts;
+mod getstateproof;
 mod getterminalblockhashes;
 mod message;
 mod newblock;
 mod newblockhashes;
+mod stateproof;
 mod status;
 mod terminalblockhashes;
 mod transactions;
@@ -35,6 +39,7 @@ pub use crate::{
     blocks::{GetBlocksResponse, GetBlocksWithPublicResponse},
     blocktxn::GetBlockTxnResponse,
     cmpctblocks::GetCompactBlocksResponse,
+    epochreceipts::GetEpochReceiptsResponse,
     getblockbodies::GetBlockBodies,
     getblockhashes::GetBlockHashes,
     getblockhashesbyepoch::GetBlockHashesByEpoch,
@@ -42,10 +47,13 @@ pub use crate::{
     getblocks::GetBlocks,
     getblocktxn::GetBlockTxn,
     getcmpctblocks::GetCompactBlocks,
+    getepochreceipts::GetEpochReceipts,
+    getstateproof::GetStateProof,
     getterminalblockhashes::GetTerminalBlockHashes,
     message::{Message, MsgId, RequestId},
     newblock::NewBlock,
     newblockhashes::NewBlockHashes,
+    stateproof::GetStateProofResponse,
     status::Status,
     terminalblockhashes::GetTerminalBlockHashesResponse,
     transactions::{
@@ -43,6 +43,33 @@ build_msgid! {
     GET_TRANSACTIONS = 0x15
     GET_TRANSACTIONS_RESPONSE = 0x16
     GET_BLOCK_HASHES_BY_EPOCH = 0x17
+    GET_STATE_PROOF = 0x18
+    GET_STATE_PROOF_RESPONSE = 0x19
+    GET_EPOCH_RECEIPTS = 0x1a
+    GET_EPOCH_RECEIPTS_RESPONSE = 0x1b
+}
+
+/// Message ids which are not valid in every protocol version, as
+/// `(id, introduced in version, deprecated in version)`. A deprecated id must
+/// not be reused for another message while peers may still speak a version
+/// that accepts it.
+const MSG_ID_VERSIONS: &[(MsgIdInner, u8, Option<u8>)] = &[
+    // Never answered, peers use `GET_BLOCK_HASHES_BY_EPOCH` instead.
+    (MsgId::GET_BLOCK_HASHES.0, 1, Some(2)),
+];
+
+impl MsgId {
+    /// Whether the message may be exchanged with a peer that negotiated
+    /// protocol `version`.
+    pub fn is_valid_in(&self, version: u8) -> bool {
+        match MSG_ID_VERSIONS.iter().find(|(id, _, _)| *id == self.0) {
+            Some(&(_, introduced, deprecated)) => {
+                version >= introduced
+                    && deprecated.map_or(true, |d| version < d)
+            }
+            None => true,
+        }
+    }
 }
 
 impl From<u8> for MsgId {
@@ -0,0 +1,47 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{Message, MsgId, RequestId};
+use primitives::TrieProof;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::ops::{Deref, DerefMut};
+
+/// One proof for each requested key, in the order of the request.
+#[derive(Debug, PartialEq, Default)]
+pub struct GetStateProofResponse {
+    pub request_id: RequestId,
+    pub proofs: Vec<TrieProof>,
+}
+
+impl Message for GetStateProofResponse {
+    fn msg_id(&self) -> MsgId { MsgId::GET_STATE_PROOF_RESPONSE }
+}
+
+impl Deref for GetStateProofResponse {
+    type Target = RequestId;
+
+    fn deref(&self) -> &Self::Target { &self.request_id }
+}
+
+impl DerefMut for GetStateProofResponse {
+    fn deref_mut(&mut self) -> &mut RequestId { &mut self.request_id }
+}
+
+impl Encodable for GetStateProofResponse {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream
+            .begin_list(2)
+            .append(&self.request_id)
+            .append_list(&self.proofs);
+    }
+}
+
+impl Decodable for GetStateProofResponse {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        Ok(GetStateProofResponse {
+            request_id: rlp.val_at(0)?,
+            proofs: rlp.list_at(1)?,
+        })
+    }
+}
@@ -7,14 +7,14 @@ use cfx_types::H256;
 use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 
 #[allow(dead_code)]
-pub const MAINNET_ID: u8 = 0x0;
+pub const MAINNET_ID: u64 = 0x0;
 #[allow(dead_code)]
-pub const TESTNET_ID: u8 = 0x1;
+pub const TESTNET_ID: u64 = 0x1;
 
 #[derive(Debug, PartialEq)]
 pub struct Status {
     pub protocol_version: u8,
-    pub network_id: u8,
+    pub network_id: u64,
     pub genesis_hash: H256,
     pub best_epoch: u64,
     pub terminal_block_hashes: Vec<H256>,
@@ -40,7 +40,7 @@ impl Decodable for Status {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
         Ok(Status {
             protocol_version: rlp.val_at::<u8>(0)?,
-            network_id: rlp.val_at::<u8>(1)?,
+            network_id: rlp.val_at::<u64>(1)?,
             genesis_hash: rlp.val_at::<H256>(2)?,
             best_epoch: rlp.val_at::<u64>(3)?,
             terminal_block_hashes: rlp.list_at(4)?,
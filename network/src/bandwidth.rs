@@ -0,0 +1,239 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::ProtocolId;
+use lazy_static::lazy_static;
+use metrics::CounterVec;
+use std::{
+    collections::BTreeMap,
+    str,
+    time::{Duration, Instant},
+};
+
+lazy_static! {
+    static ref INGRESS_MSG_BYTES: CounterVec = CounterVec::register(
+        "network_ingress_msg_bytes",
+        &["protocol", "msg_id"]
+    );
+    static ref INGRESS_MSG_PACKETS: CounterVec = CounterVec::register(
+        "network_ingress_msg_packets",
+        &["protocol", "msg_id"]
+    );
+    static ref EGRESS_MSG_BYTES: CounterVec = CounterVec::register(
+        "network_egress_msg_bytes",
+        &["protocol", "msg_id"]
+    );
+    static ref EGRESS_MSG_PACKETS: CounterVec = CounterVec::register(
+        "network_egress_msg_packets",
+        &["protocol", "msg_id"]
+    );
+}
+
+/// Bytes and packets transferred in one direction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TrafficStats {
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+impl TrafficStats {
+    pub fn record(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+        self.packets += 1;
+    }
+}
+
+/// Traffic of one message type, identified by the first byte of the user
+/// packets of a protocol.
+#[derive(Clone, Debug, Serialize)]
+pub struct MessageTrafficStats {
+    pub protocol: String,
+    pub msg_id: u8,
+    pub ingress: TrafficStats,
+    pub egress: TrafficStats,
+}
+
+/// Traffic with a peer. `ingress` and `egress` count the bytes on the wire,
+/// while `messages` counts the user packets before compression.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PeerTrafficStats {
+    pub ingress: TrafficStats,
+    pub egress: TrafficStats,
+    pub messages: Vec<MessageTrafficStats>,
+}
+
+/// Per message type traffic of a session.
+#[derive(Default)]
+pub struct MessageTraffic {
+    stats: BTreeMap<(ProtocolId, u8), (TrafficStats, TrafficStats)>,
+}
+
+impl MessageTraffic {
+    pub fn on_ingress(&mut self, protocol: ProtocolId, data: &[u8]) {
+        if let Some(msg_id) = data.first() {
+            self.stats
+                .entry((protocol, *msg_id))
+                .or_default()
+                .0
+                .record(data.len());
+            let labels = [protocol_name(&protocol), &msg_id.to_string()];
+            INGRESS_MSG_BYTES.inc_by(&labels, data.len() as i64);
+            INGRESS_MSG_PACKETS.inc_by(&labels, 1);
+        }
+    }
+
+    pub fn on_egress(&mut self, protocol: ProtocolId, data: &[u8]) {
+        if let Some(msg_id) = data.first() {
+            self.stats
+                .entry((protocol, *msg_id))
+                .or_default()
+                .1
+                .record(data.len());
+            let labels = [protocol_name(&protocol), &msg_id.to_string()];
+            EGRESS_MSG_BYTES.inc_by(&labels, data.len() as i64);
+            EGRESS_MSG_PACKETS.inc_by(&labels, 1);
+        }
+    }
+
+    pub fn stats(&self) -> Vec<MessageTrafficStats> {
+        self.stats
+            .iter()
+            .map(|((protocol, msg_id), (ingress, egress))| {
+                MessageTrafficStats {
+                    protocol: protocol_name(protocol).into(),
+                    msg_id: *msg_id,
+                    ingress: *ingress,
+                    egress: *egress,
+                }
+            })
+            .collect()
+    }
+}
+
+fn protocol_name(protocol: &ProtocolId) -> &str {
+    str::from_utf8(&protocol[..]).unwrap_or("???")
+}
+
+/// Token bucket limiting the upload rate to a peer, which allows bursts of up
+/// to one second of traffic. Sending is allowed as long as the bucket is not
+/// in debt, so that packets larger than the burst size still go through at
+/// the limited rate on average. The time is passed in by the caller.
+pub struct RateLimiter {
+    /// Bytes per second.
+    rate: usize,
+    /// The allowance in bytes multiplied by `NANOS_PER_SEC`, so that the
+    /// refill is exact.
+    tokens: i64,
+    last_refill: Instant,
+}
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+impl RateLimiter {
+    pub fn new(rate: usize, now: Instant) -> Self {
+        RateLimiter {
+            rate,
+            tokens: Self::capacity(rate),
+            last_refill: now,
+        }
+    }
+
+    fn capacity(rate: usize) -> i64 {
+        (rate as i64).saturating_mul(NANOS_PER_SEC)
+    }
+
+    pub fn rate(&self) -> usize { self.rate }
+
+    /// How long to wait from `now` until sending is allowed again, which is
+    /// zero if it is allowed now.
+    pub fn delay(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 0 || self.rate == 0 {
+            return Duration::from_secs(0);
+        }
+        let rate = self.rate as i64;
+        Duration::from_nanos(((-self.tokens + rate - 1) / rate) as u64)
+    }
+
+    /// Counts `size` bytes sent at `now` towards the limit.
+    pub fn consume(&mut self, size: usize, now: Instant) {
+        self.refill(now);
+        self.tokens = self
+            .tokens
+            .saturating_sub((size as i64).saturating_mul(NANOS_PER_SEC));
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now <= self.last_refill {
+            return;
+        }
+        let elapsed = now - self.last_refill;
+        self.last_refill = now;
+        let capacity = Self::capacity(self.rate);
+        let refill = (elapsed.as_secs() as i64)
+            .saturating_mul(capacity)
+            .saturating_add(
+                (elapsed.subsec_nanos() as i64)
+                    .saturating_mul(self.rate as i64),
+            );
+        self.tokens = self.tokens.saturating_add(refill).min(capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_traffic() {
+        let mut traffic = MessageTraffic::default();
+        traffic.on_ingress(*b"cfx", &[1, 0, 0]);
+        traffic.on_ingress(*b"cfx", &[1, 0]);
+        traffic.on_egress(*b"cfx", &[2, 0, 0, 0]);
+        traffic.on_egress(*b"cfx", &[]);
+
+        let stats = traffic.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].protocol, "cfx");
+        assert_eq!(stats[0].msg_id, 1);
+        assert_eq!(
+            stats[0].ingress,
+            TrafficStats {
+                bytes: 5,
+                packets: 2
+            }
+        );
+        assert_eq!(stats[0].egress, TrafficStats::default());
+        assert_eq!(stats[1].msg_id, 2);
+        assert_eq!(
+            stats[1].egress,
+            TrafficStats {
+                bytes: 4,
+                packets: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(1000, start);
+        limiter.consume(600, start);
+        assert_eq!(limiter.delay(start), Duration::from_secs(0));
+        // The bucket is not in debt before this packet, so it is sent in
+        // full, and the next one waits until the debt is paid off.
+        limiter.consume(600, start);
+        assert_eq!(limiter.delay(start), Duration::from_millis(200));
+
+        let later = start + Duration::from_millis(150);
+        assert_eq!(limiter.delay(later), Duration::from_millis(50));
+        let later = start + Duration::from_millis(200);
+        assert_eq!(limiter.delay(later), Duration::from_secs(0));
+
+        // The bucket holds at most one second of traffic.
+        let later = start + Duration::from_secs(10);
+        limiter.consume(1500, later);
+        assert_eq!(limiter.delay(later), Duration::from_millis(500));
+    }
+}
@@ -3,15 +3,19 @@
 // See http://www.gnu.org/licenses/
 
 use crate::{
+    bandwidth::{RateLimiter, TrafficStats},
     io::{IoContext, StreamToken},
     throttling::THROTTLING_SERVICE,
 };
 use bytes::Bytes;
+use lazy_static::lazy_static;
+use metrics::{Counter, Meter};
 use mio::{deprecated::*, tcp::*, *};
 use std::{
     io::{self, Read, Write},
     marker::PhantomData,
     sync::atomic::{AtomicBool, Ordering as AtomicOrdering},
+    time::{Duration, Instant},
 };
 
 use priority_send_queue::{PrioritySendQueue, SendQueuePriority};
@@ -23,11 +27,25 @@ use std::sync::atomic::AtomicUsize;
 pub enum WriteStatus {
     Ongoing,
     LowPriority,
+    /// The upload rate limit is reached, and writing can resume after the
+    /// delay.
+    RateLimited(Duration),
     Complete,
 }
 
 pub const MAX_PAYLOAD_SIZE: usize = (1 << 24) - 1;
 
+lazy_static! {
+    static ref EGRESS_ENQUEUE_METER: Meter =
+        Meter::register("network_egress_enqueue_bytes");
+    static ref EGRESS_SENT_METER: Meter =
+        Meter::register("network_egress_sent_bytes");
+    static ref EGRESS_REJECTED_COUNTER: Counter =
+        Counter::register("network_egress_rejected_packets");
+    static ref INGRESS_RECV_METER: Meter =
+        Meter::register("network_ingress_recv_bytes");
+}
+
 static HIGH_PRIORITY_PACKETS: AtomicUsize = AtomicUsize::new(0);
 
 fn incr_high_priority_packets() {
@@ -69,6 +87,10 @@ pub struct GenericConnection<Socket: GenericSocket, Sizer: PacketSizer> {
     send_queue: PrioritySendQueue<(Vec<u8>, usize)>,
     interest: Ready,
     registered: AtomicBool,
+    /// Bytes and packets received and sent on the socket.
+    ingress: TrafficStats,
+    egress: TrafficStats,
+    upload_limiter: Option<RateLimiter>,
     phantom: PhantomData<Sizer>,
 }
 
@@ -105,6 +127,8 @@ impl<Socket: GenericSocket, Sizer: PacketSizer>
                     if size == 0 {
                         break;
                     }
+                    INGRESS_RECV_METER.mark(size);
+                    self.ingress.bytes += size as u64;
                     self.recv_buf.extend_from_slice(&buf[0..size]);
                 }
                 Err(e) => {
@@ -122,11 +146,12 @@ impl<Socket: GenericSocket, Sizer: PacketSizer>
             Ok(None)
         } else {
             trace!("Packet received, token = {}, size = {}", self.token, size);
+            self.ingress.packets += 1;
             Ok(Some(self.recv_buf.split_to(size)))
         }
     }
 
-    fn write(&mut self) -> Result<WriteStatus, Error> {
+    fn write(&mut self, now: Instant) -> Result<WriteStatus, Error> {
         if self.send_queue.is_send_queue_empty(SendQueuePriority::High)
             && has_high_priority_packets()
         {
@@ -137,6 +162,29 @@ impl<Socket: GenericSocket, Sizer: PacketSizer>
             return Ok(WriteStatus::LowPriority);
         }
 
+        // High priority packets, e.g. handshakes and pings, and the rest of a
+        // partially sent packet are never held back, but still count towards
+        // the upload rate limit.
+        let partially_sent = match self.send_queue.front() {
+            Some((_, pos)) => *pos > 0,
+            None => false,
+        };
+        if let Some(ref mut limiter) = self.upload_limiter {
+            if self.send_queue.is_send_queue_empty(SendQueuePriority::High)
+                && !self.send_queue.is_empty()
+                && !partially_sent
+            {
+                let delay = limiter.delay(now);
+                if delay > Duration::from_secs(0) {
+                    trace!(
+                        "Upload rate limit reached, token = {}, rate = {}, delay = {:?}",
+                        self.token, limiter.rate(), delay
+                    );
+                    return Ok(WriteStatus::RateLimited(delay));
+                }
+            }
+        }
+
         let buf = match self.send_queue.front_mut() {
             Some((buf, promoted)) => {
                 if promoted {
@@ -165,12 +213,18 @@ impl<Socket: GenericSocket, Sizer: PacketSizer>
             size
         );
         THROTTLING_SERVICE.write().on_dequeue(size);
+        EGRESS_SENT_METER.mark(size);
+        self.egress.bytes += size as u64;
+        if let Some(ref mut limiter) = self.upload_limiter {
+            limiter.consume(size, now);
+        }
 
         if pos + size < len {
             buf.1 += size;
             Ok(WriteStatus::Ongoing)
         } else {
             trace!("Packet sent, token = {}, size = {}", self.token, len);
+            self.egress.packets += 1;
             decr_high_priority_packets();
             Ok(WriteStatus::Complete)
         }
@@ -179,14 +233,28 @@ impl<Socket: GenericSocket, Sizer: PacketSizer>
     pub fn writable<Message: Sync + Send + Clone + 'static>(
         &mut self, io: &IoContext<Message>,
     ) -> Result<WriteStatus, Error> {
-        let status = self.write();
+        self.writable_at(io, Instant::now())
+    }
+
+    fn writable_at<Message: Sync + Send + Clone + 'static>(
+        &mut self, io: &IoContext<Message>, now: Instant,
+    ) -> Result<WriteStatus, Error> {
+        let status = self.write(now);
 
         if let Ok(WriteStatus::Complete) = status {
             self.send_queue.pop_front();
         }
 
-        if self.send_queue.is_empty() {
+        // While rate limited, the caller resumes writing after the delay
+        // instead of polling the socket.
+        let rate_limited = match status {
+            Ok(WriteStatus::RateLimited(_)) => true,
+            _ => false,
+        };
+        if self.send_queue.is_empty() || rate_limited {
             self.interest.remove(Ready::writable());
+        } else {
+            self.interest.insert(Ready::writable());
         }
 
         io.update_registration(self.token)?;
@@ -205,7 +273,11 @@ impl<Socket: GenericSocket, Sizer: PacketSizer>
                 self.token,
                 data.len()
             );
-            THROTTLING_SERVICE.write().on_enqueue(data.len())?;
+            if let Err(e) = THROTTLING_SERVICE.write().on_enqueue(data.len()) {
+                EGRESS_REJECTED_COUNTER.inc();
+                return Err(e);
+            }
+            EGRESS_ENQUEUE_METER.mark(data.len());
             let message = data.to_vec();
             self.send_queue.push_back((message, 0), priority);
             if priority == SendQueuePriority::High {
@@ -223,6 +295,17 @@ impl<Socket: GenericSocket, Sizer: PacketSizer>
     }
 
     pub fn is_sending(&self) -> bool { self.interest.is_writable() }
+
+    pub fn ingress(&self) -> TrafficStats { self.ingress }
+
+    pub fn egress(&self) -> TrafficStats { self.egress }
+
+    /// Limit the upload rate in bytes per second, or lift the limit if `None`.
+    /// Packets exceeding the limit are queued until the rate allows them.
+    pub fn set_upload_rate_limit(&mut self, rate: Option<usize>) {
+        let now = Instant::now();
+        self.upload_limiter = rate.map(|rate| RateLimiter::new(rate, now));
+    }
 }
 
 pub type Connection<Sizer> = GenericConnection<TcpStream, Sizer>;
@@ -236,6 +319,9 @@ impl<Sizer: PacketSizer> Connection<Sizer> {
             send_queue: PrioritySendQueue::new(),
             interest: Ready::hup() | Ready::readable(),
             registered: AtomicBool::new(false),
+            ingress: TrafficStats::default(),
+            egress: TrafficStats::default(),
+            upload_limiter: None,
             phantom: PhantomData,
         }
     }
@@ -405,6 +491,9 @@ mod tests {
                 recv_buf: Bytes::new(),
                 interest: Ready::hup() | Ready::readable(),
                 registered: AtomicBool::new(false),
+                ingress: TrafficStats::default(),
+                egress: TrafficStats::default(),
+                upload_limiter: None,
                 phantom: PhantomData,
             }
         }
@@ -461,5 +550,82 @@ mod tests {
             assert!(status.is_ok());
             assert!(status.unwrap().is_none());
         }
+
+        assert_eq!(
+            connection.ingress(),
+            TrafficStats {
+                bytes: 3,
+                packets: 1
+            }
+        );
+    }
+
+    #[test]
+    fn connection_send_rate_limited() {
+        let start = Instant::now();
+        let mut connection = TestConnection::new();
+        connection.upload_limiter = Some(RateLimiter::new(100, start));
+
+        let data = vec![0u8; 150];
+        for _ in 0..2 {
+            assert!(connection
+                .send(&test_io(), &data, SendQueuePriority::Normal)
+                .is_ok());
+        }
+        assert_eq!(2, connection.send_queue.len());
+
+        // The first packet is sent in full, and the second one is queued
+        // until the debt is paid off.
+        let status = connection.writable_at(&test_io(), start).unwrap();
+        assert!(status == WriteStatus::Complete);
+        let delay = Duration::from_millis(500);
+        let status = connection.writable_at(&test_io(), start).unwrap();
+        assert!(status == WriteStatus::RateLimited(delay));
+        assert!(!connection.is_sending());
+        assert_eq!(1, connection.send_queue.len());
+
+        // High priority packets are sent regardless of the limit.
+        assert!(connection
+            .send(&test_io(), &data, SendQueuePriority::High)
+            .is_ok());
+        let status = connection.writable_at(&test_io(), start).unwrap();
+        assert!(status == WriteStatus::Complete);
+        assert_eq!(1, connection.send_queue.len());
+
+        let later = start + Duration::from_millis(1500);
+        let status = connection.writable_at(&test_io(), later).unwrap();
+        assert!(status == WriteStatus::RateLimited(delay));
+        let later = start + Duration::from_millis(2000);
+        let status = connection.writable_at(&test_io(), later).unwrap();
+        assert!(status == WriteStatus::Complete);
+        assert!(connection.send_queue.is_empty());
+        assert_eq!(connection.socket.write_buf.len(), 450);
+    }
+
+    #[test]
+    fn connection_send_rest_of_packet_when_rate_limited() {
+        let start = Instant::now();
+        let mut connection = TestConnection::new();
+        connection.socket = TestSocket::with_buf(100);
+        connection.upload_limiter = Some(RateLimiter::new(50, start));
+
+        let data = vec![0u8; 150];
+        for _ in 0..2 {
+            assert!(connection
+                .send(&test_io(), &data, SendQueuePriority::Normal)
+                .is_ok());
+        }
+
+        // The limit is exceeded while the first packet is half sent, but its
+        // rest is still sent.
+        let status = connection.writable_at(&test_io(), start).unwrap();
+        assert!(status == WriteStatus::Ongoing);
+        let status = connection.writable_at(&test_io(), start).unwrap();
+        assert!(status == WriteStatus::Complete);
+        assert_eq!(connection.socket.write_buf.len(), 150);
+
+        let status = connection.writable_at(&test_io(), start).unwrap();
+        assert!(status == WriteStatus::RateLimited(Duration::from_secs(2)));
+        assert_eq!(1, connection.send_queue.len());
     }
 }
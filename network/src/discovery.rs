@@ -4,9 +4,10 @@
 
 use crate::{
     hash::keccak,
+    node_record::NodeRecord,
     node_table::{NodeId, *},
     service::{UdpIoContext, MAX_DATAGRAM_SIZE, UDP_PROTOCOL_DISCOVERY},
-    Error, ErrorKind, IpFilter,
+    Capability, Error, ErrorKind, IpFilter,
 };
 use cfx_bytes::Bytes;
 use cfx_types::{H256, H520};
@@ -18,7 +19,10 @@ use std::{
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-const DISCOVER_PROTOCOL_VERSION: u32 = 1;
+/// Version 2 appends the `seq` of the node record of the sender to `Ping` and
+/// `Pong`, and adds the packets to fetch node records. Version 1 nodes ignore
+/// the extra item.
+const DISCOVER_PROTOCOL_VERSION: u32 = 2;
 
 const DISCOVERY_MAX_STEPS: u16 = 4; // Max iterations of discovery. (discover)
 
@@ -26,10 +30,17 @@ const PACKET_PING: u8 = 1;
 const PACKET_PONG: u8 = 2;
 const PACKET_FIND_NODE: u8 = 3;
 const PACKET_NEIGHBOURS: u8 = 4;
+const PACKET_RECORD_REQUEST: u8 = 5;
+const PACKET_RECORD: u8 = 6;
 
 const PING_TIMEOUT: Duration = Duration::from_millis(500);
 const FIND_NODE_TIMEOUT: Duration = Duration::from_secs(2);
+const RECORD_REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
 const EXPIRY_TIME: Duration = Duration::from_secs(20);
+/// How long a node is bonded after it answers a `Ping` from its endpoint.
+const BOND_EXPIRATION: Duration = Duration::from_secs(60 * 60);
+/// The maximum number of node records kept.
+const MAX_RECORDS: usize = 4096;
 
 pub const DISCOVER_NODES_COUNT: u32 = 16;
 const MAX_NODES_PING: usize = 32; // Max nodes to add/ping at once
@@ -52,6 +63,29 @@ struct FindNodeRequest {
     answered: bool,
 }
 
+struct RecordRequest {
+    // Time when the request was sent
+    sent_at: Instant,
+    // The node to which the request was sent
+    node: NodeEntry,
+    // The hash sent in the RecordRequest request
+    request_hash: H256,
+}
+
+struct Bond {
+    // The address from which the node answered
+    address: SocketAddr,
+    // Time when the Pong was received
+    bonded_at: Instant,
+}
+
+struct PendingRecordRequest {
+    // The address from which the request was received
+    address: SocketAddr,
+    // The hash of the RecordRequest packet
+    request_hash: H256,
+}
+
 #[allow(dead_code)]
 pub struct Discovery {
     id: NodeId,
@@ -66,13 +100,35 @@ pub struct Discovery {
     check_timestamps: bool,
     adding_nodes: Vec<NodeEntry>,
     ip_filter: IpFilter,
+    /// The record of the local node.
+    record: NodeRecord,
+    /// The latest records received from the nodes in the node table.
+    records: HashMap<NodeId, NodeRecord>,
+    in_flight_records: HashMap<NodeId, RecordRequest>,
+    /// The nodes which recently answered a `Ping`, and so are known to own
+    /// their endpoints.
+    bonds: HashMap<NodeId, Bond>,
+    /// Record requests from nodes without a bond, which are answered once
+    /// the nodes answer a `Ping`.
+    pending_record_requests: HashMap<NodeId, PendingRecordRequest>,
 }
 
 impl Discovery {
     pub fn new(
         key: &KeyPair, public: NodeEndpoint, ip_filter: IpFilter,
-    ) -> Discovery {
-        Discovery {
+        network_id: u64, chain_id: u64,
+    ) -> Result<Discovery, Error>
+    {
+        let record = NodeRecord::new(
+            key.secret(),
+            key.public().clone(),
+            0,
+            network_id,
+            chain_id,
+            Vec::new(),
+            public.clone(),
+        )?;
+        Ok(Discovery {
             id: key.public().clone(),
             id_hash: keccak(key.public()),
             secret: key.secret().clone(),
@@ -85,11 +141,61 @@ impl Discovery {
             check_timestamps: true,
             adding_nodes: Vec::new(),
             ip_filter,
+            record,
+            records: HashMap::new(),
+            in_flight_records: HashMap::new(),
+            bonds: HashMap::new(),
+            pending_record_requests: HashMap::new(),
+        })
+    }
+
+    /// Sets the endpoint advertised in pings and in the node record, e.g.
+    /// after the NAT port mapping changed.
+    pub fn set_public_endpoint(&mut self, endpoint: NodeEndpoint) {
+        self.public_endpoint = endpoint.clone();
+        self.update_record(|record| record.endpoint = endpoint);
+    }
+
+    /// Sets the protocols advertised in the node record.
+    pub fn set_capabilities(&mut self, capabilities: Vec<Capability>) {
+        self.update_record(|record| record.capabilities = capabilities);
+    }
+
+    fn update_record<F>(&mut self, update: F)
+    where F: FnOnce(&mut NodeRecord) {
+        if let Err(e) = self.record.update(&self.secret, update) {
+            warn!("Error signing node record: {:?}", e);
         }
     }
 
+    pub fn set_ip_filter(&mut self, ip_filter: IpFilter) {
+        self.ip_filter = ip_filter;
+    }
+
     fn is_allowed(&self, entry: &NodeEntry) -> bool {
-        entry.endpoint.is_allowed(&self.ip_filter) && entry.id != self.id
+        entry.endpoint.is_allowed(&self.ip_filter)
+            && entry.id != self.id
+            && self.is_compatible(&entry.id)
+    }
+
+    /// Nodes are assumed to be compatible until their records tell otherwise.
+    fn is_compatible(&self, id: &NodeId) -> bool {
+        self.records
+            .get(id)
+            .map_or(true, |record| record.is_compatible_with(&self.record))
+    }
+
+    /// Whether `seq` is newer than the known record of the node.
+    fn is_record_outdated(&self, id: &NodeId, seq: u64) -> bool {
+        self.records.get(id).map_or(true, |record| record.seq < seq)
+    }
+
+    /// Whether the node recently answered a `Ping` from `address`.
+    fn is_bonded(&self, id: &NodeId, address: &SocketAddr) -> bool {
+        self.bonds.get(id).map_or(false, |bond| {
+            bond.address == *address
+                && bond.bonded_at.elapsed() < BOND_EXPIRATION
+        })
     }
 
     pub fn try_ping_nodes(
@@ -107,6 +213,7 @@ impl Discovery {
         }
         if self.in_flight_pings.contains_key(&node.id)
             || self.in_flight_find_nodes.contains_key(&node.id)
+            || self.in_flight_records.contains_key(&node.id)
         {
             trace!("Node {:?} in flight requests", node);
             return;
@@ -128,11 +235,12 @@ impl Discovery {
     fn ping(
         &mut self, uio: &UdpIoContext, node: &NodeEntry,
     ) -> Result<(), Error> {
-        let mut rlp = RlpStream::new_list(4);
+        let mut rlp = RlpStream::new_list(5);
         rlp.append(&DISCOVER_PROTOCOL_VERSION);
         self.public_endpoint.to_rlp_list(&mut rlp);
         node.endpoint.to_rlp_list(&mut rlp);
         append_expiration(&mut rlp);
+        rlp.append(&self.record.seq);
         let hash = self.send_packet(
             uio,
             PACKET_PING,
@@ -196,6 +304,10 @@ impl Discovery {
             PACKET_PONG => self.on_pong(uio, &rlp, &node_id, &from),
             PACKET_FIND_NODE => self.on_find_node(uio, &rlp, &node_id, &from),
             PACKET_NEIGHBOURS => self.on_neighbours(uio, &rlp, &node_id, &from),
+            PACKET_RECORD_REQUEST => {
+                self.on_record_request(uio, &rlp, &node_id, &from, &hash_signed)
+            }
+            PACKET_RECORD => self.on_record(uio, &rlp, &node_id, &from),
             _ => {
                 debug!("Unknown UDP packet: {}", packet_id);
                 Ok(())
@@ -227,8 +339,9 @@ impl Discovery {
         let ping_to = NodeEndpoint::from_rlp(&rlp.at(2)?)?;
         let timestamp: u64 = rlp.val_at(3)?;
         self.check_timestamp(timestamp)?;
+        let seq = record_seq_at(rlp, 4)?;
 
-        let mut response = RlpStream::new_list(3);
+        let mut response = RlpStream::new_list(4);
         let pong_to = NodeEndpoint {
             address: from.clone(),
             udp_port: ping_from.udp_port,
@@ -244,6 +357,7 @@ impl Discovery {
 
         response.append(&echo_hash);
         append_expiration(&mut response);
+        response.append(&self.record.seq);
         self.send_packet(uio, PACKET_PONG, from, &response.drain())?;
 
         let entry = NodeEntry {
@@ -256,7 +370,13 @@ impl Discovery {
         } else if !self.is_allowed(&entry) {
             debug!("Address not allowed: {:?}", entry);
         } else {
-            uio.node_db.write().insert(entry);
+            uio.node_db.write().insert(entry.clone());
+            // The node may have changed its address or protocols.
+            if let Some(seq) = seq {
+                if self.is_record_outdated(&entry.id, seq) {
+                    self.request_record(uio, entry)?;
+                }
+            }
         }
         Ok(())
     }
@@ -271,6 +391,7 @@ impl Discovery {
         let echo_hash: H256 = rlp.val_at(1)?;
         let timestamp: u64 = rlp.val_at(2)?;
         self.check_timestamp(timestamp)?;
+        let seq = record_seq_at(rlp, 3)?;
 
         let expected_node = match self.in_flight_pings.entry(*node_id) {
             Entry::Occupied(entry) => {
@@ -293,7 +414,33 @@ impl Discovery {
         };
 
         if let Some(node) = expected_node {
-            uio.node_db.write().insert_with_promotion(node);
+            self.bonds.insert(
+                *node_id,
+                Bond {
+                    address: *from,
+                    bonded_at: Instant::now(),
+                },
+            );
+            if let Some(pending) = self.pending_record_requests.remove(node_id)
+            {
+                if pending.address == *from {
+                    self.send_record(
+                        uio,
+                        &pending.address,
+                        &pending.request_hash,
+                    )?;
+                }
+            }
+            match seq {
+                // Nodes with records are trusted once their records are
+                // known to be compatible.
+                Some(seq) if self.is_record_outdated(&node.id, seq) => {
+                    self.request_record(uio, node)?;
+                }
+                _ => {
+                    uio.node_db.write().insert_with_promotion(node);
+                }
+            }
             Ok(())
         } else {
             debug!("Got unexpected Pong from {:?} ; request not found", &from);
@@ -400,6 +547,158 @@ impl Discovery {
         Ok(())
     }
 
+    fn request_record(
+        &mut self, uio: &UdpIoContext, node: NodeEntry,
+    ) -> Result<(), Error> {
+        if self.in_flight_records.contains_key(&node.id) {
+            return Ok(());
+        }
+        let mut rlp = RlpStream::new_list(1);
+        append_expiration(&mut rlp);
+        let hash = self.send_packet(
+            uio,
+            PACKET_RECORD_REQUEST,
+            &node.endpoint.udp_address(),
+            &rlp.drain(),
+        )?;
+
+        trace!("Sent RecordRequest to {:?}", &node.endpoint);
+        self.in_flight_records.insert(
+            node.id.clone(),
+            RecordRequest {
+                sent_at: Instant::now(),
+                node,
+                request_hash: hash,
+            },
+        );
+        Ok(())
+    }
+
+    fn on_record_request(
+        &mut self, uio: &UdpIoContext, rlp: &Rlp, node_id: &NodeId,
+        from: &SocketAddr, request_hash: &[u8],
+    ) -> Result<(), Error>
+    {
+        trace!("Got RecordRequest from {:?}", &from);
+        let timestamp: u64 = rlp.val_at(0)?;
+        self.check_timestamp(timestamp)?;
+
+        if self.is_bonded(node_id, from) {
+            return self.send_record(uio, from, request_hash);
+        }
+
+        // The record is only sent to an address which answers a Ping, so
+        // that it cannot be reflected to a spoofed address.
+        let node = NodeEntry {
+            id: *node_id,
+            endpoint: NodeEndpoint {
+                address: *from,
+                udp_port: from.port(),
+            },
+        };
+        if !self.is_allowed(&node) {
+            debug!("Address not allowed: {:?}", node);
+            return Ok(());
+        }
+        if !self.in_flight_pings.contains_key(node_id) {
+            if self.in_flight_pings.len() >= MAX_NODES_PING {
+                debug!(
+                    "Too many pings in flight, RecordRequest from {:?} dropped",
+                    &from
+                );
+                return Ok(());
+            }
+            self.ping(uio, &node)?;
+        }
+        self.pending_record_requests.insert(
+            *node_id,
+            PendingRecordRequest {
+                address: *from,
+                request_hash: H256::from_slice(request_hash),
+            },
+        );
+        Ok(())
+    }
+
+    fn send_record(
+        &mut self, uio: &UdpIoContext, to: &SocketAddr, request_hash: &[u8],
+    ) -> Result<(), Error> {
+        let mut response = RlpStream::new_list(2);
+        response.append(&request_hash);
+        response.append(&self.record);
+        self.send_packet(uio, PACKET_RECORD, to, &response.drain())?;
+        Ok(())
+    }
+
+    fn on_record(
+        &mut self, uio: &UdpIoContext, rlp: &Rlp, node_id: &NodeId,
+        from: &SocketAddr,
+    ) -> Result<(), Error>
+    {
+        trace!("Got Record from {:?} ; node_id={:#x}", &from, node_id);
+        let request_hash: H256 = rlp.val_at(0)?;
+        let request = match self.in_flight_records.entry(*node_id) {
+            Entry::Occupied(ref entry)
+                if entry.get().request_hash != request_hash =>
+            {
+                None
+            }
+            Entry::Occupied(entry) => Some(entry.remove()),
+            Entry::Vacant(_) => None,
+        };
+        let request = match request {
+            Some(request) => request,
+            None => {
+                debug!("Got unexpected Record from {:?}", &from);
+                return Ok(());
+            }
+        };
+
+        let record: NodeRecord = rlp.val_at(1)?;
+        if record.id != *node_id {
+            debug!("Got Record of another node from {:?}", &from);
+            return Err(ErrorKind::BadProtocol.into());
+        }
+        if !self.is_record_outdated(node_id, record.seq) {
+            return Ok(());
+        }
+
+        let compatible = record.is_compatible_with(&self.record);
+        // The signed endpoint is preferred to the observed one, so that the
+        // node can change its ports. A new IP address is only used after it
+        // answers a Ping.
+        let mut moved_to = None;
+        let entry = if record.endpoint.is_valid()
+            && record.endpoint.is_allowed(&self.ip_filter)
+        {
+            if record.endpoint.address.ip() == from.ip() {
+                record.entry()
+            } else {
+                moved_to = Some(record.entry());
+                request.node
+            }
+        } else {
+            request.node
+        };
+        if self.records.len() < MAX_RECORDS
+            || self.records.contains_key(node_id)
+        {
+            self.records.insert(*node_id, record);
+        } else {
+            debug!("Too many node records, record of {:?} dropped", entry);
+        }
+        if compatible {
+            uio.node_db.write().insert_with_promotion(entry);
+            if let Some(moved_to) = moved_to {
+                self.try_ping(uio, moved_to);
+            }
+        } else {
+            debug!("Incompatible node {:?}", entry);
+            uio.node_db.write().remove(node_id);
+        }
+        Ok(())
+    }
+
     /// Starts the discovery process at round 0
     fn start(&mut self) {
         trace!("Starting discovery");
@@ -428,6 +727,20 @@ impl Discovery {
                 true
             }
         });
+        self.in_flight_records.retain(|node_id, record_request| {
+            if time.duration_since(record_request.sent_at)
+                > RECORD_REQUEST_TIMEOUT
+            {
+                debug!(
+                    "Removing expired RECORD request for node_id={:#x}",
+                    node_id
+                );
+                nodes_to_expire.push(*node_id);
+                false
+            } else {
+                true
+            }
+        });
         self.in_flight_find_nodes.retain(|node_id, find_node_request| {
             if time.duration_since(find_node_request.sent_at) > FIND_NODE_TIMEOUT {
                 if !find_node_request.answered {
@@ -442,6 +755,19 @@ impl Discovery {
         for node_id in nodes_to_expire {
             self.expire_node_request(uio, node_id);
         }
+        let in_flight_pings = &self.in_flight_pings;
+        self.pending_record_requests
+            .retain(|node_id, _| in_flight_pings.contains_key(node_id));
+        self.bonds.retain(|_, bond| {
+            time.duration_since(bond.bonded_at) < BOND_EXPIRATION
+        });
+    }
+
+    /// Forget the records of the nodes which left the node table.
+    fn expire_records(&mut self, uio: &UdpIoContext) {
+        let node_db = uio.node_db.read();
+        self.records
+            .retain(|node_id, _| node_db.get(node_id, false).is_some());
     }
 
     fn expire_node_request(&mut self, uio: &UdpIoContext, node_id: NodeId) {
@@ -527,6 +853,7 @@ impl Discovery {
 
     pub fn round(&mut self, uio: &UdpIoContext) {
         self.check_expired(uio, Instant::now());
+        self.expire_records(uio);
         self.update_new_nodes(uio);
 
         if self.discovery_round.is_some() {
@@ -555,6 +882,15 @@ fn append_expiration(rlp: &mut RlpStream) {
     rlp.append(&timestamp);
 }
 
+/// The optional `seq` of the node record appended by version 2 nodes.
+fn record_seq_at(rlp: &Rlp, index: usize) -> Result<Option<u64>, Error> {
+    if rlp.item_count()? > index {
+        Ok(Some(rlp.val_at(index)?))
+    } else {
+        Ok(None)
+    }
+}
+
 fn assemble_packet(
     packet_id: u8, bytes: &[u8], secret: &Secret,
 ) -> Result<Bytes, Error> {
@@ -577,3 +913,212 @@ fn assemble_packet(
     packet[1..(1 + 32)].copy_from_slice(&signed_hash);
     Ok(packet)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{node_database::NodeDatabase, service::UdpChannel};
+    use keylib::{Generator, Random};
+    use parking_lot::RwLock;
+    use std::str::FromStr;
+
+    struct TestNode {
+        key: KeyPair,
+        entry: NodeEntry,
+        discovery: Discovery,
+        channel: RwLock<UdpChannel>,
+        node_db: RwLock<NodeDatabase>,
+    }
+
+    impl TestNode {
+        fn new(address: &str) -> Self {
+            let key = Random.generate().unwrap();
+            let endpoint = NodeEndpoint::from_str(address).unwrap();
+            let discovery = Discovery::new(
+                &key,
+                endpoint.clone(),
+                IpFilter::default(),
+                1,
+                1,
+            )
+            .unwrap();
+            TestNode {
+                entry: NodeEntry {
+                    id: key.public().clone(),
+                    endpoint,
+                },
+                key,
+                discovery,
+                channel: RwLock::new(UdpChannel::new()),
+                node_db: RwLock::new(NodeDatabase::new(None, 0)),
+            }
+        }
+
+        fn address(&self) -> SocketAddr { self.entry.endpoint.udp_address() }
+
+        fn ping(&mut self, node: NodeEntry) {
+            let uio = UdpIoContext::new(&self.channel, &self.node_db);
+            self.discovery.try_ping(&uio, node);
+        }
+
+        fn on_packet(&mut self, packet: &[u8], from: SocketAddr) {
+            let uio = UdpIoContext::new(&self.channel, &self.node_db);
+            self.discovery.on_packet(&uio, packet, from).unwrap();
+        }
+
+        /// The packets sent by the node, without the UDP protocol byte.
+        fn take_sent(&self) -> Vec<(Bytes, SocketAddr)> {
+            self.channel
+                .write()
+                .send_queue
+                .drain(..)
+                .map(|datagram| {
+                    (datagram.payload[1..].to_vec(), datagram.address)
+                })
+                .collect()
+        }
+
+        fn has_trusted(&self, node: &TestNode) -> bool {
+            self.node_db.read().get(&node.entry.id, true).is_some()
+        }
+    }
+
+    /// A packet signed by `key`, as received from the UDP socket.
+    fn new_packet(packet_id: u8, payload: &[u8], key: &KeyPair) -> Bytes {
+        assemble_packet(packet_id, payload, key.secret()).unwrap()[1..].to_vec()
+    }
+
+    fn packet_id(packet: &[u8]) -> u8 { packet[32 + 65] }
+
+    /// Deliver the packets between `a` and `b` until both are quiet. Returns
+    /// the ids and destinations of all packets sent. Packets sent to other
+    /// addresses are lost.
+    fn exchange(a: &mut TestNode, b: &mut TestNode) -> Vec<(u8, SocketAddr)> {
+        let mut sent = Vec::new();
+        loop {
+            let from_a = a.take_sent();
+            let from_b = b.take_sent();
+            if from_a.is_empty() && from_b.is_empty() {
+                return sent;
+            }
+            for (packet, to) in from_a {
+                sent.push((packet_id(&packet), to));
+                if to == b.address() {
+                    b.on_packet(&packet, a.address());
+                }
+            }
+            for (packet, to) in from_b {
+                sent.push((packet_id(&packet), to));
+                if to == a.address() {
+                    a.on_packet(&packet, b.address());
+                }
+            }
+        }
+    }
+
+    fn count(sent: &[(u8, SocketAddr)], id: u8) -> usize {
+        sent.iter()
+            .filter(|(packet_id, _)| *packet_id == id)
+            .count()
+    }
+
+    #[test]
+    fn test_exchange_records() {
+        let mut a = TestNode::new("10.0.0.1:32323");
+        let mut b = TestNode::new("10.0.0.2:32323");
+        a.ping(b.entry.clone());
+        let sent = exchange(&mut a, &mut b);
+
+        // `b` has no bond with `a` when asked for its record, so it pings `a`
+        // before answering.
+        assert_eq!(count(&sent, PACKET_PING), 2);
+        assert_eq!(count(&sent, PACKET_PONG), 2);
+        assert_eq!(count(&sent, PACKET_RECORD_REQUEST), 2);
+        assert_eq!(count(&sent, PACKET_RECORD), 2);
+        assert!(a.has_trusted(&b));
+        assert!(b.has_trusted(&a));
+        assert_eq!(a.discovery.records[&b.entry.id], b.discovery.record);
+        assert_eq!(b.discovery.records[&a.entry.id], a.discovery.record);
+    }
+
+    #[test]
+    fn test_record_request_needs_bond() {
+        let a = TestNode::new("10.0.0.1:32323");
+        let mut b = TestNode::new("10.0.0.2:32323");
+        let spoofed = SocketAddr::from_str("10.0.0.3:32323").unwrap();
+
+        let mut rlp = RlpStream::new_list(1);
+        append_expiration(&mut rlp);
+        let request = new_packet(PACKET_RECORD_REQUEST, &rlp.drain(), &a.key);
+        b.on_packet(&request, spoofed);
+
+        // Only a Ping is sent to the address, which does not answer.
+        let sent = b.take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(packet_id(&sent[0].0), PACKET_PING);
+        assert_eq!(sent[0].1, spoofed);
+    }
+
+    #[test]
+    fn test_moved_node_is_pinged() {
+        let mut a = TestNode::new("10.0.0.1:32323");
+        let mut b = TestNode::new("10.0.0.2:32323");
+        a.ping(b.entry.clone());
+        exchange(&mut a, &mut b);
+
+        // `a` signs an address it does not send from.
+        let moved = NodeEndpoint::from_str("10.0.0.3:32323").unwrap();
+        a.discovery.set_public_endpoint(moved.clone());
+        a.ping(b.entry.clone());
+        let sent = exchange(&mut a, &mut b);
+        assert!(sent.contains(&(PACKET_PING, moved.udp_address())));
+        assert_eq!(b.discovery.records[&a.entry.id].endpoint, moved);
+        assert_eq!(
+            b.node_db.read().get(&a.entry.id, true).unwrap().endpoint,
+            a.entry.endpoint
+        );
+    }
+
+    #[test]
+    fn test_version_1_node() {
+        let mut a = TestNode::new("10.0.0.1:32323");
+        let mut b = TestNode::new("10.0.0.2:32323");
+
+        // A version 1 Ping has no record seq, so no record is requested.
+        let mut rlp = RlpStream::new_list(4);
+        rlp.append(&1u32);
+        a.entry.endpoint.to_rlp_list(&mut rlp);
+        b.entry.endpoint.to_rlp_list(&mut rlp);
+        append_expiration(&mut rlp);
+        b.on_packet(
+            &new_packet(PACKET_PING, &rlp.drain(), &a.key),
+            a.address(),
+        );
+        let sent = b.take_sent();
+        assert_eq!(sent.len(), 1);
+        let pong = &sent[0].0;
+        assert_eq!(packet_id(pong), PACKET_PONG);
+        // The appended seq is ignored by version 1 nodes.
+        let pong = Rlp::new(&pong[(32 + 65 + 1)..]);
+        assert_eq!(pong.item_count().unwrap(), 4);
+        assert!(b.node_db.read().get(&a.entry.id, false).is_some());
+
+        // A version 1 Pong has no record seq either, so the node is trusted
+        // without a record.
+        b.ping(a.entry.clone());
+        let sent = b.take_sent();
+        assert_eq!(sent.len(), 1);
+        let ping = &sent[0].0;
+        let mut rlp = RlpStream::new_list(3);
+        b.entry.endpoint.to_rlp_list(&mut rlp);
+        rlp.append(&H256::from_slice(&ping[0..32]));
+        append_expiration(&mut rlp);
+        b.on_packet(
+            &new_packet(PACKET_PONG, &rlp.drain(), &a.key),
+            a.address(),
+        );
+        assert!(b.take_sent().is_empty());
+        assert!(b.has_trusted(&a));
+        assert!(b.discovery.records.is_empty());
+    }
+}
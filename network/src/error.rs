@@ -5,6 +5,7 @@
 use crate::io::IoError;
 use keylib;
 use rlp;
+use snappy;
 use std::{fmt, io, net};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -14,6 +15,8 @@ pub enum DisconnectReason {
     WrongEndpointInfo,
     IpLimited,
     UpdateNodeIdFailed,
+    Blacklisted,
+    TooManyPeers,
     Unknown,
 }
 
@@ -25,6 +28,8 @@ impl DisconnectReason {
             2 => DisconnectReason::WrongEndpointInfo,
             3 => DisconnectReason::IpLimited,
             4 => DisconnectReason::UpdateNodeIdFailed,
+            5 => DisconnectReason::Blacklisted,
+            6 => DisconnectReason::TooManyPeers,
             _ => DisconnectReason::Unknown,
         }
     }
@@ -38,6 +43,8 @@ impl fmt::Display for DisconnectReason {
             DisconnectReason::WrongEndpointInfo => "wrong node id",
             DisconnectReason::IpLimited => "IP limited",
             DisconnectReason::UpdateNodeIdFailed => "Update node id failed",
+            DisconnectReason::Blacklisted => "blacklisted",
+            DisconnectReason::TooManyPeers => "too many peers",
             DisconnectReason::Unknown => "unknown",
         };
 
@@ -142,6 +149,10 @@ impl From<rlp::DecoderError> for Error {
     fn from(_err: rlp::DecoderError) -> Self { ErrorKind::Decoder.into() }
 }
 
+impl From<snappy::InvalidInput> for Error {
+    fn from(_err: snappy::InvalidInput) -> Self { ErrorKind::Decoder.into() }
+}
+
 impl From<keylib::Error> for Error {
     fn from(_err: keylib::Error) -> Self { ErrorKind::Auth.into() }
 }
@@ -20,13 +20,10 @@
 // Conflux is free software and distributed under GNU General Public License.
 // See http://www.gnu.org/licenses/
 
-use crate::node_table::NodeEndpoint;
-use igd::{search_gateway_from_timeout, PortMappingProtocol};
 use ipnetwork::IpNetwork;
 use std::{
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
-    time::Duration,
 };
 
 /// Socket address extension for rustc beta. To be replaces with now unstable
@@ -311,50 +308,6 @@ pub fn select_public_address(port: u16) -> SocketAddr {
     SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port))
 }
 
-pub fn map_external_address(local: &NodeEndpoint) -> Option<NodeEndpoint> {
-    if let SocketAddr::V4(ref local_addr) = local.address {
-        match search_gateway_from_timeout(*local_addr.ip(), Duration::new(5, 0))
-        {
-            Err(ref err) => debug!("Gateway search error: {}", err),
-            Ok(gateway) => match gateway.get_external_ip() {
-                Err(ref err) => {
-                    debug!("IP request error: {}", err);
-                }
-                Ok(external_addr) => match gateway.add_any_port(
-                    PortMappingProtocol::TCP,
-                    SocketAddrV4::new(*local_addr.ip(), local_addr.port()),
-                    0,
-                    "Parity Node/TCP",
-                ) {
-                    Err(ref err) => {
-                        debug!("Port mapping error: {}", err);
-                    }
-                    Ok(tcp_port) => match gateway.add_any_port(
-                        PortMappingProtocol::UDP,
-                        SocketAddrV4::new(*local_addr.ip(), local.udp_port),
-                        0,
-                        "Parity Node/UDP",
-                    ) {
-                        Err(ref err) => {
-                            debug!("Port mapping error: {}", err);
-                        }
-                        Ok(udp_port) => {
-                            return Some(NodeEndpoint {
-                                address: SocketAddr::V4(SocketAddrV4::new(
-                                    external_addr,
-                                    tcp_port,
-                                )),
-                                udp_port,
-                            });
-                        }
-                    },
-                },
-            },
-        }
-    }
-    None
-}
-
 #[test]
 fn can_select_public_address() {
     let pub_address = select_public_address(40477);
@@ -365,10 +318,13 @@ fn can_select_public_address() {
 #[test]
 fn can_map_external_address_or_fail() {
     let pub_address = select_public_address(40478);
-    let _ = map_external_address(&NodeEndpoint {
-        address: pub_address,
-        udp_port: 40478,
-    });
+    let _ = crate::nat::PortMapping::new(
+        &crate::node_table::NodeEndpoint {
+            address: pub_address,
+            udp_port: 40478,
+        },
+        crate::nat::DEFAULT_NAT_LEASE_DURATION,
+    );
 }
 
 #[test]
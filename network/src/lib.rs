@@ -24,6 +24,7 @@ extern crate keccak_hash as hash;
 extern crate keylib;
 extern crate libc;
 extern crate parity_path;
+extern crate parity_snappy as snappy;
 extern crate rand;
 #[macro_use]
 extern crate enum_map_derive;
@@ -36,30 +37,37 @@ pub type ProtocolId = [u8; 3];
 pub type HandlerWorkType = u8;
 pub type PeerId = usize;
 
+mod bandwidth;
 mod connection;
 mod discovery;
 mod error;
 mod ip_utils;
+mod nat;
 mod node_database;
+mod node_record;
 pub mod node_table;
 mod service;
 mod session;
 mod session_manager;
+#[cfg(any(test, feature = "test-simulation"))]
+pub mod simulation;
 pub mod throttling;
 
 pub use crate::{
+    bandwidth::{MessageTrafficStats, PeerTrafficStats, TrafficStats},
     error::{DisconnectReason, Error, ErrorKind, ThrottlingReason},
     service::NetworkService,
 };
 pub use io::TimerToken;
 
 use crate::{
-    node_table::NodeId,
+    node_table::{NodeEndpoint, NodeId, NodeReputation},
     service::{
         DEFAULT_CONNECTION_LIFETIME_FOR_PROMOTION,
         DEFAULT_DISCOVERY_REFRESH_TIMEOUT, DEFAULT_DISCOVERY_ROUND_TIMEOUT,
         DEFAULT_FAST_DISCOVERY_REFRESH_TIMEOUT, DEFAULT_HOUSEKEEPING_TIMEOUT,
-        DEFAULT_NODE_TABLE_TIMEOUT,
+        DEFAULT_NODE_TABLE_TIMEOUT, DEFAULT_PEER_BAN_DURATION,
+        DEFAULT_PEER_BAN_SCORE, DEFAULT_PEER_DISCONNECT_SCORE,
     },
 };
 use ipnetwork::{IpNetwork, IpNetworkError};
@@ -71,7 +79,7 @@ use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     str::{self, FromStr},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -117,6 +125,21 @@ pub struct NetworkConfiguration {
     pub test_mode: bool,
     /// Maximum number of P2P nodes per IP address.
     pub nodes_per_ip: usize,
+    /// A peer is disconnected when its score drops to this value.
+    pub peer_disconnect_score: i32,
+    /// A peer is disconnected and banned when its score drops to this value.
+    pub peer_ban_score: i32,
+    /// How long a banned peer is refused.
+    pub peer_ban_duration: Duration,
+    /// Compress user packets sent to peers which support it.
+    pub compression_enabled: bool,
+    /// Maximum upload rate to each peer in bytes per second.
+    pub peer_upload_rate_limit: Option<usize>,
+    /// Advertised in the node record, so that nodes of other networks and
+    /// chains are not dialed. The network id is also sent in the `Status` of
+    /// the sync protocols.
+    pub network_id: u64,
+    pub chain_id: u64,
 }
 
 impl Default for NetworkConfiguration {
@@ -149,6 +172,13 @@ impl NetworkConfiguration {
                 DEFAULT_CONNECTION_LIFETIME_FOR_PROMOTION,
             test_mode: false,
             nodes_per_ip: 1,
+            peer_disconnect_score: DEFAULT_PEER_DISCONNECT_SCORE,
+            peer_ban_score: DEFAULT_PEER_BAN_SCORE,
+            peer_ban_duration: DEFAULT_PEER_BAN_DURATION,
+            compression_enabled: true,
+            peer_upload_rate_limit: None,
+            network_id: 1,
+            chain_id: 1,
         }
     }
 
@@ -194,6 +224,12 @@ pub enum NetworkIoMessage {
         /// Work type.
         work_type: HandlerWorkType,
     },
+    /// The NAT port mapping thread mapped us to a new external endpoint.
+    NatMapped {
+        endpoint: NodeEndpoint,
+        /// Mapping method, UPnP or NAT-PMP.
+        method: &'static str,
+    },
 }
 
 pub trait NetworkProtocolHandler: Sync + Send {
@@ -216,12 +252,20 @@ pub trait NetworkProtocolHandler: Sync + Send {
 pub trait NetworkContext {
     fn get_peer_node_id(&self, peer: PeerId) -> NodeId;
 
+    /// The version of this protocol negotiated with `peer`, i.e. the highest
+    /// version both sides registered.
+    fn get_protocol_version(&self, peer: PeerId) -> Option<u8>;
+
     fn send(
         &self, peer: PeerId, msg: Vec<u8>, priority: SendQueuePriority,
     ) -> Result<(), Error>;
 
     fn disconnect_peer(&self, peer: PeerId);
 
+    /// Decrease the score of a misbehaving peer. The peer is disconnected,
+    /// and banned for a while, if its score becomes too low.
+    fn decrease_peer_score(&self, peer: PeerId, amount: i32);
+
     /// Register a new IO timer. 'IoHandler::timeout' will be called with the
     /// token.
     fn register_timer(
@@ -229,6 +273,13 @@ pub trait NetworkContext {
     ) -> Result<(), Error>;
 
     fn dispatch_work(&self, work_type: HandlerWorkType);
+
+    /// The network id of the local node, see `NetworkConfiguration`.
+    fn network_id(&self) -> u64;
+
+    /// The current time seen by the handlers, which a simulated network
+    /// replaces with its virtual time.
+    fn now(&self) -> Instant { Instant::now() }
 }
 
 #[derive(Debug, Clone)]
@@ -288,6 +339,29 @@ pub struct PeerInfo {
     pub addr: SocketAddr,
     pub nodeid: NodeId,
     pub caps: Vec<Capability>,
+    pub score: i32,
+    pub traffic: PeerTrafficStats,
+}
+
+/// A node in the node database.
+#[derive(Serialize)]
+pub struct NodeInfo {
+    pub nodeid: NodeId,
+    pub addr: SocketAddr,
+    pub udp_port: u16,
+    /// Whether the node is in the trusted node table.
+    pub trusted: bool,
+    pub reputation: NodeReputation,
+    pub score: i32,
+    pub banned: bool,
+    pub connected: bool,
+}
+
+/// A banned node and the time its ban expires, in seconds since the epoch.
+#[derive(Serialize)]
+pub struct BannedNodeInfo {
+    pub nodeid: NodeId,
+    pub until: u64,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -0,0 +1,555 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Port mapping on NAT gateways, so that nodes behind home routers can accept
+//! inbound peers. UPnP IGD is tried first and NAT-PMP (RFC 6886) is used as a
+//! fallback. Mappings are leased and have to be renewed periodically.
+
+use crate::node_table::NodeEndpoint;
+use igd::{search_gateway_from_timeout, Gateway, PortMappingProtocol};
+use std::{
+    cmp::min,
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Lifetime requested for every port mapping. A mapping is renewed once half
+/// of its lifetime has elapsed.
+pub const DEFAULT_NAT_LEASE_DURATION: Duration = Duration::from_secs(3600);
+/// How long to wait before retrying a failed renewal.
+const RENEWAL_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+const UPNP_SEARCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+const NAT_PMP_PORT: u16 = 5351;
+const NAT_PMP_VERSION: u8 = 0;
+const NAT_PMP_OP_EXTERNAL_ADDRESS: u8 = 0;
+const NAT_PMP_OP_MAP_UDP: u8 = 1;
+const NAT_PMP_OP_MAP_TCP: u8 = 2;
+const NAT_PMP_OP_RESPONSE: u8 = 128;
+const NAT_PMP_RESULT_SUCCESS: u16 = 0;
+const NAT_PMP_INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const NAT_PMP_MAX_ATTEMPTS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    fn upnp(self) -> PortMappingProtocol {
+        match self {
+            Protocol::Tcp => PortMappingProtocol::TCP,
+            Protocol::Udp => PortMappingProtocol::UDP,
+        }
+    }
+
+    fn nat_pmp_op(self) -> u8 {
+        match self {
+            Protocol::Tcp => NAT_PMP_OP_MAP_TCP,
+            Protocol::Udp => NAT_PMP_OP_MAP_UDP,
+        }
+    }
+}
+
+fn other_error<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+/// A minimal NAT-PMP client.
+struct NatPmpClient {
+    gateway: SocketAddr,
+}
+
+impl NatPmpClient {
+    fn new(gateway: SocketAddr) -> Self { NatPmpClient { gateway } }
+
+    /// Sends `request` to the gateway, retrying with a doubling timeout as
+    /// recommended by the RFC, and returns the validated response.
+    fn request(
+        &self, request: &[u8], response_len: usize,
+    ) -> io::Result<Vec<u8>> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(self.gateway)?;
+        let mut timeout = NAT_PMP_INITIAL_TIMEOUT;
+        let mut buf = [0u8; 16];
+        for _ in 0..NAT_PMP_MAX_ATTEMPTS {
+            socket.send(request)?;
+            socket.set_read_timeout(Some(timeout))?;
+            match socket.recv(&mut buf) {
+                Ok(len) => {
+                    if len < response_len
+                        || buf[0] != NAT_PMP_VERSION
+                        || buf[1] != NAT_PMP_OP_RESPONSE + request[1]
+                    {
+                        // Not the response we are waiting for.
+                        continue;
+                    }
+                    let result = u16::from_be_bytes([buf[2], buf[3]]);
+                    if result != NAT_PMP_RESULT_SUCCESS {
+                        return Err(other_error(format!(
+                            "NAT-PMP request failed with result code {}",
+                            result
+                        )));
+                    }
+                    return Ok(buf[..response_len].to_vec());
+                }
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    timeout *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "NAT-PMP gateway did not respond",
+        ))
+    }
+
+    fn external_address(&self) -> io::Result<Ipv4Addr> {
+        let response =
+            self.request(&[NAT_PMP_VERSION, NAT_PMP_OP_EXTERNAL_ADDRESS], 12)?;
+        Ok(Ipv4Addr::new(
+            response[8],
+            response[9],
+            response[10],
+            response[11],
+        ))
+    }
+
+    /// Returns the mapped external port and the lifetime granted by the
+    /// gateway.
+    fn map_port(
+        &self, protocol: Protocol, internal_port: u16, external_port: u16,
+        lifetime: Duration,
+    ) -> io::Result<(u16, Duration)>
+    {
+        let mut request = vec![NAT_PMP_VERSION, protocol.nat_pmp_op(), 0, 0];
+        request.extend_from_slice(&internal_port.to_be_bytes());
+        request.extend_from_slice(&external_port.to_be_bytes());
+        request.extend_from_slice(&(lifetime.as_secs() as u32).to_be_bytes());
+        let response = self.request(&request, 16)?;
+        let port = u16::from_be_bytes([response[10], response[11]]);
+        let lifetime = u32::from_be_bytes([
+            response[12],
+            response[13],
+            response[14],
+            response[15],
+        ]);
+        Ok((port, Duration::from_secs(lifetime as u64)))
+    }
+}
+
+enum NatGateway {
+    Upnp(Gateway),
+    NatPmp(NatPmpClient),
+}
+
+impl NatGateway {
+    fn name(&self) -> &'static str {
+        match self {
+            NatGateway::Upnp(_) => "UPnP",
+            NatGateway::NatPmp(_) => "NAT-PMP",
+        }
+    }
+
+    fn external_address(&self) -> io::Result<Ipv4Addr> {
+        match self {
+            NatGateway::Upnp(gateway) => {
+                gateway.get_external_ip().map_err(other_error)
+            }
+            NatGateway::NatPmp(client) => client.external_address(),
+        }
+    }
+
+    fn map_port(
+        &self, protocol: Protocol, local: SocketAddrV4, external_port: u16,
+        lifetime: Duration,
+    ) -> io::Result<(u16, Duration)>
+    {
+        match self {
+            NatGateway::Upnp(gateway) => {
+                let description = match protocol {
+                    Protocol::Tcp => "Conflux Node/TCP",
+                    Protocol::Udp => "Conflux Node/UDP",
+                };
+                let lease = lifetime.as_secs() as u32;
+                // Keep the previous external port if the gateway allows it.
+                let port = match gateway.add_port(
+                    protocol.upnp(),
+                    external_port,
+                    local,
+                    lease,
+                    description,
+                ) {
+                    Ok(()) => external_port,
+                    Err(_) => gateway
+                        .add_any_port(
+                            protocol.upnp(),
+                            local,
+                            lease,
+                            description,
+                        )
+                        .map_err(other_error)?,
+                };
+                Ok((port, lifetime))
+            }
+            NatGateway::NatPmp(client) => {
+                client.map_port(protocol, local.port(), external_port, lifetime)
+            }
+        }
+    }
+}
+
+/// TCP and UDP port mappings of the local endpoint on a NAT gateway.
+pub struct PortMapping {
+    gateway: NatGateway,
+    local: SocketAddrV4,
+    local_udp_port: u16,
+    external: NodeEndpoint,
+    lease: Duration,
+    next_renewal: Instant,
+}
+
+impl PortMapping {
+    /// Looks for a UPnP or NAT-PMP gateway and maps the TCP and UDP ports of
+    /// `local` on it.
+    pub fn new(local: &NodeEndpoint, lease: Duration) -> Option<PortMapping> {
+        let local_addr = match local.address {
+            SocketAddr::V4(addr) => addr,
+            SocketAddr::V6(_) => return None,
+        };
+
+        match search_gateway_from_timeout(*local_addr.ip(), UPNP_SEARCH_TIMEOUT)
+        {
+            Ok(gateway) => {
+                let local_ip = if local_addr.ip().is_unspecified() {
+                    local_ip_towards(SocketAddr::V4(gateway.addr))
+                } else {
+                    Ok(*local_addr.ip())
+                };
+                let mapping = local_ip.and_then(|ip| {
+                    PortMapping::with_gateway(
+                        NatGateway::Upnp(gateway),
+                        SocketAddrV4::new(ip, local_addr.port()),
+                        local.udp_port,
+                        lease,
+                    )
+                });
+                match mapping {
+                    Ok(mapping) => return Some(mapping),
+                    Err(e) => debug!("UPnP port mapping error: {}", e),
+                }
+            }
+            Err(e) => debug!("UPnP gateway search error: {}", e),
+        }
+
+        let gateway = match default_gateway() {
+            Some(gateway) => gateway,
+            None => {
+                debug!("No default gateway found for NAT-PMP");
+                return None;
+            }
+        };
+        let client = NatPmpClient::new(SocketAddr::V4(SocketAddrV4::new(
+            gateway,
+            NAT_PMP_PORT,
+        )));
+        match PortMapping::with_gateway(
+            NatGateway::NatPmp(client),
+            local_addr,
+            local.udp_port,
+            lease,
+        ) {
+            Ok(mapping) => Some(mapping),
+            Err(e) => {
+                debug!("NAT-PMP port mapping error: {}", e);
+                None
+            }
+        }
+    }
+
+    fn with_gateway(
+        gateway: NatGateway, local: SocketAddrV4, local_udp_port: u16,
+        lease: Duration,
+    ) -> io::Result<PortMapping>
+    {
+        let mut mapping = PortMapping {
+            gateway,
+            local,
+            local_udp_port,
+            external: NodeEndpoint {
+                address: SocketAddr::V4(local),
+                udp_port: local_udp_port,
+            },
+            lease,
+            next_renewal: Instant::now(),
+        };
+        mapping.external =
+            mapping.request(local.port(), local_udp_port, lease)?;
+        Ok(mapping)
+    }
+
+    /// Maps both ports, asking the gateway for the given external ports.
+    fn request(
+        &mut self, tcp_port: u16, udp_port: u16, lease: Duration,
+    ) -> io::Result<NodeEndpoint> {
+        let address = self.gateway.external_address()?;
+        let (tcp_port, tcp_lease) = self.gateway.map_port(
+            Protocol::Tcp,
+            self.local,
+            tcp_port,
+            lease,
+        )?;
+        let local_udp =
+            SocketAddrV4::new(*self.local.ip(), self.local_udp_port);
+        let (udp_port, udp_lease) =
+            self.gateway
+                .map_port(Protocol::Udp, local_udp, udp_port, lease)?;
+        self.next_renewal = Instant::now() + min(tcp_lease, udp_lease) / 2;
+        Ok(NodeEndpoint {
+            address: SocketAddr::V4(SocketAddrV4::new(address, tcp_port)),
+            udp_port,
+        })
+    }
+
+    /// The endpoint other nodes can reach us at.
+    pub fn external_endpoint(&self) -> &NodeEndpoint { &self.external }
+
+    pub fn needs_renewal(&self) -> bool { Instant::now() >= self.next_renewal }
+
+    /// Renews the leases, keeping the current external ports if the gateway
+    /// allows it, and returns the possibly changed external endpoint.
+    pub fn renew(&mut self) -> io::Result<&NodeEndpoint> {
+        let tcp_port = self.external.address.port();
+        let udp_port = self.external.udp_port;
+        match self.request(tcp_port, udp_port, self.lease) {
+            Ok(external) => {
+                self.external = external;
+                Ok(&self.external)
+            }
+            Err(e) => {
+                self.next_renewal = Instant::now() + RENEWAL_RETRY_INTERVAL;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn method(&self) -> &'static str { self.gateway.name() }
+}
+
+/// Maps the local endpoint on the NAT gateway and renews the leases on a
+/// dedicated thread, since talking to the gateway may block for seconds. The
+/// thread stops when the worker is dropped.
+pub struct PortMappingWorker {
+    _stop: mpsc::Sender<()>,
+}
+
+impl PortMappingWorker {
+    /// Starts mapping `local`. `on_mapped` is called with the external
+    /// endpoint and the mapping method once the mapping is created and every
+    /// time a renewal changes the external endpoint. The thread stops as soon
+    /// as `on_mapped` returns false.
+    pub fn spawn<F>(
+        local: NodeEndpoint, lease: Duration, on_mapped: F,
+    ) -> io::Result<PortMappingWorker>
+    where F: Fn(NodeEndpoint, &'static str) -> bool + Send + 'static {
+        let (stop, stopped) = mpsc::channel();
+        thread::Builder::new()
+            .name("NAT Mapping".into())
+            .spawn(move || {
+                let mut mapping = match PortMapping::new(&local, lease) {
+                    Some(mapping) => mapping,
+                    None => {
+                        debug!("No NAT gateway could map {}", local.address);
+                        return;
+                    }
+                };
+                let method = mapping.method();
+                if !on_mapped(mapping.external_endpoint().clone(), method) {
+                    return;
+                }
+                loop {
+                    let now = Instant::now();
+                    let wait = if mapping.next_renewal > now {
+                        mapping.next_renewal - now
+                    } else {
+                        Duration::from_secs(0)
+                    };
+                    match stopped.recv_timeout(wait) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => return,
+                    }
+                    let previous = mapping.external_endpoint().clone();
+                    match mapping.renew() {
+                        Ok(endpoint) if *endpoint != previous => {
+                            if !on_mapped(endpoint.clone(), method) {
+                                return;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            debug!("Failed to renew NAT port mapping: {}", e)
+                        }
+                    }
+                }
+            })?;
+        Ok(PortMappingWorker { _stop: stop })
+    }
+}
+
+/// Returns the local address used to reach `remote`, which is the address the
+/// gateway sees us at.
+fn local_ip_towards(remote: SocketAddr) -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(remote)?;
+    match socket.local_addr()? {
+        SocketAddr::V4(addr) => Ok(*addr.ip()),
+        SocketAddr::V6(_) => Err(other_error("not an IPv4 address")),
+    }
+}
+
+/// Reads the IPv4 default gateway from the kernel routing table.
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            return None;
+        }
+        // The table is written in the host byte order.
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Option<Ipv4Addr> { None }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    const EXTERNAL_IP: [u8; 4] = [203, 0, 113, 7];
+
+    /// A stand-in NAT-PMP gateway which maps every internal port to the
+    /// internal port plus 10000 and grants at most `max_lifetime` seconds.
+    struct StandInGateway {
+        addr: SocketAddr,
+        stop: Arc<AtomicBool>,
+    }
+
+    impl StandInGateway {
+        fn start(max_lifetime: u32) -> StandInGateway {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_millis(50)))
+                .unwrap();
+            let addr = socket.local_addr().unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+            let stopped = stop.clone();
+            thread::spawn(move || {
+                let mut buf = [0u8; 16];
+                while !stopped.load(Ordering::SeqCst) {
+                    let (len, from) = match socket.recv_from(&mut buf) {
+                        Ok(r) => r,
+                        Err(_) => continue,
+                    };
+                    let mut response =
+                        vec![NAT_PMP_VERSION, NAT_PMP_OP_RESPONSE + buf[1]];
+                    response.extend_from_slice(&[0, 0, 0, 0, 0, 1]);
+                    if len == 2 && buf[1] == NAT_PMP_OP_EXTERNAL_ADDRESS {
+                        response.extend_from_slice(&EXTERNAL_IP);
+                    } else if len == 12 {
+                        let internal = u16::from_be_bytes([buf[4], buf[5]]);
+                        let lifetime = min(
+                            u32::from_be_bytes([
+                                buf[8], buf[9], buf[10], buf[11],
+                            ]),
+                            max_lifetime,
+                        );
+                        response.extend_from_slice(&buf[4..6]);
+                        response.extend_from_slice(
+                            &(internal + 10000).to_be_bytes(),
+                        );
+                        response.extend_from_slice(&lifetime.to_be_bytes());
+                    } else {
+                        continue;
+                    }
+                    socket.send_to(&response, from).unwrap();
+                }
+            });
+            StandInGateway { addr, stop }
+        }
+    }
+
+    impl Drop for StandInGateway {
+        fn drop(&mut self) { self.stop.store(true, Ordering::SeqCst); }
+    }
+
+    #[test]
+    fn test_nat_pmp_mapping() {
+        let gateway = StandInGateway::start(7200);
+        let local = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 32323);
+        let mut mapping = PortMapping::with_gateway(
+            NatGateway::NatPmp(NatPmpClient::new(gateway.addr)),
+            local,
+            32324,
+            DEFAULT_NAT_LEASE_DURATION,
+        )
+        .unwrap();
+
+        let expected = NodeEndpoint {
+            address: SocketAddr::V4(SocketAddrV4::new(
+                EXTERNAL_IP.into(),
+                42323,
+            )),
+            udp_port: 42324,
+        };
+        assert_eq!(mapping.external_endpoint(), &expected);
+        assert!(!mapping.needs_renewal());
+
+        assert_eq!(mapping.renew().unwrap(), &expected);
+    }
+
+    #[test]
+    fn test_nat_pmp_short_lease_needs_renewal() {
+        let gateway = StandInGateway::start(0);
+        let local = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 32323);
+        let mapping = PortMapping::with_gateway(
+            NatGateway::NatPmp(NatPmpClient::new(gateway.addr)),
+            local,
+            32323,
+            DEFAULT_NAT_LEASE_DURATION,
+        )
+        .unwrap();
+        assert!(mapping.needs_renewal());
+    }
+
+    #[test]
+    fn test_nat_pmp_no_gateway() {
+        // Nothing listens on this socket once it is dropped.
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client = NatPmpClient::new(addr);
+        assert!(client.external_address().is_err());
+    }
+}
@@ -4,17 +4,51 @@
 
 use crate::{
     node_table::{Node, NodeContact, NodeEntry, NodeId, NodeTable},
-    IpFilter,
+    BannedNodeInfo, IpFilter, NodeInfo,
 };
 use io::StreamToken;
-use std::{collections::HashMap, net::IpAddr, time::Duration};
+use std::{
+    collections::HashMap,
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const BLACKLISTED_NODES_FILE: &str = "blacklisted_nodes.json";
+/// A node with a negative score recovers one point per interval, i.e. 100
+/// points per hour.
+const SCORE_RECOVERY_INTERVAL: Duration = Duration::from_secs(36);
 
 /// Node database maintains all P2P nodes in trusted and untrusted node tables,
-/// and support to limit the number of nodes for the same IP address.
+/// and support to limit the number of nodes for the same IP address. It also
+/// keeps the scores of misbehaving nodes and the nodes banned for a while.
 pub struct NodeDatabase {
     trusted_nodes: NodeTable,
     untrusted_nodes: NodeTable,
     ip_limit: NodeIpLimit,
+    scores: HashMap<NodeId, NodeScore>,
+    blacklist: NodeBlacklist,
+}
+
+/// The score of a node when it was last decreased.
+struct NodeScore {
+    score: i32,
+    updated: Instant,
+}
+
+impl NodeScore {
+    /// The score at `now`, recovered towards 0 since it was decreased.
+    fn at(&self, now: Instant) -> i32 {
+        let elapsed = now.duration_since(self.updated);
+        let recovered = elapsed.as_secs() / SCORE_RECOVERY_INTERVAL.as_secs();
+        if recovered >= -(self.score as i64) as u64 {
+            0
+        } else {
+            self.score + recovered as i32
+        }
+    }
 }
 
 impl NodeDatabase {
@@ -30,6 +64,8 @@ impl NodeDatabase {
             trusted_nodes,
             untrusted_nodes,
             ip_limit,
+            scores: HashMap::new(),
+            blacklist: NodeBlacklist::new(path),
         }
     }
 
@@ -196,13 +232,108 @@ impl NodeDatabase {
         self.trusted_nodes.sample_node_ids(count, filter)
     }
 
-    /// Persist trust and untrusted node tables and clear all useless nodes.
+    /// Persist trust and untrusted node tables and clear all useless nodes,
+    /// together with their scores.
     pub fn save(&mut self) {
         self.trusted_nodes.save();
         self.trusted_nodes.clear_useless();
 
         self.untrusted_nodes.save();
         self.untrusted_nodes.clear_useless();
+
+        self.blacklist.save();
+        self.clear_scores(Instant::now());
+    }
+
+    /// Forget the scores which have recovered to 0, and those of the nodes
+    /// no longer in the node tables.
+    fn clear_scores(&mut self, now: Instant) {
+        let trusted_nodes = &self.trusted_nodes;
+        let untrusted_nodes = &self.untrusted_nodes;
+        self.scores.retain(|id, score| {
+            score.at(now) < 0
+                && (trusted_nodes.contains(id) || untrusted_nodes.contains(id))
+        });
+    }
+
+    /// The score of the specified node, which starts from 0, is decreased
+    /// when the node misbehaves and recovers towards 0 over time.
+    pub fn score(&self, id: &NodeId) -> i32 {
+        self.score_at(id, Instant::now())
+    }
+
+    fn score_at(&self, id: &NodeId, now: Instant) -> i32 {
+        self.scores.get(id).map_or(0, |score| score.at(now))
+    }
+
+    /// Decrease the score of the specified node, and return the new score.
+    pub fn decrease_score(&mut self, id: &NodeId, amount: i32) -> i32 {
+        self.decrease_score_at(id, amount, Instant::now())
+    }
+
+    fn decrease_score_at(
+        &mut self, id: &NodeId, amount: i32, now: Instant,
+    ) -> i32 {
+        let score = self.score_at(id, now).saturating_sub(amount);
+        self.scores.insert(
+            *id,
+            NodeScore {
+                score,
+                updated: now,
+            },
+        );
+        score
+    }
+
+    /// Refuse to connect the specified node for `duration`. The node gets a
+    /// new score when the ban expires.
+    pub fn ban(&mut self, id: &NodeId, duration: Duration) {
+        self.scores.remove(id);
+        self.blacklist.insert(*id, SystemTime::now() + duration);
+    }
+
+    /// Check if the specified node is banned now.
+    pub fn is_banned(&self, id: &NodeId) -> bool { self.blacklist.contains(id) }
+
+    /// Lift the ban of the specified node. Returns false if it was not banned.
+    pub fn unban(&mut self, id: &NodeId) -> bool { self.blacklist.remove(id) }
+
+    /// The nodes banned now, with the time when their bans expire.
+    pub fn banned_nodes(&self) -> Vec<BannedNodeInfo> {
+        let now = SystemTime::now();
+        self.blacklist
+            .nodes
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(id, until)| BannedNodeInfo {
+                nodeid: *id,
+                until: until
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// All nodes in the trusted and untrusted node tables.
+    pub fn nodes(&self) -> Vec<NodeInfo> {
+        let trusted = self.trusted_nodes.nodes_with_reputation();
+        let untrusted = self.untrusted_nodes.nodes_with_reputation();
+        trusted
+            .into_iter()
+            .map(|node| (node, true))
+            .chain(untrusted.into_iter().map(|node| (node, false)))
+            .map(|((node, reputation), trusted)| NodeInfo {
+                nodeid: node.id,
+                addr: node.endpoint.address,
+                udp_port: node.endpoint.udp_port,
+                trusted,
+                reputation,
+                score: self.score(&node.id),
+                banned: self.is_banned(&node.id),
+                connected: node.stream_token.is_some(),
+            })
+            .collect()
     }
 
     /// Promote untrusted nodes to trusted with the given duration.
@@ -229,6 +360,7 @@ impl NodeDatabase {
 
     /// Remove node from database for the specified id
     pub fn remove(&mut self, id: &NodeId) -> Option<Node> {
+        self.scores.remove(id);
         let node = self
             .trusted_nodes
             .remove_with_id(id)
@@ -244,6 +376,136 @@ impl NodeDatabase {
     }
 }
 
+/// The nodes banned until some time, backed by disk file so that the bans
+/// survive restarts.
+struct NodeBlacklist {
+    nodes: HashMap<NodeId, SystemTime>,
+    path: Option<PathBuf>,
+}
+
+impl NodeBlacklist {
+    fn new(path: Option<String>) -> Self {
+        let mut blacklist = NodeBlacklist {
+            nodes: HashMap::new(),
+            path: path
+                .map(|path| PathBuf::from(path).join(BLACKLISTED_NODES_FILE)),
+        };
+        blacklist.load_from_file();
+        blacklist
+    }
+
+    fn insert(&mut self, id: NodeId, until: SystemTime) {
+        self.nodes.insert(id, until);
+    }
+
+    fn remove(&mut self, id: &NodeId) -> bool {
+        match self.nodes.remove(id) {
+            Some(until) => until > SystemTime::now(),
+            None => false,
+        }
+    }
+
+    fn contains(&self, id: &NodeId) -> bool {
+        match self.nodes.get(id) {
+            Some(until) => *until > SystemTime::now(),
+            None => false,
+        }
+    }
+
+    fn load_from_file(&mut self) {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return,
+        };
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                debug!("node blacklist file not found: {:?}", e);
+                return;
+            }
+        };
+        let res: Result<json::NodeBlacklist, _> = serde_json::from_reader(file);
+        match res {
+            Ok(blacklist) => {
+                for node in blacklist.nodes {
+                    match NodeId::from_str(&node.id) {
+                        Ok(id) => {
+                            self.nodes.insert(
+                                id,
+                                UNIX_EPOCH + Duration::from_secs(node.until),
+                            );
+                        }
+                        Err(e) => {
+                            warn!(
+                                "Invalid node id {:?} in blacklist: {:?}",
+                                node.id, e
+                            );
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Error reading node blacklist file: {:?}", e);
+            }
+        }
+    }
+
+    /// Forget the expired bans and save the others.
+    fn save(&mut self) {
+        let now = SystemTime::now();
+        self.nodes.retain(|_, until| *until > now);
+
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return,
+        };
+        if let Some(dir) = path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                warn!("Error creating node blacklist directory: {:?}", e);
+                return;
+            }
+        }
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(id, until)| json::BlacklistedNode {
+                id: format!("{:x}", id),
+                until: until
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+            })
+            .collect();
+        match fs::File::create(path) {
+            Ok(file) => {
+                if let Err(e) = serde_json::to_writer_pretty(
+                    file,
+                    &json::NodeBlacklist { nodes },
+                ) {
+                    warn!("Error writing node blacklist file: {:?}", e);
+                }
+            }
+            Err(e) => {
+                warn!("Error creating node blacklist file: {:?}", e);
+            }
+        }
+    }
+}
+
+mod json {
+    #[derive(Serialize, Deserialize)]
+    pub struct NodeBlacklist {
+        pub nodes: Vec<BlacklistedNode>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct BlacklistedNode {
+        pub id: String,
+        /// Seconds since the unix epoch.
+        pub until: u64,
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum InsertResult {
     Added,
@@ -528,10 +790,11 @@ mod tests {
     #[cfg(test)]
     mod node_database_tests {
         use super::{
-            super::{InsertResult, NodeDatabase},
+            super::{InsertResult, NodeDatabase, SCORE_RECOVERY_INTERVAL},
             new_entry,
         };
         use crate::node_table::NodeId;
+        use std::time::{Duration, Instant};
 
         #[test]
         fn test_insert_with_token() {
@@ -648,5 +911,83 @@ mod tests {
 
             assert_eq!(db.ip_limit.ip_to_nodes.len(), 0);
         }
+
+        #[test]
+        fn test_score_and_ban() {
+            let mut db = NodeDatabase::new(None, 1);
+            let id = NodeId::random();
+            assert_eq!(db.score(&id), 0);
+            assert_eq!(db.decrease_score(&id, 10), -10);
+            assert_eq!(db.decrease_score(&id, 20), -30);
+            assert_eq!(db.score(&id), -30);
+            assert!(!db.is_banned(&id));
+
+            db.ban(&id, Duration::from_secs(60));
+            assert!(db.is_banned(&id));
+            assert_eq!(db.score(&id), 0);
+            assert!(!db.is_banned(&NodeId::random()));
+
+            // expired bans are ignored and forgotten when saved
+            db.ban(&id, Duration::from_secs(0));
+            assert!(!db.is_banned(&id));
+            db.save();
+            assert!(db.blacklist.nodes.is_empty());
+        }
+
+        #[test]
+        fn test_score_recovery() {
+            let mut db = NodeDatabase::new(None, 1);
+            let entry = new_entry(None, "127.0.0.1:999");
+            db.insert_with_token(entry.clone(), 9);
+            let id = entry.id;
+            let now = Instant::now();
+
+            assert_eq!(db.decrease_score_at(&id, 10, now), -10);
+            assert_eq!(db.score_at(&id, now + SCORE_RECOVERY_INTERVAL * 4), -6);
+            // the recovered score is decreased again
+            let later = now + SCORE_RECOVERY_INTERVAL * 4;
+            assert_eq!(db.decrease_score_at(&id, 10, later), -16);
+            assert_eq!(
+                db.score_at(&id, later + SCORE_RECOVERY_INTERVAL * 20),
+                0
+            );
+
+            // recovered scores are forgotten
+            db.clear_scores(later);
+            assert_eq!(db.scores.len(), 1);
+            db.clear_scores(later + SCORE_RECOVERY_INTERVAL * 16);
+            assert!(db.scores.is_empty());
+        }
+
+        #[test]
+        fn test_scores_removed_with_nodes() {
+            let mut db = NodeDatabase::new(None, 1);
+            let entry = new_entry(None, "127.0.0.1:999");
+            db.insert_with_token(entry.clone(), 9);
+            db.decrease_score(&entry.id, 10);
+            db.decrease_score(&NodeId::random(), 10);
+
+            // the score of a node not in the node tables is forgotten
+            db.clear_scores(Instant::now());
+            assert_eq!(db.scores.len(), 1);
+
+            db.remove(&entry.id);
+            assert!(db.scores.is_empty());
+            assert_eq!(db.score(&entry.id), 0);
+        }
+
+        #[test]
+        fn test_unban() {
+            let mut db = NodeDatabase::new(None, 1);
+            let id = NodeId::random();
+            db.ban(&id, Duration::from_secs(60));
+            assert_eq!(db.banned_nodes().len(), 1);
+            assert_eq!(db.banned_nodes()[0].nodeid, id);
+
+            assert!(db.unban(&id));
+            assert!(!db.is_banned(&id));
+            assert!(db.banned_nodes().is_empty());
+            assert!(!db.unban(&id));
+        }
     }
 }
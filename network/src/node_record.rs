@@ -0,0 +1,206 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+use crate::{
+    hash::keccak,
+    node_table::{NodeEndpoint, NodeEntry, NodeId},
+    Capability, Error,
+};
+use cfx_types::{H256, H520};
+use keylib::{recover, sign, Secret};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A signed and versioned description of a node, exchanged in discovery like
+/// the Ethereum Node Records. It tells whether a node is worth dialing before
+/// the handshake, and lets a node move to a new address, since only the owner
+/// of the node key can publish a record with a higher `seq`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeRecord {
+    /// Increased whenever the content changes.
+    pub seq: u64,
+    pub id: NodeId,
+    pub network_id: u64,
+    pub chain_id: u64,
+    pub capabilities: Vec<Capability>,
+    pub endpoint: NodeEndpoint,
+    signature: H520,
+}
+
+impl NodeRecord {
+    pub fn new(
+        secret: &Secret, id: NodeId, seq: u64, network_id: u64, chain_id: u64,
+        capabilities: Vec<Capability>, endpoint: NodeEndpoint,
+    ) -> Result<Self, Error>
+    {
+        let mut record = NodeRecord {
+            seq,
+            id,
+            network_id,
+            chain_id,
+            capabilities,
+            endpoint,
+            signature: H520::default(),
+        };
+        record.sign(secret)?;
+        Ok(record)
+    }
+
+    /// Change the content of the record with `update`, and sign it with a
+    /// higher `seq`.
+    pub fn update<F>(
+        &mut self, secret: &Secret, update: F,
+    ) -> Result<(), Error>
+    where F: FnOnce(&mut NodeRecord) {
+        update(self);
+        // Using the time as `seq` keeps it increasing across restarts without
+        // persisting the record.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.seq = (self.seq + 1).max(now);
+        self.sign(secret)
+    }
+
+    pub fn entry(&self) -> NodeEntry {
+        NodeEntry {
+            id: self.id,
+            endpoint: self.endpoint.clone(),
+        }
+    }
+
+    /// Whether a node with this record could be a useful peer of the local
+    /// node described by `local`, i.e. it is on the same network and chain,
+    /// and speaks one of the local protocols. The protocols are not checked
+    /// if no protocol is registered locally yet.
+    pub fn is_compatible_with(&self, local: &NodeRecord) -> bool {
+        self.network_id == local.network_id
+            && self.chain_id == local.chain_id
+            && (local.capabilities.is_empty()
+                || self.capabilities.iter().any(|c| {
+                    local.capabilities.iter().any(|lc| {
+                        lc.protocol == c.protocol && lc.version == c.version
+                    })
+                }))
+    }
+
+    fn sign(&mut self, secret: &Secret) -> Result<(), Error> {
+        let signature = sign(secret, &self.content_hash())?;
+        self.signature = H520::from_slice(&signature[..]);
+        Ok(())
+    }
+
+    fn content_hash(&self) -> H256 {
+        let mut rlp = RlpStream::new_list(6);
+        self.append_content(&mut rlp);
+        keccak(rlp.out())
+    }
+
+    fn append_content(&self, rlp: &mut RlpStream) {
+        rlp.append(&self.seq);
+        rlp.append(&self.id);
+        rlp.append(&self.network_id);
+        rlp.append(&self.chain_id);
+        rlp.append_list(&self.capabilities);
+        self.endpoint.to_rlp_list(rlp);
+    }
+}
+
+impl Encodable for NodeRecord {
+    fn rlp_append(&self, rlp: &mut RlpStream) {
+        rlp.begin_list(7);
+        rlp.append(&self.signature);
+        self.append_content(rlp);
+    }
+}
+
+/// Only records signed by the key of `id` are decoded.
+impl Decodable for NodeRecord {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 7 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let record = NodeRecord {
+            signature: rlp.val_at(0)?,
+            seq: rlp.val_at(1)?,
+            id: rlp.val_at(2)?,
+            network_id: rlp.val_at(3)?,
+            chain_id: rlp.val_at(4)?,
+            capabilities: rlp.list_at(5)?,
+            endpoint: NodeEndpoint::from_rlp(&rlp.at(6)?)?,
+        };
+        match recover(&record.signature.into(), &record.content_hash()) {
+            Ok(ref signer) if *signer == record.id => Ok(record),
+            _ => Err(DecoderError::Custom("Invalid node record signature")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keylib::{Generator, KeyPair, Random};
+
+    fn new_record(key: &KeyPair, network_id: u64) -> NodeRecord {
+        NodeRecord::new(
+            key.secret(),
+            *key.public(),
+            1,
+            network_id,
+            1,
+            vec![Capability {
+                protocol: *b"cfx",
+                version: 1,
+            }],
+            "127.0.0.1:32323".parse().unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let key = Random.generate().unwrap();
+        let record = new_record(&key, 1);
+        let decoded: NodeRecord = rlp::decode(&rlp::encode(&record)).unwrap();
+        assert_eq!(decoded, record);
+    }
+
+    #[test]
+    fn test_reject_forged_record() {
+        let key = Random.generate().unwrap();
+        let mut record = new_record(&key, 1);
+        record.endpoint = "10.0.0.1:32323".parse().unwrap();
+        assert!(rlp::decode::<NodeRecord>(&rlp::encode(&record)).is_err());
+
+        // The owner can publish the new endpoint with a higher seq.
+        let old_seq = record.seq;
+        record.update(key.secret(), |_| {}).unwrap();
+        assert!(record.seq > old_seq);
+        let decoded: NodeRecord = rlp::decode(&rlp::encode(&record)).unwrap();
+        assert_eq!(decoded.endpoint, record.endpoint);
+
+        // Others cannot sign records of the node.
+        let other = Random.generate().unwrap();
+        record.update(other.secret(), |_| {}).unwrap();
+        assert!(rlp::decode::<NodeRecord>(&rlp::encode(&record)).is_err());
+    }
+
+    #[test]
+    fn test_compatibility() {
+        let key = Random.generate().unwrap();
+        let local = new_record(&key, 1);
+        let mut remote = new_record(&Random.generate().unwrap(), 1);
+        assert!(remote.is_compatible_with(&local));
+
+        remote.capabilities[0].version = 2;
+        assert!(!remote.is_compatible_with(&local));
+
+        let mut remote = new_record(&Random.generate().unwrap(), 2);
+        assert!(!remote.is_compatible_with(&local));
+        remote.network_id = 1;
+        remote.chain_id = 2;
+        assert!(!remote.is_compatible_with(&local));
+    }
+}
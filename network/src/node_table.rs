@@ -303,8 +303,19 @@ const MAX_NODES: usize = 4096;
 const TRUSTED_NODES_FILE: &str = "trusted_nodes.json";
 const UNTRUSTED_NODES_FILE: &str = "untrusted_nodes.json";
 
-#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Enum, EnumIter)]
-enum NodeReputation {
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Ord,
+    PartialOrd,
+    Eq,
+    PartialEq,
+    Enum,
+    EnumIter,
+    Serialize,
+)]
+pub enum NodeReputation {
     Success = 0,
     Unknown = 1,
     Failure = 2,
@@ -650,6 +661,16 @@ impl NodeTable {
             .collect()
     }
 
+    /// All nodes with their reputation levels.
+    pub fn nodes_with_reputation(&self) -> Vec<(&Node, NodeReputation)> {
+        self.node_reputation_table
+            .iter()
+            .flat_map(|(reputation, nodes)| {
+                nodes.iter().map(move |node| (node, reputation))
+            })
+            .collect()
+    }
+
     /// Get particular node
     pub fn get_mut(&mut self, id: &NodeId) -> Option<&mut Node> {
         let index = self.node_index.get(id);
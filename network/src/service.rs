@@ -3,18 +3,22 @@
 // See http://www.gnu.org/licenses/
 
 use crate::{
+    connection::WriteStatus,
     discovery::{Discovery, DISCOVER_NODES_COUNT},
     io::*,
-    ip_utils::{map_external_address, select_public_address},
+    ip_utils::select_public_address,
+    nat::{PortMappingWorker, DEFAULT_NAT_LEASE_DURATION},
     node_database::NodeDatabase,
     node_table::*,
     session::{self, Session, SessionData},
     session_manager::SessionManager,
-    Capability, Error, ErrorKind, HandlerWorkType, IpFilter,
+    BannedNodeInfo, Capability, Error, ErrorKind, HandlerWorkType, IpFilter,
     NetworkConfiguration, NetworkContext as NetworkContextTrait,
-    NetworkIoMessage, NetworkProtocolHandler, PeerId, PeerInfo, ProtocolId,
+    NetworkIoMessage, NetworkProtocolHandler, NodeInfo, PeerId, PeerInfo,
+    ProtocolId,
 };
 use cfx_bytes::Bytes;
+use ipnetwork::IpNetwork;
 use keccak_hash::keccak;
 use keylib::{sign, Generator, KeyPair, Random, Secret};
 use mio::{deprecated::EventLoop, tcp::*, udp::*, *};
@@ -29,7 +33,10 @@ use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     path::{Path, PathBuf},
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 
@@ -67,6 +74,10 @@ pub const DEFAULT_NODE_TABLE_TIMEOUT: Duration = Duration::from_secs(300);
 // to trusted.
 pub const DEFAULT_CONNECTION_LIFETIME_FOR_PROMOTION: Duration =
     Duration::from_secs(3 * 24 * 3600);
+// The score thresholds to disconnect and to ban a misbehaving peer.
+pub const DEFAULT_PEER_DISCONNECT_SCORE: i32 = -100;
+pub const DEFAULT_PEER_BAN_SCORE: i32 = -300;
+pub const DEFAULT_PEER_BAN_DURATION: Duration = Duration::from_secs(24 * 3600);
 
 pub const MAX_DATAGRAM_SIZE: usize = 1280;
 
@@ -246,6 +257,102 @@ impl NetworkService {
             Err("Network service not started yet!".into())
         }
     }
+
+    fn started_inner(&self) -> Result<&NetworkServiceInner, Error> {
+        match self.inner {
+            Some(ref inner) => Ok(inner),
+            None => Err("Network service not started yet!".into()),
+        }
+    }
+
+    pub fn reserved_nodes(&self) -> Result<Vec<NodeId>, Error> {
+        Ok(self.started_inner()?.reserved_nodes())
+    }
+
+    /// Add a reserved node, which is always connected regardless of the
+    /// outgoing peer limit.
+    pub fn add_reserved_node(&self, node: NodeEntry) -> Result<(), Error> {
+        self.started_inner()?.insert_reserved_node(node);
+        Ok(())
+    }
+
+    /// Forget a reserved node and disconnect it. Returns false if the node was
+    /// not reserved.
+    pub fn remove_reserved_node(&self, id: &NodeId) -> Result<bool, Error> {
+        Ok(self.started_inner()?.remove_reserved_node(id))
+    }
+
+    pub fn banned_nodes(&self) -> Result<Vec<BannedNodeInfo>, Error> {
+        Ok(self.started_inner()?.node_db.read().banned_nodes())
+    }
+
+    /// Ban a node for `duration`, or for the configured ban duration of
+    /// misbehaving peers if it is `None`, and disconnect it.
+    pub fn ban_node(
+        &self, id: &NodeId, duration: Option<Duration>,
+    ) -> Result<(), Error> {
+        let inner = self.started_inner()?;
+        inner.ban_node(id, duration.unwrap_or(inner.config.peer_ban_duration));
+        Ok(())
+    }
+
+    /// Returns false if the node was not banned.
+    pub fn unban_node(&self, id: &NodeId) -> Result<bool, Error> {
+        Ok(self.started_inner()?.node_db.write().unban(id))
+    }
+
+    pub fn get_ip_filter(&self) -> Result<IpFilter, Error> {
+        Ok(self.started_inner()?.get_ip_filter())
+    }
+
+    /// Refuse nodes in the IP range, and disconnect the connected ones.
+    pub fn block_ip(&self, network: IpNetwork) -> Result<(), Error> {
+        self.started_inner()?.block_ip(network);
+        Ok(())
+    }
+
+    /// Returns false if the IP range was not blocked.
+    pub fn unblock_ip(&self, network: &IpNetwork) -> Result<bool, Error> {
+        Ok(self.started_inner()?.unblock_ip(network))
+    }
+
+    /// The maximum numbers of incoming and outgoing peers.
+    pub fn max_peers(&self) -> Result<(usize, usize), Error> {
+        Ok(self.started_inner()?.max_peers())
+    }
+
+    /// Change the maximum numbers of incoming and/or outgoing peers. Peers
+    /// above a lowered limit are not disconnected, but no new ones are
+    /// accepted until the number drops below the limit.
+    pub fn set_max_peers(
+        &self, incoming: Option<usize>, outgoing: Option<usize>,
+    ) -> Result<(), Error> {
+        self.started_inner()?.set_max_peers(incoming, outgoing);
+        Ok(())
+    }
+
+    /// Limit the upload rate to a connected peer in bytes per second, or lift
+    /// the limit if `None`. Returns false if the peer is not connected.
+    pub fn set_peer_upload_rate_limit(
+        &self, id: &NodeId, rate: Option<usize>,
+    ) -> Result<bool, Error> {
+        let inner = self.started_inner()?;
+        match inner.sessions.get_index_by_id(id) {
+            Some(token) => match inner.sessions.get(token) {
+                Some(session) => {
+                    session.write().set_upload_rate_limit(rate);
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// All nodes in the node database with their reputation levels.
+    pub fn get_node_table(&self) -> Result<Vec<NodeInfo>, Error> {
+        Ok(self.started_inner()?.node_db.read().nodes())
+    }
 }
 
 type SharedSession = Arc<RwLock<Session>>;
@@ -258,8 +365,9 @@ pub struct HostMetadata {
     pub local_address: SocketAddr,
     /// Local address + discovery port
     pub local_endpoint: NodeEndpoint,
-    /// Public address + discovery port
-    pub public_endpoint: NodeEndpoint,
+    /// Public address + discovery port. Updated when the NAT port mapping
+    /// changes.
+    pub public_endpoint: RwLock<NodeEndpoint>,
 }
 
 impl HostMetadata {
@@ -283,11 +391,18 @@ pub struct NetworkServiceInner {
     tcp_listener: Mutex<TcpListener>,
     udp_channel: RwLock<UdpChannel>,
     discovery: Mutex<Option<Discovery>>,
+    /// Thread mapping our ports on the NAT gateway, if `nat_enabled`.
+    nat: Mutex<Option<PortMappingWorker>>,
     handlers: RwLock<HashMap<ProtocolId, Arc<NetworkProtocolHandler + Sync>>>,
     timers: RwLock<HashMap<TimerToken, ProtocolTimer>>,
     timer_counter: RwLock<usize>,
     pub node_db: RwLock<NodeDatabase>,
     reserved_nodes: RwLock<HashSet<NodeId>>,
+    /// The peer limits and IP filter of `config`, which can be changed at
+    /// runtime.
+    max_incoming_peers: AtomicUsize,
+    max_outgoing_peers: AtomicUsize,
+    ip_filter: RwLock<IpFilter>,
     nodes: RwLock<HashMap<NodeId, NodeEntry>>,
     dropped_nodes: RwLock<HashSet<StreamToken>>,
 
@@ -401,23 +516,9 @@ impl NetworkServiceInner {
             None => {
                 let public_address =
                     select_public_address(local_endpoint.address.port());
-                let public_endpoint = NodeEndpoint {
+                NodeEndpoint {
                     address: public_address,
                     udp_port: local_endpoint.udp_port,
-                };
-                if config.nat_enabled {
-                    match map_external_address(&local_endpoint) {
-                        Some(endpoint) => {
-                            info!(
-                                "NAT mapped to external address {}",
-                                endpoint.address
-                            );
-                            endpoint
-                        }
-                        None => public_endpoint,
-                    }
-                } else {
-                    public_endpoint
                 }
             }
             Some(addr) => NodeEndpoint {
@@ -429,7 +530,13 @@ impl NetworkServiceInner {
         let allow_ips = config.ip_filter.clone();
         let discovery = {
             if config.discovery_enabled {
-                Some(Discovery::new(&keys, public_endpoint.clone(), allow_ips))
+                Some(Discovery::new(
+                    &keys,
+                    public_endpoint.clone(),
+                    allow_ips,
+                    config.network_id,
+                    config.chain_id,
+                )?)
             } else {
                 None
             }
@@ -437,17 +544,18 @@ impl NetworkServiceInner {
 
         let nodes_path = config.config_path.clone();
 
-        let mut inner = NetworkServiceInner {
+        let inner = NetworkServiceInner {
             metadata: HostMetadata {
                 keys,
                 capabilities: RwLock::new(Vec::new()),
                 local_address: listen_address,
                 local_endpoint,
-                public_endpoint,
+                public_endpoint: RwLock::new(public_endpoint),
             },
             config: config.clone(),
             udp_channel: RwLock::new(UdpChannel::new()),
             discovery: Mutex::new(discovery),
+            nat: Mutex::new(None),
             udp_socket: Mutex::new(udp_socket),
             tcp_listener: Mutex::new(tcp_listener),
             sessions: SessionManager::new(
@@ -463,6 +571,13 @@ impl NetworkServiceInner {
                 config.nodes_per_ip,
             )),
             reserved_nodes: RwLock::new(HashSet::new()),
+            max_incoming_peers: AtomicUsize::new(
+                config.max_incoming_peers as usize,
+            ),
+            max_outgoing_peers: AtomicUsize::new(
+                config.max_outgoing_peers as usize,
+            ),
+            ip_filter: RwLock::new(config.ip_filter.clone()),
             nodes: RwLock::new(HashMap::new()),
             dropped_nodes: RwLock::new(HashSet::new()),
             delayed_queue: None,
@@ -511,7 +626,7 @@ impl NetworkServiceInner {
         }
     }
 
-    pub fn get_ip_filter(&self) -> &IpFilter { &self.config.ip_filter }
+    pub fn get_ip_filter(&self) -> IpFilter { self.ip_filter.read().clone() }
 
     fn add_boot_node(&self, id: &str) {
         match Node::from_str(id) {
@@ -527,22 +642,121 @@ impl NetworkServiceInner {
         }
     }
 
-    fn add_reserved_node(&mut self, id: &str) -> Result<(), Error> {
+    fn add_reserved_node(&self, id: &str) -> Result<(), Error> {
         let n = Node::from_str(id)?;
-        self.node_db.write().insert_trusted(NodeEntry {
-            id: n.id.clone(),
-            endpoint: n.endpoint.clone(),
+        self.insert_reserved_node(NodeEntry {
+            id: n.id,
+            endpoint: n.endpoint,
         });
-        self.reserved_nodes.write().insert(n.id);
         Ok(())
     }
 
+    pub fn insert_reserved_node(&self, entry: NodeEntry) {
+        let id = entry.id;
+        self.node_db.write().insert_trusted(entry);
+        self.reserved_nodes.write().insert(id);
+    }
+
+    /// Forgets a reserved node and disconnects it. Returns false if the node
+    /// was not reserved.
+    pub fn remove_reserved_node(&self, id: &NodeId) -> bool {
+        if !self.reserved_nodes.write().remove(id) {
+            return false;
+        }
+        self.node_db.write().remove(id);
+        if let Some(token) = self.sessions.get_index_by_id(id) {
+            self.dropped_nodes.write().insert(token);
+        }
+        true
+    }
+
+    /// Whether an incoming peer which completed the handshake can be kept.
+    /// Reserved nodes are always accepted, other nodes up to
+    /// `max_incoming_peers`.
+    pub fn accepts_incoming_peer(&self, id: &NodeId) -> bool {
+        if self.reserved_nodes.read().contains(id) {
+            return true;
+        }
+        let (_, _, ingress_count) = self.sessions.stat();
+        ingress_count < self.max_incoming_peers.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn reserved_nodes(&self) -> Vec<NodeId> {
+        self.reserved_nodes.read().iter().cloned().collect()
+    }
+
+    pub fn ban_node(&self, id: &NodeId, duration: Duration) {
+        self.node_db.write().ban(id, duration);
+        if let Some(token) = self.sessions.get_index_by_id(id) {
+            self.dropped_nodes.write().insert(token);
+        }
+    }
+
+    pub fn block_ip(&self, network: IpNetwork) {
+        let filter = {
+            let mut filter = self.ip_filter.write();
+            filter.custom_allow.retain(|n| *n != network);
+            if !filter.custom_block.contains(&network) {
+                filter.custom_block.push(network);
+            }
+            filter.clone()
+        };
+        if let Some(discovery) = self.discovery.lock().as_mut() {
+            discovery.set_ip_filter(filter);
+        }
+
+        let mut dropped_nodes = self.dropped_nodes.write();
+        for session in self.sessions.all() {
+            let session = session.read();
+            if network.contains(session.address().ip()) {
+                dropped_nodes.insert(session.token());
+            }
+        }
+    }
+
+    pub fn unblock_ip(&self, network: &IpNetwork) -> bool {
+        let filter = {
+            let mut filter = self.ip_filter.write();
+            let count = filter.custom_block.len();
+            filter.custom_block.retain(|n| n != network);
+            if filter.custom_block.len() == count {
+                return false;
+            }
+            filter.clone()
+        };
+        if let Some(discovery) = self.discovery.lock().as_mut() {
+            discovery.set_ip_filter(filter);
+        }
+        true
+    }
+
+    pub fn max_peers(&self) -> (usize, usize) {
+        (
+            self.max_incoming_peers.load(AtomicOrdering::Relaxed),
+            self.max_outgoing_peers.load(AtomicOrdering::Relaxed),
+        )
+    }
+
+    pub fn set_max_peers(
+        &self, incoming: Option<usize>, outgoing: Option<usize>,
+    )
+    {
+        if let Some(incoming) = incoming {
+            self.max_incoming_peers
+                .store(incoming, AtomicOrdering::Relaxed);
+        }
+        if let Some(outgoing) = outgoing {
+            self.max_outgoing_peers
+                .store(outgoing, AtomicOrdering::Relaxed);
+        }
+    }
+
     fn initialize_udp_protocols(
         &self, io: &IoContext<NetworkIoMessage>,
     ) -> Result<(), Error> {
         // Initialize discovery
         if let Some(discovery) = self.discovery.lock().as_mut() {
-            let allow_ips = self.config.ip_filter.clone();
+            let allow_ips = self.get_ip_filter();
             let nodes = self
                 .node_db
                 .read()
@@ -608,7 +822,8 @@ impl NetworkServiceInner {
 
     fn has_enough_outgoing_peers(&self) -> bool {
         let (_, egress_count, _) = self.sessions.stat();
-        return egress_count >= self.config.max_outgoing_peers as usize;
+        return egress_count
+            >= self.max_outgoing_peers.load(AtomicOrdering::Relaxed);
     }
 
     fn on_housekeeping(&self, io: &IoContext<NetworkIoMessage>) {
@@ -616,6 +831,43 @@ impl NetworkServiceInner {
         self.drop_peers(io);
     }
 
+    /// Starts mapping our ports on the NAT gateway in the background. The
+    /// external endpoint is posted back with `NetworkIoMessage::NatMapped`.
+    fn start_nat_mapping(
+        &self, io: &IoContext<NetworkIoMessage>,
+    ) -> Result<(), Error> {
+        if !self.config.nat_enabled || self.config.public_address.is_some() {
+            return Ok(());
+        }
+        let channel = io.channel();
+        let worker = PortMappingWorker::spawn(
+            self.metadata.local_endpoint.clone(),
+            DEFAULT_NAT_LEASE_DURATION,
+            move |endpoint, method| {
+                channel
+                    .send(NetworkIoMessage::NatMapped { endpoint, method })
+                    .is_ok()
+            },
+        )?;
+        *self.nat.lock() = Some(worker);
+        Ok(())
+    }
+
+    /// Advertises the external endpoint of a new or renewed NAT port mapping.
+    fn on_nat_mapped(&self, endpoint: &NodeEndpoint, method: &str) {
+        if *endpoint == *self.metadata.public_endpoint.read() {
+            return;
+        }
+        info!(
+            "NAT mapped to external address {} via {}",
+            endpoint.address, method
+        );
+        *self.metadata.public_endpoint.write() = endpoint.clone();
+        if let Some(discovery) = self.discovery.lock().as_mut() {
+            discovery.set_public_endpoint(endpoint.clone());
+        }
+    }
+
     // Connect to all reserved and trusted peers if not yet
     fn connect_peers(&self, io: &IoContext<NetworkIoMessage>) {
         if self.metadata.capabilities.read().is_empty() {
@@ -623,10 +875,10 @@ impl NetworkServiceInner {
         }
 
         let self_id = self.metadata.id().clone();
-        let max_outgoing_peers = self.config.max_outgoing_peers as usize;
-        //let max_incoming_peers = self.config.max_incoming_peers;
+        let max_outgoing_peers =
+            self.max_outgoing_peers.load(AtomicOrdering::Relaxed);
         let max_handshakes = self.config.max_handshakes;
-        let allow_ips = self.config.ip_filter.clone();
+        let allow_ips = self.get_ip_filter();
 
         let (handshake_count, egress_count, ingress_count) =
             self.sessions.stat();
@@ -649,7 +901,11 @@ impl NetworkServiceInner {
         let max_handshakes_per_round = max_handshakes / 2;
         let mut started: usize = 0;
         for id in nodes
-            .filter(|id| !self.sessions.contains_node(id) && *id != self_id)
+            .filter(|id| {
+                !self.sessions.contains_node(id)
+                    && *id != self_id
+                    && !self.node_db.read().is_banned(id)
+            })
             .take(min(
                 max_handshakes_per_round as usize,
                 max_handshakes as usize - handshake_count,
@@ -727,11 +983,14 @@ impl NetworkServiceInner {
         for session in self.sessions.all() {
             let sess = session.read();
             if !sess.expired() {
+                let nodeid = sess.id().unwrap_or(&NodeId::default()).clone();
                 peers.push(PeerInfo {
                     id: sess.token(),
-                    nodeid: sess.id().unwrap_or(&NodeId::default()).clone(),
+                    score: self.node_db.read().score(&nodeid),
+                    nodeid,
                     addr: sess.address(),
                     caps: sess.metadata.peer_capabilities.clone(),
+                    traffic: sess.traffic_stats(),
                 });
             }
         }
@@ -751,10 +1010,42 @@ impl NetworkServiceInner {
         }
     }
 
+    pub fn get_peer_protocol_version(
+        &self, peer: PeerId, protocol: ProtocolId,
+    ) -> Option<u8> {
+        self.sessions
+            .get(peer)
+            .and_then(|session| session.read().protocol_version(protocol))
+    }
+
+    fn decrease_peer_score(
+        &self, peer: PeerId, amount: i32, io: &IoContext<NetworkIoMessage>,
+    ) {
+        let id = match self.sessions.get(peer) {
+            Some(session) => match session.read().id() {
+                Some(id) => id.clone(),
+                None => return,
+            },
+            None => return,
+        };
+
+        let score = self.node_db.write().decrease_score(&id, amount);
+        debug!("Decrease score of peer {} by {} to {}", peer, amount, score);
+        if score <= self.config.peer_ban_score {
+            info!("Ban node {:?} with score {}", id, score);
+            self.node_db.write().ban(&id, self.config.peer_ban_duration);
+            self.kill_connection(peer, io, true);
+        } else if score <= self.config.peer_disconnect_score {
+            info!("Disconnect node {:?} with score {}", id, score);
+            self.kill_connection(peer, io, true);
+        }
+    }
+
     fn start(&self, io: &IoContext<NetworkIoMessage>) -> Result<(), Error> {
         self.initialize_udp_protocols(io)?;
         io.register_stream(UDP_MESSAGE)?;
         io.register_stream(TCP_ACCEPT)?;
+        self.start_nat_mapping(io)?;
         Ok(())
     }
 
@@ -879,8 +1170,17 @@ impl NetworkServiceInner {
 
         if let Some(session) = self.sessions.get(stream) {
             let mut sess = session.write();
-            if let Err(e) = sess.writable(io) {
-                trace!("{}: Session write error: {:?}", stream, e);
+            match sess.writable(io) {
+                Ok(WriteStatus::RateLimited(delay)) => {
+                    // Resume writing once the upload rate allows it.
+                    sess.schedule_write(io, delay).unwrap_or_else(|e| {
+                        debug!("Error registering write timer: {:?}", e)
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    trace!("{}: Session write error: {:?}", stream, e);
+                }
             }
             if sess.done() {
                 io.deregister_stream(stream).unwrap_or_else(|e| {
@@ -1107,6 +1407,12 @@ impl IoHandler<NetworkIoMessage> for NetworkServiceInner {
 
     fn timeout(&self, io: &IoContext<NetworkIoMessage>, token: TimerToken) {
         match token {
+            FIRST_SESSION...LAST_SESSION => {
+                if let Some(session) = self.sessions.get(token) {
+                    session.write().on_write_timer();
+                }
+                self.session_writable(token, io)
+            }
             HOUSEKEEPING => self.on_housekeeping(io),
             DISCOVERY_REFRESH => {
                 // Run the _slow_ discovery if enough peers are connected
@@ -1191,6 +1497,9 @@ impl IoHandler<NetworkIoMessage> for NetworkServiceInner {
                         version,
                     });
                 }
+                if let Some(discovery) = self.discovery.lock().as_mut() {
+                    discovery.set_capabilities(caps.clone());
+                }
             }
             NetworkIoMessage::AddTimer {
                 ref protocol,
@@ -1229,6 +1538,10 @@ impl IoHandler<NetworkIoMessage> for NetworkServiceInner {
                     warn!("Work is dispatched to unknown handler");
                 }
             }
+            NetworkIoMessage::NatMapped {
+                ref endpoint,
+                method,
+            } => self.on_nat_mapped(endpoint, method),
         }
     }
 
@@ -1411,6 +1724,11 @@ impl<'a> NetworkContextTrait for NetworkContext<'a> {
         self.network_service.get_peer_node_id(peer)
     }
 
+    fn get_protocol_version(&self, peer: PeerId) -> Option<u8> {
+        self.network_service
+            .get_peer_protocol_version(peer, self.protocol)
+    }
+
     fn send(
         &self, peer: PeerId, msg: Vec<u8>, priority: SendQueuePriority,
     ) -> Result<(), Error> {
@@ -1469,6 +1787,11 @@ impl<'a> NetworkContextTrait for NetworkContext<'a> {
         self.network_service.kill_connection(peer, self.io, true);
     }
 
+    fn decrease_peer_score(&self, peer: PeerId, amount: i32) {
+        self.network_service
+            .decrease_peer_score(peer, amount, self.io);
+    }
+
     fn register_timer(
         &self, token: TimerToken, delay: Duration,
     ) -> Result<(), Error> {
@@ -1492,6 +1815,8 @@ impl<'a> NetworkContextTrait for NetworkContext<'a> {
             })
             .expect("Error sending network IO message");
     }
+
+    fn network_id(&self) -> u64 { self.network_service.config.network_id }
 }
 
 fn save_key(path: &Path, key: &Secret) {
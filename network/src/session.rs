@@ -3,9 +3,10 @@
 // See http://www.gnu.org/licenses/
 
 use crate::{
+    bandwidth::{MessageTraffic, PeerTrafficStats},
     connection::{
         Connection as TcpConnection, PacketSizer as PacketSizerTrait,
-        SendQueueStatus, MAX_PAYLOAD_SIZE,
+        SendQueueStatus, WriteStatus, MAX_PAYLOAD_SIZE,
     },
     hash::keccak,
     node_database::InsertResult,
@@ -22,7 +23,8 @@ use keylib::{recover, sign};
 use mio::{deprecated::*, tcp::*, *};
 use priority_send_queue::SendQueuePriority;
 use rlp::{Rlp, RlpStream};
-use std::{fmt, net::SocketAddr, str};
+use snappy;
+use std::{fmt, net::SocketAddr, str, time::Duration};
 
 struct PacketSizer;
 
@@ -51,6 +53,12 @@ pub struct Session {
     sent_hello: bool,
     had_hello: bool,
     expired: bool,
+    /// Whether both sides support compressed user packets.
+    compression: bool,
+    message_traffic: MessageTraffic,
+    /// Whether a timer is registered to resume writing once the upload rate
+    /// allows it, so that at most one is pending.
+    write_timer_pending: bool,
 }
 
 pub enum SessionData {
@@ -65,6 +73,17 @@ const PACKET_DISCONNECT: u8 = 0x01;
 const PACKET_PING: u8 = 0x02;
 const PACKET_PONG: u8 = 0x03;
 pub const PACKET_USER: u8 = 0x10;
+/// A `PACKET_USER` payload, i.e. protocol id and data, compressed with snappy.
+const PACKET_USER_COMPRESSED: u8 = 0x11;
+
+/// Advertised in `PACKET_HELLO` besides the protocol capabilities by nodes
+/// which accept `PACKET_USER_COMPRESSED`.
+const COMPRESSION_CAPABILITY: Capability = Capability {
+    protocol: *b"snp",
+    version: 1,
+};
+/// Smaller user packets are not worth compressing.
+const MIN_COMPRESSION_SIZE: usize = 256;
 
 impl Session {
     pub fn new<Message: Send + Sync + Clone + 'static>(
@@ -85,7 +104,13 @@ impl Session {
             sent_hello: false,
             had_hello: false,
             expired: false,
+            compression: false,
+            message_traffic: MessageTraffic::default(),
+            write_timer_pending: false,
         };
+        session
+            .connection
+            .set_upload_rate_limit(host.config.peer_upload_rate_limit);
         if true {
             session.write_hello(io, host)?;
             session.sent_hello = true;
@@ -100,6 +125,15 @@ impl Session {
             .any(|c| c.protocol == protocol)
     }
 
+    /// The version of `protocol` negotiated in the hello handshake.
+    pub fn protocol_version(&self, protocol: ProtocolId) -> Option<u8> {
+        self.metadata
+            .capabilities
+            .iter()
+            .find(|c| c.protocol == protocol)
+            .map(|c| c.version)
+    }
+
     /// Get id of the remote peer
     pub fn id(&self) -> Option<&NodeId> { self.metadata.id.as_ref() }
 
@@ -119,6 +153,20 @@ impl Session {
 
     pub fn address(&self) -> SocketAddr { self.address }
 
+    pub fn traffic_stats(&self) -> PeerTrafficStats {
+        PeerTrafficStats {
+            ingress: self.connection.ingress(),
+            egress: self.connection.egress(),
+            messages: self.message_traffic.stats(),
+        }
+    }
+
+    /// Limit the upload rate to the peer in bytes per second, or lift the
+    /// limit if `None`.
+    pub fn set_upload_rate_limit(&mut self, rate: Option<usize>) {
+        self.connection.set_upload_rate_limit(rate);
+    }
+
     pub fn register_socket<H: Handler>(
         &self, reg: Token, event_loop: &mut EventLoop<H>,
     ) -> Result<(), Error> {
@@ -153,7 +201,15 @@ impl Session {
             self.sent_hello = true;
         }
         match self.connection.readable()? {
-            Some(data) => Ok(self.read_packet(io, &data, host)?),
+            Some(data) => {
+                let session_data = self.read_packet(io, &data, host)?;
+                if let SessionData::Message { ref data, protocol } =
+                    session_data
+                {
+                    self.message_traffic.on_ingress(protocol, data);
+                }
+                Ok(session_data)
+            }
             None => Ok(SessionData::None),
         }
     }
@@ -232,17 +288,12 @@ impl Session {
                 Ok(SessionData::Continue)
             }
             PACKET_PONG => Ok(SessionData::Continue),
-            PACKET_USER => {
-                if data.len() < 3 {
-                    Err(ErrorKind::Decoder.into())
-                } else {
-                    let mut protocol: ProtocolId = [0u8; 3];
-                    protocol.clone_from_slice(&data[..3]);
-                    Ok(SessionData::Message {
-                        data: (&data[3..]).to_vec(),
-                        protocol,
-                    })
+            PACKET_USER => read_user_packet(data),
+            PACKET_USER_COMPRESSED => {
+                if !self.compression {
+                    return Err(ErrorKind::BadProtocol.into());
                 }
+                read_user_packet(&decompress_user_packet(data)?)
             }
             _ => {
                 debug!(
@@ -259,6 +310,17 @@ impl Session {
         host: &NetworkServiceInner,
     ) -> Result<(), Error>
     {
+        if host.node_db.read().is_banned(id) {
+            debug!("Refuse banned node {:?}, session = {:?}", id, self);
+            return Err(self.disconnect(io, DisconnectReason::Blacklisted));
+        }
+        // The peer limit is checked once the node id is known, so that
+        // reserved nodes can always connect.
+        if !self.metadata.originated && !host.accepts_incoming_peer(id) {
+            debug!("Refuse incoming node {:?}: too many incoming peers", id);
+            return Err(self.disconnect(io, DisconnectReason::TooManyPeers));
+        }
+
         let peer_caps: Vec<Capability> = rlp.list_at(0)?;
 
         let mut caps: Vec<Capability> = Vec::new();
@@ -290,6 +352,8 @@ impl Session {
         }
         caps.sort();
 
+        self.compression = host.config.compression_enabled
+            && peer_caps.contains(&COMPRESSION_CAPABILITY);
         self.metadata.capabilities = caps;
         self.metadata.peer_capabilities = peer_caps;
         if self.metadata.capabilities.is_empty() {
@@ -315,7 +379,7 @@ impl Session {
         if !entry.endpoint.is_valid() {
             debug!("Got invalid endpoint {:?}, session = {:?}", entry, self);
             return Err(self.disconnect(io, DisconnectReason::WrongEndpointInfo));
-        } else if !(entry.endpoint.is_allowed(host.get_ip_filter())
+        } else if !(entry.endpoint.is_allowed(&host.get_ip_filter())
             && entry.id != *host.metadata.id())
         {
             debug!(
@@ -363,6 +427,39 @@ impl Session {
             );
             bail!(ErrorKind::OversizedPacket);
         }
+        let compressed = if packet_id == PACKET_USER
+            && self.compression
+            && packet_size >= MIN_COMPRESSION_SIZE
+        {
+            compress_user_packet(protocol, data)
+        } else {
+            None
+        };
+        let status = match compressed {
+            Some(compressed) => self.write_packet(
+                io,
+                PACKET_USER_COMPRESSED,
+                None,
+                &compressed,
+                priority,
+            )?,
+            None => {
+                self.write_packet(io, packet_id, protocol, data, priority)?
+            }
+        };
+        if let (PACKET_USER, Some(protocol)) = (packet_id, protocol) {
+            self.message_traffic.on_egress(protocol, data);
+        }
+        Ok(status)
+    }
+
+    fn write_packet<Message: Send + Sync + Clone>(
+        &mut self, io: &IoContext<Message>, packet_id: u8,
+        protocol: Option<ProtocolId>, data: &[u8], priority: SendQueuePriority,
+    ) -> Result<SendQueueStatus, Error>
+    {
+        let packet_size =
+            1 + protocol.map(|p| p.len()).unwrap_or(0) + data.len();
         let mut packet = BytesMut::with_capacity(3 + packet_size);
         packet.put_uint_le(packet_size as u64, 3);
         packet.put_u8(packet_id);
@@ -408,9 +505,13 @@ impl Session {
         &mut self, io: &IoContext<Message>, host: &NetworkServiceInner,
     ) -> Result<(), Error> {
         debug!("Sending Hello, session = {:?}", self);
+        let mut caps = host.metadata.capabilities.read().clone();
+        if host.config.compression_enabled {
+            caps.push(COMPRESSION_CAPABILITY);
+        }
         let mut rlp = RlpStream::new_list(2);
-        rlp.append_list(&*host.metadata.capabilities.read());
-        host.metadata.public_endpoint.to_rlp_list(&mut rlp);
+        rlp.append_list(&caps);
+        host.metadata.public_endpoint.read().to_rlp_list(&mut rlp);
 
         let mut packet =
             cfx_bytes::Bytes::with_capacity(rlp.as_raw().len() + 32 + 65);
@@ -439,10 +540,22 @@ impl Session {
 
     pub fn writable<Message: Send + Sync + Clone>(
         &mut self, io: &IoContext<Message>,
+    ) -> Result<WriteStatus, Error> {
+        self.connection.writable(io)
+    }
+
+    /// Resume writing after `delay`, unless a timer for it is pending.
+    pub fn schedule_write<Message: Send + Sync + Clone>(
+        &mut self, io: &IoContext<Message>, delay: Duration,
     ) -> Result<(), Error> {
-        self.connection.writable(io)?;
+        if !self.write_timer_pending {
+            io.register_timer_once(self.token(), delay)?;
+            self.write_timer_pending = true;
+        }
         Ok(())
     }
+
+    pub fn on_write_timer(&mut self) { self.write_timer_pending = false; }
 }
 
 impl fmt::Debug for Session {
@@ -451,3 +564,83 @@ impl fmt::Debug for Session {
                self.token(), self.id(), self.metadata.originated, self.address, self.sent_hello, self.had_hello, self.expired)
     }
 }
+
+fn read_user_packet(data: &[u8]) -> Result<SessionData, Error> {
+    if data.len() < 3 {
+        return Err(ErrorKind::Decoder.into());
+    }
+    let mut protocol: ProtocolId = [0u8; 3];
+    protocol.clone_from_slice(&data[..3]);
+    Ok(SessionData::Message {
+        data: data[3..].to_vec(),
+        protocol,
+    })
+}
+
+/// Returns the compressed protocol id and data, or `None` if compression does
+/// not make the packet smaller.
+fn compress_user_packet(
+    protocol: Option<ProtocolId>, data: &[u8],
+) -> Option<Vec<u8>> {
+    let mut payload = Vec::with_capacity(3 + data.len());
+    if let Some(protocol) = protocol {
+        payload.extend_from_slice(&protocol);
+    }
+    payload.extend_from_slice(data);
+    let compressed = snappy::compress(&payload);
+    if compressed.len() < payload.len() {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+/// Decompresses a `PACKET_USER_COMPRESSED` payload. The decompressed size is
+/// checked against the maximum packet size before anything is allocated.
+fn decompress_user_packet(data: &[u8]) -> Result<Vec<u8>, Error> {
+    if snappy::decompressed_len(data)? > MAX_PAYLOAD_SIZE {
+        return Err(ErrorKind::OversizedPacket.into());
+    }
+    Ok(snappy::decompress(data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_user_packet() {
+        let data = vec![7u8; 4096];
+        let compressed = compress_user_packet(Some(*b"cfx"), &data).unwrap();
+        assert!(compressed.len() < data.len());
+
+        let payload = decompress_user_packet(&compressed).unwrap();
+        match read_user_packet(&payload).unwrap() {
+            SessionData::Message {
+                data: decompressed,
+                protocol,
+            } => {
+                assert_eq!(&protocol, b"cfx");
+                assert_eq!(decompressed, data);
+            }
+            _ => panic!("expected a user message"),
+        }
+    }
+
+    #[test]
+    fn test_incompressible_user_packet() {
+        let data: Vec<u8> = (0..64u8).collect();
+        assert!(compress_user_packet(Some(*b"cfx"), &data).is_none());
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        // A snappy header claiming a 4 GiB payload.
+        let bomb = [0xff, 0xff, 0xff, 0xff, 0x0f, 0x00];
+        match decompress_user_packet(&bomb) {
+            Err(Error(ErrorKind::OversizedPacket, _)) => {}
+            _ => panic!("expected an oversized packet error"),
+        }
+        assert!(decompress_user_packet(&[0xff]).is_err());
+    }
+}
@@ -0,0 +1,688 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! An in-process network for testing protocol handlers deterministically.
+//!
+//! Every node is a `NetworkProtocolHandler` driven through an in-memory
+//! `NetworkContext`. Messages, timers and dispatched works are events ordered
+//! in virtual time, so a run only depends on the configured latencies and
+//! the random seed, which also decides the lost messages. The handlers see
+//! the virtual time through `NetworkContext::now()`.
+//!
+//! The peer id of a node is its index in the network.
+
+use crate::{
+    node_table::NodeId, Error, HandlerWorkType, NetworkContext,
+    NetworkProtocolHandler, PeerId, ProtocolId,
+};
+use io::TimerToken;
+use parking_lot::Mutex;
+use priority_send_queue::SendQueuePriority;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cmp::{self, Ordering},
+    collections::{BTreeSet, BinaryHeap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The network id of all simulated nodes.
+const SIMULATED_NETWORK_ID: u64 = 1;
+
+/// Delivery of messages from one node to another.
+#[derive(Clone, Debug)]
+pub struct LinkConfig {
+    pub latency: Duration,
+    /// A uniformly random delay of up to `jitter` is added to `latency`.
+    /// Messages on a link are still delivered in order.
+    pub jitter: Duration,
+    /// The probability that a message is lost.
+    pub loss_rate: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        LinkConfig {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(0),
+            loss_rate: 0.0,
+        }
+    }
+}
+
+enum Event {
+    Connect(usize, usize),
+    Disconnect(usize, usize),
+    Deliver {
+        from: usize,
+        to: usize,
+        data: Vec<u8>,
+    },
+    Timer {
+        node: usize,
+        token: TimerToken,
+        period: Duration,
+    },
+    Work {
+        node: usize,
+        work_type: HandlerWorkType,
+    },
+}
+
+struct ScheduledEvent {
+    time: Duration,
+    /// Orders the events scheduled at the same time.
+    seq: u64,
+    event: Event,
+}
+
+impl PartialEq for ScheduledEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.time == other.time && self.seq == other.seq
+    }
+}
+
+impl Eq for ScheduledEvent {}
+
+impl PartialOrd for ScheduledEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledEvent {
+    // Reversed, so that `BinaryHeap` pops the earliest event first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.time, other.seq).cmp(&(self.time, self.seq))
+    }
+}
+
+struct State {
+    now: Duration,
+    seq: u64,
+    events: BinaryHeap<ScheduledEvent>,
+    rng: StdRng,
+    default_link: LinkConfig,
+    links: HashMap<(usize, usize), LinkConfig>,
+    /// The time of the last scheduled delivery on each link.
+    last_delivery: HashMap<(usize, usize), Duration>,
+    /// Connected pairs of nodes, with the smaller index first.
+    connections: BTreeSet<(usize, usize)>,
+    /// The group of each node while the network is partitioned.
+    partition: Option<Vec<usize>>,
+    /// Connections closed by the partition, which are restored on healing.
+    partitioned_connections: BTreeSet<(usize, usize)>,
+    scores: HashMap<(usize, usize), i32>,
+    delivered: u64,
+    lost: u64,
+}
+
+impl State {
+    fn schedule(&mut self, delay: Duration, event: Event) {
+        self.seq += 1;
+        self.events.push(ScheduledEvent {
+            time: self.now + delay,
+            seq: self.seq,
+            event,
+        });
+    }
+
+    fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.connections.contains(&pair(a, b))
+    }
+
+    fn is_partitioned(&self, a: usize, b: usize) -> bool {
+        match self.partition {
+            Some(ref groups) => groups[a] != groups[b],
+            None => false,
+        }
+    }
+
+    fn link(&self, from: usize, to: usize) -> &LinkConfig {
+        self.links.get(&(from, to)).unwrap_or(&self.default_link)
+    }
+
+    fn send(&mut self, from: usize, to: usize, data: Vec<u8>) {
+        let link = self.link(from, to).clone();
+        if link.loss_rate > 0.0 && self.rng.gen::<f64>() < link.loss_rate {
+            trace!("Simulated message lost, from = {}, to = {}", from, to);
+            self.lost += 1;
+            return;
+        }
+
+        let jitter = link.jitter.as_secs() * 1_000_000_000
+            + link.jitter.subsec_nanos() as u64;
+        let jitter = if jitter > 0 {
+            Duration::from_nanos(self.rng.gen_range(0, jitter + 1))
+        } else {
+            Duration::from_nanos(0)
+        };
+        let mut time = self.now + link.latency + jitter;
+        if let Some(last) = self.last_delivery.get(&(from, to)) {
+            time = cmp::max(time, *last);
+        }
+        self.last_delivery.insert((from, to), time);
+        let delay = time - self.now;
+        self.schedule(delay, Event::Deliver { from, to, data });
+    }
+}
+
+fn pair(a: usize, b: usize) -> (usize, usize) {
+    (cmp::min(a, b), cmp::max(a, b))
+}
+
+struct SimulatedNode {
+    id: NodeId,
+    handler: Arc<NetworkProtocolHandler + Sync>,
+}
+
+/// A network of protocol handlers speaking `protocol` at `version`.
+pub struct SimulatedNetwork {
+    protocol: ProtocolId,
+    version: u8,
+    nodes: Vec<SimulatedNode>,
+    state: Mutex<State>,
+    /// The instant of virtual time zero.
+    start: Instant,
+    after_event: Option<Box<Fn() + Send + Sync>>,
+}
+
+impl SimulatedNetwork {
+    pub fn new(protocol: ProtocolId, version: u8, seed: u64) -> Self {
+        let mut rng_seed = [0u8; 32];
+        for (i, byte) in rng_seed.iter_mut().enumerate().take(8) {
+            *byte = (seed >> (8 * i)) as u8;
+        }
+        SimulatedNetwork {
+            protocol,
+            version,
+            nodes: Vec::new(),
+            state: Mutex::new(State {
+                now: Duration::from_millis(0),
+                seq: 0,
+                events: BinaryHeap::new(),
+                rng: StdRng::from_seed(rng_seed),
+                default_link: LinkConfig::default(),
+                links: HashMap::new(),
+                last_delivery: HashMap::new(),
+                connections: BTreeSet::new(),
+                partition: None,
+                partitioned_connections: BTreeSet::new(),
+                scores: HashMap::new(),
+                delivered: 0,
+                lost: 0,
+            }),
+            start: Instant::now(),
+            after_event: None,
+        }
+    }
+
+    pub fn protocol(&self) -> ProtocolId { self.protocol }
+
+    /// Add a node and initialize its handler. Returns the index of the node,
+    /// which is also its peer id on the other nodes.
+    pub fn add_node(
+        &mut self, handler: Arc<NetworkProtocolHandler + Sync>,
+    ) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(SimulatedNode {
+            id: NodeId::from(index as u64 + 1),
+            handler,
+        });
+        if let Some(ref mut groups) = self.state.get_mut().partition {
+            // A node added while partitioned is isolated.
+            groups.push(usize::max_value() - index);
+        }
+        self.with_context(index, |io| self.nodes[index].handler.initialize(io));
+        index
+    }
+
+    pub fn node_count(&self) -> usize { self.nodes.len() }
+
+    pub fn node_id(&self, node: usize) -> NodeId { self.nodes[node].id }
+
+    /// The current virtual time, starting from zero.
+    pub fn now(&self) -> Duration { self.state.lock().now }
+
+    /// Call `f` after every event is handled, e.g. to wait for the work
+    /// which the handlers pass to other threads, so that the next event sees
+    /// its result.
+    pub fn set_after_event<F>(&mut self, f: F)
+    where F: Fn() + Send + Sync + 'static {
+        self.after_event = Some(Box::new(f));
+    }
+
+    pub fn set_default_link(&self, link: LinkConfig) {
+        self.state.lock().default_link = link;
+    }
+
+    /// Configure the links between `a` and `b` in both directions.
+    pub fn set_link(&self, a: usize, b: usize, link: LinkConfig) {
+        let mut state = self.state.lock();
+        state.links.insert((a, b), link.clone());
+        state.links.insert((b, a), link);
+    }
+
+    pub fn connect(&self, a: usize, b: usize) {
+        assert!(a != b && a < self.nodes.len() && b < self.nodes.len());
+        self.state
+            .lock()
+            .schedule(Duration::from_millis(0), Event::Connect(a, b));
+    }
+
+    pub fn connect_all(&self) {
+        for a in 0..self.nodes.len() {
+            for b in (a + 1)..self.nodes.len() {
+                self.connect(a, b);
+            }
+        }
+    }
+
+    pub fn disconnect(&self, a: usize, b: usize) {
+        self.state
+            .lock()
+            .schedule(Duration::from_millis(0), Event::Disconnect(a, b));
+    }
+
+    pub fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.state.lock().is_connected(a, b)
+    }
+
+    /// Split the network into `groups`. The nodes not in any group are
+    /// isolated. Connections across groups are closed, and no new ones can be
+    /// made until the partition is healed.
+    pub fn partition(&self, groups: &[&[usize]]) {
+        let mut state = self.state.lock();
+        let mut node_groups: Vec<usize> = (0..self.nodes.len())
+            .map(|node| usize::max_value() - node)
+            .collect();
+        for (group, nodes) in groups.iter().enumerate() {
+            for node in nodes.iter() {
+                node_groups[*node] = group;
+            }
+        }
+        state.partition = Some(node_groups);
+
+        let cut: Vec<_> = state
+            .connections
+            .iter()
+            .filter(|(a, b)| state.is_partitioned(*a, *b))
+            .cloned()
+            .collect();
+        for (a, b) in cut {
+            state.partitioned_connections.insert((a, b));
+            state.schedule(Duration::from_millis(0), Event::Disconnect(a, b));
+        }
+    }
+
+    /// Remove the partition and restore the connections it closed.
+    pub fn heal(&self) {
+        let mut state = self.state.lock();
+        state.partition = None;
+        let restored: Vec<_> =
+            state.partitioned_connections.iter().cloned().collect();
+        state.partitioned_connections.clear();
+        for (a, b) in restored {
+            state.schedule(Duration::from_millis(0), Event::Connect(a, b));
+        }
+    }
+
+    /// The sum of the score changes `node` applied to `peer`.
+    pub fn peer_score(&self, node: usize, peer: usize) -> i32 {
+        *self.state.lock().scores.get(&(node, peer)).unwrap_or(&0)
+    }
+
+    /// The numbers of delivered and lost messages.
+    pub fn message_stats(&self) -> (u64, u64) {
+        let state = self.state.lock();
+        (state.delivered, state.lost)
+    }
+
+    /// Run `f` with the network context of `node`, e.g. to make its handler
+    /// send messages.
+    pub fn with_context<F, R>(&self, node: usize, f: F) -> R
+    where F: FnOnce(&NetworkContext) -> R {
+        f(&SimulatedContext {
+            network: self,
+            node,
+        })
+    }
+
+    /// Process the events in the next `duration` of virtual time.
+    pub fn run_for(&self, duration: Duration) {
+        let end = self.now() + duration;
+        while self.step(end) {}
+        self.state.lock().now = end;
+    }
+
+    /// Process events until `condition` holds or `timeout` of virtual time
+    /// elapses. Returns whether `condition` holds.
+    pub fn run_until<F>(&self, timeout: Duration, condition: F) -> bool
+    where F: Fn(&SimulatedNetwork) -> bool {
+        let end = self.now() + timeout;
+        while !condition(self) {
+            if !self.step(end) {
+                self.state.lock().now = end;
+                return condition(self);
+            }
+        }
+        true
+    }
+
+    /// Process the next event scheduled no later than `end`. Returns false
+    /// if there is no such event.
+    fn step(&self, end: Duration) -> bool {
+        let event = {
+            let mut state = self.state.lock();
+            match state.events.peek() {
+                Some(next) if next.time <= end => {}
+                _ => return false,
+            }
+            let next = state.events.pop().expect("peeked above");
+            state.now = next.time;
+            next.event
+        };
+        // The lock is released, since the handlers call back into the
+        // network context.
+        self.handle(event);
+        if let Some(ref after_event) = self.after_event {
+            after_event();
+        }
+        true
+    }
+
+    fn handle(&self, event: Event) {
+        match event {
+            Event::Connect(a, b) => {
+                {
+                    let mut state = self.state.lock();
+                    if state.is_connected(a, b) || state.is_partitioned(a, b) {
+                        return;
+                    }
+                    state.connections.insert(pair(a, b));
+                }
+                self.with_context(a, |io| {
+                    self.nodes[a].handler.on_peer_connected(io, b)
+                });
+                self.with_context(b, |io| {
+                    self.nodes[b].handler.on_peer_connected(io, a)
+                });
+            }
+            Event::Disconnect(a, b) => {
+                if !self.state.lock().connections.remove(&pair(a, b)) {
+                    return;
+                }
+                self.with_context(a, |io| {
+                    self.nodes[a].handler.on_peer_disconnected(io, b)
+                });
+                self.with_context(b, |io| {
+                    self.nodes[b].handler.on_peer_disconnected(io, a)
+                });
+            }
+            Event::Deliver { from, to, data } => {
+                {
+                    let mut state = self.state.lock();
+                    // Messages in flight are lost when the connection is
+                    // closed.
+                    if !state.is_connected(from, to) {
+                        state.lost += 1;
+                        return;
+                    }
+                    state.delivered += 1;
+                }
+                self.with_context(to, |io| {
+                    self.nodes[to].handler.on_message(io, from, &data)
+                });
+            }
+            Event::Timer {
+                node,
+                token,
+                period,
+            } => {
+                self.state.lock().schedule(
+                    period,
+                    Event::Timer {
+                        node,
+                        token,
+                        period,
+                    },
+                );
+                self.with_context(node, |io| {
+                    self.nodes[node].handler.on_timeout(io, token)
+                });
+            }
+            Event::Work { node, work_type } => {
+                self.with_context(node, |io| {
+                    self.nodes[node].handler.on_work_dispatch(io, work_type)
+                });
+            }
+        }
+    }
+}
+
+struct SimulatedContext<'a> {
+    network: &'a SimulatedNetwork,
+    node: usize,
+}
+
+impl<'a> NetworkContext for SimulatedContext<'a> {
+    fn get_peer_node_id(&self, peer: PeerId) -> NodeId {
+        self.network
+            .nodes
+            .get(peer)
+            .map(|node| node.id)
+            .unwrap_or_default()
+    }
+
+    fn get_protocol_version(&self, peer: PeerId) -> Option<u8> {
+        if self.network.state.lock().is_connected(self.node, peer) {
+            Some(self.network.version)
+        } else {
+            None
+        }
+    }
+
+    fn send(
+        &self, peer: PeerId, msg: Vec<u8>, _priority: SendQueuePriority,
+    ) -> Result<(), Error> {
+        let mut state = self.network.state.lock();
+        if !state.is_connected(self.node, peer) {
+            bail!("Peer {} is not connected", peer);
+        }
+        state.send(self.node, peer, msg);
+        Ok(())
+    }
+
+    fn disconnect_peer(&self, peer: PeerId) {
+        self.network.disconnect(self.node, peer);
+    }
+
+    fn decrease_peer_score(&self, peer: PeerId, amount: i32) {
+        *self
+            .network
+            .state
+            .lock()
+            .scores
+            .entry((self.node, peer))
+            .or_insert(0) -= amount;
+    }
+
+    fn register_timer(
+        &self, token: TimerToken, delay: Duration,
+    ) -> Result<(), Error> {
+        self.network.state.lock().schedule(
+            delay,
+            Event::Timer {
+                node: self.node,
+                token,
+                period: delay,
+            },
+        );
+        Ok(())
+    }
+
+    fn dispatch_work(&self, work_type: HandlerWorkType) {
+        self.network.state.lock().schedule(
+            Duration::from_millis(0),
+            Event::Work {
+                node: self.node,
+                work_type,
+            },
+        );
+    }
+
+    fn network_id(&self) -> u64 { SIMULATED_NETWORK_ID }
+
+    fn now(&self) -> Instant { self.network.start + self.network.now() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PING: u8 = 1;
+    const PONG: u8 = 2;
+    const PING_TIMER: TimerToken = 0;
+
+    /// Pings every connected peer on a timer, and records the virtual time
+    /// of every message received.
+    #[derive(Default)]
+    struct PingHandler {
+        peers: Mutex<Vec<PeerId>>,
+        received: Mutex<Vec<(PeerId, u8)>>,
+        timeouts: Mutex<usize>,
+    }
+
+    impl NetworkProtocolHandler for PingHandler {
+        fn initialize(&self, io: &NetworkContext) {
+            io.register_timer(PING_TIMER, Duration::from_millis(1000))
+                .unwrap();
+        }
+
+        fn on_message(&self, io: &NetworkContext, peer: PeerId, data: &[u8]) {
+            self.received.lock().push((peer, data[0]));
+            if data[0] == PING {
+                io.send(peer, vec![PONG], SendQueuePriority::High).unwrap();
+            }
+        }
+
+        fn on_peer_connected(&self, io: &NetworkContext, peer: PeerId) {
+            assert_eq!(io.get_protocol_version(peer), Some(1));
+            self.peers.lock().push(peer);
+        }
+
+        fn on_peer_disconnected(&self, _io: &NetworkContext, peer: PeerId) {
+            self.peers.lock().retain(|p| *p != peer);
+        }
+
+        fn on_timeout(&self, io: &NetworkContext, timer: TimerToken) {
+            assert_eq!(timer, PING_TIMER);
+            *self.timeouts.lock() += 1;
+            for peer in self.peers.lock().iter() {
+                io.send(*peer, vec![PING], SendQueuePriority::Normal)
+                    .unwrap();
+            }
+        }
+    }
+
+    fn new_network(count: usize) -> (SimulatedNetwork, Vec<Arc<PingHandler>>) {
+        let mut network = SimulatedNetwork::new(*b"tst", 1, 7);
+        let handlers: Vec<_> = (0..count)
+            .map(|_| Arc::new(PingHandler::default()))
+            .collect();
+        for handler in handlers.iter() {
+            network.add_node(handler.clone());
+        }
+        (network, handlers)
+    }
+
+    #[test]
+    fn test_latency_and_timers() {
+        let (network, handlers) = new_network(2);
+        network.connect(0, 1);
+        network.set_link(
+            0,
+            1,
+            LinkConfig {
+                latency: Duration::from_millis(300),
+                ..Default::default()
+            },
+        );
+        let start = network.with_context(0, |io| io.now());
+
+        // Both nodes ping at 1000ms, pings arrive at 1300ms and pongs at
+        // 1600ms.
+        network.run_for(Duration::from_millis(1299));
+        assert!(handlers[0].received.lock().is_empty());
+        network.run_for(Duration::from_millis(1));
+        assert_eq!(*handlers[0].received.lock(), vec![(1, PING)]);
+        network.run_for(Duration::from_millis(300));
+        assert_eq!(*handlers[0].received.lock(), vec![(1, PING), (1, PONG)]);
+        assert_eq!(*handlers[1].timeouts.lock(), 1);
+
+        network.run_for(Duration::from_millis(400));
+        assert_eq!(*handlers[1].timeouts.lock(), 2);
+        assert_eq!(network.now(), Duration::from_millis(2000));
+        // The handlers see the virtual time.
+        let elapsed = network.with_context(1, |io| io.now()) - start;
+        assert_eq!(elapsed, Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn test_loss_is_deterministic() {
+        let run = || {
+            let (network, _) = new_network(3);
+            network.set_default_link(LinkConfig {
+                loss_rate: 0.3,
+                jitter: Duration::from_millis(20),
+                ..Default::default()
+            });
+            network.connect_all();
+            network.run_for(Duration::from_secs(20));
+            network.message_stats()
+        };
+
+        let (delivered, lost) = run();
+        assert!(delivered > 0);
+        assert!(lost > 0);
+        assert_eq!(run(), (delivered, lost));
+    }
+
+    #[test]
+    fn test_partition() {
+        let (network, handlers) = new_network(3);
+        network.connect_all();
+        network.run_for(Duration::from_millis(10));
+        assert!(network.is_connected(0, 2));
+
+        network.partition(&[&[0, 1], &[2]]);
+        network.run_for(Duration::from_millis(10));
+        assert!(network.is_connected(0, 1));
+        assert!(!network.is_connected(0, 2));
+        assert!(!network.is_connected(1, 2));
+        assert!(handlers[2].peers.lock().is_empty());
+
+        // No connections are made across the partition.
+        network.connect(0, 2);
+        network.run_for(Duration::from_millis(10));
+        assert!(!network.is_connected(0, 2));
+
+        network.heal();
+        network.run_for(Duration::from_millis(10));
+        assert!(network.is_connected(0, 2));
+        assert!(network.is_connected(1, 2));
+        assert_eq!(handlers[2].peers.lock().len(), 2);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let (network, handlers) = new_network(2);
+        network.connect(0, 1);
+        let pinged =
+            |_: &SimulatedNetwork| handlers[0].received.lock().len() >= 4;
+        assert!(network.run_until(Duration::from_secs(10), pinged));
+        assert!(network.now() < Duration::from_secs(3));
+        assert!(!network.run_until(Duration::from_secs(1), |_| false));
+    }
+}
@@ -17,4 +17,9 @@ pub enum EpochNumber {
     LatestMined,
     /// Latest block with state.
     LatestState,
+    /// Latest block with state whose confirmation risk is low enough to be
+    /// considered confirmed.
+    LatestConfirmed,
+    /// Latest block with state whose confirmation risk is negligible.
+    LatestStable,
 }
@@ -21,6 +21,7 @@ pub mod epoch;
 pub mod filter;
 pub mod log_entry;
 pub mod receipt;
+pub mod state_proof;
 pub mod transaction;
 pub mod transaction_address;
 
@@ -30,6 +31,7 @@ pub use crate::{
     block_header::{BlockHeader, BlockHeaderBuilder},
     epoch::{EpochId, EpochNumber},
     log_entry::LogEntry,
+    state_proof::{TrieProof, TrieProofNode},
     transaction::{
         Action, SignedTransaction, Transaction, TransactionWithSignature,
         TxPropagateId,
@@ -0,0 +1,352 @@
+// Copyright 2019 Conflux Foundation. All rights reserved.
+// Conflux is free software and distributed under GNU General Public License.
+// See http://www.gnu.org/licenses/
+
+//! Merkle proofs for keys of the state trie, and their verification.
+//!
+//! A proof is the sequence of trie nodes from the state root down to the
+//! node where the walk for the key stops. Each node carries what is needed to
+//! recompute its merkle hash, so that a light client only has to trust the
+//! state root, e.g. the `deferred_state_root` of a block header.
+
+use crate::hash::{keccak, KECCAK_EMPTY};
+use cfx_types::H256;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::cmp::min;
+
+/// The number of children of a trie node, one for each nibble.
+pub const TRIE_CHILDREN_COUNT: usize = 16;
+
+/// The merkle hash of an empty trie and of an absent child.
+pub const MERKLE_NULL_NODE: H256 = KECCAK_EMPTY;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrieProofNode {
+    /// Set to 0x0f when only the first nibble of the last byte of
+    /// `compressed_path` belongs to the path.
+    pub path_end_mask: u8,
+    pub compressed_path: Vec<u8>,
+    /// The merkle hashes of the children, `None` for a leaf.
+    pub children_merkles: Option<Vec<H256>>,
+    pub value: Option<Vec<u8>>,
+}
+
+/// Where the walk for a key stops in a `TrieProofNode`.
+enum ProofWalk<'key> {
+    Arrived,
+    Descent {
+        child_index: usize,
+        key_remaining: &'key [u8],
+    },
+    PathDiverted,
+}
+
+impl TrieProofNode {
+    const FIRST_NIBBLE_MASK: u8 = 0x0f;
+
+    /// The path end mask is only meaningful for a non-empty path, and
+    /// `merkle_hash` ignores it otherwise.
+    fn has_valid_path(&self) -> bool {
+        match self.path_end_mask {
+            0 => true,
+            Self::FIRST_NIBBLE_MASK => !self.compressed_path.is_empty(),
+            _ => false,
+        }
+    }
+
+    pub fn merkle_hash(&self) -> H256 {
+        let mut node_rlp = RlpStream::new();
+        node_rlp.begin_unbounded_list();
+        if let Some(ref merkles) = self.children_merkles {
+            node_rlp.append_list(merkles);
+        }
+        if let Some(ref value) = self.value {
+            node_rlp.append(value);
+        }
+        node_rlp.complete_unbounded_list();
+        let node_merkle = keccak(node_rlp.as_raw());
+
+        if self.compressed_path.is_empty() {
+            node_merkle
+        } else {
+            let mut path_rlp = RlpStream::new_list(3);
+            path_rlp
+                .append(&self.path_end_mask)
+                .append(&self.compressed_path)
+                .append(&node_merkle);
+            keccak(path_rlp.as_raw())
+        }
+    }
+
+    /// Read-only counterpart of the walk in the storage trie node.
+    fn walk<'key>(&self, key: &'key [u8]) -> ProofWalk<'key> {
+        let path = &self.compressed_path;
+        let memcmp_len =
+            min(path.len() - ((self.path_end_mask != 0) as usize), key.len());
+        if path[0..memcmp_len] != key[0..memcmp_len] {
+            return ProofWalk::PathDiverted;
+        }
+
+        if key.len() == memcmp_len {
+            if path.len() > memcmp_len {
+                ProofWalk::PathDiverted
+            } else {
+                ProofWalk::Arrived
+            }
+        } else if path.len() == memcmp_len {
+            ProofWalk::Descent {
+                child_index: (key[memcmp_len] & Self::FIRST_NIBBLE_MASK)
+                    as usize,
+                key_remaining: &key[memcmp_len..],
+            }
+        } else if (path[memcmp_len] ^ key[memcmp_len]) & Self::FIRST_NIBBLE_MASK
+            != 0
+        {
+            ProofWalk::PathDiverted
+        } else {
+            ProofWalk::Descent {
+                child_index: (key[memcmp_len] >> 4) as usize,
+                key_remaining: &key[memcmp_len + 1..],
+            }
+        }
+    }
+}
+
+impl Encodable for TrieProofNode {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(4)
+            .append(&self.path_end_mask)
+            .append(&self.compressed_path);
+        match self.children_merkles {
+            Some(ref merkles) => s.append_list(merkles),
+            None => s.begin_list(0),
+        };
+        match self.value {
+            Some(ref value) => s.begin_list(1).append(value),
+            None => s.begin_list(0),
+        };
+    }
+}
+
+impl Decodable for TrieProofNode {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let children_merkles = rlp.list_at::<H256>(2)?;
+        let children_merkles = match children_merkles.len() {
+            0 => None,
+            TRIE_CHILDREN_COUNT => Some(children_merkles),
+            _ => return Err(DecoderError::RlpIncorrectListLen),
+        };
+        let value = rlp.at(3)?;
+        let value = match value.item_count()? {
+            0 => None,
+            1 => Some(value.val_at(0)?),
+            _ => return Err(DecoderError::RlpIncorrectListLen),
+        };
+        let node = TrieProofNode {
+            path_end_mask: rlp.val_at(0)?,
+            compressed_path: rlp.val_at(1)?,
+            children_merkles,
+            value,
+        };
+        if !node.has_valid_path() {
+            return Err(DecoderError::Custom("Invalid path end mask"));
+        }
+        Ok(node)
+    }
+}
+
+/// The trie nodes from the root to the node where the walk for a key stops.
+/// An empty proof proves that the trie is empty.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrieProof {
+    pub nodes: Vec<TrieProofNode>,
+}
+
+impl TrieProof {
+    pub fn new(nodes: Vec<TrieProofNode>) -> Self { TrieProof { nodes } }
+
+    /// Check the proof against `root` and return the value of `key` it
+    /// proves, `None` if it proves that `key` is absent.
+    pub fn verify(
+        &self, root: &H256, key: &[u8],
+    ) -> Result<Option<Vec<u8>>, String> {
+        if self.nodes.is_empty() {
+            return if *root == MERKLE_NULL_NODE {
+                Ok(None)
+            } else {
+                Err("Empty proof for a non-empty trie".into())
+            };
+        }
+
+        let mut expected_merkle = *root;
+        let mut key_remaining = key;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.merkle_hash() != expected_merkle {
+                return Err(format!("Merkle hash mismatch at node {}", i));
+            }
+            if !node.has_valid_path() {
+                return Err(format!("Invalid path at node {}", i));
+            }
+            let is_last = i + 1 == self.nodes.len();
+            let proven = match node.walk(key_remaining) {
+                ProofWalk::Arrived => Some(node.value.clone()),
+                ProofWalk::PathDiverted => Some(None),
+                ProofWalk::Descent {
+                    child_index,
+                    key_remaining: key_remaining_in_child,
+                } => match node.children_merkles {
+                    None => Some(None),
+                    Some(ref merkles) => {
+                        if merkles.len() != TRIE_CHILDREN_COUNT {
+                            return Err(format!(
+                                "Invalid children table at node {}",
+                                i
+                            ));
+                        }
+                        if merkles[child_index] == MERKLE_NULL_NODE {
+                            Some(None)
+                        } else {
+                            expected_merkle = merkles[child_index];
+                            key_remaining = key_remaining_in_child;
+                            None
+                        }
+                    }
+                },
+            };
+            match proven {
+                Some(value) => {
+                    return if is_last {
+                        Ok(value)
+                    } else {
+                        Err(format!("Unexpected nodes after node {}", i))
+                    };
+                }
+                None => {
+                    if is_last {
+                        return Err("Proof is incomplete".into());
+                    }
+                }
+            }
+        }
+
+        unreachable!()
+    }
+
+    /// Whether the proof shows that `key` maps to `value` under `root`.
+    pub fn is_valid(
+        &self, root: &H256, key: &[u8], value: Option<&[u8]>,
+    ) -> bool {
+        match self.verify(root, key) {
+            Ok(proven) => proven.as_ref().map(|v| v.as_slice()) == value,
+            Err(_) => false,
+        }
+    }
+}
+
+impl Encodable for TrieProof {
+    fn rlp_append(&self, s: &mut RlpStream) { s.append_list(&self.nodes); }
+}
+
+impl Decodable for TrieProof {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        Ok(TrieProof {
+            nodes: rlp.as_list()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrieProof, TrieProofNode, MERKLE_NULL_NODE};
+    use cfx_types::H256;
+
+    fn leaf(path: &[u8], value: &[u8]) -> TrieProofNode {
+        TrieProofNode {
+            path_end_mask: 0,
+            compressed_path: path.to_vec(),
+            children_merkles: None,
+            value: Some(value.to_vec()),
+        }
+    }
+
+    /// A root with an empty path and two leaf children for keys
+    /// [0x01, 0x02] and [0x02, 0x03].
+    fn two_leaves() -> (H256, TrieProofNode, TrieProofNode, TrieProofNode) {
+        let leaf_1 = leaf(&[0x01, 0x02], b"one");
+        let leaf_2 = leaf(&[0x02, 0x03], b"two");
+        let mut merkles = vec![MERKLE_NULL_NODE; 16];
+        merkles[1] = leaf_1.merkle_hash();
+        merkles[2] = leaf_2.merkle_hash();
+        let root = TrieProofNode {
+            path_end_mask: 0,
+            compressed_path: vec![],
+            children_merkles: Some(merkles),
+            value: None,
+        };
+        (root.merkle_hash(), root, leaf_1, leaf_2)
+    }
+
+    #[test]
+    fn test_verify_existing_key() {
+        let (root_merkle, root, leaf_1, leaf_2) = two_leaves();
+        let proof = TrieProof::new(vec![root.clone(), leaf_1]);
+        assert!(proof.is_valid(&root_merkle, &[0x01, 0x02], Some(&b"one"[..])));
+        assert!(!proof.is_valid(
+            &root_merkle,
+            &[0x01, 0x02],
+            Some(&b"two"[..])
+        ));
+        assert!(!proof.is_valid(
+            &H256::default(),
+            &[0x01, 0x02],
+            Some(&b"one"[..])
+        ));
+
+        let proof = TrieProof::new(vec![root, leaf_2]);
+        assert!(proof.is_valid(&root_merkle, &[0x02, 0x03], Some(&b"two"[..])));
+        assert!(proof.verify(&root_merkle, &[0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn test_verify_absent_key() {
+        let (root_merkle, root, leaf_1, _) = two_leaves();
+        // Child 3 doesn't exist.
+        let proof = TrieProof::new(vec![root.clone()]);
+        assert_eq!(proof.verify(&root_merkle, &[0x03]), Ok(None));
+        // Path of child 1 diverges.
+        let proof = TrieProof::new(vec![root.clone(), leaf_1.clone()]);
+        assert_eq!(proof.verify(&root_merkle, &[0x01, 0x05]), Ok(None));
+        // The proof must not stop before the leaf.
+        let proof = TrieProof::new(vec![root]);
+        assert!(proof.verify(&root_merkle, &[0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn test_verify_empty_trie() {
+        let proof = TrieProof::default();
+        assert_eq!(proof.verify(&MERKLE_NULL_NODE, &[0x01]), Ok(None));
+        assert!(proof.verify(&H256::default(), &[0x01]).is_err());
+    }
+
+    #[test]
+    fn test_reject_path_end_mask_on_empty_path() {
+        let node = TrieProofNode {
+            path_end_mask: 0x0f,
+            compressed_path: vec![],
+            children_merkles: None,
+            value: Some(b"one".to_vec()),
+        };
+        // The mask is not covered by the merkle hash of the node.
+        let proof = TrieProof::new(vec![node.clone()]);
+        assert!(proof.verify(&node.merkle_hash(), &[0x01]).is_err());
+        assert!(proof.verify(&node.merkle_hash(), &[]).is_err());
+        assert!(rlp::decode::<TrieProofNode>(&rlp::encode(&node)).is_err());
+    }
+
+    #[test]
+    fn test_rlp_round_trip() {
+        let (_, root, leaf_1, _) = two_leaves();
+        let proof = TrieProof::new(vec![root, leaf_1]);
+        assert_eq!(rlp::decode::<TrieProof>(&rlp::encode(&proof)), Ok(proof));
+    }
+}
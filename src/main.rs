@@ -8,8 +8,10 @@ extern crate log;
 extern crate log4rs;
 extern crate parking_lot;
 
-use clap::{App, Arg};
-use client::{Client, Configuration};
+use clap::{App, Arg, SubCommand};
+use client::{
+    check_db, export_blocks, import_blocks, Client, Configuration, LightClient,
+};
 use log::LevelFilter;
 use log4rs::{
     append::{console::ConsoleAppender, file::FileAppender},
@@ -196,6 +198,13 @@ fn main() {
                 .help("Sets test mode for adding latency")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("light-node")
+                .long("light-node")
+                .value_name("BOOL")
+                .help("Runs as a light node which only syncs block headers")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("db-compact-profile")
                 .long("db-compact-profile")
@@ -203,6 +212,13 @@ fn main() {
                 .help("Sets the compaction profile of RocksDB.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("db-backend")
+                .long("db-backend")
+                .value_name("ENUM")
+                .help("Sets the database backend, rocksdb or memory.")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("db-dir")
                 .long("db-dir")
@@ -249,6 +265,35 @@ fn main() {
                 .takes_value(true)
                 .validator(from_str_validator::<usize>),
         )
+        .subcommand(
+            SubCommand::with_name("export-blocks")
+                .about("Export all the blocks in topological order to an RLP file")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("The file to write the blocks to")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import-blocks")
+                .about("Verify and import the blocks from an RLP file")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("The file to read the blocks from")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check-db")
+                .about("Check the integrity of the blocks, receipts, transaction indices and states in the database")
+                .arg(
+                    Arg::with_name("repair")
+                        .long("repair")
+                        .help("Truncate the database back to the last consistent epoch"),
+                ),
+        )
         .get_matches_from(std::env::args().collect::<Vec<_>>());
 
     let conf = Configuration::parse(&matches).unwrap();
@@ -311,8 +356,24 @@ fn main() {
 
     let exit = Arc::new((Mutex::new(false), Condvar::new()));
 
-    process::exit(match Client::start(conf, exit.clone()) {
-        Ok(client_handle) => Client::run_until_closed(exit, client_handle),
+    let result = if let Some(matches) =
+        matches.subcommand_matches("export-blocks")
+    {
+        export_blocks(conf, matches.value_of("file").unwrap()).map(|_| 0)
+    } else if let Some(matches) = matches.subcommand_matches("import-blocks") {
+        import_blocks(conf, matches.value_of("file").unwrap()).map(|_| 0)
+    } else if let Some(matches) = matches.subcommand_matches("check-db") {
+        check_db(conf, matches.is_present("repair")).map(|_| 0)
+    } else if conf.raw_conf.light_node {
+        LightClient::start(conf).map(|light_client_handle| {
+            LightClient::run_until_closed(exit, light_client_handle)
+        })
+    } else {
+        Client::start(conf, exit.clone())
+            .map(|client_handle| Client::run_until_closed(exit, client_handle))
+    };
+    process::exit(match result {
+        Ok(exit_code) => exit_code,
         Err(err) => {
             writeln!(&mut stdio::stderr(), "{}", err).unwrap();
             1
@@ -24,6 +24,8 @@ class RpcClient:
         self.EPOCH_EARLIEST = "earliest"
         self.EPOCH_LATEST_MINED = "latest_mined"
         self.EPOCH_LATEST_STATE = "latest_state"
+        self.EPOCH_LATEST_CONFIRMED = "latest_confirmed"
+        self.EPOCH_LATEST_STABLE = "latest_stable"
 
         # hash/address definitions
         self.GENESIS_ADDR = eth_utils.encode_hex(privtoaddr(default_config["GENESIS_PRI_KEY"]))
@@ -205,6 +207,36 @@ class RpcClient:
     def get_receipt(self, tx_hash: str) -> dict:
         return self.node.gettransactionreceipt(tx_hash)
 
+    def syncing(self) -> dict:
+        return self.node.cfx_syncing()
+
+    def confirmation_risk(self, block_hash: str):
+        return self.node.cfx_getConfirmationRiskByHash(block_hash)
+
+    def get_proof(self, addr: str, storage_keys: list = [], epoch: str = None) -> dict:
+        if epoch is None:
+            return self.node.cfx_getProof(addr, storage_keys)
+        else:
+            return self.node.cfx_getProof(addr, storage_keys, epoch)
+
+    def dump_state(self, epoch: str = None, start_key: str = None, limit: int = None) -> dict:
+        if epoch is None:
+            epoch = self.EPOCH_LATEST_STATE
+        if limit is None:
+            return self.node.cfx_dumpState(epoch, start_key)
+        else:
+            return self.node.cfx_dumpState(epoch, start_key, hex(limit))
+
+    def dump_all_accounts(self, epoch: str = None, limit: int = None) -> list:
+        accounts = []
+        start_key = None
+        while True:
+            page = self.dump_state(epoch, start_key, limit)
+            accounts.extend(page["accounts"])
+            start_key = page["nextKey"]
+            if start_key is None:
+                return accounts
+
     def txpool_status(self) -> (int, int):
         status = self.node.txpool_status()
         return (status["pending"], status["ready"])
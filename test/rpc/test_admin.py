@@ -0,0 +1,54 @@
+import os
+import eth_utils
+import sys
+sys.path.append("..")
+
+from conflux.rpc import RpcClient
+from test_framework.util import assert_equal, assert_raises_rpc_error
+
+class TestAdmin(RpcClient):
+    def rand_node_id(self) -> str:
+        return eth_utils.encode_hex(os.urandom(64))
+
+    def test_peer_limits(self):
+        limits = self.node.admin_peerLimits()
+        incoming = limits["maxIncomingPeers"]
+        outgoing = limits["maxOutgoingPeers"]
+
+        self.node.admin_setPeerLimits(incoming + 1, None)
+        limits = self.node.admin_peerLimits()
+        assert_equal(limits["maxIncomingPeers"], incoming + 1)
+        assert_equal(limits["maxOutgoingPeers"], outgoing)
+
+        self.node.admin_setPeerLimits(incoming, outgoing)
+        assert_equal(self.node.admin_peerLimits()["maxIncomingPeers"], incoming)
+
+    def test_ban_node(self):
+        node_id = self.rand_node_id()
+        self.node.admin_banNode(node_id, 60)
+        banned = [n["nodeid"] for n in self.node.admin_bannedNodes()]
+        assert node_id in banned
+
+        assert_equal(self.node.admin_unbanNode(node_id), True)
+        banned = [n["nodeid"] for n in self.node.admin_bannedNodes()]
+        assert node_id not in banned
+        assert_equal(self.node.admin_unbanNode(node_id), False)
+
+    def test_block_ip(self):
+        self.node.admin_blockIp("10.1.0.0/16")
+        assert "10.1.0.0/16" in self.node.admin_blockedIps()
+
+        assert_equal(self.node.admin_unblockIp("10.1.0.0/16"), True)
+        assert "10.1.0.0/16" not in self.node.admin_blockedIps()
+        assert_equal(self.node.admin_unblockIp("10.1.0.0/16"), False)
+
+        assert_raises_rpc_error(None, None, self.node.admin_blockIp, "10.1.0.0/99")
+
+    def test_reserved_nodes(self):
+        node_id = self.rand_node_id()
+        self.node.admin_addReservedNode(node_id, "127.0.0.1:1")
+        assert node_id in self.node.admin_reservedNodes()
+        assert node_id in [n["nodeid"] for n in self.node.admin_nodeTable()]
+
+        assert_equal(self.node.admin_removeReservedNode(node_id), True)
+        assert node_id not in self.node.admin_reservedNodes()
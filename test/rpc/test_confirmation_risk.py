@@ -0,0 +1,31 @@
+import sys
+sys.path.append("..")
+
+from conflux.rpc import RpcClient
+from test_framework.util import assert_equal, assert_greater_than_or_equal
+
+class TestConfirmationRisk(RpcClient):
+    def test_unknown_block(self):
+        assert_equal(self.confirmation_risk(self.rand_hash()), None)
+
+    def test_genesis(self):
+        genesis = self.block_by_epoch(self.EPOCH_EARLIEST)["hash"]
+        assert_equal(self.confirmation_risk(genesis), 0.0)
+
+    def test_risk_decreases(self):
+        block = self.generate_block()
+        self.generate_blocks(20)
+        risk = self.confirmation_risk(block)
+        assert risk is not None
+        assert risk < 1
+
+    def test_finality_tags(self):
+        self.generate_blocks(20)
+        state = self.epoch_number(self.EPOCH_LATEST_STATE)
+        confirmed = self.epoch_number(self.EPOCH_LATEST_CONFIRMED)
+        stable = self.epoch_number(self.EPOCH_LATEST_STABLE)
+        assert_greater_than_or_equal(state, confirmed)
+        assert_greater_than_or_equal(confirmed, stable)
+
+        self.get_balance(self.GENESIS_ADDR, self.EPOCH_LATEST_CONFIRMED)
+        self.get_balance(self.GENESIS_ADDR, self.EPOCH_LATEST_STABLE)
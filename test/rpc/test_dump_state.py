@@ -0,0 +1,27 @@
+import sys
+sys.path.append("..")
+
+from conflux.rpc import RpcClient
+from test_framework.util import assert_equal, assert_greater_than, assert_raises_rpc_error
+
+class TestDumpState(RpcClient):
+    def test_genesis_account(self):
+        accounts = self.dump_all_accounts()
+        genesis = [a for a in accounts if a["address"] == self.GENESIS_ADDR]
+        assert_equal(len(genesis), 1)
+        assert_equal(int(genesis[0]["balance"], 0), self.get_balance(self.GENESIS_ADDR, self.EPOCH_LATEST_STATE))
+
+    def test_epoch_earliest(self):
+        accounts = self.dump_state(self.EPOCH_EARLIEST)["accounts"]
+        assert_greater_than(len(accounts), 0)
+
+    def test_paging(self):
+        page = self.dump_state(limit=1)
+        assert_equal(len(page["accounts"]), 1)
+        accounts = self.dump_all_accounts(limit=1)
+        assert_equal(accounts, self.dump_all_accounts())
+        assert_equal(accounts[0], page["accounts"][0])
+
+    def test_epoch_num_too_large(self):
+        mined_epoch = self.epoch_number()
+        assert_raises_rpc_error(None, None, self.dump_state, self.EPOCH_NUM(mined_epoch + 1))
@@ -0,0 +1,37 @@
+import sys
+sys.path.append("..")
+
+from conflux.rpc import RpcClient
+from test_framework.util import assert_equal, assert_greater_than, assert_raises_rpc_error
+
+class TestGetProof(RpcClient):
+    def test_genesis_account(self):
+        proof = self.get_proof(self.GENESIS_ADDR)
+        assert proof["account"] is not None
+        assert_greater_than(len(proof["accountProof"]), 0)
+        assert_equal(proof["storageProofs"], [])
+
+        block = self.block_by_epoch(self.EPOCH_LATEST_MINED)
+        assert_equal(proof["deferredStateRoot"], block["deferredStateRoot"])
+
+    def test_address_not_exists(self):
+        proof = self.get_proof(self.rand_addr())
+        assert_equal(proof["account"], None)
+        assert_greater_than(len(proof["accountProof"]), 0)
+
+    def test_storage_keys(self):
+        keys = [self.rand_hash(), self.rand_hash()]
+        proof = self.get_proof(self.GENESIS_ADDR, keys)
+        assert_equal(len(proof["storageProofs"]), 2)
+        for (key, storage_proof) in zip(keys, proof["storageProofs"]):
+            assert_equal(storage_proof["key"], key)
+            assert_equal(storage_proof["value"], None)
+
+    def test_epoch_earliest(self):
+        proof = self.get_proof(self.GENESIS_ADDR, [], self.EPOCH_EARLIEST)
+        block = self.block_by_epoch(self.EPOCH_EARLIEST)
+        assert_equal(proof["deferredStateRoot"], block["deferredStateRoot"])
+
+    def test_epoch_num_too_large(self):
+        mined_epoch = self.epoch_number()
+        assert_raises_rpc_error(None, None, self.get_proof, self.GENESIS_ADDR, [], self.EPOCH_NUM(mined_epoch + 1))
@@ -0,0 +1,23 @@
+import sys
+sys.path.append("..")
+
+from conflux.rpc import RpcClient
+from test_framework.util import assert_equal, assert_greater_than_or_equal
+
+class TestSyncing(RpcClient):
+    def test_local_epoch(self):
+        status = self.syncing()
+        epoch = self.epoch_number()
+        assert_equal(status["localEpoch"], epoch)
+
+        self.generate_blocks(3)
+        status = self.syncing()
+        assert_equal(status["localEpoch"], epoch + 3)
+
+    def test_fields(self):
+        status = self.syncing()
+        assert_greater_than_or_equal(status["peerCount"], 0)
+        assert_greater_than_or_equal(status["epochLag"], 0)
+        assert_greater_than_or_equal(status["executionQueueSize"], 0)
+        assert_greater_than_or_equal(status["blocksInFlight"], 0)
+        assert_equal(type(status["catchUpMode"]), bool)
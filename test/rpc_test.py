@@ -10,7 +10,7 @@ from conflux.utils import int_to_hex, privtoaddr, encode_hex
 from test_framework.blocktools import make_genesis, create_transaction
 from test_framework.mininode import start_p2p_connection
 from test_framework.test_framework import ConfluxTestFramework
-from test_framework.util import assert_equal, connect_nodes, get_peer_addr, wait_until, WaitHandler, checktx
+from test_framework.util import assert_equal, assert_greater_than, connect_nodes, get_peer_addr, wait_until, WaitHandler, checktx
 
 class RpcTest(ConfluxTestFramework):
     def set_test_params(self):
@@ -86,6 +86,10 @@ class RpcTest(ConfluxTestFramework):
         assert_equal(len(res), 1)
         assert_equal(len(self.nodes[1].getpeerinfo()), 1)
         assert_equal(res[0]['addr'], get_peer_addr(self.nodes[1]))
+        assert_equal(res[0]['score'], 0)
+        # The hello and status messages have been exchanged.
+        assert_greater_than(res[0]['traffic']['ingress']['bytes'], 0)
+        assert_greater_than(res[0]['traffic']['egress']['packets'], 0)
         self.nodes[0].removenode(self.nodes[1].key, get_peer_addr(self.nodes[1]))
         try:
             wait_until(lambda: len(self.nodes[0].getpeerinfo()) == 0, timeout=10)
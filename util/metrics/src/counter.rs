@@ -0,0 +1,128 @@
+use crate::is_enabled;
+use prometheus;
+
+pub struct Counter {
+    inner: Option<prometheus::IntCounter>,
+}
+
+impl Counter {
+    pub fn register(key: &'static str) -> Self {
+        if !is_enabled() {
+            return Counter { inner: None };
+        }
+
+        let counter = prometheus::IntCounter::new(key, " ").unwrap();
+        prometheus::default_registry()
+            .register(Box::new(counter.clone()))
+            .unwrap();
+
+        Counter {
+            inner: Some(counter),
+        }
+    }
+
+    pub fn inc(&self) {
+        if let Some(ref counter) = self.inner {
+            counter.inc();
+        }
+    }
+
+    pub fn inc_by(&self, delta: i64) {
+        if let Some(ref counter) = self.inner {
+            counter.inc_by(delta);
+        }
+    }
+
+    pub fn count(&self) -> i64 {
+        match self.inner {
+            Some(ref counter) => counter.get(),
+            None => 0,
+        }
+    }
+}
+
+/// A family of counters distinguished by label values, e.g. the bytes of each
+/// message type.
+pub struct CounterVec {
+    inner: Option<prometheus::IntCounterVec>,
+}
+
+impl CounterVec {
+    pub fn register(key: &'static str, labels: &[&str]) -> Self {
+        if !is_enabled() {
+            return CounterVec { inner: None };
+        }
+
+        let opts = prometheus::Opts::new(key, " ");
+        let counter = prometheus::IntCounterVec::new(opts, labels).unwrap();
+        prometheus::default_registry()
+            .register(Box::new(counter.clone()))
+            .unwrap();
+
+        CounterVec {
+            inner: Some(counter),
+        }
+    }
+
+    pub fn inc_by(&self, label_values: &[&str], delta: i64) {
+        if let Some(ref counter) = self.inner {
+            counter.with_label_values(label_values).inc_by(delta);
+        }
+    }
+
+    pub fn count(&self, label_values: &[&str]) -> i64 {
+        match self.inner {
+            Some(ref counter) => counter.with_label_values(label_values).get(),
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter() {
+        crate::enable();
+        let counter = Counter::register("test_counter");
+        counter.inc();
+        counter.inc_by(2);
+        assert_eq!(counter.count(), 3);
+    }
+
+    #[test]
+    fn test_counter_vec() {
+        crate::enable();
+        let counter = CounterVec::register("test_counter_vec", &["kind"]);
+        counter.inc_by(&["a"], 2);
+        counter.inc_by(&["b"], 5);
+        counter.inc_by(&["a"], 1);
+        assert_eq!(counter.count(&["a"]), 3);
+
+        let family = prometheus::default_registry()
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "test_counter_vec")
+            .unwrap();
+        let mut values: Vec<(String, f64)> = family
+            .get_metric()
+            .iter()
+            .map(|m| {
+                (
+                    m.get_label()[0].get_value().to_string(),
+                    m.get_counter().get_value(),
+                )
+            })
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(values, vec![("a".into(), 3.0), ("b".into(), 5.0)]);
+    }
+
+    #[test]
+    fn test_disabled_counter() {
+        let counter = Counter { inner: None };
+        counter.inc();
+        assert_eq!(counter.count(), 0);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::is_enabled;
+use prometheus;
+use std::time::Instant;
+
+pub struct Histogram {
+    inner: Option<prometheus::Histogram>,
+}
+
+impl Histogram {
+    /// Registers a histogram with the default prometheus buckets, which are
+    /// suitable for latencies measured in seconds.
+    pub fn register(key: &'static str) -> Self {
+        Self::register_with_buckets(key, prometheus::DEFAULT_BUCKETS.to_vec())
+    }
+
+    pub fn register_with_buckets(key: &'static str, buckets: Vec<f64>) -> Self {
+        if !is_enabled() {
+            return Histogram { inner: None };
+        }
+
+        let opts = prometheus::HistogramOpts::new(key, " ").buckets(buckets);
+        let histogram = prometheus::Histogram::with_opts(opts).unwrap();
+        prometheus::default_registry()
+            .register(Box::new(histogram.clone()))
+            .unwrap();
+
+        Histogram {
+            inner: Some(histogram),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        if let Some(ref histogram) = self.inner {
+            histogram.observe(value);
+        }
+    }
+
+    /// Returns a guard that records the elapsed time in seconds when dropped.
+    pub fn start_timer(&self) -> Timer {
+        Timer {
+            histogram: self,
+            start: Instant::now(),
+        }
+    }
+}
+
+pub struct Timer<'a> {
+    histogram: &'a Histogram,
+    start: Instant,
+}
+
+impl<'a> Drop for Timer<'a> {
+    fn drop(&mut self) {
+        if self.histogram.inner.is_some() {
+            let elapsed = self.start.elapsed();
+            self.histogram.observe(
+                elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        crate::enable();
+        let histogram =
+            Histogram::register_with_buckets("test_histogram", vec![1.0, 10.0]);
+        histogram.observe(0.5);
+        histogram.observe(5.0);
+        {
+            let _timer = histogram.start_timer();
+        }
+
+        let family = prometheus::default_registry()
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "test_histogram")
+            .unwrap();
+        let histogram = family.get_metric()[0].get_histogram();
+        assert_eq!(histogram.get_sample_count(), 3);
+        let buckets: Vec<u64> = histogram
+            .get_bucket()
+            .iter()
+            .map(|b| b.get_cumulative_count())
+            .collect();
+        assert_eq!(buckets, vec![2, 3]);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{is_enabled, meter};
+use prometheus::{self, Encoder, TextEncoder};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the default prometheus registry in the text exposition format at
+/// `http://<addr>/metrics`, so that a prometheus server can scrape the node.
+pub fn report_http(addr: SocketAddr) -> Result<(), String> {
+    if !is_enabled() {
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| format!("failed to bind metrics endpoint, {:?}", e))?;
+
+    thread::Builder::new()
+        .name("metrics_http".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .map_err(|e| format!("failed to accept, {:?}", e))
+                    .and_then(serve_once);
+                if let Err(e) = result {
+                    error!("Failed to serve metrics request: {:?}", e);
+                }
+            }
+        })
+        .map_err(|e| format!("failed to spawn metrics thread, {:?}", e))?;
+
+    Ok(())
+}
+
+fn serve_once(mut stream: TcpStream) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|e| format!("failed to set timeout, {:?}", e))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut request_line)
+        .map_err(|e| format!("failed to read request, {:?}", e))?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let response = if method != "GET" {
+        plain_response("405 Method Not Allowed", "text/plain", b"")
+    } else if path == "/metrics" || path == "/" {
+        meter::tick_all();
+        let encoder = TextEncoder::new();
+        let mut body = Vec::new();
+        encoder
+            .encode(&prometheus::default_registry().gather(), &mut body)
+            .map_err(|e| format!("failed to encode metrics, {:?}", e))?;
+        plain_response("200 OK", encoder.format_type(), &body)
+    } else {
+        plain_response("404 Not Found", "text/plain", b"")
+    };
+
+    stream
+        .write_all(&response)
+        .map_err(|e| format!("failed to write response, {:?}", e))
+}
+
+fn plain_response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn request(path: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let request = format!("GET {} HTTP/1.1\r\n\r\n", path);
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let (stream, _) = listener.accept().unwrap();
+        serve_once(stream).unwrap();
+        client.join().unwrap()
+    }
+
+    #[test]
+    fn test_serve_metrics() {
+        crate::enable();
+        let counter = crate::Counter::register("test_http_counter");
+        counter.inc_by(3);
+
+        let response = request("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("test_http_counter 3"));
+
+        assert!(request("/other").starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
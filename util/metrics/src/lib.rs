@@ -1,7 +1,23 @@
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate log;
+
+mod counter;
 mod gauge;
+mod histogram;
+mod http;
+mod meter;
 mod report;
 
-pub use self::{gauge::Gauge, report::report_file};
+pub use self::{
+    counter::{Counter, CounterVec},
+    gauge::Gauge,
+    histogram::{Histogram, Timer},
+    http::report_http,
+    meter::Meter,
+    report::report_file,
+};
 
 use std::sync::atomic::{AtomicBool, Ordering};
 
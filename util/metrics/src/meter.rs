@@ -0,0 +1,169 @@
+use crate::is_enabled;
+use parking_lot::Mutex;
+use prometheus;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Weight of the latest one-second sample in the exponentially weighted
+/// moving average, roughly a one minute window.
+const EWMA_ALPHA: f64 = 1.0 / 60.0;
+
+lazy_static! {
+    /// All registered meters, which are ticked before the metrics are read,
+    /// so that idle meters report a decaying rate.
+    static ref METERS: Mutex<Vec<Arc<MeterInner>>> = Mutex::new(Vec::new());
+}
+
+/// Update the rates of all meters to the current time.
+pub(crate) fn tick_all() {
+    let now = Instant::now();
+    for meter in METERS.lock().iter() {
+        meter.tick(now);
+    }
+}
+
+struct MeterInner {
+    total: prometheus::IntCounter,
+    rate: prometheus::Gauge,
+    state: Mutex<MeterState>,
+}
+
+impl MeterInner {
+    fn tick(&self, now: Instant) {
+        if let Some(rate) = self.state.lock().tick(now) {
+            self.rate.set(rate);
+        }
+    }
+}
+
+struct MeterState {
+    last_tick: Instant,
+    uncounted: u64,
+    ewma: Option<f64>,
+}
+
+impl MeterState {
+    fn new(now: Instant) -> Self {
+        MeterState {
+            last_tick: now,
+            uncounted: 0,
+            ewma: None,
+        }
+    }
+
+    /// Fold every whole second elapsed since the last tick into the moving
+    /// average, where the events marked since then count for the first
+    /// second and the others are idle. Returns the new rate if any second
+    /// has elapsed.
+    fn tick(&mut self, now: Instant) -> Option<f64> {
+        if now <= self.last_tick {
+            return None;
+        }
+        let ticks = now.duration_since(self.last_tick).as_secs();
+        if ticks == 0 {
+            return None;
+        }
+
+        let rate = self.uncounted as f64;
+        let mut ewma = match self.ewma {
+            Some(prev) => prev + EWMA_ALPHA * (rate - prev),
+            None => rate,
+        };
+        // Each idle second decays the average by `1 - EWMA_ALPHA`.
+        let idle = (ticks - 1).min(i32::max_value() as u64) as i32;
+        ewma *= (1.0 - EWMA_ALPHA).powi(idle);
+
+        self.ewma = Some(ewma);
+        self.uncounted = 0;
+        self.last_tick += Duration::from_secs(ticks);
+        Some(ewma)
+    }
+}
+
+/// Meter tracks the total number of events marked, and exposes the moving
+/// average rate (per second) as a separate `<key>_rate` gauge.
+pub struct Meter {
+    inner: Option<Arc<MeterInner>>,
+}
+
+impl Meter {
+    pub fn register(key: &'static str) -> Self {
+        if !is_enabled() {
+            return Meter { inner: None };
+        }
+
+        let total =
+            prometheus::IntCounter::new(format!("{}_total", key), " ").unwrap();
+        let rate =
+            prometheus::Gauge::new(format!("{}_rate", key), " ").unwrap();
+        prometheus::default_registry()
+            .register(Box::new(total.clone()))
+            .unwrap();
+        prometheus::default_registry()
+            .register(Box::new(rate.clone()))
+            .unwrap();
+
+        let inner = Arc::new(MeterInner {
+            total,
+            rate,
+            state: Mutex::new(MeterState::new(Instant::now())),
+        });
+        METERS.lock().push(inner.clone());
+        Meter { inner: Some(inner) }
+    }
+
+    pub fn mark(&self, n: usize) {
+        if let Some(ref inner) = self.inner {
+            inner.total.inc_by(n as i64);
+            // Events after the last whole second belong to the next tick.
+            inner.tick(Instant::now());
+            inner.state.lock().uncounted += n as u64;
+        }
+    }
+
+    /// The moving average rate per second, up to date with the current time.
+    pub fn rate(&self) -> f64 {
+        match self.inner {
+            Some(ref inner) => {
+                inner.tick(Instant::now());
+                inner.rate.get()
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_of_steady_events() {
+        let start = Instant::now();
+        let mut state = MeterState::new(start);
+        for second in 1..=120 {
+            state.uncounted += 10;
+            let rate = state.tick(start + Duration::from_secs(second));
+            assert_eq!(rate, Some(10.0));
+        }
+        assert_eq!(state.tick(start + Duration::from_millis(120_500)), None);
+    }
+
+    #[test]
+    fn test_rate_decays_when_idle() {
+        let start = Instant::now();
+        let mut state = MeterState::new(start);
+        state.uncounted = 60;
+        assert_eq!(state.tick(start + Duration::from_secs(1)), Some(60.0));
+
+        // No event is marked for a minute.
+        let rate = state.tick(start + Duration::from_secs(61)).unwrap();
+        let expected = 60.0 * (1.0 - EWMA_ALPHA).powi(60);
+        assert!((rate - expected).abs() < 1e-9);
+
+        let rate = state.tick(start + Duration::from_secs(3600)).unwrap();
+        assert!(rate < 0.01);
+    }
+}
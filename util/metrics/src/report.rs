@@ -1,4 +1,4 @@
-use crate::is_enabled;
+use crate::{is_enabled, meter};
 use prometheus;
 use std::{
     fs::OpenOptions,
@@ -38,6 +38,7 @@ fn report_file_once<P: AsRef<Path>>(path: P) -> Result<(), String> {
         .open(path)
         .map_err(|e| format!("failed to open file, {:?}", e))?;
 
+    meter::tick_all();
     for m in prometheus::default_registry().gather() {
         file.write(format!("{}, {:?}\n", now.as_millis(), m).as_bytes())
             .map_err(|e| format!("failed to write file, {:?}", e))?;
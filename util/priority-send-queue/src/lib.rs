@@ -59,6 +59,13 @@ impl<T> PrioritySendQueue<T> {
             .map(|data| (data, promoted))
     }
 
+    /// The value to be sent next, without promoting it like `front_mut`.
+    pub fn front(&self) -> Option<&T> {
+        self.queue(SendQueuePriority::High)
+            .front()
+            .or_else(|| self.queue(SendQueuePriority::Normal).front())
+    }
+
     pub fn is_empty(&self) -> bool {
         self.queue(SendQueuePriority::High).is_empty()
             && self.queue(SendQueuePriority::Normal).is_empty()